@@ -2,7 +2,9 @@
 
 use std::f64::consts::{E, PI};
 
+#[derive(Debug, Clone, Copy)]
 pub struct Function {
+    name: &'static str,
     activator: fn(&mut f64),
     derivative: fn(&mut f64),
 }
@@ -14,11 +16,35 @@ impl Function {
     pub fn derive(&self) -> fn(&mut f64) {
         self.derivative
     }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// finds a function by the name returned from [`Function::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sigmoid" => Some(Function::sigmoid()),
+            "swish" => Some(Function::swish()),
+            "arctan" => Some(Function::arctan()),
+            "normal_arctan" => Some(Function::normal_arctan()),
+            "relu" => Some(Function::relu()),
+            "leaky_relu" => Some(Function::leaky_relu()),
+            "tanh" => Some(Function::tanh()),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
 }
 
 impl Function {
     pub fn sigmoid() -> Self {
         Function {
+            name: "sigmoid",
             activator: sigmoid,
             derivative: sigmoid_derivative,
         }
@@ -26,6 +52,7 @@ impl Function {
 
     pub fn swish() -> Self {
         Function {
+            name: "swish",
             activator: swish,
             derivative: swish_derivative,
         }
@@ -33,6 +60,7 @@ impl Function {
 
     pub fn arctan() -> Self {
         Function {
+            name: "arctan",
             activator: arctan,
             derivative: arctan_derivative,
         }
@@ -40,6 +68,7 @@ impl Function {
 
     pub fn normal_arctan() -> Self {
         Function {
+            name: "normal_arctan",
             activator: normalized_arctan,
             derivative: normalized_arctan_derivative,
         }
@@ -47,6 +76,7 @@ impl Function {
 
     pub fn relu() -> Self {
         Function {
+            name: "relu",
             activator: relu,
             derivative: relu_derivative,
         }
@@ -54,10 +84,19 @@ impl Function {
 
     pub fn leaky_relu() -> Self {
         Function {
+            name: "leaky_relu",
             activator: leaky_relu,
             derivative: leaky_relu_derivative,
         }
     }
+
+    pub fn tanh() -> Self {
+        Function {
+            name: "tanh",
+            activator: tanh,
+            derivative: tanh_derivative,
+        }
+    }
 }
 
 fn sigmoid(t: &mut f64) {
@@ -118,3 +157,11 @@ fn leaky_relu_derivative(t: &mut f64) {
 
     *t = 1.0;
 }
+
+fn tanh(t: &mut f64) {
+    *t = t.tanh()
+}
+
+fn tanh_derivative(t: &mut f64) {
+    *t = 1.0 - t.tanh().powi(2)
+}
@@ -0,0 +1,25 @@
+use std::error::Error;
+
+use clap::Args;
+
+use super::load_network;
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// a network file, checkpoint, older text layout, or ONNX graph
+    input: String,
+
+    /// written as ONNX if it ends in `.onnx`, otherwise as a network file
+    output: String,
+}
+
+pub fn run(args: ConvertArgs) -> Result<(), Box<dyn Error>> {
+    let nn = load_network(&args.input)?;
+    if args.output.ends_with(".onnx") {
+        nn.export_onnx(&args.output)?;
+    } else {
+        nn.save(&args.output)?;
+    }
+    println!("Converted {} to {}", args.input, args.output);
+    Ok(())
+}
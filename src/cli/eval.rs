@@ -0,0 +1,52 @@
+use std::error::Error;
+
+use clap::Args;
+
+use mathematics::machine_learning::neural_network::cost_functions::CostFunction;
+
+use super::{class_name, load_network, DataArgs};
+
+#[derive(Args, Debug)]
+pub struct EvalArgs {
+    /// the network file, checkpoint, or ONNX graph
+    #[arg(long)]
+    model: String,
+
+    #[command(flatten, next_help_heading = "Data")]
+    data: DataArgs,
+
+    /// the split of the dataset to evaluate on
+    #[arg(long, default_value = "test")]
+    split: String,
+
+    /// the name of the cost function
+    #[arg(long, default_value = "quadratic")]
+    cost: String,
+
+    /// the k of top-k accuracy
+    #[arg(long, default_value_t = 3)]
+    top_k: usize,
+}
+
+pub fn run(args: EvalArgs) -> Result<(), Box<dyn Error>> {
+    let cost = CostFunction::from_name(&args.cost)
+        .ok_or(format!("unknown cost function `{}`", args.cost))?;
+    let mut nn = load_network(&args.model)?;
+
+    // the inputs are transformed as they were for training
+    let data_config = args.data.config();
+    let mut data = data_config.load_split(&args.split)?;
+    if let Some(d) = data.first() {
+        nn.check_input(&d.data)?;
+    }
+    nn.check_targets(&data)?;
+    data.iter_mut()
+        .for_each(|d| d.data = nn.preprocessing().transform(&d.data));
+
+    let evaluation = nn.evaluate(&data, &cost, args.top_k);
+    if nn.metadata().labels.is_empty() {
+        nn.metadata_mut().labels = data_config.label_names(evaluation.classes());
+    }
+    println!("{}", evaluation.table(|class| class_name(&nn, class)));
+    Ok(())
+}
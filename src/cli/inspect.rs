@@ -0,0 +1,104 @@
+use std::{error::Error, fs};
+
+use clap::Args;
+
+use mathematics::machine_learning::neural_network::{
+    checkpoint::Checkpoint, model_file, NeuralNetwork,
+};
+
+use super::load_network;
+
+#[derive(Args, Debug)]
+pub struct InspectArgs {
+    /// a network file, checkpoint, older text layout, or ONNX graph
+    path: String,
+}
+
+pub fn run(args: InspectArgs) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(&args.path)?;
+    let checkpoint = Checkpoint::decode(&bytes).ok();
+    let format = match &checkpoint {
+        _ if args.path.ends_with(".onnx") => "ONNX graph".to_string(),
+        Some(_) => "checkpoint".to_string(),
+        None if model_file::is_model_file(&bytes) => {
+            let version = bytes[model_file::MAGIC.len()..][..4].try_into()?;
+            format!("network file, version {}", u32::from_le_bytes(version))
+        }
+        None => "text layout of older versions".to_string(),
+    };
+    println!("{}: {}", args.path, format);
+
+    let nn = match checkpoint {
+        Some(checkpoint) => {
+            let progress = &checkpoint.progress;
+            println!(
+                "Progress: {} epochs and {} batches",
+                progress.epoch, progress.batch
+            );
+            if let Some(metrics) = progress.history.last().filter(|m| !m.is_empty()) {
+                let metrics: Vec<String> = metrics
+                    .iter()
+                    .map(|(name, value)| format!("{} {:.4}", name, value))
+                    .collect();
+                println!("Last epoch: {}", metrics.join(", "));
+            }
+            checkpoint.network
+        }
+        None => load_network(&args.path)?,
+    };
+    describe(&nn);
+    Ok(())
+}
+
+fn describe(nn: &NeuralNetwork) {
+    println!();
+    println!("Layers:");
+    let mut total = 0;
+    for (i, layer) in nn.model().layers().iter().enumerate() {
+        let record = layer.record();
+        let config: Vec<String> = record
+            .config
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        let shapes: Vec<String> = layer
+            .parameters()
+            .iter()
+            .map(|p| {
+                let (r, c) = p.get_dims();
+                total += r * c;
+                format!("{}x{}", r, c)
+            })
+            .collect();
+        println!(
+            "{:>4}  {:<14}{:<24}{}",
+            i,
+            record.kind,
+            shapes.join(", "),
+            config.join(" ")
+        );
+    }
+    println!("Parameters: {}", total);
+
+    let preprocessing = nn.preprocessing();
+    if !preprocessing.is_empty() {
+        let steps: Vec<String> = preprocessing
+            .steps()
+            .iter()
+            .map(|step| step.record().kind)
+            .collect();
+        println!("Preprocessing: {}", steps.join(", "));
+    }
+
+    let metadata = nn.metadata();
+    if !metadata.hyperparameters.is_empty() {
+        println!();
+        println!("Hyperparameters:");
+        for (key, value) in metadata.hyperparameters.iter() {
+            println!("  {} = {}", key, value);
+        }
+    }
+    if !metadata.labels.is_empty() {
+        println!("Labels: {}", metadata.labels.join(", "));
+    }
+}
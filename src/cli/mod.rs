@@ -0,0 +1,221 @@
+//! The command-line interface, every setting is a flag so runs can be scripted.
+
+use std::error::Error;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use mathematics::machine_learning::{
+    config::{self, DataConfig},
+    neural_network::NeuralNetwork,
+};
+
+mod convert;
+mod eval;
+mod inspect;
+mod predict;
+mod search;
+mod train;
+mod visualize;
+
+#[derive(Parser, Debug)]
+#[command(about = "Trains and runs neural networks", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Trains a network and saves it
+    Train(Box<train::TrainArgs>),
+    /// Trains a network for every combination of hyperparameters and compares them
+    Search(Box<search::SearchArgs>),
+    /// Evaluates a saved network on a dataset
+    Eval(eval::EvalArgs),
+    /// Predicts the classes of the samples in a CSV file, or of images of handwriting
+    Predict(predict::PredictArgs),
+    /// Describes a saved network or checkpoint
+    Inspect(inspect::InspectArgs),
+    /// Converts between network files and ONNX
+    Convert(convert::ConvertArgs),
+    /// Draws the weights, samples, activations, confusion matrix or training curves
+    Visualize(Box<visualize::VisualizeArgs>),
+}
+
+impl Cli {
+    pub fn run(self) -> Result<(), Box<dyn Error>> {
+        match self.command {
+            Command::Train(args) => train::run(*args),
+            Command::Search(args) => search::run(*args),
+            Command::Eval(args) => eval::run(args),
+            Command::Predict(args) => predict::run(args),
+            Command::Inspect(args) => inspect::run(args),
+            Command::Convert(args) => convert::run(args),
+            Command::Visualize(args) => visualize::run(*args),
+        }
+    }
+}
+
+/// The file layout of a dataset
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// `{name}.{split}.images` and `.labels` in the IDX format of MNIST
+    Idx,
+    /// `{name}.{split}.csv` or `.tsv`
+    Csv,
+    /// `{name}.{split}.npz`, or `.x.npy` and `.y.npy`
+    Npy,
+    /// `{name}/{split}/{class}/` directories of images
+    Images,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DataArgs {
+    /// the directory holding the dataset
+    #[arg(long, default_value = "src/assets/machine_learning/")]
+    data_dir: String,
+
+    /// the name of the dataset within the directory
+    #[arg(long, default_value = "letters")]
+    dataset: String,
+
+    #[arg(long, value_enum, default_value_t = Format::Idx)]
+    format: Format,
+
+    /// subtracted from every label so the first class is 0 [default: 1 for idx, 0 otherwise]
+    #[arg(long)]
+    label_offset: Option<usize>,
+
+    /// the column of the label in CSV files; `first`, `last`, an index, or a header name
+    #[arg(long, default_value = "last")]
+    label_column: String,
+
+    /// CSV files have no header line
+    #[arg(long)]
+    no_header: bool,
+
+    /// the height and width images are resized to, and the shape of convolutions
+    #[arg(long, default_value_t = 28)]
+    image_size: usize,
+
+    /// keeps the color channels of images
+    #[arg(long)]
+    rgb: bool,
+
+    /// the names of the classes; `letters`, `digits`, or comma separated names
+    #[arg(long)]
+    labels: Option<String>,
+}
+
+impl DataArgs {
+    /// the dataset described by the flags
+    pub fn config(&self) -> DataConfig {
+        DataConfig {
+            directory: self.data_dir.clone(),
+            name: self.dataset.clone(),
+            format: match self.format {
+                Format::Idx => config::Format::Idx,
+                Format::Csv => config::Format::Csv,
+                Format::Npy => config::Format::Npy,
+                Format::Images => config::Format::Images,
+            },
+            label_offset: self.label_offset,
+            label_column: self.label_column.clone(),
+            header: !self.no_header,
+            image_size: self.image_size,
+            rgb: self.rgb,
+            labels: self.labels.clone(),
+            ..DataConfig::default()
+        }
+    }
+}
+
+/// Reads a network file, a checkpoint, or an ONNX graph (by its `.onnx` extension)
+pub fn load_network(path: &str) -> Result<NeuralNetwork, Box<dyn Error>> {
+    if path.ends_with(".onnx") {
+        return NeuralNetwork::import_onnx(path);
+    }
+    NeuralNetwork::load(path).map_err(|e| format!("Could not load {}: {}", path, e).into())
+}
+
+/// names a class by the labels saved with the network, or its number
+pub fn class_name(nn: &NeuralNetwork, class: usize) -> String {
+    nn.metadata()
+        .labels
+        .get(class)
+        .cloned()
+        .unwrap_or_else(|| class.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cli;
+    use clap::{CommandFactory, Parser};
+
+    #[test]
+    fn test_arguments() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "mathematics",
+            "train",
+            "--hidden",
+            "128,64",
+            "--epochs",
+            "3",
+            "--format",
+            "csv",
+        ])
+        .unwrap();
+        let text = format!("{:?}", cli);
+        assert!(text.contains("hidden: [128, 64]"), "{}", text);
+
+        assert!(Cli::try_parse_from(["mathematics", "train", "--epochs", "x"]).is_err());
+        assert!(Cli::try_parse_from(["mathematics", "eval"]).is_err());
+
+        // a config file replaces the flags describing the run, but not the output ones
+        let config = ["mathematics", "train", "--config", "run.toml"];
+        assert!(Cli::try_parse_from([&config[..], &["--output-dir", "out"]].concat()).is_ok());
+        assert!(Cli::try_parse_from([&config[..], &["--epochs", "3"]].concat()).is_err());
+        assert!(Cli::try_parse_from([&config[..], &["--dataset", "digits"]].concat()).is_err());
+        let logs = ["--logs", "csv,jsonl", "--log-every", "10"];
+        assert!(Cli::try_parse_from([&config[..], &logs].concat()).is_ok());
+        assert!(Cli::try_parse_from([&config[..], &["--logs", "xml"]].concat()).is_err());
+
+        let predict = ["mathematics", "predict", "--model", "network.nn"];
+        let images = ["--image", "a.png", "b.jpg", "--ink", "dark"];
+        assert!(Cli::try_parse_from([&predict[..], &images].concat()).is_ok());
+        let both = ["--image", "a.png", "--input", "samples.csv"];
+        assert!(Cli::try_parse_from([&predict[..], &both].concat()).is_err());
+        let no_room = ["--image", "a.png", "--size", "2"];
+        assert!(Cli::try_parse_from([&predict[..], &no_room].concat()).is_err());
+
+        let visualize = ["mathematics", "visualize", "--log", "output/epochs.csv"];
+        assert!(
+            Cli::try_parse_from([&visualize[..], &["--loss-curve", "loss.svg"]].concat()).is_ok()
+        );
+        assert!(Cli::try_parse_from(["mathematics", "visualize", "--weights", "w.png"]).is_err());
+        assert!(
+            Cli::try_parse_from(["mathematics", "visualize", "--loss-curve", "l.svg"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "mathematics",
+            "search",
+            "--hidden-layers",
+            "64",
+            "--hidden-layers",
+            "128,64",
+            "--activations",
+            "relu,tanh",
+        ])
+        .unwrap();
+        let text = format!("{:?}", cli);
+        assert!(
+            text.contains("hidden_layers: [[64], [128, 64]]"),
+            "{}",
+            text
+        );
+        assert!(Cli::try_parse_from(["mathematics", "search", "--activations", "cube"]).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    error::Error,
+    fs,
+    io::{stdin, Read},
+    path::Path,
+};
+
+use clap::{builder::RangedU64ValueParser, Args, ValueEnum};
+
+use mathematics::{
+    linear_algebra::Matrix,
+    machine_learning::{
+        dataset::handwriting::{HandwritingParser, Ink},
+        neural_network::NeuralNetwork,
+    },
+};
+
+use super::{class_name, load_network};
+
+#[derive(Args, Debug)]
+pub struct PredictArgs {
+    /// the network file, checkpoint, or ONNX graph
+    #[arg(long)]
+    model: String,
+
+    /// a CSV file with the features of one sample per line, `-` reads standard input
+    #[arg(long, default_value = "-", conflicts_with = "image")]
+    input: String,
+
+    /// the first line names the columns
+    #[arg(long, conflicts_with = "image")]
+    header: bool,
+
+    /// PNG or JPEG images of single handwritten characters, converted like the images of EMNIST
+    #[arg(long, num_args = 1..)]
+    image: Vec<String>,
+
+    /// the color of the writing in the images
+    #[arg(long, value_enum, default_value_t = InkArg::Auto)]
+    ink: InkArg,
+
+    /// the width and height the images are resized to, a pixel on every side is left empty
+    #[arg(long, default_value_t = 28, value_parser = RangedU64ValueParser::<usize>::new().range(3..))]
+    size: usize,
+
+    /// keeps the images upright, for networks not trained on the transposed images of EMNIST
+    #[arg(long)]
+    no_transpose: bool,
+
+    /// the number of most likely classes printed for every sample
+    #[arg(long, default_value_t = 1)]
+    top: usize,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum InkArg {
+    /// dark if the border of the image is mostly light
+    Auto,
+    Dark,
+    Light,
+}
+
+/// Prints a line per sample; its number, then the most likely classes with their outputs,
+/// all separated by tabs.
+pub fn run(args: PredictArgs) -> Result<(), Box<dyn Error>> {
+    let nn = load_network(&args.model)?;
+    if !args.image.is_empty() {
+        return predict_images(&nn, &args);
+    }
+
+    let contents = match args.input.as_str() {
+        "-" => {
+            let mut contents = String::new();
+            stdin().read_to_string(&mut contents)?;
+            contents
+        }
+        path => fs::read_to_string(path)?,
+    };
+
+    let lines = contents
+        .lines()
+        .skip(args.header as usize)
+        .filter(|line| !line.trim().is_empty());
+    for (i, line) in lines.enumerate() {
+        let values = line
+            .split([',', '\t'])
+            .map(|v| {
+                v.trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid value `{}` in sample {}", v.trim(), i))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        let input = Matrix::from_vec(values.len(), 1, values);
+        nn.check_input(&input)
+            .map_err(|e| format!("Sample {}: {}", i, e))?;
+        let output = nn.propagate(&nn.preprocessing().transform(&input));
+
+        let mut classes: Vec<(usize, f64)> = output.iter().copied().enumerate().collect();
+        classes.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let predictions: Vec<String> = classes
+            .into_iter()
+            .take(args.top.max(1))
+            .map(|(class, value)| format!("{}\t{:.4}", class_name(&nn, class), value))
+            .collect();
+        println!("{}\t{}", i, predictions.join("\t"));
+    }
+    Ok(())
+}
+
+/// Prints a line per image; its path, then the most likely classes with their probabilities,
+/// all separated by tabs.
+fn predict_images(nn: &NeuralNetwork, args: &PredictArgs) -> Result<(), Box<dyn Error>> {
+    let parser = HandwritingParser::default()
+        .size(args.size)
+        .transposed(!args.no_transpose)
+        .ink(match args.ink {
+            InkArg::Auto => Ink::Auto,
+            InkArg::Dark => Ink::Dark,
+            InkArg::Light => Ink::Light,
+        });
+
+    for path in args.image.iter() {
+        let input = parser.load(Path::new(path))?;
+        nn.check_input(&input)
+            .map_err(|e| format!("{}: {}, check --size", path, e))?;
+        let predictions: Vec<String> = nn
+            .predict(&input, args.top.max(1))
+            .into_iter()
+            .map(|p| format!("{}\t{:.4}", class_name(nn, p.class), p.probability))
+            .collect();
+        println!("{}\t{}", path, predictions.join("\t"));
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::{error::Error, path::Path};
+
+use clap::{Args, ValueEnum};
+
+use mathematics::{
+    calculus::functions::Function,
+    machine_learning::search::{Search, SearchSpace, Strategy},
+};
+
+use super::train::RunArgs;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum StrategyArg {
+    /// every combination of the values
+    Grid,
+    /// random combinations, learning rates are drawn between the smallest and largest given
+    Random,
+    /// random combinations, the worse ones are stopped early
+    Halving,
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// the run every trial starts from
+    #[command(flatten)]
+    run: RunArgs,
+
+    #[command(flatten, next_help_heading = "Search")]
+    space: SpaceArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SpaceArgs {
+    #[arg(long, value_enum, default_value_t = StrategyArg::Grid)]
+    strategy: StrategyArg,
+
+    /// the number of random trials
+    #[arg(long, default_value_t = 10)]
+    trials: usize,
+
+    /// the epochs of the first round of successive halving
+    #[arg(long, default_value_t = 1)]
+    min_epochs: usize,
+
+    /// successive halving keeps the best 1/reduction of the trials after every round
+    #[arg(long, default_value_t = 3)]
+    reduction: usize,
+
+    /// e.g. `0.1,1,3`
+    #[arg(long, value_delimiter = ',')]
+    learning_rates: Vec<f64>,
+
+    /// e.g. `16,32`
+    #[arg(long, value_delimiter = ',')]
+    batch_sizes: Vec<usize>,
+
+    /// the sizes of the hidden dense layers of a trial, repeated for every shape,
+    /// e.g. `--hidden-layers 64 --hidden-layers 128,64`
+    #[arg(long, value_parser = parse_layers)]
+    hidden_layers: Vec<Vec<usize>>,
+
+    /// e.g. `sigmoid,relu`
+    #[arg(long, value_delimiter = ',', value_parser = parse_activation)]
+    activations: Vec<String>,
+
+    /// makes the random trials reproducible
+    #[arg(long)]
+    search_seed: Option<u64>,
+
+    /// the number of trials trained at once, 0 uses every core
+    #[arg(long, default_value_t = 0)]
+    parallel: usize,
+}
+
+fn parse_layers(sizes: &str) -> Result<Vec<usize>, String> {
+    sizes
+        .split(',')
+        .map(|size| {
+            size.trim()
+                .parse()
+                .map_err(|_| format!("invalid layer size `{}`", size))
+        })
+        .collect()
+}
+
+fn parse_activation(name: &str) -> Result<String, String> {
+    match Function::from_name(name) {
+        Some(_) => Ok(name.to_string()),
+        None => Err(format!("unknown activation function `{}`", name)),
+    }
+}
+
+pub fn run(args: SearchArgs) -> Result<(), Box<dyn Error>> {
+    let space = &args.space;
+    let strategy = match space.strategy {
+        StrategyArg::Grid => Strategy::Grid,
+        StrategyArg::Random => Strategy::Random {
+            trials: space.trials,
+        },
+        StrategyArg::Halving => Strategy::Halving {
+            trials: space.trials,
+            min_epochs: space.min_epochs,
+            reduction: space.reduction,
+        },
+    };
+
+    let mut search = Search::new(
+        args.run.config()?,
+        SearchSpace {
+            learning_rate: space.learning_rates.clone(),
+            batch_size: space.batch_sizes.clone(),
+            hidden: space.hidden_layers.clone(),
+            activation: space.activations.clone(),
+        },
+    )
+    .strategy(strategy)
+    .parallel(space.parallel);
+    if let Some(seed) = space.search_seed {
+        search = search.seed(seed);
+    }
+
+    println!("Training {} trials", search.candidates().len());
+    let trials = search.run()?;
+    print!("{}", search.table(&trials));
+
+    let directory = Path::new(&search.base().output.directory);
+    println!(
+        "Wrote the results to {}",
+        directory.join("results.csv").display()
+    );
+    if let Some(best) = trials.first().filter(|trial| trial.evaluation.is_ok()) {
+        println!(
+            "The best network is in {}",
+            search.trial_directory(best.index)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,418 @@
+use std::error::Error;
+
+use clap::{Args, ValueEnum};
+
+use mathematics::{
+    calculus::functions::Function,
+    machine_learning::{
+        config::{
+            Config, LayerConfig, LogFormat, NetworkConfig, OptimizerConfig, OutputConfig,
+            PreprocessingStep, SamplingConfig, ScheduleConfig, ScheduleKind, TrainingConfig,
+        },
+        dataset::augmentation::Transform,
+        neural_network::{early_stopping::Metric, learning_rate::Stepping},
+        trainer::Trainer,
+    },
+};
+
+use super::{class_name, DataArgs};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    None,
+    Batch,
+    Layer,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    Constant,
+    /// halved every 10 epochs
+    StepDecay,
+    /// multiplied by 0.95 every epoch
+    ExponentialDecay,
+    /// cosine annealing, restarting after 10, 20, 40... epochs
+    Cosine,
+    OneCycle,
+    /// halved when the metric has not improved for 2 epochs
+    Plateau,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    None,
+    Standardize,
+    /// scales every feature to -1, 1
+    MinMax,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum LogArg {
+    /// `epochs.csv`, and `batches.csv` with `--log-every`
+    Csv,
+    /// `log.jsonl`
+    Jsonl,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum SamplingArg {
+    Shuffled,
+    Stratified,
+    /// draws every class equally often
+    Balanced,
+}
+
+#[derive(Args, Debug)]
+pub struct TrainArgs {
+    #[command(flatten)]
+    run: RunArgs,
+
+    /// continues from the latest checkpoint in the output directory,
+    /// which is otherwise cleared; the other flags have to match the checkpointed run
+    #[arg(long, help_heading = "Output")]
+    resume: bool,
+
+    /// no progress bar
+    #[arg(long, help_heading = "Output")]
+    quiet: bool,
+}
+
+/// The flags describing a training run
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// a TOML or JSON file describing the run, instead of the data, network and training flags
+    #[arg(long, conflicts_with_all = ["DataArgs", "NetworkArgs", "TrainingArgs"])]
+    config: Option<String>,
+
+    #[command(flatten, next_help_heading = "Data")]
+    data: DataArgs,
+
+    #[command(flatten, next_help_heading = "Network")]
+    network: NetworkArgs,
+
+    #[command(flatten, next_help_heading = "Training")]
+    training: TrainingArgs,
+
+    #[command(flatten, next_help_heading = "Output")]
+    output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct NetworkArgs {
+    /// continues training a saved network, instead of creating one from the flags below
+    #[arg(long)]
+    init: Option<String>,
+
+    /// 5x5 convolution filters, followed by 2x2 max pooling, before the dense layers
+    #[arg(long, default_value_t = 0)]
+    conv_filters: usize,
+
+    /// the sizes of the hidden dense layers, e.g. `128,64`
+    #[arg(long, value_delimiter = ',')]
+    hidden: Vec<usize>,
+
+    /// sigmoid, swish, arctan, normal_arctan, relu, leaky_relu or tanh
+    #[arg(long, default_value = "sigmoid", value_parser = parse_activation)]
+    activation: Function,
+
+    /// the dropout rate after the hidden layers
+    #[arg(long, default_value_t = 0.0)]
+    dropout: f64,
+
+    /// normalizes the hidden layers before their activation
+    #[arg(long, value_enum, default_value_t = Normalization::None)]
+    normalization: Normalization,
+
+    /// fitted on the training data and saved with the network
+    #[arg(long, value_enum, default_value_t = Scaling::None)]
+    preprocessing: Scaling,
+}
+
+#[derive(Args, Debug)]
+pub struct TrainingArgs {
+    #[arg(long, default_value_t = 30)]
+    epochs: usize,
+
+    #[arg(long, default_value_t = 16)]
+    batch_size: usize,
+
+    #[arg(long, default_value_t = 1.0)]
+    learning_rate: f64,
+
+    #[arg(long, value_enum, default_value_t = Schedule::Constant)]
+    schedule: Schedule,
+
+    /// epochs of linear warmup before the schedule
+    #[arg(long, default_value_t = 0)]
+    warmup: usize,
+
+    /// the name of the cost function
+    #[arg(long, default_value = "quadratic")]
+    cost: String,
+
+    #[arg(long, default_value_t = 0.0)]
+    l1: f64,
+
+    /// L2 weight decay
+    #[arg(long, default_value_t = 0.0)]
+    l2: f64,
+
+    /// the largest norm of the weights into a node
+    #[arg(long)]
+    max_norm: Option<f64>,
+
+    /// clips the global norm of the gradient
+    #[arg(long)]
+    clip_norm: Option<f64>,
+
+    /// the fraction of the training data held out for validation
+    #[arg(long, default_value_t = 0.1)]
+    validation_split: f64,
+
+    #[arg(long, value_enum, default_value_t = SamplingArg::Shuffled)]
+    sampling: SamplingArg,
+
+    /// makes the order of the training data reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// randomly shifts, rotates, scales, distorts and erases the training images
+    #[arg(long)]
+    augment: bool,
+
+    /// stops after this many epochs without improvement, 0 disables early stopping
+    #[arg(long, default_value_t = 0)]
+    patience: usize,
+
+    /// accuracy, top_k_accuracy, macro_f1, mean_loss or log_loss; monitored for early stopping,
+    /// the plateau schedule, and the best checkpoint
+    #[arg(long, default_value = "accuracy", value_parser = parse_metric)]
+    metric: Metric,
+
+    /// the k of top-k accuracy
+    #[arg(long, default_value_t = 3)]
+    top_k: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    /// where the network, its configuration, and the checkpoints are saved [default: output]
+    #[arg(long)]
+    output_dir: Option<String>,
+
+    /// also saves a checkpoint every this many batches, besides after every epoch [default: 0]
+    #[arg(long)]
+    checkpoint_every: Option<usize>,
+
+    /// the number of checkpoints kept, 0 keeps every one [default: 3]
+    #[arg(long)]
+    keep_last: Option<usize>,
+
+    /// the formats of the training logs in the output directory, e.g. `csv,jsonl` [default: csv]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    logs: Option<Vec<LogArg>>,
+
+    /// also logs every this many batches, besides every epoch [default: 0]
+    #[arg(long)]
+    log_every: Option<usize>,
+}
+
+fn parse_activation(name: &str) -> Result<Function, String> {
+    Function::from_name(name).ok_or(format!("unknown activation function `{}`", name))
+}
+
+fn parse_metric(name: &str) -> Result<Metric, String> {
+    Metric::from_name(name).ok_or(format!("unknown metric `{}`", name))
+}
+
+pub fn run(args: TrainArgs) -> Result<(), Box<dyn Error>> {
+    let mut config = args.run.config()?;
+    config.data.progress = !args.quiet;
+    let mut trainer = Trainer::from_config(config)?
+        .quiet(args.quiet)
+        .resume(args.resume);
+    let evaluation = trainer.run()?;
+
+    let nn = trainer.network();
+    println!("{}", evaluation.table(|class| class_name(nn, class)));
+    println!(
+        "Saved the network to {}",
+        trainer.config().output.network_path().display()
+    );
+    Ok(())
+}
+
+impl RunArgs {
+    /// the run of the config file, or the one described by the flags
+    pub fn config(&self) -> Result<Config, Box<dyn Error>> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => self.to_config(),
+        };
+        self.output.apply(&mut config.output);
+        Ok(config)
+    }
+
+    /// the run described by the flags
+    fn to_config(&self) -> Config {
+        let (network, training) = (&self.network, &self.training);
+        let mut data = self.data.config();
+        data.validation_split = training.validation_split;
+
+        let preprocessing = match network.preprocessing {
+            Scaling::None => vec![],
+            Scaling::Standardize => vec![PreprocessingStep::Standardize],
+            Scaling::MinMax => vec![PreprocessingStep::MinMax {
+                low: -1.0,
+                high: 1.0,
+            }],
+        };
+
+        let kind = match training.schedule {
+            Schedule::Constant => ScheduleKind::Constant,
+            Schedule::StepDecay => ScheduleKind::StepDecay {
+                step_size: 10,
+                gamma: 0.5,
+            },
+            Schedule::ExponentialDecay => ScheduleKind::ExponentialDecay { gamma: 0.95 },
+            Schedule::Cosine => ScheduleKind::Cosine {
+                period: 10,
+                period_multiplier: 2,
+                min_learning_rate: 0.0,
+            },
+            Schedule::OneCycle => ScheduleKind::OneCycle,
+            Schedule::Plateau => ScheduleKind::Plateau {
+                factor: 0.5,
+                patience: 2,
+            },
+        };
+
+        Config {
+            data,
+            preprocessing,
+            network: NetworkConfig {
+                init: network.init.clone(),
+                layers: network.layers(),
+            },
+            optimizer: OptimizerConfig {
+                learning_rate: training.learning_rate,
+                l1: training.l1,
+                l2: training.l2,
+                max_norm: training.max_norm,
+                clip_norm: training.clip_norm,
+            },
+            schedule: ScheduleConfig {
+                kind,
+                warmup: training.warmup,
+                stepping: Stepping::Epoch,
+            },
+            training: TrainingConfig {
+                epochs: training.epochs,
+                batch_size: training.batch_size,
+                cost: training.cost.clone(),
+                seed: training.seed,
+                sampling: match training.sampling {
+                    SamplingArg::Shuffled => SamplingConfig::Shuffled,
+                    SamplingArg::Stratified => SamplingConfig::Stratified,
+                    SamplingArg::Balanced => SamplingConfig::Balanced,
+                },
+                patience: training.patience,
+                metric: training.metric,
+                top_k: training.top_k,
+                augmentation: match training.augment {
+                    true => augmentation(),
+                    false => vec![],
+                },
+            },
+            output: OutputConfig::default(),
+        }
+    }
+}
+
+impl NetworkArgs {
+    fn layers(&self) -> Vec<LayerConfig> {
+        let mut layers = vec![];
+        let activation = || LayerConfig::Activation {
+            function: self.activation.name().to_string(),
+        };
+
+        if self.conv_filters > 0 {
+            // 5x5 kernels keeping the image size, followed by 2x2 max pooling
+            layers.push(LayerConfig::Conv2d {
+                filters: self.conv_filters,
+                kernel: 5,
+                stride: 1,
+                padding: 2,
+            });
+            layers.push(LayerConfig::Activation {
+                function: Function::relu().name().to_string(),
+            });
+            layers.push(LayerConfig::MaxPool2d { size: 2, stride: 2 });
+        }
+
+        for units in &self.hidden {
+            layers.push(LayerConfig::Dense {
+                units: Some(*units),
+            });
+            // normalize before the activation, so sigmoid stays out of saturation
+            match self.normalization {
+                Normalization::None => {}
+                Normalization::Batch => layers.push(LayerConfig::BatchNorm),
+                Normalization::Layer => layers.push(LayerConfig::LayerNorm),
+            }
+            layers.push(activation());
+            if self.dropout > 0.0 {
+                layers.push(LayerConfig::Dropout { rate: self.dropout });
+            }
+        }
+
+        layers.push(LayerConfig::Dense { units: None });
+        layers.push(activation());
+        layers
+    }
+}
+
+impl OutputArgs {
+    /// overrides the output settings of a config file
+    fn apply(&self, output: &mut OutputConfig) {
+        if let Some(directory) = &self.output_dir {
+            output.directory = directory.clone();
+        }
+        if let Some(checkpoint_every) = self.checkpoint_every {
+            output.checkpoint_every = checkpoint_every;
+        }
+        if let Some(keep_last) = self.keep_last {
+            output.keep_last = keep_last;
+        }
+        if let Some(logs) = &self.logs {
+            output.logs = logs
+                .iter()
+                .map(|log| match log {
+                    LogArg::Csv => LogFormat::Csv,
+                    LogArg::Jsonl => LogFormat::Jsonl,
+                })
+                .collect();
+        }
+        if let Some(log_every) = self.log_every {
+            output.log_every = log_every;
+        }
+    }
+}
+
+/// small random distortions of the handwriting, the letters stay readable
+fn augmentation() -> Vec<Transform> {
+    vec![
+        Transform::Shift { max: 2.0 },
+        Transform::Rotate { max_degrees: 10.0 },
+        Transform::Scale { min: 0.9, max: 1.1 },
+        Transform::Elastic {
+            alpha: 8.0,
+            sigma: 3.0,
+        },
+        Transform::Erase {
+            probability: 0.3,
+            min_area: 0.02,
+            max_area: 0.1,
+        },
+    ]
+}
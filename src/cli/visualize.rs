@@ -0,0 +1,155 @@
+use std::{error::Error, fs, path::Path};
+
+use clap::Args;
+
+use mathematics::machine_learning::{
+    config::Format,
+    neural_network::{cost_functions::CostFunction, logging::read_epochs},
+    visualization::{confusion_image, confusion_svg, training_curves, Levels, Tiles},
+};
+
+use super::{class_name, load_network, DataArgs};
+
+#[derive(Args, Debug)]
+pub struct VisualizeArgs {
+    /// the network file, checkpoint, or ONNX graph
+    #[arg(long)]
+    model: Option<String>,
+
+    #[command(flatten, next_help_heading = "Data")]
+    data: DataArgs,
+
+    /// the split of the dataset to draw samples from and evaluate on
+    #[arg(long, default_value = "test")]
+    split: String,
+
+    /// writes the weights of the first layer as tiles, e.g. `weights.png`
+    #[arg(long, requires = "model", help_heading = "Output")]
+    weights: Option<String>,
+
+    /// writes the first `--count` samples as tiles
+    #[arg(long, help_heading = "Output")]
+    samples: Option<String>,
+
+    /// writes every channel of the output of `--layer` for the sample `--sample` as tiles
+    #[arg(long, requires = "model", help_heading = "Output")]
+    activations: Option<String>,
+
+    /// writes the confusion matrix as a heatmap, labelled if it is an `.svg`
+    #[arg(long, requires = "model", help_heading = "Output")]
+    confusion: Option<String>,
+
+    /// writes the loss of every epoch of `--log` as a chart, labelled if it is an `.svg`
+    #[arg(long, requires = "log", help_heading = "Output")]
+    loss_curve: Option<String>,
+
+    /// writes the accuracy of every epoch of `--log` as a chart, labelled if it is an `.svg`
+    #[arg(long, requires = "log", help_heading = "Output")]
+    accuracy_curve: Option<String>,
+
+    /// the epochs of a training run, `epochs.csv` in its output directory
+    #[arg(long)]
+    log: Option<String>,
+
+    /// the number of samples drawn
+    #[arg(long, default_value_t = 64)]
+    count: usize,
+
+    /// the layer whose activations are drawn, counting from 0
+    #[arg(long, default_value_t = 0)]
+    layer: usize,
+
+    /// the sample whose activations are drawn
+    #[arg(long, default_value_t = 0)]
+    sample: usize,
+
+    /// the size in pixels of every pixel of a tile
+    #[arg(long, default_value_t = 4)]
+    scale: u32,
+
+    /// draws IDX images as they are stored, instead of turning the transposed EMNIST images upright
+    #[arg(long)]
+    no_transpose: bool,
+}
+
+pub fn run(args: VisualizeArgs) -> Result<(), Box<dyn Error>> {
+    let nn = args.model.as_deref().map(load_network).transpose()?;
+    let data_config = args.data.config();
+    let tiles = Tiles::new(data_config.image_shape())
+        .scale(args.scale)
+        .transposed(data_config.format == Format::Idx && !args.no_transpose);
+    let mut written = vec![];
+
+    if let (Some(path), Some(nn)) = (&args.weights, &nn) {
+        tiles.levels(Levels::Signed).weights(nn)?.save(path)?;
+        written.push(path);
+    }
+
+    if args.samples.is_some() || args.activations.is_some() || args.confusion.is_some() {
+        let data = data_config.load_split(&args.split)?;
+        if let Some(path) = &args.samples {
+            let count = args.count.min(data.len());
+            tiles.samples(&data[..count]).save(path)?;
+            written.push(path);
+        }
+
+        if let (Some(path), Some(nn)) = (&args.activations, &nn) {
+            let sample = data
+                .get(args.sample)
+                .ok_or(format!("The split has only {} samples", data.len()))?;
+            tiles
+                .levels(Levels::Stretched)
+                .activations(nn, sample, args.layer)?
+                .save(path)?;
+            written.push(path);
+        }
+
+        if let (Some(path), Some(nn)) = (&args.confusion, &nn) {
+            if let Some(d) = data.first() {
+                nn.check_input(&d.data)?;
+            }
+            nn.check_targets(&data)?;
+            // the inputs are transformed as they were for training
+            let data: Vec<_> = data
+                .into_iter()
+                .map(|mut d| {
+                    d.data = nn.preprocessing().transform(&d.data);
+                    d
+                })
+                .collect();
+            let evaluation = nn.evaluate(&data, &CostFunction::quadratic(), 1);
+            let names = data_config.label_names(evaluation.classes());
+            let name = |class: usize| match nn.metadata().labels.is_empty() {
+                true => names
+                    .get(class)
+                    .cloned()
+                    .unwrap_or_else(|| class.to_string()),
+                false => class_name(nn, class),
+            };
+            match Path::new(path).extension().is_some_and(|e| e == "svg") {
+                true => fs::write(path, confusion_svg(&evaluation, name))?,
+                false => confusion_image(&evaluation, 16).save(path)?,
+            }
+            written.push(path);
+        }
+    }
+
+    if let Some(log) = &args.log {
+        let records = read_epochs(&fs::read_to_string(log)?)?;
+        let [loss, accuracy] = training_curves(&records);
+        for (path, chart) in [(&args.loss_curve, loss), (&args.accuracy_curve, accuracy)] {
+            if let Some(path) = path {
+                chart.save(path)?;
+                written.push(path);
+            }
+        }
+    }
+
+    if written.is_empty() {
+        return Err("Nothing to draw, choose an output such as --weights or --loss-curve".into());
+    }
+    for path in written {
+        println!("Wrote {}", path);
+    }
+    Ok(())
+}
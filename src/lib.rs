@@ -0,0 +1,7 @@
+pub mod calculus;
+pub mod linear_algebra;
+pub mod machine_learning;
+pub mod utilities;
+
+extern crate cblas;
+extern crate openblas_src;
@@ -1,5 +1,6 @@
+use std::ops::Range;
+
 use rayon::prelude::*;
-use std::ptr::swap_nonoverlapping;
 
 use super::Matrix;
 
@@ -10,10 +11,10 @@ impl Matrix {
     }
 
     pub fn transpose(&self) -> Self {
-        let mut v: Vec<f64> = vec![];
-        for i in 0..self.c {
-            for x in 0..self.r {
-                v.push(self.arr[i + x * self.c]);
+        let mut v: Vec<f64> = Vec::with_capacity(self.r * self.c);
+        for x in 0..self.r {
+            for i in 0..self.c {
+                v.push(self[(x, i)]);
             }
         }
         Matrix {
@@ -40,18 +41,68 @@ impl Matrix {
     }
 
     pub fn apply_into(mut self, func: fn(&mut f64)) -> Self {
-        self.par_iter_mut().for_each(|f| func(f));
+        self.par_iter_mut().for_each(func);
         self
     }
 
     pub fn component_mul(mut self, other: &Matrix) -> Self {
         self.iter_mut()
             .zip(other.iter())
-            .for_each(|(a, b)| *a = *a * *b);
+            .for_each(|(a, b)| *a *= *b);
 
         self
     }
 
+    /// adds `column` to every column of the matrix
+    pub fn add_to_columns(mut self, column: &Matrix) -> Self {
+        for chunk in self.arr.chunks_mut(self.r.max(1)) {
+            chunk
+                .iter_mut()
+                .zip(column.iter())
+                .for_each(|(a, b)| *a += *b);
+        }
+        self
+    }
+
+    /// sums every row of the matrix into a column vector
+    pub fn sum_columns(&self) -> Matrix {
+        let mut sum = Matrix::zeros(self.r, 1);
+        for chunk in self.arr.chunks(self.r.max(1)) {
+            sum.arr
+                .iter_mut()
+                .zip(chunk.iter())
+                .for_each(|(a, b)| *a += *b);
+        }
+        sum
+    }
+
+    /// returns column `i` as a column vector
+    pub fn column(&self, i: usize) -> Matrix {
+        Matrix {
+            r: self.r,
+            c: 1,
+            arr: self.arr[i * self.r..(i + 1) * self.r].to_vec(),
+        }
+    }
+
+    /// returns the rows in `range` as a new matrix
+    pub fn rows(&self, range: Range<usize>) -> Matrix {
+        let mut arr = Vec::with_capacity(range.len() * self.c);
+        for chunk in self.arr.chunks(self.r.max(1)) {
+            arr.extend_from_slice(&chunk[range.clone()]);
+        }
+        Matrix {
+            r: range.len(),
+            c: self.c,
+            arr,
+        }
+    }
+
+    /// `false` if any value is `NaN` or infinite
+    pub fn is_finite(&self) -> bool {
+        self.arr.iter().all(|v| v.is_finite())
+    }
+
     pub fn index_of_max(&self) -> usize {
         let mut index: usize = 0;
         let mut max: f64 = f64::MIN;
@@ -104,12 +155,22 @@ impl Matrix {
     }
 
     fn swap_rows(&mut self, i0: usize, i1: usize) {
-        unsafe {
-            swap_nonoverlapping(
-                self.get_row_mut(i0).unwrap().as_mut_ptr(),
-                self.get_row_mut(i1).unwrap().as_mut_ptr(),
-                self.c,
-            )
+        for x in 0..self.c {
+            self.arr.swap(i0 + x * self.r, i1 + x * self.r);
+        }
+    }
+
+    /// `row[i0] -= scale * row[i1]`
+    fn subtract_scaled_row(&mut self, i0: usize, i1: usize, scale: f64) {
+        for x in 0..self.c {
+            let v = self[(i1, x)];
+            self[(i0, x)] -= scale * v;
+        }
+    }
+
+    fn scale_row(&mut self, i: usize, scale: f64) {
+        for x in 0..self.c {
+            self[(i, x)] *= scale;
         }
     }
 
@@ -127,15 +188,7 @@ impl Matrix {
 
         // swap rows
         for i in 0..self.c {
-            if let Some(c) = self.get_col(i) {
-                let mut i2 = i;
-                for x in i..self.r {
-                    if c[x] != 0.0 {
-                        i2 = x;
-                        break;
-                    }
-                }
-
+            if let Some(i2) = (i..self.r).find(|x| self[(*x, i)] != 0.0) {
                 if i2 != i {
                     self.swap_rows(i, i2);
                     identity.swap_rows(i, i2);
@@ -143,30 +196,18 @@ impl Matrix {
             }
 
             // normalize
-            if let (Some(r), Some(ri)) = (self.get_row_mut(i), identity.get_row_mut(i)) {
-                let d = r[i];
-                if d != 0.0 {
-                    r.iter_mut().for_each(|e| *e = *e / d);
-                    ri.iter_mut().for_each(|e| *e = *e / d);
-                }
-            };
-
-            if let (Some(r), Some(ri)) = (self.get_row(i), identity.get_row(i)) {
-                // reduce all other values in column to zero
-                for x in 0..self.r {
-                    if i != x {
-                        if let (Some(rc), Some(rci)) =
-                            (self.get_row_mut(x), identity.get_row_mut(x))
-                        {
-                            let v = rc[i];
-                            rc.iter_mut()
-                                .zip(r.iter())
-                                .for_each(|(x1, y1)| *x1 -= y1 * v);
-                            rci.iter_mut()
-                                .zip(ri.iter())
-                                .for_each(|(x1, y1)| *x1 -= y1 * v);
-                        }
-                    }
+            let d = self[(i, i)];
+            if d != 0.0 {
+                self.scale_row(i, 1.0 / d);
+                identity.scale_row(i, 1.0 / d);
+            }
+
+            // reduce all other values in column to zero
+            for x in 0..self.r {
+                if i != x {
+                    let v = self[(x, i)];
+                    self.subtract_scaled_row(x, i, v);
+                    identity.subtract_scaled_row(x, i, v);
                 }
             }
         }
@@ -175,38 +216,83 @@ impl Matrix {
         Some(identity)
     }
 
-    pub fn get_row(&self, i: usize) -> Option<Vec<f64>> {
-        if i >= self.r {
+    /// Eigenvalues and eigenvectors of a symmetric matrix (cyclic Jacobi, O(n^3) per sweep),
+    /// sorted by descending eigenvalue; every column of the matrix is an eigenvector.
+    ///
+    /// Only the upper triangle is trusted to be symmetric, `None` if the matrix is not square.
+    pub fn symmetric_eigen(&self) -> Option<(Vec<f64>, Matrix)> {
+        if self.c != self.r {
             return None;
         }
 
-        let start: usize = self.c * i;
-        let v = self.arr[start..start + self.c].to_owned();
+        let n = self.r;
+        let mut a = self.clone();
+        let mut vectors = Matrix::identity(n, n);
+        let total: f64 = a.arr.iter().map(|v| v * v).sum();
+
+        for _ in 0..100 {
+            let off_diagonal: f64 = (0..n)
+                .flat_map(|p| (p + 1..n).map(move |q| (p, q)))
+                .map(|(p, q)| a[(p, q)] * a[(p, q)])
+                .sum();
+            if off_diagonal <= total * 1e-24 || off_diagonal == 0.0 {
+                break;
+            }
 
-        Some(v)
-    }
+            for p in 0..n {
+                for q in p + 1..n {
+                    if a[(p, q)] == 0.0 {
+                        continue;
+                    }
 
-    pub fn get_col(&self, i: usize) -> Option<Vec<f64>> {
-        if i >= self.c {
-            return None;
+                    // the rotation that zeroes a[(p, q)]
+                    let theta = (a[(q, q)] - a[(p, p)]) / (2.0 * a[(p, q)]);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+
+                    for k in 0..n {
+                        let (x, y) = (a[(k, p)], a[(k, q)]);
+                        a[(k, p)] = c * x - s * y;
+                        a[(k, q)] = s * x + c * y;
+                    }
+                    for k in 0..n {
+                        let (x, y) = (a[(p, k)], a[(q, k)]);
+                        a[(p, k)] = c * x - s * y;
+                        a[(q, k)] = s * x + c * y;
+                    }
+                    for k in 0..n {
+                        let (x, y) = (vectors[(k, p)], vectors[(k, q)]);
+                        vectors[(k, p)] = c * x - s * y;
+                        vectors[(k, q)] = s * x + c * y;
+                    }
+                }
+            }
         }
 
-        let mut v = vec![];
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|i, j| a[(*j, *j)].total_cmp(&a[(*i, *i)]));
+        let values = order.iter().map(|i| a[(*i, *i)]).collect();
+        let columns: Vec<Matrix> = order.iter().map(|i| vectors.column(*i)).collect();
 
-        for x in 0..self.r {
-            v.push(self.arr[i + x * self.c]);
+        Some((values, Matrix::from_columns(&columns)))
+    }
+
+    pub fn get_row(&self, i: usize) -> Option<Vec<f64>> {
+        if i >= self.r {
+            return None;
         }
 
-        Some(v)
+        Some((0..self.c).map(|x| self[(i, x)]).collect())
     }
 
-    fn get_row_mut(&mut self, i: usize) -> Option<&mut [f64]> {
-        if i >= self.r {
+    pub fn get_col(&self, i: usize) -> Option<Vec<f64>> {
+        if i >= self.c {
             return None;
         }
 
-        let start: usize = self.c * i;
-        let v = &mut self.arr[start..start + self.c];
+        let start: usize = self.r * i;
+        let v = self.arr[start..start + self.r].to_owned();
 
         Some(v)
     }
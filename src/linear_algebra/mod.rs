@@ -3,6 +3,8 @@ use rand::{distributions::Distribution, Rng};
 pub mod methods;
 pub mod trait_impls;
 
+/// A dense matrix of `f64` values stored in column-major order,
+/// matching the layout expected by BLAS.
 #[derive(PartialEq, Clone)]
 pub struct Matrix {
     r: usize,
@@ -35,9 +37,44 @@ impl Matrix {
         Matrix { r, c, arr }
     }
 
+    /// joins column vectors into a matrix, every input becomes one column
+    pub fn from_columns<'a>(columns: impl IntoIterator<Item = &'a Matrix>) -> Self {
+        let (mut r, mut c) = (0, 0);
+        let mut arr: Vec<f64> = vec![];
+        for column in columns {
+            assert!(
+                c == 0 || column.r == r,
+                "Cannot join columns of different lengths"
+            );
+            r = column.r;
+            c += column.c;
+            arr.extend_from_slice(&column.arr);
+        }
+        Matrix { r, c, arr }
+    }
+
+    /// stacks matrices with the same number of columns on top of each other
+    pub fn from_rows<'a>(blocks: impl IntoIterator<Item = &'a Matrix>) -> Self {
+        let blocks: Vec<&Matrix> = blocks.into_iter().collect();
+        let c = blocks.first().map_or(0, |b| b.c);
+        assert!(
+            blocks.iter().all(|b| b.c == c),
+            "Cannot stack matrices with a different number of columns"
+        );
+
+        let r = blocks.iter().map(|b| b.r).sum();
+        let mut arr = Vec::with_capacity(r * c);
+        for j in 0..c {
+            for block in blocks.iter() {
+                arr.extend_from_slice(&block.arr[j * block.r..(j + 1) * block.r]);
+            }
+        }
+        Matrix { r, c, arr }
+    }
+
     pub fn from_distribution(r: usize, c: usize, distribution: &impl Distribution<f64>) -> Self {
         let v: Vec<f64> = (0..r * c)
-            .map(|_| rand::thread_rng().sample(&distribution))
+            .map(|_| rand::thread_rng().sample(distribution))
             .collect();
         Matrix { r, c, arr: v }
     }
@@ -45,10 +82,8 @@ impl Matrix {
     pub fn identity(r: usize, c: usize) -> Self {
         let mut matrix: Matrix = Matrix::zeros(r, c);
 
-        let mut i = 0;
-        for x in 0..r {
-            matrix.arr[i + x * c] = 1.0;
-            i += 1;
+        for x in 0..r.min(c) {
+            matrix[(x, x)] = 1.0;
         }
 
         matrix
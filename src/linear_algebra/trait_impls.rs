@@ -1,6 +1,7 @@
 use std::{
     fmt::{Debug, Display},
     ops::{Add, AddAssign, Div, Index, IndexMut, Mul, Sub, SubAssign},
+    str::FromStr,
 };
 
 use super::Matrix;
@@ -10,14 +11,14 @@ impl Index<(usize, usize)> for Matrix {
 
     /// (row, col)
     fn index(&self, index: (usize, usize)) -> &f64 {
-        &self.arr[index.0 + self.c * index.1]
+        &self.arr[index.0 + self.r * index.1]
     }
 }
 
 impl IndexMut<(usize, usize)> for Matrix {
     /// (row, col)
     fn index_mut(&mut self, index: (usize, usize)) -> &mut Self::Output {
-        &mut self.arr[index.0 + self.c * index.1]
+        &mut self.arr[index.0 + self.r * index.1]
     }
 }
 
@@ -105,10 +106,15 @@ impl<T: AsRef<Matrix>> Mul<T> for &Matrix {
 
     fn mul(self, rhs: T) -> Self::Output {
         let _rhs = rhs.as_ref();
-        if self.c != _rhs.r {}
+        if self.c != _rhs.r {
+            panic!(
+                "Cannot multiply matrices of incompatible shape. shape1:{:?} - shape2:{:?}",
+                self.get_dims(),
+                _rhs.get_dims()
+            );
+        }
 
-        let mut v: Vec<f64> = Vec::with_capacity(self.r * _rhs.c);
-        unsafe { v.set_len(self.r * _rhs.c) }
+        let mut v: Vec<f64> = vec![0.0; self.r * _rhs.c];
         unsafe {
             cblas::dgemm(
                 cblas::Layout::ColumnMajor,
@@ -148,10 +154,10 @@ impl Display for Matrix {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = String::from("");
 
-        let first = self.arr[0];
-        let second = self.arr[self.c * (self.r - 1)];
-        let third = self.arr[self.c - 1];
-        let fourth = self.arr[self.c * self.r - 1];
+        let first = self[(0, 0)];
+        let second = self[(0, self.c - 1)];
+        let third = self[(self.r - 1, 0)];
+        let fourth = self[(self.r - 1, self.c - 1)];
 
         s = s.add(&format!(
             "{:.2}\t...\t{:.2}\n...\t...\t...\n{:.2}\t...\t{:.2}",
@@ -186,6 +192,47 @@ impl Debug for Matrix {
     }
 }
 
+impl FromStr for Matrix {
+    type Err = String;
+
+    /// parses the format written by [`Matrix::to_str`]; `r,c - v0,v1,...`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (shape, values) = s
+            .trim()
+            .split_once(" - ")
+            .ok_or(format!("Incorrect Format for matrix `{}`", s))?;
+        let (r, c) = shape
+            .split_once(',')
+            .ok_or(format!("Incorrect Format for matrix shape `{}`", shape))?;
+        let (r, c): (usize, usize) = (
+            r.parse()
+                .map_err(|_| format!("Invalid row count `{}`", r))?,
+            c.parse()
+                .map_err(|_| format!("Invalid column count `{}`", c))?,
+        );
+
+        let arr = values
+            .split(',')
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| format!("Invalid matrix value `{}`", v))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        if arr.len() != r * c {
+            return Err(format!(
+                "Expected {} values for a {}x{} matrix, found {}",
+                r * c,
+                r,
+                c,
+                arr.len()
+            ));
+        }
+
+        Ok(Matrix { r, c, arr })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::linear_algebra::Matrix;
@@ -232,4 +279,69 @@ mod tests {
 
         assert_eq!(c, (a * b), "Testing Matrix Mult 1,4x4,1");
     }
+
+    #[test]
+    fn matrix_index_and_transpose() {
+        // column-major: the first column is [1, 2, 3]
+        let a = Matrix::from_vec(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        assert_eq!(a[(2, 0)], 3.0);
+        assert_eq!(a[(0, 1)], 4.0);
+        assert_eq!(a.get_row(1), Some(vec![2.0, 5.0]));
+        assert_eq!(a.get_col(1), Some(vec![4.0, 5.0, 6.0]));
+
+        let t = a.transpose();
+        assert_eq!(t.get_dims(), (2, 3));
+        assert_eq!(
+            t,
+            Matrix::from_vec(2, 3, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0])
+        );
+
+        let b = Matrix::from_vec(2, 2, vec![3.0, 1.0, 11.0, 0.0]);
+        assert_eq!(
+            b.transpose() * &a.transpose(),
+            (&a * &b).transpose(),
+            "Testing (AB)^T = B^T A^T"
+        );
+    }
+
+    #[test]
+    fn matrix_rows_and_stacking() {
+        let a = Matrix::from_vec(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        let (top, bottom) = (a.rows(0..1), a.rows(1..3));
+        assert_eq!(top, Matrix::from_vec(1, 2, vec![1.0, 4.0]));
+        assert_eq!(bottom, Matrix::from_vec(2, 2, vec![2.0, 3.0, 5.0, 6.0]));
+        assert_eq!(Matrix::from_rows([&top, &bottom]), a);
+    }
+
+    #[test]
+    fn matrix_invert() {
+        let a = Matrix::from_vec(2, 2, vec![0.0, 1.0, 2.0, 3.0]);
+        let inverse = a.clone().invert().unwrap();
+
+        assert_eq!(a * inverse, Matrix::identity(2, 2));
+    }
+
+    #[test]
+    fn matrix_symmetric_eigen() {
+        let a = Matrix::from_vec(3, 3, vec![2.0, 1.0, 0.0, 1.0, 2.0, 0.0, 0.0, 0.0, 5.0]);
+        let (values, vectors) = a.symmetric_eigen().unwrap();
+
+        let expected = [5.0, 3.0, 1.0];
+        assert!(values
+            .iter()
+            .zip(expected)
+            .all(|(v, e)| (v - e).abs() < 1e-12));
+
+        // A V = V diag(values)
+        let scaled = Matrix::from_columns(
+            &(0..3)
+                .map(|i| vectors.column(i) * values[i])
+                .collect::<Vec<Matrix>>(),
+        );
+        let difference = &a * &vectors - &scaled;
+        assert!(difference.iter().all(|d| d.abs() < 1e-12));
+        assert!(Matrix::zeros(2, 3).symmetric_eigen().is_none());
+    }
 }
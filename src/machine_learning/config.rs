@@ -0,0 +1,892 @@
+//! A declarative description of a training run, read from TOML or JSON and run by a
+//! [`Trainer`](super::trainer::Trainer).
+//!
+//! Every section and setting is optional, the defaults train a single sigmoid layer on the
+//! letters of EMNIST. The resolved configuration is saved next to the trained network.
+//!
+//! ```toml
+//! [data]
+//! directory = "src/assets/machine_learning/"
+//! name = "letters"
+//! format = "idx"
+//! labels = "letters"
+//!
+//! [[preprocessing]]
+//! type = "standardize"
+//!
+//! [[network.layers]]
+//! type = "dense"
+//! units = 128
+//!
+//! [[network.layers]]
+//! type = "activation"
+//! function = "relu"
+//!
+//! # without units, the layer has one output per class
+//! [[network.layers]]
+//! type = "dense"
+//!
+//! [[network.layers]]
+//! type = "activation"
+//! function = "sigmoid"
+//!
+//! [optimizer]
+//! learning_rate = 0.5
+//! l2 = 0.0001
+//!
+//! [schedule]
+//! type = "step_decay"
+//! step_size = 10
+//! gamma = 0.5
+//!
+//! [training]
+//! epochs = 30
+//! batch_size = 16
+//! seed = 7
+//!
+//! [[training.augmentation]]
+//! type = "shift"
+//! max = 2.0
+//!
+//! [output]
+//! directory = "output/letters"
+//! ```
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::calculus::functions::Function;
+
+use super::{
+    dataset::{
+        augmentation::Transform,
+        csv::{CsvParser, LabelColumn},
+        image_folder::ImageFolderParser,
+        mnist::{letter_from_number, MnistParser},
+        npy::NpyParser,
+        preprocessing::{MinMaxScaler, OneHotEncoder, PcaWhitening, Preprocessing, StandardScaler},
+        DataSet, DataVector,
+    },
+    neural_network::{
+        early_stopping::Metric,
+        layers::{
+            activation::Activation,
+            convolution::{Conv2D, ImageShape},
+            dense::Dense,
+            dropout::Dropout,
+            normalization::{BatchNorm1d, BatchNorm2d, LayerNorm},
+            pooling::{AvgPool2D, MaxPool2D},
+            sequential::Sequential,
+        },
+        learning_rate::{LrSchedule, Stepping},
+    },
+};
+
+/// Everything needed to repeat a training run
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data: DataConfig,
+    /// fitted on the training data in order, and saved with the network
+    pub preprocessing: Vec<PreprocessingStep>,
+    pub network: NetworkConfig,
+    pub optimizer: OptimizerConfig,
+    pub schedule: ScheduleConfig,
+    pub training: TrainingConfig,
+    pub output: OutputConfig,
+}
+
+impl Config {
+    /// Reads a `.json` file as JSON, and any other file as TOML
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let config = match is_json(path) {
+            true => Config::from_json(&text),
+            false => Config::from_toml(&text),
+        };
+        config.map_err(|e| format!("Invalid config {}: {}", path.display(), e).into())
+    }
+
+    /// Writes a `.json` file as JSON, and any other file as TOML
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let text = match is_json(path.as_ref()) {
+            true => self.to_json()?,
+            false => self.to_toml()?,
+        };
+        Ok(fs::write(path, text)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Config, Box<dyn Error>> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Config, Box<dyn Error>> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+/// The file layout of a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// `{name}.{split}.images` and `.labels` in the IDX format of MNIST
+    Idx,
+    /// `{name}.{split}.csv` or `.tsv`
+    Csv,
+    /// `{name}.{split}.npz`, or `.x.npy` and `.y.npy`
+    Npy,
+    /// `{name}/{split}/{class}/` directories of images
+    Images,
+}
+
+/// Where the dataset is and how to read it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    pub directory: String,
+    /// the name of the dataset within the directory
+    pub name: String,
+    pub format: Format,
+    /// subtracted from every label so the first class is 0, by default 1 for idx and 0 otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_offset: Option<usize>,
+    /// the column of the label in CSV files; `first`, `last`, an index, or a header name
+    pub label_column: String,
+    /// whether CSV files start with a header line
+    pub header: bool,
+    /// the height and width images are resized to, and the shape of convolutions
+    pub image_size: usize,
+    /// keeps the color channels of images
+    pub rgb: bool,
+    /// the names of the classes; `letters`, `digits`, or comma separated names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<String>,
+    /// the fraction of the training data held out for validation
+    pub validation_split: f64,
+    /// prints a line while loading IDX files, set by the caller rather than the file
+    #[serde(skip)]
+    pub progress: bool,
+}
+
+impl Default for DataConfig {
+    fn default() -> Self {
+        DataConfig {
+            directory: "src/assets/machine_learning/".to_string(),
+            name: "letters".to_string(),
+            format: Format::Idx,
+            label_offset: None,
+            label_column: "last".to_string(),
+            header: true,
+            image_size: 28,
+            rgb: false,
+            labels: None,
+            validation_split: 0.1,
+            progress: false,
+        }
+    }
+}
+
+impl DataConfig {
+    /// the training and testing splits
+    pub fn load(&self) -> Result<DataSet, Box<dyn Error>> {
+        Ok(DataSet::load_data(&self.path(), &self.name, |p, n, e| {
+            self.parse(p, n, e)
+        })?)
+    }
+
+    /// a single split, e.g. `test`
+    pub fn load_split(&self, split: &str) -> Result<Vec<DataVector>, Box<dyn Error>> {
+        Ok(self.parse(&self.path(), &self.name, split)?)
+    }
+
+    fn parse(&self, path: &str, name: &str, ext: &str) -> Result<Vec<DataVector>, std::io::Error> {
+        let offset = self.label_offset.unwrap_or(0);
+        match self.format {
+            Format::Idx => MnistParser::default()
+                .label_offset(self.label_offset.unwrap_or(1))
+                .progress(self.progress)
+                .parse(path, name, ext),
+            Format::Csv => {
+                let column = match self.label_column.as_str() {
+                    "first" => LabelColumn::First,
+                    "last" => LabelColumn::Last,
+                    column => match column.parse() {
+                        Ok(index) => LabelColumn::Index(index),
+                        Err(_) => LabelColumn::Name(column.to_string()),
+                    },
+                };
+                CsvParser::default()
+                    .header(self.header)
+                    .label_column(column)
+                    .label_offset(offset)
+                    .parse(path, name, ext)
+            }
+            Format::Npy => NpyParser::default()
+                .label_offset(offset)
+                .parse(path, name, ext),
+            Format::Images => ImageFolderParser::new(self.image_size, self.image_size)
+                .rgb(self.rgb)
+                .parse(path, name, ext),
+        }
+    }
+
+    /// the parsers expect the directory to end in a separator
+    fn path(&self) -> String {
+        match self.directory.ends_with('/') {
+            true => self.directory.clone(),
+            false => format!("{}/", self.directory),
+        }
+    }
+
+    /// the shape of the inputs as images
+    pub fn image_shape(&self) -> ImageShape {
+        let channels = if self.rgb && self.format == Format::Images {
+            3
+        } else {
+            1
+        };
+        ImageShape::new(channels, self.image_size, self.image_size)
+    }
+
+    /// the names of `classes` classes given by `labels`
+    pub fn label_names(&self, classes: usize) -> Vec<String> {
+        match self.labels.as_deref() {
+            None => vec![],
+            Some("letters") => (0..classes)
+                .filter_map(letter_from_number)
+                .map(String::from)
+                .collect(),
+            Some("digits") => (0..classes).map(|d| d.to_string()).collect(),
+            Some(names) => names.split(',').map(|n| n.trim().to_string()).collect(),
+        }
+    }
+}
+
+/// A step of the [`Preprocessing`] of the inputs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PreprocessingStep {
+    /// zero mean and unit variance
+    Standardize,
+    /// scales every feature to `low`-`high`
+    MinMax { low: f64, high: f64 },
+    /// PCA whitening, keeping every component when `components` is not given
+    Pca {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        components: Option<usize>,
+        #[serde(default = "pca_epsilon")]
+        epsilon: f64,
+    },
+    /// one-hot encodes the categorical features at these indices
+    OneHot { features: Vec<usize> },
+}
+
+fn pca_epsilon() -> f64 {
+    1e-5
+}
+
+impl PreprocessingStep {
+    /// the unfitted preprocessing of `steps`
+    pub fn preprocessing(steps: &[PreprocessingStep]) -> Preprocessing {
+        let mut preprocessing = Preprocessing::default();
+        for step in steps {
+            match step {
+                PreprocessingStep::Standardize => preprocessing.push(StandardScaler::new()),
+                PreprocessingStep::MinMax { low, high } => {
+                    preprocessing.push(MinMaxScaler::new(*low, *high))
+                }
+                PreprocessingStep::Pca {
+                    components,
+                    epsilon,
+                } => preprocessing.push(PcaWhitening::new(*components, *epsilon)),
+                PreprocessingStep::OneHot { features } => {
+                    preprocessing.push(OneHotEncoder::new(features.clone()))
+                }
+            }
+        }
+        preprocessing
+    }
+}
+
+/// The layers of the network, or a saved network to continue training
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// a network file, checkpoint, or ONNX graph; its layers and preprocessing are used
+    /// instead of the ones configured here
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init: Option<String>,
+    pub layers: Vec<LayerConfig>,
+}
+
+impl Default for NetworkConfig {
+    /// a single sigmoid layer
+    fn default() -> Self {
+        NetworkConfig {
+            init: None,
+            layers: vec![
+                LayerConfig::Dense { units: None },
+                LayerConfig::Activation {
+                    function: "sigmoid".to_string(),
+                },
+            ],
+        }
+    }
+}
+
+/// A layer of the network, its input size follows from the layer before it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LayerConfig {
+    /// fully connected, by default with one output per class (or target value)
+    Dense {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        units: Option<usize>,
+    },
+    /// sigmoid, swish, arctan, normal_arctan, relu, leaky_relu or tanh
+    Activation {
+        function: String,
+    },
+    /// zeroes every value with probability `rate`, at least 0 and below 1
+    Dropout {
+        rate: f64,
+    },
+    /// batch normalization, over the channels of images
+    BatchNorm,
+    LayerNorm,
+    /// a convolution of the input images, see [`DataConfig::image_shape`]
+    Conv2d {
+        filters: usize,
+        kernel: usize,
+        #[serde(default = "one")]
+        stride: usize,
+        #[serde(default)]
+        padding: usize,
+    },
+    MaxPool2d {
+        size: usize,
+        stride: usize,
+    },
+    AvgPool2d {
+        size: usize,
+        stride: usize,
+    },
+}
+
+fn one() -> usize {
+    1
+}
+
+/// The shape of the values passed between layers while building a network
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    Flat(usize),
+    Image(ImageShape),
+}
+
+impl Shape {
+    fn size(&self) -> usize {
+        match self {
+            Shape::Flat(size) => *size,
+            Shape::Image(shape) => shape.size(),
+        }
+    }
+
+    fn image(&self, layer: &str) -> Result<ImageShape, String> {
+        match self {
+            Shape::Image(shape) => Ok(*shape),
+            Shape::Flat(size) => Err(format!("{} needs images, not {} values", layer, size)),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Builds the layers for inputs of `input_size` values, which are images of `image`
+    /// when the sizes match, and `outputs` values out.
+    ///
+    /// With a seed, dropout layer `i` draws its masks from `seed + i`.
+    pub fn build(
+        &self,
+        image: ImageShape,
+        input_size: usize,
+        outputs: usize,
+        seed: Option<u64>,
+    ) -> Result<Sequential, Box<dyn Error>> {
+        let mut model = Sequential::default();
+        let mut shape = match image.size() == input_size {
+            true => Shape::Image(image),
+            false => Shape::Flat(input_size),
+        };
+        let mut dropouts = 0;
+
+        for layer in &self.layers {
+            match layer {
+                LayerConfig::Dense { units } => {
+                    let units = units.unwrap_or(outputs);
+                    model.push(Dense::random(shape.size(), units));
+                    shape = Shape::Flat(units);
+                }
+                LayerConfig::Activation { function } => {
+                    let function = Function::from_name(function)
+                        .ok_or(format!("unknown activation function `{}`", function))?;
+                    model.push(Activation::new(function));
+                }
+                LayerConfig::Dropout { rate } => {
+                    if !(0.0..1.0).contains(rate) {
+                        return Err(format!("The dropout rate {} is not in [0, 1)", rate).into());
+                    }
+                    let mut dropout = Dropout::new(*rate);
+                    if let Some(seed) = seed {
+                        dropout = dropout.with_seed(seed.wrapping_add(dropouts));
+                    }
+                    dropouts += 1;
+                    model.push(dropout);
+                }
+                LayerConfig::BatchNorm => match shape {
+                    Shape::Flat(size) => model.push(BatchNorm1d::new(size)),
+                    Shape::Image(image) => model.push(BatchNorm2d::new(image)),
+                },
+                LayerConfig::LayerNorm => model.push(LayerNorm::new(shape.size())),
+                LayerConfig::Conv2d {
+                    filters,
+                    kernel,
+                    stride,
+                    padding,
+                } => {
+                    let image = shape.image("conv2d")?;
+                    if *filters == 0 || image.windowed(*kernel, *stride, *padding).is_none() {
+                        return Err(
+                            format!("A conv2d layer does not fit images of {:?}", image).into()
+                        );
+                    }
+                    let conv = Conv2D::random(image, *filters, *kernel, *stride, *padding);
+                    shape = Shape::Image(conv.output_shape());
+                    model.push(conv);
+                }
+                LayerConfig::MaxPool2d { size, stride } => {
+                    let image = shape.image("max_pool2d")?;
+                    if image.windowed(*size, *stride, 0).is_none() {
+                        return Err(format!(
+                            "A max_pool2d layer does not fit images of {:?}",
+                            image
+                        )
+                        .into());
+                    }
+                    let pool = MaxPool2D::new(image, *size, *stride);
+                    shape = Shape::Image(pool.output_shape());
+                    model.push(pool);
+                }
+                LayerConfig::AvgPool2d { size, stride } => {
+                    let image = shape.image("avg_pool2d")?;
+                    if image.windowed(*size, *stride, 0).is_none() {
+                        return Err(format!(
+                            "An avg_pool2d layer does not fit images of {:?}",
+                            image
+                        )
+                        .into());
+                    }
+                    let pool = AvgPool2D::new(image, *size, *stride);
+                    shape = Shape::Image(pool.output_shape());
+                    model.push(pool);
+                }
+            }
+        }
+
+        if shape.size() != outputs {
+            return Err(format!(
+                "The network has {} outputs, the data needs {}",
+                shape.size(),
+                outputs
+            )
+            .into());
+        }
+        Ok(model)
+    }
+}
+
+/// Stochastic gradient descent, with regularization and clipping
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptimizerConfig {
+    pub learning_rate: f64,
+    pub l1: f64,
+    /// L2 weight decay
+    pub l2: f64,
+    /// the largest norm of the weights into a node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_norm: Option<f64>,
+    /// clips the global norm of the gradient
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clip_norm: Option<f64>,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        OptimizerConfig {
+            learning_rate: 1.0,
+            l1: 0.0,
+            l2: 0.0,
+            max_norm: None,
+            clip_norm: None,
+        }
+    }
+}
+
+/// How the learning rate changes over training
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    #[serde(flatten)]
+    pub kind: ScheduleKind,
+    /// steps of linear warmup before the schedule
+    #[serde(default)]
+    pub warmup: usize,
+    /// whether the schedule steps every epoch or every batch
+    #[serde(default = "epoch")]
+    pub stepping: Stepping,
+}
+
+fn epoch() -> Stepping {
+    Stepping::Epoch
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            kind: ScheduleKind::Constant,
+            warmup: 0,
+            stepping: Stepping::Epoch,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleKind {
+    Constant,
+    /// multiplied by `gamma` every `step_size` steps
+    StepDecay {
+        step_size: usize,
+        gamma: f64,
+    },
+    /// multiplied by `gamma` every step
+    ExponentialDecay {
+        gamma: f64,
+    },
+    /// cosine annealing, restarting after `period` steps, then `period * period_multiplier`...
+    Cosine {
+        period: usize,
+        #[serde(default = "one")]
+        period_multiplier: usize,
+        #[serde(default)]
+        min_learning_rate: f64,
+    },
+    /// one cycle over the whole run
+    OneCycle,
+    /// multiplied by `factor` when the monitored metric has not improved for `patience` epochs
+    Plateau {
+        factor: f64,
+        patience: usize,
+    },
+}
+
+impl ScheduleKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScheduleKind::Constant => "constant",
+            ScheduleKind::StepDecay { .. } => "step_decay",
+            ScheduleKind::ExponentialDecay { .. } => "exponential_decay",
+            ScheduleKind::Cosine { .. } => "cosine",
+            ScheduleKind::OneCycle => "one_cycle",
+            ScheduleKind::Plateau { .. } => "plateau",
+        }
+    }
+}
+
+impl ScheduleConfig {
+    /// The schedule of a run of `steps` steps, plateaus are judged by `metric`
+    pub fn schedule(&self, learning_rate: f64, steps: usize, metric: Metric) -> LrSchedule {
+        let schedule = match self.kind {
+            ScheduleKind::Constant => LrSchedule::constant(learning_rate),
+            ScheduleKind::StepDecay { step_size, gamma } => {
+                LrSchedule::step_decay(learning_rate, step_size, gamma)
+            }
+            ScheduleKind::ExponentialDecay { gamma } => {
+                LrSchedule::exponential_decay(learning_rate, gamma)
+            }
+            ScheduleKind::Cosine {
+                period,
+                period_multiplier,
+                min_learning_rate,
+            } => LrSchedule::cosine_annealing(
+                learning_rate,
+                period,
+                period_multiplier,
+                min_learning_rate,
+            ),
+            ScheduleKind::OneCycle => LrSchedule::one_cycle(learning_rate, steps),
+            ScheduleKind::Plateau { factor, patience } => {
+                LrSchedule::reduce_on_plateau(learning_rate, metric.mode(), factor, patience)
+            }
+        };
+        schedule.with_warmup(self.warmup).stepped_per(self.stepping)
+    }
+}
+
+/// How the training data is drawn into batches
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingConfig {
+    Shuffled,
+    Stratified,
+    /// draws every class equally often
+    Balanced,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrainingConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    /// the name of the cost function
+    pub cost: String,
+    /// makes the order of the training data and the dropout masks reproducible
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    pub sampling: SamplingConfig,
+    /// stops after this many epochs without improvement, 0 disables early stopping
+    pub patience: usize,
+    /// monitored for early stopping, the plateau schedule, and the best checkpoint
+    pub metric: Metric,
+    /// the k of top-k accuracy
+    pub top_k: usize,
+    /// random changes to the training images, applied in order to the inputs before preprocessing
+    pub augmentation: Vec<Transform>,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            epochs: 30,
+            batch_size: 16,
+            cost: "quadratic".to_string(),
+            seed: None,
+            sampling: SamplingConfig::Shuffled,
+            patience: 0,
+            metric: Metric::Accuracy,
+            top_k: 3,
+            augmentation: vec![],
+        }
+    }
+}
+
+/// Where the network, its configuration, and the checkpoints are saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub directory: String,
+    /// also saves a checkpoint every this many batches, besides after every epoch
+    pub checkpoint_every: usize,
+    /// the number of checkpoints kept, 0 keeps every one
+    pub keep_last: usize,
+    /// the formats of the training logs
+    pub logs: Vec<LogFormat>,
+    /// also logs every this many batches, 0 only logs the epochs
+    pub log_every: usize,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            directory: "output".to_string(),
+            checkpoint_every: 0,
+            keep_last: 3,
+            logs: vec![LogFormat::Csv],
+            log_every: 0,
+        }
+    }
+}
+
+/// How the records of training are written to the output directory
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// `epochs.csv`, and `batches.csv` when batches are logged
+    Csv,
+    /// `log.jsonl`, a line of JSON per record
+    Jsonl,
+}
+
+impl OutputConfig {
+    /// `network.nn` in the directory
+    pub fn network_path(&self) -> PathBuf {
+        Path::new(&self.directory).join("network.nn")
+    }
+
+    /// `config.toml` in the directory
+    pub fn config_path(&self) -> PathBuf {
+        Path::new(&self.directory).join("config.toml")
+    }
+
+    /// the training log of `name` in the directory
+    pub fn log_path(&self, name: &str) -> PathBuf {
+        Path::new(&self.directory).join(name)
+    }
+
+    /// `checkpoints/` in the directory
+    pub fn checkpoint_directory(&self) -> PathBuf {
+        Path::new(&self.directory).join("checkpoints")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, LayerConfig, PreprocessingStep, ScheduleKind};
+    use crate::machine_learning::{
+        dataset::augmentation::Transform,
+        neural_network::{early_stopping::Metric, layers::convolution::ImageShape},
+    };
+
+    #[test]
+    fn test_reading_and_writing() {
+        let config = Config::from_toml(
+            r#"
+            preprocessing = [{ type = "standardize" }, { type = "pca", components = 4 }]
+
+            [data]
+            format = "csv"
+            validation_split = 0.2
+
+            [[network.layers]]
+            type = "conv2d"
+            filters = 2
+            kernel = 3
+
+            [[network.layers]]
+            type = "dense"
+
+            [schedule]
+            type = "step_decay"
+            step_size = 2
+            gamma = 0.5
+            warmup = 1
+
+            [training]
+            metric = "macro_f1"
+            augmentation = [{ type = "rotate", max_degrees = 10.0 }]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.data.validation_split, 0.2);
+        assert_eq!(config.data.name, "letters");
+        assert_eq!(
+            config.preprocessing[1],
+            PreprocessingStep::Pca {
+                components: Some(4),
+                epsilon: 1e-5
+            }
+        );
+        assert_eq!(config.network.layers[1], LayerConfig::Dense { units: None });
+        assert_eq!(
+            config.schedule.kind,
+            ScheduleKind::StepDecay {
+                step_size: 2,
+                gamma: 0.5
+            }
+        );
+        assert_eq!(config.training.metric, Metric::MacroF1);
+        assert_eq!(
+            config.training.augmentation,
+            vec![Transform::Rotate { max_degrees: 10.0 }]
+        );
+        assert_eq!(config.training.epochs, 30);
+
+        assert_eq!(
+            Config::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+        assert_eq!(
+            Config::from_json(&config.to_json().unwrap()).unwrap(),
+            config
+        );
+
+        assert!(Config::from_toml("[training]\nepoch = 3").is_err());
+        assert!(
+            Config::from_json(r#"{"network": {"layers": [{"type": "dense", "size": 3}]}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_building_the_layers() {
+        let config = Config::from_toml(
+            r#"
+            [[network.layers]]
+            type = "conv2d"
+            filters = 2
+            kernel = 3
+            padding = 1
+
+            [[network.layers]]
+            type = "max_pool2d"
+            size = 2
+            stride = 2
+
+            [[network.layers]]
+            type = "batch_norm"
+
+            [[network.layers]]
+            type = "dense"
+            units = 5
+
+            [[network.layers]]
+            type = "activation"
+            function = "relu"
+
+            [[network.layers]]
+            type = "dropout"
+            rate = 0.5
+
+            [[network.layers]]
+            type = "dense"
+            "#,
+        )
+        .unwrap();
+
+        let image = ImageShape::new(1, 4, 4);
+        let model = config.network.build(image, 16, 3, Some(1)).unwrap();
+        assert_eq!(model.len(), 7);
+
+        // convolutions need images, and the last layer has to match the data
+        assert!(config.network.build(image, 15, 3, None).is_err());
+        let mut flat = config.network.clone();
+        flat.layers.push(LayerConfig::Dense { units: Some(4) });
+        assert!(flat.build(image, 16, 3, None).is_err());
+
+        for rate in [-0.1, 1.0, f64::NAN] {
+            let mut dropout = config.network.clone();
+            dropout.layers[5] = LayerConfig::Dropout { rate };
+            assert!(dropout.build(image, 16, 3, None).is_err());
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    linear_algebra::Matrix, machine_learning::neural_network::layers::convolution::ImageShape,
+};
+
+/// A random change to an image, see [`Augmentation`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transform {
+    /// moves the image by up to `max` pixels along each axis
+    Shift { max: f64 },
+    /// rotates the image about its center by up to `max_degrees` either way
+    Rotate { max_degrees: f64 },
+    /// scales the image about its center by a factor between `min` and `max`
+    Scale { min: f64, max: f64 },
+    /// moves every pixel along a random displacement field, smoothed by a gaussian
+    /// of `sigma` pixels and scaled by `alpha` (Simard et al., 2003)
+    Elastic { alpha: f64, sigma: f64 },
+    /// adds gaussian noise, the values are kept between 0-1
+    Noise { std_dev: f64 },
+    /// with `probability`, zeroes a random rectangle covering between
+    /// `min_area` and `max_area` (fractions) of the image
+    Erase {
+        probability: f64,
+        min_area: f64,
+        max_area: f64,
+    },
+}
+
+impl Transform {
+    /// Checks that the ranges of the transform can be sampled,
+    /// e.g. that no bound is `NaN` and no minimum is above its maximum
+    pub fn validate(&self) -> Result<(), String> {
+        let non_negative = |name: &str, value: f64| match value.is_finite() && value >= 0.0 {
+            true => Ok(()),
+            false => Err(format!(
+                "`{}` must be a non-negative number, not {}",
+                name, value
+            )),
+        };
+        let range = |min: f64, max: f64| match min <= max {
+            true => Ok(()),
+            false => Err(format!("the minimum {} is above the maximum {}", min, max)),
+        };
+
+        match *self {
+            Transform::Shift { max } => non_negative("max", max),
+            Transform::Rotate { max_degrees } => non_negative("max_degrees", max_degrees),
+            Transform::Scale { min, max } => {
+                non_negative("min", min)?;
+                non_negative("max", max)?;
+                if min == 0.0 {
+                    return Err("`min` must be above 0".to_string());
+                }
+                range(min, max)
+            }
+            Transform::Elastic { alpha, sigma } => {
+                non_negative("alpha", alpha)?;
+                non_negative("sigma", sigma)
+            }
+            Transform::Noise { std_dev } => non_negative("std_dev", std_dev),
+            Transform::Erase {
+                probability,
+                min_area,
+                max_area,
+            } => {
+                if !(0.0..=1.0).contains(&probability) {
+                    return Err(format!(
+                        "`probability` must be between 0-1, not {}",
+                        probability
+                    ));
+                }
+                non_negative("min_area", min_area)?;
+                non_negative("max_area", max_area)?;
+                range(min_area, max_area)
+            }
+        }
+        .map_err(|e| format!("Invalid {:?}: {}", self, e))
+    }
+
+    fn apply(&self, image: &Matrix, shape: ImageShape, rng: &mut impl Rng) -> Matrix {
+        match *self {
+            Transform::Shift { max } => {
+                let (dy, dx) = (rng.gen_range(-max..=max), rng.gen_range(-max..=max));
+                shift(image, shape, dy, dx)
+            }
+            Transform::Rotate { max_degrees } => {
+                rotate(image, shape, rng.gen_range(-max_degrees..=max_degrees))
+            }
+            Transform::Scale { min, max } => scale(image, shape, rng.gen_range(min..=max)),
+            Transform::Elastic { alpha, sigma } => elastic(image, shape, alpha, sigma, rng),
+            Transform::Noise { std_dev } => {
+                let mut image = image.clone();
+                image
+                    .iter_mut()
+                    .for_each(|v| *v = (*v + std_dev * gaussian(rng)).clamp(0.0, 1.0));
+                image
+            }
+            Transform::Erase {
+                probability,
+                min_area,
+                max_area,
+            } => {
+                let mut image = image.clone();
+                if rng.gen_bool(probability.clamp(0.0, 1.0)) {
+                    erase(&mut image, shape, rng.gen_range(min_area..=max_area), rng);
+                }
+                image
+            }
+        }
+    }
+}
+
+/// Random transforms applied one after the other to every image of `shape`.
+///
+/// Images are columns like those of [`Conv2D`](crate::machine_learning::neural_network::layers::convolution::Conv2D),
+/// every channel is transformed the same way.
+///
+/// ### Example
+/// ```
+/// # use mathematics::{linear_algebra::Matrix, machine_learning::{dataset::augmentation::{Augmentation, Transform}, neural_network::layers::convolution::ImageShape}};
+/// let augmentation = Augmentation::new(ImageShape::new(1, 28, 28))
+///     .then(Transform::Rotate { max_degrees: 10.0 })
+///     .then(Transform::Noise { std_dev: 0.05 });
+///
+/// let image = Matrix::zeros(28 * 28, 1);
+/// let augmented = augmentation.apply(&image, &mut rand::thread_rng());
+/// assert_eq!(augmented.get_dims(), (28 * 28, 1));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Augmentation {
+    shape: ImageShape,
+    transforms: Vec<Transform>,
+}
+
+impl Augmentation {
+    pub fn new(shape: ImageShape) -> Self {
+        Augmentation {
+            shape,
+            transforms: vec![],
+        }
+    }
+
+    /// # Panics
+    /// if the transform is invalid, see [`Transform::validate`]
+    pub fn then(mut self, transform: Transform) -> Self {
+        if let Err(e) = transform.validate() {
+            panic!("{}", e);
+        }
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn shape(&self) -> ImageShape {
+        self.shape
+    }
+
+    /// # Panics
+    /// if `image` is not a single column of the size of the shape
+    pub fn apply(&self, image: &Matrix, rng: &mut impl Rng) -> Matrix {
+        assert_eq!(
+            image.get_dims(),
+            (self.shape.size(), 1),
+            "the image does not match the shape of the augmentation"
+        );
+
+        self.transforms
+            .iter()
+            .fold(image.clone(), |image, transform| {
+                transform.apply(&image, self.shape, rng)
+            })
+    }
+}
+
+/// a standard normal sample (Box-Muller)
+fn gaussian(rng: &mut impl Rng) -> f64 {
+    let (u, v): (f64, f64) = (1.0 - rng.gen::<f64>(), rng.gen());
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+}
+
+/// Builds every pixel from the position `source(y, x)` of the original image,
+/// interpolating bilinearly; positions outside of the image are 0.
+fn warp(image: &Matrix, shape: ImageShape, source: impl Fn(usize, usize) -> (f64, f64)) -> Matrix {
+    let (height, width) = (shape.height as isize, shape.width as isize);
+    let mut warped = Matrix::zeros(shape.size(), 1);
+
+    for y in 0..shape.height {
+        for x in 0..shape.width {
+            let (sy, sx) = source(y, x);
+            let (y0, x0) = (sy.floor(), sx.floor());
+            let (fy, fx) = (sy - y0, sx - x0);
+
+            for channel in 0..shape.channels {
+                let pixel = |py: isize, px: isize| match (0..height).contains(&py)
+                    && (0..width).contains(&px)
+                {
+                    true => image[(shape.index(channel, py as usize, px as usize), 0)],
+                    false => 0.0,
+                };
+
+                let (py, px) = (y0 as isize, x0 as isize);
+                warped[(shape.index(channel, y, x), 0)] = (1.0 - fy)
+                    * ((1.0 - fx) * pixel(py, px) + fx * pixel(py, px + 1))
+                    + fy * ((1.0 - fx) * pixel(py + 1, px) + fx * pixel(py + 1, px + 1));
+            }
+        }
+    }
+
+    warped
+}
+
+fn center(shape: ImageShape) -> (f64, f64) {
+    (
+        (shape.height as f64 - 1.0) / 2.0,
+        (shape.width as f64 - 1.0) / 2.0,
+    )
+}
+
+fn shift(image: &Matrix, shape: ImageShape, dy: f64, dx: f64) -> Matrix {
+    warp(image, shape, |y, x| (y as f64 - dy, x as f64 - dx))
+}
+
+fn rotate(image: &Matrix, shape: ImageShape, degrees: f64) -> Matrix {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cy, cx) = center(shape);
+    warp(image, shape, |y, x| {
+        let (y, x) = (y as f64 - cy, x as f64 - cx);
+        (cy + cos * y - sin * x, cx + sin * y + cos * x)
+    })
+}
+
+fn scale(image: &Matrix, shape: ImageShape, factor: f64) -> Matrix {
+    let (cy, cx) = center(shape);
+    warp(image, shape, |y, x| {
+        (cy + (y as f64 - cy) / factor, cx + (x as f64 - cx) / factor)
+    })
+}
+
+fn elastic(
+    image: &Matrix,
+    shape: ImageShape,
+    alpha: f64,
+    sigma: f64,
+    rng: &mut impl Rng,
+) -> Matrix {
+    let mut field = || {
+        let noise: Vec<f64> = (0..shape.height * shape.width)
+            .map(|_| rng.gen_range(-1.0..=1.0))
+            .collect();
+        smooth(&noise, shape, sigma)
+            .into_iter()
+            .map(|d| d * alpha)
+            .collect::<Vec<f64>>()
+    };
+    let (dy, dx) = (field(), field());
+
+    warp(image, shape, |y, x| {
+        let i = y * shape.width + x;
+        (y as f64 + dy[i], x as f64 + dx[i])
+    })
+}
+
+/// gaussian blur of a single channel, separated into a horizontal and a vertical pass
+fn smooth(values: &[f64], shape: ImageShape, sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return values.to_vec();
+    }
+
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+
+    let (height, width) = (shape.height as isize, shape.width as isize);
+    let pass = |values: &[f64], step: (isize, isize)| -> Vec<f64> {
+        let mut blurred = vec![0.0; values.len()];
+        for y in 0..height {
+            for x in 0..width {
+                blurred[(y * width + x) as usize] = (-radius..=radius)
+                    .zip(kernel.iter())
+                    .map(|(d, k)| {
+                        let (py, px) = (y + d * step.0, x + d * step.1);
+                        match (0..height).contains(&py) && (0..width).contains(&px) {
+                            true => k * values[(py * width + px) as usize],
+                            false => 0.0,
+                        }
+                    })
+                    .sum::<f64>()
+                    / total;
+            }
+        }
+        blurred
+    };
+
+    pass(&pass(values, (0, 1)), (1, 0))
+}
+
+fn erase(image: &mut Matrix, shape: ImageShape, area: f64, rng: &mut impl Rng) {
+    let area = area.clamp(0.0, 1.0) * (shape.height * shape.width) as f64;
+    let aspect: f64 = rng.gen_range(0.3..=3.3);
+    let height = ((area * aspect).sqrt().round() as usize).clamp(1, shape.height);
+    let width = ((area / aspect).sqrt().round() as usize).clamp(1, shape.width);
+
+    let top = rng.gen_range(0..=shape.height - height);
+    let left = rng.gen_range(0..=shape.width - width);
+    for channel in 0..shape.channels {
+        for y in top..top + height {
+            for x in left..left + width {
+                image[(shape.index(channel, y, x), 0)] = 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{rotate, scale, shift, Augmentation, Transform};
+    use crate::{
+        linear_algebra::Matrix, machine_learning::neural_network::layers::convolution::ImageShape,
+    };
+
+    fn image(shape: ImageShape, pixels: &[(usize, usize)]) -> Matrix {
+        let mut image = Matrix::zeros(shape.size(), 1);
+        pixels
+            .iter()
+            .for_each(|(y, x)| image[(shape.index(0, *y, *x), 0)] = 1.0);
+        image
+    }
+
+    #[test]
+    fn test_geometric_transforms() {
+        let shape = ImageShape::new(1, 5, 5);
+        let dot = image(shape, &[(1, 2)]);
+
+        assert_eq!(shift(&dot, shape, 2.0, -1.0), image(shape, &[(3, 1)]));
+        // half a pixel spreads the dot over two pixels
+        let half = shift(&dot, shape, 0.0, 0.5);
+        assert_eq!(half[(shape.index(0, 1, 2), 0)], 0.5);
+        assert_eq!(half[(shape.index(0, 1, 3), 0)], 0.5);
+
+        let rotated = rotate(&dot, shape, 90.0);
+        let expected = image(shape, &[(2, 3)]);
+        assert!(rotated
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-9));
+
+        let top = image(shape, &[(0, 2)]);
+        assert_eq!(scale(&top, shape, 0.5), image(shape, &[(1, 2)]));
+    }
+
+    #[test]
+    fn test_validation() {
+        let erase = |probability, min_area, max_area| Transform::Erase {
+            probability,
+            min_area,
+            max_area,
+        };
+        let invalid = [
+            Transform::Shift { max: -1.0 },
+            Transform::Shift { max: f64::NAN },
+            Transform::Rotate { max_degrees: -5.0 },
+            Transform::Scale { min: 1.2, max: 0.8 },
+            Transform::Scale { min: 0.0, max: 1.0 },
+            Transform::Scale {
+                min: f64::NAN,
+                max: 1.0,
+            },
+            Transform::Elastic {
+                alpha: 1.0,
+                sigma: f64::INFINITY,
+            },
+            Transform::Elastic {
+                alpha: -1.0,
+                sigma: 1.0,
+            },
+            Transform::Noise { std_dev: -0.1 },
+            erase(1.5, 0.1, 0.2),
+            erase(f64::NAN, 0.1, 0.2),
+            erase(0.5, 0.3, 0.2),
+            erase(0.5, -0.1, 0.2),
+        ];
+        for transform in invalid {
+            assert!(transform.validate().is_err(), "{:?}", transform);
+        }
+
+        assert!(Transform::Scale { min: 0.9, max: 1.1 }.validate().is_ok());
+        assert!(erase(0.5, 0.1, 0.1).validate().is_ok());
+        assert!(std::panic::catch_unwind(|| {
+            Augmentation::new(ImageShape::new(1, 2, 2)).then(Transform::Shift { max: -1.0 })
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_augmentation() {
+        let shape = ImageShape::new(2, 8, 8);
+        let ones = Matrix::from_value(shape.size(), 1, 1.0);
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let identity = Augmentation::new(shape)
+            .then(Transform::Shift { max: 0.0 })
+            .then(Transform::Rotate { max_degrees: 0.0 })
+            .then(Transform::Elastic {
+                alpha: 0.0,
+                sigma: 2.0,
+            });
+        assert_eq!(identity.apply(&ones, &mut rng), ones);
+
+        let noisy = Augmentation::new(shape)
+            .then(Transform::Noise { std_dev: 0.1 })
+            .apply(&Matrix::from_value(shape.size(), 1, 0.5), &mut rng);
+        assert!(noisy.iter().all(|v| (0.0..=1.0).contains(v)));
+        assert!(noisy.iter().any(|v| *v != 0.5));
+
+        // a quarter of every channel is erased
+        let erased = Augmentation::new(shape)
+            .then(Transform::Erase {
+                probability: 1.0,
+                min_area: 0.25,
+                max_area: 0.25,
+            })
+            .apply(&ones, &mut rng);
+        let zeros = |channel: usize| {
+            erased
+                .iter()
+                .skip(channel * 64)
+                .take(64)
+                .filter(|v| **v == 0.0)
+                .count()
+        };
+        assert!((8..=24).contains(&zeros(0)), "{}", zeros(0));
+        assert_eq!(zeros(0), zeros(1));
+
+        let elastic = Augmentation::new(shape).then(Transform::Elastic {
+            alpha: 2.0,
+            sigma: 1.0,
+        });
+        assert_ne!(
+            elastic.apply(&image(shape, &[(4, 4)]), &mut rng),
+            image(shape, &[(4, 4)])
+        );
+    }
+}
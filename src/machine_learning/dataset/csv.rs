@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+};
+
+use crate::linear_algebra::Matrix;
+
+use super::{idx::invalid_data, DataVector};
+
+/// The column of a row that holds its label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelColumn {
+    First,
+    Last,
+    Index(usize),
+    /// the column with this name in the header
+    Name(String),
+}
+
+/// Parses delimited text files with one sample per line, e.g. CSV or TSV.
+///
+/// Every column other than the label is a feature, the label is the class of the sample.
+/// Fields may be quoted with `"`, empty lines are skipped.
+///
+/// ### Example
+/// ```
+/// # use mathematics::machine_learning::dataset::csv::{CsvParser, LabelColumn};
+/// let parser = CsvParser::default().label_column(LabelColumn::Name("class".to_string()));
+/// let data = parser.parse_str("height,class,width\n0.5,1,0.25\n0.75,0,1.0\n")?;
+/// assert_eq!(data[0].label(), Some(1));
+/// assert_eq!(data[0].data.iter().copied().collect::<Vec<_>>(), vec![0.5, 0.25]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvParser {
+    delimiter: Option<char>,
+    header: bool,
+    label_column: LabelColumn,
+    label_offset: usize,
+}
+
+impl Default for CsvParser {
+    /// comma separated with a header, the label is in the last column
+    fn default() -> Self {
+        CsvParser {
+            delimiter: None,
+            header: true,
+            label_column: LabelColumn::Last,
+            label_offset: 0,
+        }
+    }
+}
+
+impl CsvParser {
+    /// by default `\t` for `.tsv` files and `,` otherwise
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// whether the first line names the columns instead of holding a sample
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn label_column(mut self, label_column: LabelColumn) -> Self {
+        self.label_column = label_column;
+        self
+    }
+
+    /// subtracted from every label, so the first class is 0
+    pub fn label_offset(mut self, label_offset: usize) -> Self {
+        self.label_offset = label_offset;
+        self
+    }
+
+    /// Parses `{dataset_path}{dataset_name}.{ext}.csv`, or `.tsv` if there is no such file.
+    pub fn parse(
+        &self,
+        dataset_path: &str,
+        dataset_name: &str,
+        ext: &str,
+    ) -> Result<Vec<DataVector>, Error> {
+        let base = format!("{}{}.{}", dataset_path, dataset_name, ext);
+        match fs::read_to_string(format!("{}.csv", base)) {
+            Ok(contents) => self.parse_str(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let contents =
+                    fs::read_to_string(format!("{}.tsv", base)).map_err(|tsv| {
+                        match tsv.kind() {
+                            ErrorKind::NotFound => e,
+                            _ => tsv,
+                        }
+                    })?;
+                match self.delimiter {
+                    Some(_) => self.parse_str(&contents),
+                    None => self.clone().delimiter('\t').parse_str(&contents),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse_str(&self, contents: &str) -> Result<Vec<DataVector>, Error> {
+        let delimiter = self.delimiter.unwrap_or(',');
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let header = match self.header {
+            true => lines.next().map(|(_, line)| split(line, delimiter)),
+            false => None,
+        };
+
+        let mut columns = header.as_ref().map(|h| h.len());
+        let mut data = vec![];
+        for (number, line) in lines {
+            let error = |message: String| invalid_data(format!("line {}: {}", number + 1, message));
+
+            let fields = split(line, delimiter);
+            let count = *columns.get_or_insert(fields.len());
+            if fields.len() != count {
+                return Err(error(format!(
+                    "expected {} columns, found {}",
+                    count,
+                    fields.len()
+                )));
+            }
+
+            let label_index = self.label_index(header.as_deref(), count)?;
+            let mut features = Vec::with_capacity(count - 1);
+            let mut label = None;
+            for (i, field) in fields.iter().enumerate() {
+                if i == label_index {
+                    label = Some(
+                        field
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|l| l.checked_sub(self.label_offset))
+                            .ok_or_else(|| {
+                                error(format!(
+                                    "label {:?} is not a class starting at {}",
+                                    field, self.label_offset
+                                ))
+                            })?,
+                    );
+                } else {
+                    features.push(
+                        field
+                            .parse::<f64>()
+                            .map_err(|_| error(format!("{:?} is not a number", field)))?,
+                    );
+                }
+            }
+
+            data.push(DataVector::new(
+                Matrix::from_vec(features.len(), 1, features),
+                label.unwrap(),
+            ));
+        }
+
+        Ok(data)
+    }
+
+    fn label_index(&self, header: Option<&[String]>, columns: usize) -> Result<usize, Error> {
+        let index = match &self.label_column {
+            LabelColumn::First => 0,
+            LabelColumn::Last => columns.saturating_sub(1),
+            LabelColumn::Index(index) => *index,
+            LabelColumn::Name(name) => header
+                .and_then(|h| h.iter().position(|column| column == name))
+                .ok_or_else(|| invalid_data(format!("there is no column named {:?}", name)))?,
+        };
+
+        match index < columns {
+            true => Ok(index),
+            false => Err(invalid_data(format!(
+                "the label column {} is out of {} columns",
+                index, columns
+            ))),
+        }
+    }
+}
+
+/// the trimmed fields of a line, delimiters within quotes are part of the field
+fn split(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // an escaped quote within quotes
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{split, CsvParser, LabelColumn};
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split(" 1, \"a,b\" ,c", ','), vec!["1", "a,b", "c"]);
+        assert_eq!(
+            split("\"say \"\"hi\"\"\"\t2", '\t'),
+            vec!["say \"hi\"", "2"]
+        );
+    }
+
+    #[test]
+    fn test_parsing() {
+        let parser = CsvParser::default()
+            .header(false)
+            .delimiter('\t')
+            .label_column(LabelColumn::First)
+            .label_offset(1);
+        let data = parser.parse_str("1\t0.5\t2\n\n3\t1\t-1\n").unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1].label(), Some(2));
+        assert_eq!(
+            data[1].data.iter().copied().collect::<Vec<_>>(),
+            vec![1.0, -1.0]
+        );
+
+        let kind =
+            |parser: &CsvParser, contents: &str| parser.parse_str(contents).unwrap_err().kind();
+        let parser = CsvParser::default();
+        assert_eq!(kind(&parser, "a,b\n1,2\n1,2,3\n"), ErrorKind::InvalidData);
+        assert_eq!(kind(&parser, "a,b\nx,2\n"), ErrorKind::InvalidData);
+        assert_eq!(kind(&parser, "a,b\n1,-2\n"), ErrorKind::InvalidData);
+        let named = parser.label_column(LabelColumn::Name("c".to_string()));
+        assert_eq!(kind(&named, "a,b\n1,2\n"), ErrorKind::InvalidData);
+    }
+}
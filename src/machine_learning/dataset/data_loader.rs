@@ -0,0 +1,603 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        mpsc::{sync_channel, Receiver, TryRecvError},
+        Arc,
+    },
+};
+
+use crate::linear_algebra::Matrix;
+use rayon::Yield;
+
+use rand::{
+    distributions::{Distribution, Uniform, WeightedIndex},
+    rngs::StdRng,
+    seq::SliceRandom,
+    Rng, SeedableRng,
+};
+
+use super::{augmentation::Augmentation, preprocessing::Preprocessing, DataVector};
+
+/// The order in which a [`DataLoader`] visits the samples of an epoch
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sampling {
+    /// every sample in order
+    Sequential,
+    /// a new random permutation every epoch (Fisher-Yates)
+    Shuffled,
+    /// shuffled, with the samples of every label spread evenly over the epoch,
+    /// so every batch holds roughly the label proportions of the whole data
+    Stratified,
+    /// as many samples as the data holds, drawn with replacement
+    /// proportionally to the weight of every sample
+    Weighted(Vec<f64>),
+}
+
+impl Sampling {
+    /// weighted sampling where every label is drawn equally often
+    pub fn class_balanced(data: &[DataVector]) -> Self {
+        let mut counts = BTreeMap::<Option<usize>, usize>::new();
+        data.iter()
+            .for_each(|d| *counts.entry(d.label()).or_default() += 1);
+
+        Sampling::Weighted(
+            data.iter()
+                .map(|d| 1.0 / counts[&d.label()] as f64)
+                .collect(),
+        )
+    }
+}
+
+/// A batch of samples, with the inputs joined into a matrix (one column per sample)
+#[derive(Debug)]
+pub struct Batch<'a> {
+    pub inputs: Matrix,
+    pub samples: Vec<&'a DataVector>,
+}
+
+impl<'a> Batch<'a> {
+    pub fn new(samples: Vec<&'a DataVector>) -> Self {
+        Batch {
+            inputs: Matrix::from_columns(samples.iter().map(|d| &d.data)),
+            samples,
+        }
+    }
+
+    /// a batch with every input passed through `augmentation`, the samples are left unchanged
+    pub fn augmented(
+        samples: Vec<&'a DataVector>,
+        augmentation: &Augmentation,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let columns: Vec<Matrix> = samples
+            .iter()
+            .map(|d| augmentation.apply(&d.data, rng))
+            .collect();
+        Batch {
+            inputs: Matrix::from_columns(&columns),
+            samples,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// the expected outputs, one column per sample
+    pub fn expected(&self, output_size: usize) -> Matrix {
+        let columns: Vec<Matrix> = self
+            .samples
+            .iter()
+            .map(|d| d.expected_matrix((output_size, 1)))
+            .collect();
+        Matrix::from_columns(&columns)
+    }
+}
+
+/// Splits data into batches for training, in a new order every epoch.
+///
+/// The order of every epoch only depends on the seed and the number of the epoch,
+/// so training can continue from any batch with [`DataLoader::seek`].
+///
+/// An [`Augmentation`] is applied to the inputs while a batch is assembled,
+/// so a loader with one should only be used for training, followed by a [`Preprocessing`]
+/// when the data is kept raw to be augmented.
+/// Such batches are assembled ahead of time on the rayon thread pool,
+/// up to `prefetch` of them while the current one is used;
+/// otherwise a batch only copies its inputs and is assembled when it is drawn.
+///
+/// ### Example
+/// ```
+/// # use mathematics::{linear_algebra::Matrix, machine_learning::dataset::{DataVector, data_loader::{DataLoader, Sampling}}};
+/// let data: Vec<DataVector> = (0..10)
+///     .map(|i| DataVector::new(Matrix::from_value(1, 1, i as f64), i % 2))
+///     .collect();
+///
+/// let mut loader = DataLoader::new(&data, 4).with_seed(7).drop_last(true);
+/// let sizes: Vec<usize> = loader.epoch().map(|batch| batch.len()).collect();
+/// assert_eq!(sizes, vec![4, 4]);
+///
+/// // the same seed always gives the same order
+/// let order = |loader: &mut DataLoader| -> Vec<f64> {
+///     loader.epoch().flat_map(|batch| batch.inputs.iter().copied().collect::<Vec<_>>()).collect()
+/// };
+/// assert_eq!(order(&mut DataLoader::new(&data, 4).with_seed(7)), order(&mut DataLoader::new(&data, 4).with_seed(7)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DataLoader<'a> {
+    data: &'a [DataVector],
+    batch_size: usize,
+    drop_last: bool,
+    sampling: Sampling,
+    prefetch: usize,
+    augmentation: Option<Augmentation>,
+    preprocessing: Option<Arc<Preprocessing>>,
+    seed: u64,
+    epochs: u64,
+    /// batches to skip at the start of the next epoch
+    skip: usize,
+}
+
+impl<'a> DataLoader<'a> {
+    /// shuffles the data with a random seed
+    pub fn new(data: &'a [DataVector], batch_size: usize) -> Self {
+        DataLoader {
+            data,
+            batch_size: batch_size.max(1),
+            drop_last: false,
+            sampling: Sampling::Shuffled,
+            prefetch: rayon::current_num_threads().max(1),
+            augmentation: None,
+            preprocessing: None,
+            seed: rand::random(),
+            epochs: 0,
+            skip: 0,
+        }
+    }
+
+    /// makes the order of every epoch reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// # Panics
+    /// if weighted sampling does not have one non-negative weight per sample,
+    /// or all weights are zero
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        if let Sampling::Weighted(weights) = &sampling {
+            assert_eq!(
+                weights.len(),
+                self.data.len(),
+                "weighted sampling needs one weight per sample"
+            );
+            WeightedIndex::new(weights).expect("invalid sampling weights");
+        }
+        self.sampling = sampling;
+        self
+    }
+
+    /// skips the last batch of an epoch if it is smaller than the batch size
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    /// the number of augmented or preprocessed batches assembled ahead of the current one
+    pub fn with_prefetch(mut self, batches: usize) -> Self {
+        self.prefetch = batches.max(1);
+        self
+    }
+
+    /// randomly transforms the inputs of every batch, differently every epoch
+    pub fn with_augmentation(mut self, augmentation: Augmentation) -> Self {
+        self.augmentation = Some(augmentation);
+        self
+    }
+
+    /// transforms the inputs of every batch after the augmentation,
+    /// e.g. the way the rest of the data was preprocessed
+    pub fn with_preprocessing(mut self, preprocessing: Arc<Preprocessing>) -> Self {
+        self.preprocessing = Some(preprocessing);
+        self
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// the number of epochs started so far
+    pub fn epochs(&self) -> u64 {
+        self.epochs
+    }
+
+    /// Continues with `epoch` (counting from 0) of `seed`, starting at its batch `batch`.
+    ///
+    /// The batches match the ones an uninterrupted loader would have given.
+    pub fn seek(&mut self, seed: u64, epoch: u64, batch: usize) {
+        self.seed = seed;
+        self.epochs = epoch;
+        self.skip = batch;
+    }
+
+    /// number of batches in every epoch
+    pub fn len(&self) -> usize {
+        match self.drop_last {
+            true => self.data.len() / self.batch_size,
+            false => self.data.len().div_ceil(self.batch_size),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the batches of the next epoch
+    pub fn epoch(&mut self) -> Batches<'a> {
+        // every epoch has its own stream, spread apart by the golden ratio
+        let mut rng =
+            StdRng::seed_from_u64(self.seed ^ self.epochs.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        self.epochs += 1;
+
+        let mut order = self.order(&mut rng);
+        order.truncate(self.len() * self.batch_size);
+
+        let mut batches = Batches {
+            data: self.data,
+            order,
+            position: 0,
+            batch_size: self.batch_size,
+            prefetch: self.prefetch,
+            pending: VecDeque::new(),
+            augmentation: self.augmentation.clone().map(Arc::new),
+            preprocessing: self.preprocessing.clone(),
+            rng: StdRng::seed_from_u64(rng.gen()),
+        };
+        batches.skip_batches(std::mem::take(&mut self.skip));
+        batches
+    }
+
+    fn order(&self, rng: &mut StdRng) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.data.len()).collect();
+        match &self.sampling {
+            Sampling::Sequential => {}
+            Sampling::Shuffled => order.shuffle(rng),
+            Sampling::Stratified => {
+                let mut groups = BTreeMap::<Option<usize>, Vec<usize>>::new();
+                for (i, sample) in self.data.iter().enumerate() {
+                    groups.entry(sample.label()).or_default().push(i);
+                }
+
+                // every sample is placed at its (jittered) relative position within its label
+                let jitter = Uniform::new(0.0, 1.0);
+                let mut positions: Vec<(f64, usize)> = vec![];
+                for mut group in groups.into_values() {
+                    group.shuffle(rng);
+                    let length = group.len() as f64;
+                    for (rank, i) in group.into_iter().enumerate() {
+                        let position = (rank as f64 + rng.sample(jitter)) / length;
+                        positions.push((position, i));
+                    }
+                }
+
+                positions.sort_by(|a, b| a.0.total_cmp(&b.0));
+                order = positions.into_iter().map(|(_, i)| i).collect();
+            }
+            Sampling::Weighted(weights) => {
+                let distribution = WeightedIndex::new(weights).unwrap();
+                order = (0..self.data.len())
+                    .map(|_| distribution.sample(rng))
+                    .collect();
+            }
+        }
+        order
+    }
+}
+
+/// The batches of an epoch, see [`DataLoader::epoch`]
+#[derive(Debug)]
+pub struct Batches<'a> {
+    data: &'a [DataVector],
+    order: Vec<usize>,
+    position: usize,
+    batch_size: usize,
+    prefetch: usize,
+    /// the samples of the batches being augmented, in order, with the receivers of their inputs
+    pending: VecDeque<(Vec<&'a DataVector>, Receiver<Matrix>)>,
+    augmentation: Option<Arc<Augmentation>>,
+    preprocessing: Option<Arc<Preprocessing>>,
+    /// seeds the augmentation of every batch, so the order of assembly does not matter
+    rng: StdRng,
+}
+
+impl<'a> Batches<'a> {
+    /// Skips `n` batches without assembling them,
+    /// the remaining batches are augmented as if the skipped ones were drawn
+    pub fn skip_batches(&mut self, n: usize) {
+        for _ in 0..n {
+            if self.pending.pop_front().is_some() {
+                continue;
+            }
+            if self.position >= self.order.len() {
+                break;
+            }
+            self.rng.gen::<u64>();
+            self.position = (self.position + self.batch_size).min(self.order.len());
+        }
+    }
+
+    /// the samples of the next batch in the order
+    fn take_samples(&mut self) -> Option<Vec<&'a DataVector>> {
+        if self.position >= self.order.len() {
+            return None;
+        }
+        let end = (self.position + self.batch_size).min(self.order.len());
+        let samples = self.order[self.position..end]
+            .iter()
+            .map(|i| &self.data[*i])
+            .collect();
+        self.position = end;
+        Some(samples)
+    }
+
+    /// starts assembling batches on the thread pool until `prefetch` are pending
+    fn fill(&mut self) {
+        while self.pending.len() < self.prefetch {
+            let Some(samples) = self.take_samples() else {
+                break;
+            };
+            // the seeds are drawn in order, whichever batch finishes first
+            let seed: u64 = self.rng.gen();
+            let inputs: Vec<Matrix> = samples.iter().map(|d| d.data.clone()).collect();
+            let (augmentation, preprocessing) =
+                (self.augmentation.clone(), self.preprocessing.clone());
+            let (sender, receiver) = sync_channel(1);
+            rayon::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut inputs = match augmentation {
+                    Some(augmentation) => Matrix::from_columns(
+                        &inputs
+                            .iter()
+                            .map(|input| augmentation.apply(input, &mut rng))
+                            .collect::<Vec<_>>(),
+                    ),
+                    None => Matrix::from_columns(&inputs),
+                };
+                if let Some(preprocessing) = preprocessing {
+                    inputs = preprocessing.transform(&inputs);
+                }
+                // the batches may have been dropped in the meantime
+                let _ = sender.send(inputs);
+            });
+            self.pending.push_back((samples, receiver));
+        }
+    }
+}
+
+impl<'a> Iterator for Batches<'a> {
+    type Item = Batch<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.augmentation.is_none() && self.preprocessing.is_none() {
+            return self.take_samples().map(Batch::new);
+        }
+
+        self.fill();
+        let (samples, receiver) = self.pending.pop_front()?;
+        // the following batches are assembled while this one is used
+        self.fill();
+        Some(Batch {
+            inputs: wait(&receiver),
+            samples,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining =
+            self.pending.len() + (self.order.len() - self.position).div_ceil(self.batch_size);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Batches<'_> {}
+
+/// Waits for the inputs of a batch. A thread of the pool the batch was queued to runs the
+/// queued work meanwhile, since the batch might never be assembled if every thread waited.
+fn wait(receiver: &Receiver<Matrix>) -> Matrix {
+    const PANICKED: &str = "the augmentation of a batch panicked";
+    loop {
+        match receiver.try_recv() {
+            Ok(inputs) => return inputs,
+            Err(TryRecvError::Disconnected) => panic!("{}", PANICKED),
+            Err(TryRecvError::Empty) => match rayon::yield_now() {
+                Some(Yield::Executed) => {}
+                // another thread is assembling the batch
+                Some(Yield::Idle) => std::thread::yield_now(),
+                None => return receiver.recv().expect(PANICKED),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{DataLoader, Sampling};
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::{
+                augmentation::{Augmentation, Transform},
+                preprocessing::{MinMaxScaler, Preprocessing, Preprocessor},
+                DataVector,
+            },
+            neural_network::layers::convolution::ImageShape,
+        },
+    };
+
+    fn data() -> Vec<DataVector> {
+        // 30 samples of label 0 and 10 of label 1
+        (0..40)
+            .map(|i| DataVector::new(Matrix::from_value(1, 1, i as f64), (i >= 30) as usize))
+            .collect()
+    }
+
+    fn values(loader: &mut DataLoader) -> Vec<Vec<f64>> {
+        loader
+            .epoch()
+            .map(|batch| batch.inputs.iter().copied().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_shuffling_and_batching() {
+        let data = data();
+        let mut loader = DataLoader::new(&data, 16).with_seed(3).with_prefetch(1);
+        assert_eq!(loader.len(), 3);
+
+        let first = values(&mut loader);
+        assert_eq!(
+            first.iter().map(|b| b.len()).collect::<Vec<_>>(),
+            vec![16, 16, 8]
+        );
+
+        // a permutation, different every epoch but reproducible from the seed
+        let mut all: Vec<f64> = first.concat();
+        all.sort_by(f64::total_cmp);
+        assert_eq!(all, (0..40).map(|i| i as f64).collect::<Vec<_>>());
+        assert_ne!(values(&mut loader), first);
+        assert_eq!(values(&mut DataLoader::new(&data, 16).with_seed(3)), first);
+
+        let mut dropping = DataLoader::new(&data, 16).drop_last(true);
+        assert_eq!(dropping.epoch().len(), 2);
+
+        let sequential =
+            values(&mut DataLoader::new(&data, 16).with_sampling(Sampling::Sequential));
+        assert_eq!(
+            sequential[2],
+            (32..40).map(|i| i as f64).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_seeking_to_a_batch() {
+        let data = data();
+        let erase = Augmentation::new(ImageShape::new(1, 1, 1)).then(Transform::Erase {
+            probability: 0.5,
+            min_area: 1.0,
+            max_area: 1.0,
+        });
+        let mut loader = DataLoader::new(&data, 4)
+            .with_seed(9)
+            .with_prefetch(3)
+            .with_augmentation(erase.clone());
+        values(&mut loader);
+        let second = values(&mut loader);
+
+        // the rest of the second epoch, as if the first batches had been drawn
+        let mut resumed = DataLoader::new(&data, 4).with_augmentation(erase);
+        resumed.seek(9, 1, 4);
+        assert_eq!(values(&mut resumed), second[4..]);
+        assert_eq!(resumed.epochs(), 2);
+    }
+
+    #[test]
+    fn test_prefetching() {
+        // the noise is kept between 0-1
+        let data: Vec<DataVector> = (0..40)
+            .map(|i| DataVector::new(Matrix::from_value(1, 1, i as f64 / 40.0), 0))
+            .collect();
+        let noise =
+            Augmentation::new(ImageShape::new(1, 1, 1)).then(Transform::Noise { std_dev: 0.1 });
+        let epochs = |prefetch: usize| {
+            let mut loader = DataLoader::new(&data, 3)
+                .with_seed(5)
+                .with_prefetch(prefetch)
+                .with_augmentation(noise.clone());
+            [values(&mut loader), values(&mut loader)]
+        };
+
+        // the batches do not depend on how many are assembled ahead
+        let expected = epochs(1);
+        assert_eq!(expected[0].len(), 14);
+        assert_eq!(epochs(4), expected);
+        assert_eq!(epochs(32), expected);
+    }
+
+    #[test]
+    fn test_loading_within_a_thread_pool() {
+        let data = data();
+        let erase = Augmentation::new(ImageShape::new(1, 1, 1)).then(Transform::Erase {
+            probability: 1.0,
+            min_area: 1.0,
+            max_area: 1.0,
+        });
+        // the only thread of the pool waits for the batches it queued
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let batches = pool.install(|| {
+            let mut loader = DataLoader::new(&data, 4)
+                .with_prefetch(3)
+                .with_augmentation(erase);
+            values(&mut loader)
+        });
+        assert_eq!(batches.len(), 10);
+    }
+
+    #[test]
+    fn test_stratified_and_weighted_sampling() {
+        let data = data();
+        let ones = |batch: &Vec<f64>| batch.iter().filter(|v| **v >= 30.0).count();
+
+        // every batch of 8 holds 2 samples of label 1
+        let mut stratified = DataLoader::new(&data, 8).with_sampling(Sampling::Stratified);
+        for batch in values(&mut stratified) {
+            assert!((1..=3).contains(&ones(&batch)), "{:?}", batch);
+        }
+
+        // label 1 is drawn about half of the time
+        let mut balanced = DataLoader::new(&data, 40)
+            .with_seed(1)
+            .with_sampling(Sampling::class_balanced(&data));
+        let drawn: usize = (0..50).map(|_| ones(&values(&mut balanced)[0])).sum();
+        assert!((800..1200).contains(&drawn), "{}", drawn);
+    }
+
+    #[test]
+    fn test_augmentation() {
+        let data = data();
+        let erase = Augmentation::new(ImageShape::new(1, 1, 1)).then(Transform::Erase {
+            probability: 1.0,
+            min_area: 1.0,
+            max_area: 1.0,
+        });
+        let mut loader = DataLoader::new(&data, 16).with_augmentation(erase);
+
+        // only the inputs are augmented
+        for batch in loader.epoch() {
+            assert!(batch.inputs.iter().all(|v| *v == 0.0));
+            assert!(batch.samples.iter().any(|d| d.data[(0, 0)] != 0.0));
+        }
+
+        // then preprocessed, the erased inputs are scaled to -1
+        let mut preprocessing = Preprocessing::default();
+        let mut scaler = MinMaxScaler::new(-1.0, 1.0);
+        scaler.fit(&data).unwrap();
+        preprocessing.push(scaler);
+        let mut loader = loader.with_preprocessing(Arc::new(preprocessing));
+        for batch in loader.epoch() {
+            assert!(batch.inputs.iter().all(|v| *v == -1.0));
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, GrayImage,
+};
+
+use crate::linear_algebra::Matrix;
+
+use super::idx::invalid_data;
+
+/// pixels fainter than this, relative to the darkest and brightest ones,
+/// are taken as paper rather than ink
+const BACKGROUND_THRESHOLD: f64 = 0.25;
+
+/// The color of the writing in an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ink {
+    /// dark on a light background if the border of the image is mostly light
+    Auto,
+    /// e.g. a pen on paper
+    Dark,
+    /// e.g. chalk on a board, like the images of EMNIST
+    Light,
+}
+
+/// Turns an image of a single handwritten character into an input like those of EMNIST.
+///
+/// The image is converted to grayscale with light writing on black, blurred, cropped to
+/// the writing, centered in a square frame with its aspect ratio kept, and resized to
+/// `size` x `size` pixels. The pixels are normalized to 0-1 with the brightest one at 1,
+/// and are stored transposed like the images of the EMNIST files.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::{dataset::handwriting::HandwritingParser, neural_network::NeuralNetwork};
+/// # use std::path::Path;
+/// let nn = NeuralNetwork::load("output/network.nn")?;
+/// let input = HandwritingParser::default().load(Path::new("my-letter.png"))?;
+/// for prediction in nn.predict(&input, 3) {
+///     println!("{} {:.2}", prediction.class, prediction.probability);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HandwritingParser {
+    size: usize,
+    padding: usize,
+    blur: f32,
+    ink: Ink,
+    transposed: bool,
+}
+
+impl Default for HandwritingParser {
+    /// the 28 x 28 images of EMNIST
+    fn default() -> Self {
+        HandwritingParser {
+            size: 28,
+            padding: 1,
+            blur: 1.0,
+            ink: Ink::Auto,
+            transposed: true,
+        }
+    }
+}
+
+impl HandwritingParser {
+    /// the width and height of the converted image,
+    /// converting fails unless it is more than twice the padding
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// the pixels left empty around the writing on every side (default `1`)
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// the standard deviation of the gaussian blur, in pixels of a 128 x 128 image
+    /// like the scans EMNIST was made from, `0` disables the blur
+    pub fn blur(mut self, sigma: f32) -> Self {
+        self.blur = sigma;
+        self
+    }
+
+    pub fn ink(mut self, ink: Ink) -> Self {
+        self.ink = ink;
+        self
+    }
+
+    /// whether the pixels are stored column by column, as in EMNIST (default `true`)
+    pub fn transposed(mut self, transposed: bool) -> Self {
+        self.transposed = transposed;
+        self
+    }
+
+    /// an image file as a column of `size * size` pixels
+    pub fn load(&self, path: &Path) -> Result<Matrix, Error> {
+        let image = image::open(path).map_err(|e| match e {
+            image::ImageError::IoError(e) => e,
+            e => invalid_data(format!("{}: {}", path.display(), e)),
+        })?;
+        self.convert(&image)
+    }
+
+    /// An image as a column of `size * size` pixels.
+    ///
+    /// Fails if the padding leaves no room for the writing, i.e. `2 * padding >= size`.
+    pub fn convert(&self, image: &DynamicImage) -> Result<Matrix, Error> {
+        if self.padding.saturating_mul(2) >= self.size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "A padding of {} leaves no room for the writing in an image of {} x {} pixels",
+                    self.padding, self.size, self.size
+                ),
+            ));
+        }
+
+        let mut gray = image.to_luma8();
+        let invert = match self.ink {
+            Ink::Auto => border_mean(&gray) > 127.5,
+            Ink::Dark => true,
+            Ink::Light => false,
+        };
+        if invert {
+            imageops::invert(&mut gray);
+        }
+
+        let (width, height) = gray.dimensions();
+        let sigma = self.blur * width.max(height) as f32 / 128.0;
+        if sigma > 0.0 {
+            gray = imageops::blur(&gray, sigma);
+        }
+        remove_background(&mut gray);
+
+        let size = self.size as u32;
+        let frame = match bounding_box(&gray) {
+            Some((x, y, width, height)) => {
+                let writing = imageops::crop_imm(&gray, x, y, width, height).to_image();
+                let side = width.max(height) as usize;
+                let inner = self.size - 2 * self.padding;
+                let frame = (side * self.size).div_ceil(inner) as u32;
+
+                let mut square = GrayImage::new(frame, frame);
+                let (left, top) = ((frame - width) / 2, (frame - height) / 2);
+                imageops::overlay(&mut square, &writing, left as i64, top as i64);
+                imageops::resize(&square, size, size, FilterType::CatmullRom)
+            }
+            None => GrayImage::new(size, size),
+        };
+
+        let brightest = frame.pixels().map(|p| p.0[0]).max().unwrap_or(0).max(1) as f64;
+        let mut pixels = vec![0.0; self.size * self.size];
+        for (x, y, pixel) in frame.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            let index = match self.transposed {
+                true => x * self.size + y,
+                false => y * self.size + x,
+            };
+            pixels[index] = pixel.0[0] as f64 / brightest;
+        }
+        Ok(Matrix::from_vec(pixels.len(), 1, pixels))
+    }
+}
+
+/// the mean brightness of the outermost pixels
+fn border_mean(image: &GrayImage) -> f64 {
+    let (width, height) = image.dimensions();
+    let border: Vec<f64> = image
+        .enumerate_pixels()
+        .filter(|(x, y, _)| *x == 0 || *y == 0 || *x + 1 == width || *y + 1 == height)
+        .map(|(_, _, p)| p.0[0] as f64)
+        .collect();
+    border.iter().sum::<f64>() / border.len().max(1) as f64
+}
+
+/// stretches the contrast to 0-255 and blacks out the paper, e.g. its texture and shadows
+fn remove_background(image: &mut GrayImage) {
+    let darkest = image.pixels().map(|p| p.0[0]).min().unwrap_or(0) as f64;
+    let brightest = image.pixels().map(|p| p.0[0]).max().unwrap_or(0) as f64;
+    for pixel in image.pixels_mut() {
+        let value = match brightest > darkest {
+            true => (pixel.0[0] as f64 - darkest) / (brightest - darkest),
+            false => 0.0,
+        };
+        pixel.0[0] = match value < BACKGROUND_THRESHOLD {
+            true => 0,
+            false => (value * 255.0).round() as u8,
+        };
+    }
+}
+
+/// `(x, y, width, height)` of the pixels that are not black
+fn bounding_box(image: &GrayImage) -> Option<(u32, u32, u32, u32)> {
+    let lit = image.enumerate_pixels().filter(|(_, _, p)| p.0[0] > 0);
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    let mut any = false;
+    for (x, y, _) in lit {
+        (left, top) = (left.min(x), top.min(y));
+        (right, bottom) = (right.max(x), bottom.max(y));
+        any = true;
+    }
+    any.then(|| (left, top, right - left + 1, bottom - top + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GrayImage, Luma};
+
+    use super::{HandwritingParser, Ink};
+
+    /// a dark vertical bar, 40 pixels high and 10 wide, off-center on white paper
+    fn bar() -> DynamicImage {
+        let mut image = GrayImage::from_pixel(100, 80, Luma([240]));
+        for y in 10..50 {
+            for x in 60..70 {
+                image.put_pixel(x, y, Luma([20]));
+            }
+        }
+        DynamicImage::ImageLuma8(image)
+    }
+
+    #[test]
+    fn test_handwriting() {
+        let parser = HandwritingParser::default().blur(0.0).transposed(false);
+        let input = parser.convert(&bar()).unwrap();
+        assert_eq!(input.get_dims(), (28 * 28, 1));
+        let pixel = |x: usize, y: usize| input[(y * 28 + x, 0)];
+
+        // the bar is centered and spans the frame vertically, without the padding
+        assert!(pixel(14, 14) > 0.9);
+        assert!(pixel(14, 2) > 0.5 && pixel(14, 25) > 0.5);
+        assert_eq!(pixel(2, 14), 0.0);
+        assert_eq!(pixel(25, 14), 0.0);
+        assert!(input.iter().all(|p| (0.0..=1.0).contains(p)));
+
+        // EMNIST stores the images transposed
+        let transposed = HandwritingParser::default()
+            .blur(0.0)
+            .convert(&bar())
+            .unwrap();
+        for (x, y) in [(14, 3), (10, 20), (3, 14)] {
+            assert_eq!(transposed[(x * 28 + y, 0)], pixel(x, y));
+        }
+
+        // taken as light writing, the paper is what is written
+        let paper = parser.ink(Ink::Light).convert(&bar()).unwrap();
+        assert!(paper.iter().filter(|p| **p > 0.5).count() > 28 * 28 / 2);
+
+        let blank = parser
+            .convert(&DynamicImage::ImageLuma8(GrayImage::new(5, 5)))
+            .unwrap();
+        assert!(blank.iter().all(|p| *p == 0.0));
+
+        // the writing needs at least a pixel inside the padding
+        let tiny = HandwritingParser::default()
+            .size(3)
+            .convert(&bar())
+            .unwrap();
+        assert_eq!(tiny.get_dims(), (9, 1));
+        for (size, padding) in [(0, 0), (2, 1), (28, 14)] {
+            let parser = HandwritingParser::default().size(size).padding(padding);
+            assert!(parser.convert(&bar()).is_err());
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Error, ErrorKind, Read},
+    path::Path,
+};
+
+use flate2::bufread::GzDecoder;
+
+/// the most bytes reserved up front for the values, larger arrays grow as they are read
+const MAX_PREALLOCATION: usize = 1 << 24;
+
+/// The type of the values stored in an IDX file, given by the third byte of the magic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdxType {
+    U8,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+}
+
+impl IdxType {
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x08 => Some(IdxType::U8),
+            0x09 => Some(IdxType::I8),
+            0x0B => Some(IdxType::I16),
+            0x0C => Some(IdxType::I32),
+            0x0D => Some(IdxType::F32),
+            0x0E => Some(IdxType::F64),
+            _ => None,
+        }
+    }
+
+    /// number of bytes of a single value
+    pub fn size(&self) -> usize {
+        match self {
+            IdxType::U8 | IdxType::I8 => 1,
+            IdxType::I16 => 2,
+            IdxType::I32 | IdxType::F32 => 4,
+            IdxType::F64 => 8,
+        }
+    }
+
+    /// decodes a big-endian value
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        match self {
+            IdxType::U8 => bytes[0] as f64,
+            IdxType::I8 => bytes[0] as i8 as f64,
+            IdxType::I16 => i16::from_be_bytes([bytes[0], bytes[1]]) as f64,
+            IdxType::I32 => i32::from_be_bytes(bytes.try_into().unwrap()) as f64,
+            IdxType::F32 => f32::from_be_bytes(bytes.try_into().unwrap()) as f64,
+            IdxType::F64 => f64::from_be_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+/// An array read from an IDX file, e.g. the images or labels of MNIST.
+///
+/// ### Example
+/// ```
+/// # use mathematics::machine_learning::dataset::idx::{IdxArray, IdxType};
+/// // two u8 values of shape [2]
+/// let bytes = [0, 0, 0x08, 1, 0, 0, 0, 2, 7, 9];
+/// let array = IdxArray::read(&bytes[..])?;
+/// assert_eq!(array.dtype, IdxType::U8);
+/// assert_eq!(array.dims, vec![2]);
+/// assert_eq!(array.values, vec![7.0, 9.0]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdxArray {
+    pub dtype: IdxType,
+    pub dims: Vec<usize>,
+    /// every value in row-major order
+    pub values: Vec<f64>,
+}
+
+impl IdxArray {
+    /// Reads an IDX file, gzip-compressed files are decompressed transparently.
+    pub fn open(path: impl AsRef<Path>) -> Result<IdxArray, Error> {
+        IdxArray::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads an IDX array, gzip-compressed data is decompressed transparently.
+    pub fn read(mut reader: impl BufRead) -> Result<IdxArray, Error> {
+        if reader.fill_buf()?.starts_with(&[0x1F, 0x8B]) {
+            return IdxArray::read_uncompressed(GzDecoder::new(reader));
+        }
+        IdxArray::read_uncompressed(reader)
+    }
+
+    fn read_uncompressed(mut reader: impl Read) -> Result<IdxArray, Error> {
+        // [0, 0, type, rank]
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic[0..2] != [0, 0] {
+            return Err(invalid_data(format!("invalid magic number {:?}", magic)));
+        }
+        let dtype = IdxType::from_code(magic[2])
+            .ok_or_else(|| invalid_data(format!("unknown data type {:#04x}", magic[2])))?;
+
+        // followed by the size of every dimension as big-endian u32
+        let mut dims = Vec::with_capacity(magic[3] as usize);
+        let mut int_bytes = [0; 4];
+        for _ in 0..magic[3] {
+            reader.read_exact(&mut int_bytes)?;
+            dims.push(u32::from_be_bytes(int_bytes) as usize);
+        }
+
+        let count = dims
+            .iter()
+            .try_fold(1usize, |count, d| count.checked_mul(*d))
+            .ok_or_else(|| invalid_data("the dimensions are too large".to_string()))?;
+        let length = count
+            .checked_mul(dtype.size())
+            .ok_or_else(|| invalid_data("the dimensions are too large".to_string()))?;
+
+        let mut bytes = Vec::with_capacity(length.min(MAX_PREALLOCATION));
+        reader.take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(invalid_data(format!(
+                "expected {} values of shape {:?}, found {}",
+                count,
+                dims,
+                bytes.len() / dtype.size()
+            )));
+        }
+
+        Ok(IdxArray {
+            dtype,
+            dims,
+            values: bytes
+                .chunks_exact(dtype.size())
+                .map(|b| dtype.decode(b))
+                .collect(),
+        })
+    }
+
+    /// number of items along the first dimension
+    pub fn len(&self) -> usize {
+        self.dims.first().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the values of the `i`th item along the first dimension
+    pub fn item(&self, i: usize) -> &[f64] {
+        let size = self.item_size();
+        &self.values[i * size..(i + 1) * size]
+    }
+
+    /// number of values of every item along the first dimension
+    pub fn item_size(&self) -> usize {
+        self.dims.iter().skip(1).product()
+    }
+}
+
+pub(super) fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{IdxArray, IdxType};
+
+    #[test]
+    fn test_dtypes_and_ranks() {
+        // i16 values of shape [2, 1, 2]
+        let mut bytes = vec![0, 0, 0x0B, 3, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2];
+        [-2i16, 300, 0, 7]
+            .iter()
+            .for_each(|v| bytes.extend(v.to_be_bytes()));
+
+        let array = IdxArray::read(&bytes[..]).unwrap();
+        assert_eq!(array.dtype, IdxType::I16);
+        assert_eq!(array.dims, vec![2, 1, 2]);
+        assert_eq!((array.len(), array.item_size()), (2, 2));
+        assert_eq!(array.item(0), &[-2.0, 300.0]);
+
+        let mut bytes = vec![0, 0, 0x0E, 1, 0, 0, 0, 1];
+        bytes.extend(0.25f64.to_be_bytes());
+        assert_eq!(IdxArray::read(&bytes[..]).unwrap().values, vec![0.25]);
+
+        let mut bytes = vec![0, 0, 0x09, 1, 0, 0, 0, 1, 0xFF];
+        assert_eq!(IdxArray::read(&bytes[..]).unwrap().values, vec![-1.0]);
+
+        // gzip is detected from its header
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&bytes).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(IdxArray::read(&compressed[..]).unwrap().values, vec![-1.0]);
+
+        // missing values
+        bytes[7] = 2;
+        let error = IdxArray::read(&bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_invalid_magic_numbers() {
+        let kind = |bytes: &[u8]| IdxArray::read(bytes).unwrap_err().kind();
+        // a truncated file declaring 2^31 x 2^31 bytes
+        assert_eq!(
+            kind(&[0, 0, 0x08, 2, 0x80, 0, 0, 0, 0x80, 0, 0, 0, 1, 2, 3]),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(kind(&[1, 0, 0x08, 1, 0, 0, 0, 0]), ErrorKind::InvalidData);
+        assert_eq!(kind(&[0, 0, 0x0A, 1, 0, 0, 0, 0]), ErrorKind::InvalidData);
+        assert_eq!(kind(&[0, 0, 0x08]), ErrorKind::UnexpectedEof);
+    }
+}
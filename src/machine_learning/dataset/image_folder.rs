@@ -0,0 +1,173 @@
+use std::{
+    fs,
+    io::Error,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, ImageFormat};
+use rayon::prelude::*;
+
+use crate::{
+    linear_algebra::Matrix, machine_learning::neural_network::layers::convolution::ImageShape,
+};
+
+use super::{idx::invalid_data, DataVector};
+
+/// Parses images sorted into one directory per class, from
+/// `{dataset_path}{dataset_name}/{ext}/{class}/`.
+///
+/// The classes are numbered in the alphabetical order of their directories,
+/// so every split needs the same class directories unless they are given with [`ImageFolderParser::classes`].
+/// Every image is resized to the shape and normalized to 0-1, files that are not images are skipped.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::dataset::{image_folder::ImageFolderParser, DataSet};
+/// let parser = ImageFolderParser::new(32, 32).rgb(true);
+/// let ds = DataSet::load_data("data/", "animals", |p, n, e| parser.parse(p, n, e))?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageFolderParser {
+    height: usize,
+    width: usize,
+    rgb: bool,
+    classes: Option<Vec<String>>,
+}
+
+impl ImageFolderParser {
+    /// grayscale images of `height` x `width` pixels
+    pub fn new(height: usize, width: usize) -> Self {
+        ImageFolderParser {
+            height,
+            width,
+            rgb: false,
+            classes: None,
+        }
+    }
+
+    /// keeps the red, green and blue channels instead of converting to grayscale
+    pub fn rgb(mut self, rgb: bool) -> Self {
+        self.rgb = rgb;
+        self
+    }
+
+    /// the names of the class directories, in the order of their classes
+    pub fn classes(mut self, classes: Vec<String>) -> Self {
+        self.classes = Some(classes);
+        self
+    }
+
+    /// the shape of the parsed images
+    pub fn shape(&self) -> ImageShape {
+        ImageShape::new(if self.rgb { 3 } else { 1 }, self.height, self.width)
+    }
+
+    pub fn parse(
+        &self,
+        dataset_path: &str,
+        dataset_name: &str,
+        ext: &str,
+    ) -> Result<Vec<DataVector>, Error> {
+        let root = Path::new(&format!("{}{}", dataset_path, dataset_name)).join(ext);
+        let classes = match &self.classes {
+            Some(classes) => classes.clone(),
+            None => {
+                let mut classes: Vec<String> = fs::read_dir(&root)?
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_dir())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect();
+                classes.sort();
+                classes
+            }
+        };
+
+        let mut files: Vec<(PathBuf, usize)> = vec![];
+        for (class, name) in classes.iter().enumerate() {
+            let mut paths: Vec<PathBuf> = fs::read_dir(root.join(name))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+                .collect();
+            paths.sort();
+            files.extend(paths.into_iter().map(|path| (path, class)));
+        }
+
+        files
+            .par_iter()
+            .map(|(path, class)| Ok(DataVector::new(self.load(path)?, *class)))
+            .collect()
+    }
+
+    /// a single image as a column of the shape
+    pub fn load(&self, path: &Path) -> Result<Matrix, Error> {
+        let image = image::open(path)
+            .map_err(|e| match e {
+                image::ImageError::IoError(e) => e,
+                e => invalid_data(format!("{}: {}", path.display(), e)),
+            })?
+            .resize_exact(self.width as u32, self.height as u32, FilterType::Triangle);
+
+        let shape = self.shape();
+        let mut pixels = vec![0.0; shape.size()];
+        match self.rgb {
+            true => {
+                for (x, y, pixel) in image.to_rgb8().enumerate_pixels() {
+                    for channel in 0..3 {
+                        pixels[shape.index(channel, y as usize, x as usize)] =
+                            pixel.0[channel] as f64 / 255.0;
+                    }
+                }
+            }
+            false => {
+                for (x, y, pixel) in image.to_luma8().enumerate_pixels() {
+                    pixels[shape.index(0, y as usize, x as usize)] = pixel.0[0] as f64 / 255.0;
+                }
+            }
+        }
+
+        Ok(Matrix::from_vec(shape.size(), 1, pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::{Rgb, RgbImage};
+
+    use super::ImageFolderParser;
+
+    #[test]
+    fn test_image_folders() {
+        let dir = std::env::temp_dir().join(format!("images-{}", std::process::id()));
+        let split = dir.join("colors").join("train");
+        for (class, color) in [("blue", [0, 0, 255]), ("red", [255, 0, 0])] {
+            fs::create_dir_all(split.join(class)).unwrap();
+            RgbImage::from_pixel(4, 6, Rgb(color))
+                .save(split.join(class).join("a.png"))
+                .unwrap();
+        }
+        fs::write(split.join("red").join("notes.txt"), "not an image").unwrap();
+
+        let path = format!("{}/", dir.display());
+        let parser = ImageFolderParser::new(3, 2).rgb(true);
+        let data = parser.parse(&path, "colors", "train").unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1].label(), Some(1));
+        let shape = parser.shape();
+        assert_eq!(data[1].data.get_dims(), (shape.size(), 1));
+        assert_eq!(data[1].data[(shape.index(0, 2, 1), 0)], 1.0);
+        assert_eq!(data[1].data[(shape.index(2, 2, 1), 0)], 0.0);
+
+        let gray = ImageFolderParser::new(3, 2)
+            .classes(vec!["red".to_string()])
+            .parse(&path, "colors", "train")
+            .unwrap();
+        assert_eq!(gray.len(), 1);
+        assert_eq!(gray[0].label(), Some(0));
+        assert_eq!(gray[0].data.get_dims(), (6, 1));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
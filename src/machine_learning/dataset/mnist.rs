@@ -1,107 +1,220 @@
-use std::{
-    fs::File,
-    io::{Error, Read},
-};
+use std::io::{stdout, Error, ErrorKind, Write};
 
 use crate::linear_algebra::Matrix;
 
-use super::DataVector;
+use super::{
+    idx::{invalid_data, IdxArray, IdxType},
+    DataVector,
+};
 
 pub const INPUT_SIZE: usize = 28 * 28;
 
-/// parses mnist ubyte files
+/// Parses the images and labels of a dataset in the IDX format of MNIST,
+/// from `{dataset_path}{dataset_name}.{ext}.images` and `.labels`.
+/// Either file may be gzip-compressed and end in `.gz`.
 ///
-/// ### Args
-/// - `dataset_path`: path_to_the_file/
-/// - `dataset_name`: base-name
-/// - `ext`: -test-labels
+/// Images of `u8` values are normalized to 0-1 unless disabled with [`MnistParser::normalize`],
+/// other types are kept as they are.
 ///
 /// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::dataset::mnist::MnistParser;
+/// // digit MNIST, whose labels start at 0
+/// let parser = MnistParser::default().label_offset(0).progress(true);
+/// parser.parse("data/", "digits", "train")?;
+/// # Ok::<(), std::io::Error>(())
 /// ```
-/// parse_mnist("src/assets/machine_learning/", "letters", "train")
-/// ```
-pub fn parse_mnist(
-    dataset_path: &str,
-    dataset_name: &str,
-    ext: &str,
-) -> Result<Vec<DataVector>, Error> {
-    let mut image_file = File::open(format!("{}{}.{}.images", dataset_path, dataset_name, ext))?;
-    let mut label_file = File::open(format!("{}{}.{}.labels", dataset_path, dataset_name, ext))?;
-
-    // MNIST dataset documentation:
-    // [0, 0, 8, n]; are the first 4 bytes.
-    // the first two are alwyas 0,
-    // followed by a byte that represent the type of data; 8 -> u8,
-    // followed by n, which represents the dimensions
-    // of the stored data.
-    let mut magic_number_images: [u8; 4] = [0; 4];
-    let mut magic_number_labels: [u8; 4] = [0; 4];
-    image_file.read_exact(&mut magic_number_images)?;
-    label_file.read_exact(&mut magic_number_labels)?;
-
-    // will hold the sizes of the dimensions
-    let mut image_sizes = Vec::<i32>::new();
-    let mut label_sizes = Vec::<i32>::new();
-
-    // the next n * 4 bytes will be pushed as i32 into sizes
-    let mut int_bytes: [u8; 4] = [0; 4];
-    for _ in 0..magic_number_images[3] {
-        image_file.read_exact(&mut int_bytes)?;
-        image_sizes.push(i32::from_be_bytes(int_bytes));
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MnistParser {
+    label_offset: usize,
+    progress: bool,
+    normalize: bool,
+}
 
-    for _ in 0..magic_number_labels[3] {
-        label_file.read_exact(&mut int_bytes)?;
-        label_sizes.push(i32::from_be_bytes(int_bytes));
+impl Default for MnistParser {
+    /// the EMNIST letters dataset, whose labels start at 1
+    fn default() -> Self {
+        MnistParser {
+            label_offset: 1,
+            progress: false,
+            normalize: true,
+        }
     }
+}
 
-    // these are expeected to be 28, 28
-    let (width, height) = (image_sizes[1], image_sizes[2]);
+impl MnistParser {
+    /// subtracted from every label, so the first class is 0
+    pub fn label_offset(mut self, label_offset: usize) -> Self {
+        self.label_offset = label_offset;
+        self
+    }
 
-    let image_dimensions = (width * height) as usize;
-    let mut image_vector = Vec::<DataVector>::new();
+    /// divides `u8` pixels by 255, disable to fit a [`Preprocessor`](super::preprocessing::Preprocessor) on the raw values
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
 
-    let mut buf: [u8; 1] = [0; 1];
+    /// prints which files are read
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
 
-    println!("Contructing {}.{} Dataset: ", dataset_name, ext);
-    for i in 0..image_sizes[0] {
-        let mut pixels = Vec::<f64>::new();
-        for _ in 0..image_dimensions {
-            image_file.read_exact(&mut buf)?;
-            pixels.push((buf[0] as f64) / 255.0);
+    pub fn parse(
+        &self,
+        dataset_path: &str,
+        dataset_name: &str,
+        ext: &str,
+    ) -> Result<Vec<DataVector>, Error> {
+        if self.progress {
+            print!("Constructing {}.{} Dataset... ", dataset_name, ext);
+            stdout().flush()?;
         }
 
-        label_file.read_exact(&mut buf)?;
-        let label = buf[0];
-        let d = DataVector::new(Matrix::from_vec(pixels.len(), 1, pixels), label - 1);
+        let base = format!("{}{}.{}", dataset_path, dataset_name, ext);
+        let images = open(&format!("{}.images", base))?;
+        let labels = open(&format!("{}.labels", base))?;
 
-        image_vector.push(d);
+        if labels.dims.len() != 1 {
+            return Err(invalid_data(format!(
+                "expected one label per image, found labels of shape {:?}",
+                labels.dims
+            )));
+        }
+        if images.len() != labels.len() {
+            return Err(invalid_data(format!(
+                "found {} images but {} labels",
+                images.len(),
+                labels.len()
+            )));
+        }
 
-        let fraction = ((i + 1) as f32) / (image_sizes[0] as f32) * 100.0;
-        let mut loading_indicator: [char; 10] = ['_'; 10];
-        for li in 0..(fraction as usize / 10) {
-            loading_indicator[li] = '█'
+        let scale = match images.dtype {
+            IdxType::U8 if self.normalize => 255.0,
+            _ => 1.0,
+        };
+        let size = images.item_size();
+        let data = labels
+            .values
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let class = (*label as usize)
+                    .checked_sub(self.label_offset)
+                    .filter(|_| label.fract() == 0.0 && *label >= 0.0)
+                    .ok_or_else(|| {
+                        invalid_data(format!(
+                            "label {} of sample {} is not a class starting at {}",
+                            label, i, self.label_offset
+                        ))
+                    })?;
+                let pixels = images.item(i).iter().map(|p| p / scale).collect();
+                Ok(DataVector::new(Matrix::from_vec(size, 1, pixels), class))
+            })
+            .collect::<Result<Vec<DataVector>, Error>>()?;
+
+        if self.progress {
+            println!("Done! ({} samples)", data.len());
         }
-        print!(
-            "\rcurrent({:?}) - {}  {}/{}  {:.2}%     ",
-            letter_from_number(label - 1),
-            loading_indicator.iter().collect::<String>(),
-            i + 1,
-            image_sizes[0],
-            fraction,
-        );
+        Ok(data)
     }
-    println!("\nDone!");
+}
 
-    Ok(image_vector)
+/// parses the EMNIST letters dataset, see [`MnistParser`]
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::dataset::mnist::parse_mnist;
+/// parse_mnist("src/assets/machine_learning/", "letters", "train")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn parse_mnist(
+    dataset_path: &str,
+    dataset_name: &str,
+    ext: &str,
+) -> Result<Vec<DataVector>, Error> {
+    MnistParser::default().parse(dataset_path, dataset_name, ext)
 }
 
-fn letter_from_number(n: u8) -> Option<char> {
-    let (a, b) = (b'a', b'b');
-    let c = a + (b - a) * n;
-    if c <= b'z' {
-        return Some(c as char);
+/// opens `path`, or its gzip-compressed `path.gz` if it does not exist
+fn open(path: &str) -> Result<IdxArray, Error> {
+    match IdxArray::open(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => IdxArray::open(format!("{}.gz", path))
+            .map_err(|gz| match gz.kind() {
+                ErrorKind::NotFound => e,
+                _ => gz,
+            }),
+        result => result,
+    }
+}
+
+/// the letter of a class of the EMNIST letters dataset, `0` is `'a'`
+pub fn letter_from_number(n: usize) -> Option<char> {
+    if n < 26 {
+        return Some((b'a' + n as u8) as char);
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::ErrorKind};
+
+    use super::{letter_from_number, MnistParser};
+
+    #[test]
+    fn test_label_offset() {
+        let dir = std::env::temp_dir().join(format!("mnist-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/", dir.display());
+
+        // two 1x2 images labelled 0 and 3
+        fs::write(
+            dir.join("digits.test.images"),
+            [
+                0, 0, 0x08, 3, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 255, 51, 102,
+            ],
+        )
+        .unwrap();
+        fs::write(
+            dir.join("digits.test.labels"),
+            [0, 0, 0x08, 1, 0, 0, 0, 2, 0, 3],
+        )
+        .unwrap();
+
+        let data = MnistParser::default()
+            .label_offset(0)
+            .parse(&path, "digits", "test")
+            .unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1].label(), Some(3));
+        assert_eq!(data[1].data.get_dims(), (2, 1));
+        assert_eq!(data[1].data[(1, 0)], 0.4);
+
+        let raw = MnistParser::default()
+            .label_offset(0)
+            .normalize(false)
+            .parse(&path, "digits", "test")
+            .unwrap();
+        assert_eq!(raw[1].data[(1, 0)], 102.0);
+
+        // the labels of letters start at 1, 0 would underflow
+        let error = MnistParser::default()
+            .parse(&path, "digits", "test")
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_letters() {
+        assert_eq!(letter_from_number(0), Some('a'));
+        assert_eq!(letter_from_number(25), Some('z'));
+        // classes beyond a byte do not wrap around to letters
+        assert_eq!(letter_from_number(26), None);
+        assert_eq!(letter_from_number(256), None);
+    }
+}
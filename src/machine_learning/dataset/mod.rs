@@ -1,14 +1,43 @@
-use std::io::Error;
+use std::{collections::BTreeMap, io::Error};
 
-use crate::linear_algebra::Matrix;
+use rand::seq::SliceRandom;
 
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+
+use crate::{
+    linear_algebra::Matrix, machine_learning::neural_network::layers::convolution::ImageShape,
+};
+
+pub mod augmentation;
+pub mod csv;
+pub mod data_loader;
+pub mod handwriting;
+pub mod idx;
+pub mod image_folder;
 pub mod mnist;
+pub mod npy;
+pub mod preprocessing;
 
+/// how far an output may be from its expected value to count as correct for [`Target::Values`]
+pub const VALUE_TOLERANCE: f64 = 0.1;
+
+#[derive(Clone)]
 pub struct DataSet {
     pub training_data: Vec<DataVector>,
+    /// held out from the training data by [`DataSet::split_validation`]
+    pub validation_data: Vec<DataVector>,
     pub testing_data: Vec<DataVector>,
 }
 
+/// How much of the training data is held out for validation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Split {
+    /// a fraction between 0 and 1 of the training data
+    Fraction(f64),
+    /// a number of samples
+    Count(usize),
+}
+
 impl DataSet {
     pub fn load_data(
         dataset_path: &str,
@@ -20,33 +49,304 @@ impl DataSet {
 
         Ok(DataSet {
             testing_data: _test_data,
+            validation_data: vec![],
             training_data: _train_data,
         })
     }
+
+    /// Moves a random selection of the training data to the validation data.
+    ///
+    /// The selection is stratified, every label keeps the same share of the samples
+    /// in both sets (up to rounding).
+    pub fn split_validation(&mut self, split: Split) {
+        let total = self.training_data.len();
+        let count = match split {
+            Split::Fraction(fraction) => (fraction.clamp(0.0, 1.0) * total as f64).round() as usize,
+            Split::Count(count) => count.min(total),
+        };
+
+        let mut groups = BTreeMap::<Option<usize>, Vec<usize>>::new();
+        for (i, sample) in self.training_data.iter().enumerate() {
+            groups.entry(sample.label()).or_default().push(i);
+        }
+
+        // every group gets its proportional share rounded down,
+        // the rest goes to the groups with the largest remainders
+        let mut shares: Vec<(usize, f64, Vec<usize>)> = groups
+            .into_values()
+            .map(|indices| {
+                let share = (count * indices.len()) as f64 / total as f64;
+                (share as usize, share.fract(), indices)
+            })
+            .collect();
+        let remaining = count - shares.iter().map(|(n, _, _)| n).sum::<usize>();
+        let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+        by_remainder.sort_by(|a, b| shares[*b].1.total_cmp(&shares[*a].1));
+        for i in by_remainder.into_iter().take(remaining) {
+            shares[i].0 += 1;
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut held_out = vec![false; total];
+        for (n, _, mut indices) in shares {
+            indices.shuffle(&mut rng);
+            indices.into_iter().take(n).for_each(|i| held_out[i] = true);
+        }
+
+        let (validation, training): (Vec<_>, Vec<_>) = std::mem::take(&mut self.training_data)
+            .into_iter()
+            .enumerate()
+            .partition(|(i, _)| held_out[*i]);
+        self.validation_data
+            .extend(validation.into_iter().map(|(_, sample)| sample));
+        self.training_data = training.into_iter().map(|(_, sample)| sample).collect();
+    }
+}
+
+/// What the network is expected to output for a [`DataVector`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// index of the correct class, expected as a one-hot vector
+    Class(usize),
+    /// indices of every correct class, expected as a multi-hot vector
+    Classes(Vec<usize>),
+    /// the exact expected output, e.g. for regression or autoencoders
+    Values(Matrix),
+}
+
+impl Target {
+    /// Whether `output` is counted as a correct prediction of the target:
+    /// - `Class`: the largest output is the class
+    /// - `Classes`: exactly the outputs of the classes are above 0.5
+    /// - `Values`: every output is within [`VALUE_TOLERANCE`] of its expected value
+    pub fn is_correct(&self, output: &Matrix) -> bool {
+        match self {
+            Target::Class(class) => output.index_of_max() == *class,
+            Target::Classes(classes) => output
+                .iter()
+                .enumerate()
+                .all(|(i, o)| (*o > 0.5) == classes.contains(&i)),
+            Target::Values(values) => output
+                .iter()
+                .zip(values.iter())
+                .all(|(o, v)| (o - v).abs() <= VALUE_TOLERANCE),
+        }
+    }
+
+    /// Checks that a network with `outputs` outputs can be trained or evaluated on the target,
+    /// [`DataVector::expected_matrix`] panics otherwise.
+    pub fn check(&self, outputs: usize) -> Result<(), String> {
+        let class = match self {
+            Target::Class(class) => Some(*class),
+            Target::Classes(classes) => classes.iter().max().copied(),
+            Target::Values(values) if values.get_dims() != (outputs, 1) => {
+                return Err(format!(
+                    "The target of {} values does not match the {} outputs of the network",
+                    values.get_dims().0,
+                    outputs
+                ))
+            }
+            Target::Values(_) => None,
+        };
+        match class {
+            Some(class) if class >= outputs => Err(format!(
+                "Class {} is not among the {} outputs of the network",
+                class, outputs
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DataVector {
-    /// `ArrayStorage` requires constant usize for Row and Column.
-    ///
-    /// We could also use `VecStorage` to create a matrix with
-    /// dimensions calculated at runtime, but operations would be slower.
-    ///
     /// values in the matrix are between 0-1 (inc), normalized from u8
     pub data: Matrix,
-    label: u8,
+    target: Target,
 }
 
-/// Contructs an image from the parsed ImageData
-/// Usefull for debugging
 impl DataVector {
-    pub fn new(data: Matrix, label: u8) -> Self {
-        DataVector { data, label }
+    /// a sample of the class `label`
+    pub fn new(data: Matrix, label: usize) -> Self {
+        DataVector::with_target(data, Target::Class(label))
+    }
+
+    pub fn with_target(data: Matrix, target: Target) -> Self {
+        DataVector { data, target }
+    }
+
+    /// Stores a sequence of column vectors, one per timestep, as a single column
+    /// for recurrent layers; see [`crate::machine_learning::neural_network::layers::recurrent`].
+    ///
+    /// Sequences trained in the same batch must have the same length.
+    pub fn from_sequence(steps: &[Matrix], target: Target) -> Self {
+        DataVector::with_target(Matrix::from_rows(steps), target)
     }
 
+    /// splits the data back into the timesteps of a sequence with `input_size` values per step
+    pub fn sequence(&self, input_size: usize) -> Vec<Matrix> {
+        let steps = self.data.get_dims().0 / input_size;
+        (0..steps)
+            .map(|t| self.data.rows(t * input_size..(t + 1) * input_size))
+            .collect()
+    }
+
+    /// Constructs an image of `shape` from the data, values of 0-1 are black to white.
+    /// Images with three channels are in color, any others have their channels stacked
+    /// from top to bottom. Useful for debugging
+    pub fn to_image(&self, shape: ImageShape) -> DynamicImage {
+        let values: Vec<f64> = self.data.iter().copied().collect();
+        let value = |i: usize| {
+            let v = values.get(i).copied().unwrap_or(0.0);
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        let (width, height) = (shape.width as u32, shape.height as u32);
+        match shape.channels {
+            3 => DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                let (x, y) = (x as usize, y as usize);
+                Rgb([0, 1, 2].map(|channel| value(shape.index(channel, y, x))))
+            })),
+            channels => {
+                let height = height * channels as u32;
+                DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+                    Luma([value(y as usize * shape.width + x as usize)])
+                }))
+            }
+        }
+    }
+
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// the class of single-label samples
+    pub fn label(&self) -> Option<usize> {
+        match self.target {
+            Target::Class(label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// the output expected from the network, `dims` is the shape of the output
     pub fn expected_matrix(&self, dims: (usize, usize)) -> Matrix {
-        let mut m = Matrix::zeros(dims.0, dims.1);
-        m[(self.label as usize, 0)] = 1.0;
-        m
+        match &self.target {
+            Target::Class(label) => {
+                let mut m = Matrix::zeros(dims.0, dims.1);
+                m[(*label, 0)] = 1.0;
+                m
+            }
+            Target::Classes(labels) => {
+                let mut m = Matrix::zeros(dims.0, dims.1);
+                labels.iter().for_each(|label| m[(*label, 0)] = 1.0);
+                m
+            }
+            Target::Values(values) => {
+                assert_eq!(
+                    values.get_dims(),
+                    dims,
+                    "the target does not match the output of the network"
+                );
+                values.clone()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataSet, DataVector, Split, Target};
+    use crate::{
+        linear_algebra::Matrix, machine_learning::neural_network::layers::convolution::ImageShape,
+    };
+
+    #[test]
+    fn test_validation_split() {
+        // 3/4 of the samples are class 0
+        let mut ds = DataSet {
+            training_data: (0..100)
+                .map(|i| DataVector::new(Matrix::from_value(1, 1, i as f64), (i % 4 == 0) as usize))
+                .collect(),
+            validation_data: vec![],
+            testing_data: vec![],
+        };
+
+        ds.split_validation(Split::Fraction(0.2));
+        assert_eq!(ds.validation_data.len(), 20);
+        assert_eq!(ds.training_data.len(), 80);
+        let ones = |data: &[DataVector]| data.iter().filter(|d| d.label() == Some(1)).count();
+        assert_eq!(ones(&ds.validation_data), 5);
+        assert_eq!(ones(&ds.training_data), 20);
+
+        ds.split_validation(Split::Count(7));
+        assert_eq!(ds.validation_data.len(), 27);
+
+        // no sample is lost or duplicated
+        let mut values: Vec<f64> = ds
+            .training_data
+            .iter()
+            .chain(ds.validation_data.iter())
+            .map(|d| d.data[(0, 0)])
+            .collect();
+        values.sort_by(f64::total_cmp);
+        assert_eq!(values, (0..100).map(|i| i as f64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_targets() {
+        let data = || Matrix::zeros(1, 1);
+        let output = Matrix::from_vec(4, 1, vec![0.1, 0.7, 0.2, 0.9]);
+
+        let class = DataVector::new(data(), 3);
+        assert_eq!(class.label(), Some(3));
+        assert_eq!(
+            class.expected_matrix((4, 1)),
+            Matrix::from_vec(4, 1, vec![0.0, 0.0, 0.0, 1.0])
+        );
+        assert!(class.target().is_correct(&output));
+
+        let classes = DataVector::with_target(data(), Target::Classes(vec![1, 3]));
+        assert_eq!(classes.label(), None);
+        assert_eq!(
+            classes.expected_matrix((4, 1)),
+            Matrix::from_vec(4, 1, vec![0.0, 1.0, 0.0, 1.0])
+        );
+        assert!(classes.target().is_correct(&output));
+        assert!(!Target::Classes(vec![3]).is_correct(&output));
+
+        let values = Matrix::from_vec(4, 1, vec![0.0, 0.75, 0.25, 1.0]);
+        let regression = DataVector::with_target(data(), Target::Values(values.clone()));
+        assert_eq!(regression.expected_matrix((4, 1)), values);
+        assert!(regression.target().is_correct(&output));
+        let close = |o: f64, v: f64| {
+            Target::Values(Matrix::from_vec(1, 1, vec![v])).is_correct(&Matrix::from_vec(
+                1,
+                1,
+                vec![o],
+            ))
+        };
+        assert!(!close(0.49, 0.0));
+        assert!(close(0.49, 0.51));
+
+        assert!(class.target().check(4).is_ok());
+        assert!(class.target().check(3).is_err());
+        assert!(classes.target().check(3).is_err());
+        assert!(regression.target().check(4).is_ok());
+        assert!(regression.target().check(5).is_err());
+    }
+
+    #[test]
+    fn test_images() {
+        let pixels = Matrix::from_vec(6, 1, vec![0.0, 0.5, 1.0, 1.0, 0.0, 0.0]);
+        let sample = DataVector::new(pixels, 0);
+
+        let gray = sample.to_image(ImageShape::new(1, 2, 3)).to_luma8();
+        assert_eq!(gray.dimensions(), (3, 2));
+        assert_eq!(gray.get_pixel(1, 0).0, [128]);
+        assert_eq!(gray.get_pixel(0, 1).0, [255]);
+
+        // red, green and blue channels of a 1x2 image
+        let rgb = sample.to_image(ImageShape::new(3, 1, 2)).to_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0).0, [0, 255, 0]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [128, 255, 0]);
     }
 }
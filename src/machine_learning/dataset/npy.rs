@@ -0,0 +1,406 @@
+use std::{
+    fs::{self, File},
+    io::{Error, ErrorKind, Read},
+    path::Path,
+};
+
+use zip::ZipArchive;
+
+use crate::linear_algebra::Matrix;
+
+use super::{idx::invalid_data, DataVector};
+
+/// An array read from a NumPy `.npy` file, the values are in row-major order.
+///
+/// Supports booleans, integers and floats of either byte order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyArray {
+    pub shape: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+impl NpyArray {
+    pub fn open(path: impl AsRef<Path>) -> Result<NpyArray, Error> {
+        NpyArray::read(&fs::read(path)?)
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<NpyArray, Error> {
+        if !bytes.starts_with(b"\x93NUMPY") || bytes.len() < 10 {
+            return Err(invalid_data("not a .npy file".to_string()));
+        }
+
+        // version 1 stores the length of the header in 2 bytes, later versions in 4
+        let (header_start, header_length) = match bytes[6] {
+            1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+            _ if bytes.len() >= 12 => (
+                12,
+                u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+            ),
+            _ => return Err(invalid_data("truncated .npy header".to_string())),
+        };
+        let header = bytes
+            .get(header_start..header_start + header_length)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .ok_or_else(|| invalid_data("invalid .npy header".to_string()))?;
+
+        let descr = header_value(header, "descr")?;
+        let (little_endian, kind, size) =
+            parse_descr(descr.trim_matches(|c| c == '\'' || c == '"'))?;
+        let fortran_order = header_value(header, "fortran_order")? == "True";
+        let shape: Vec<usize> = header_value(header, "shape")?
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| d.parse::<usize>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid_data(format!("invalid shape in {}", header)))?;
+
+        let count = shape
+            .iter()
+            .try_fold(1usize, |count, d| count.checked_mul(*d))
+            .ok_or_else(|| invalid_data("the shape is too large".to_string()))?;
+        let length = count
+            .checked_mul(size)
+            .ok_or_else(|| invalid_data("the shape is too large".to_string()))?;
+        let data = &bytes[header_start + header_length..];
+        if data.len() != length {
+            return Err(invalid_data(format!(
+                "expected {} values of shape {:?}, found {} bytes",
+                count,
+                shape,
+                data.len()
+            )));
+        }
+
+        let mut values: Vec<f64> = data
+            .chunks_exact(size)
+            .map(|chunk| {
+                let mut b = [0; 8];
+                match little_endian {
+                    true => b[..size].copy_from_slice(chunk),
+                    false => chunk.iter().rev().enumerate().for_each(|(i, v)| b[i] = *v),
+                }
+                decode(kind, size, b)
+            })
+            .collect();
+
+        if fortran_order && shape.len() > 1 {
+            values = to_row_major(&values, &shape);
+        }
+
+        Ok(NpyArray { shape, values })
+    }
+
+    /// number of items along the first dimension
+    pub fn len(&self) -> usize {
+        self.shape.first().copied().unwrap_or(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the values of the `i`th item along the first dimension
+    pub fn item(&self, i: usize) -> &[f64] {
+        let size = self.item_size();
+        &self.values[i * size..(i + 1) * size]
+    }
+
+    /// number of values of every item along the first dimension
+    pub fn item_size(&self) -> usize {
+        self.shape.iter().skip(1).product()
+    }
+}
+
+/// the raw value of `key` in the python dict of a .npy header
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, Error> {
+    let missing = || invalid_data(format!("the .npy header has no {:?}", key));
+    let start = header
+        .find(&format!("'{}'", key))
+        .and_then(|i| header[i..].find(':').map(|j| i + j + 1))
+        .ok_or_else(missing)?;
+
+    let rest = header[start..].trim_start();
+    let end = match rest.chars().next() {
+        Some('(') => rest.find(')').map(|i| i + 1),
+        _ => rest.find([',', '}']),
+    }
+    .ok_or_else(missing)?;
+    Ok(rest[..end].trim())
+}
+
+/// byte order, kind and size of a dtype like `<f8`
+fn parse_descr(descr: &str) -> Result<(bool, char, usize), Error> {
+    let unsupported = || invalid_data(format!("unsupported dtype {:?}", descr));
+    let mut chars = descr.chars();
+    let little_endian = match chars.next() {
+        Some('<') | Some('|') => true,
+        Some('=') => cfg!(target_endian = "little"),
+        Some('>') => false,
+        _ => return Err(unsupported()),
+    };
+    let kind = chars.next().ok_or_else(unsupported)?;
+    let size = chars.as_str().parse::<usize>().map_err(|_| unsupported())?;
+
+    match (kind, size) {
+        ('b', 1) | ('u' | 'i', 1 | 2 | 4 | 8) | ('f', 4 | 8) => Ok((little_endian, kind, size)),
+        _ => Err(unsupported()),
+    }
+}
+
+/// a little-endian value of `size` bytes
+fn decode(kind: char, size: usize, b: [u8; 8]) -> f64 {
+    match (kind, size) {
+        ('f', 4) => f32::from_le_bytes(b[..4].try_into().unwrap()) as f64,
+        ('f', _) => f64::from_le_bytes(b),
+        ('i', _) => {
+            // sign-extend the value
+            let shift = 64 - 8 * size as u32;
+            ((i64::from_le_bytes(b) << shift) >> shift) as f64
+        }
+        _ => u64::from_le_bytes(b) as f64,
+    }
+}
+
+fn to_row_major(values: &[f64], shape: &[usize]) -> Vec<f64> {
+    let strides: Vec<usize> = shape
+        .iter()
+        .scan(1, |stride, size| {
+            let current = *stride;
+            *stride *= size;
+            Some(current)
+        })
+        .collect();
+
+    (0..values.len())
+        .map(|row_major| {
+            // the column-major position of the same index
+            let (mut rest, mut position) = (row_major, 0);
+            for (size, stride) in shape.iter().zip(strides.iter()).rev() {
+                position += (rest % size) * stride;
+                rest /= size;
+            }
+            values[position]
+        })
+        .collect()
+}
+
+/// Parses NumPy arrays of inputs and labels, from `{dataset_path}{dataset_name}.{ext}.npz`,
+/// or `.{inputs}.npy` and `.{labels}.npy` if there is no such file.
+///
+/// The first dimension of the inputs holds the samples, every sample is flattened
+/// in row-major order, as expected by [`ImageShape`](crate::machine_learning::neural_network::layers::convolution::ImageShape)
+/// for arrays of `(samples, channels, height, width)`.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::dataset::npy::NpyParser;
+/// // np.savez("data/digits.train.npz", x=images, y=labels)
+/// let data = NpyParser::default().parse("data/", "digits", "train")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpyParser {
+    inputs: String,
+    labels: String,
+    label_offset: usize,
+}
+
+impl Default for NpyParser {
+    /// the inputs are named `x` and the labels `y`
+    fn default() -> Self {
+        NpyParser {
+            inputs: "x".to_string(),
+            labels: "y".to_string(),
+            label_offset: 0,
+        }
+    }
+}
+
+impl NpyParser {
+    /// the names of the arrays within the archive
+    pub fn names(mut self, inputs: &str, labels: &str) -> Self {
+        self.inputs = inputs.to_string();
+        self.labels = labels.to_string();
+        self
+    }
+
+    /// subtracted from every label, so the first class is 0
+    pub fn label_offset(mut self, label_offset: usize) -> Self {
+        self.label_offset = label_offset;
+        self
+    }
+
+    pub fn parse(
+        &self,
+        dataset_path: &str,
+        dataset_name: &str,
+        ext: &str,
+    ) -> Result<Vec<DataVector>, Error> {
+        let base = format!("{}{}.{}", dataset_path, dataset_name, ext);
+        let (inputs, labels) = match File::open(format!("{}.npz", base)) {
+            Ok(file) => {
+                let mut archive = ZipArchive::new(file).map_err(zip_error)?;
+                let mut read = |name: &str| -> Result<NpyArray, Error> {
+                    let mut bytes = vec![];
+                    archive
+                        .by_name(&format!("{}.npy", name))
+                        .map_err(zip_error)?
+                        .read_to_end(&mut bytes)?;
+                    NpyArray::read(&bytes)
+                };
+                (read(&self.inputs)?, read(&self.labels)?)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (
+                NpyArray::open(format!("{}.{}.npy", base, self.inputs))?,
+                NpyArray::open(format!("{}.{}.npy", base, self.labels))?,
+            ),
+            Err(e) => return Err(e),
+        };
+
+        self.to_data(&inputs, &labels)
+    }
+
+    /// pairs every item of `inputs` with the label at the same position
+    pub fn to_data(&self, inputs: &NpyArray, labels: &NpyArray) -> Result<Vec<DataVector>, Error> {
+        if labels.item_size() != 1 || inputs.len() != labels.len() {
+            return Err(invalid_data(format!(
+                "expected one label per input, found inputs of shape {:?} and labels of shape {:?}",
+                inputs.shape, labels.shape
+            )));
+        }
+
+        let size = inputs.item_size();
+        (0..inputs.len())
+            .map(|i| {
+                let label = labels.values[i];
+                let class = (label as usize)
+                    .checked_sub(self.label_offset)
+                    .filter(|_| label.fract() == 0.0 && label >= 0.0)
+                    .ok_or_else(|| {
+                        invalid_data(format!(
+                            "label {} of sample {} is not a class starting at {}",
+                            label, i, self.label_offset
+                        ))
+                    })?;
+                Ok(DataVector::new(
+                    Matrix::from_vec(size, 1, inputs.item(i).to_vec()),
+                    class,
+                ))
+            })
+            .collect()
+    }
+}
+
+fn zip_error(error: zip::result::ZipError) -> Error {
+    match error {
+        zip::result::ZipError::Io(e) => e,
+        zip::result::ZipError::FileNotFound => Error::new(ErrorKind::NotFound, error),
+        _ => invalid_data(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{ErrorKind, Write},
+    };
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::{NpyArray, NpyParser};
+
+    fn npy(descr: &str, fortran_order: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}\n",
+            descr,
+            if fortran_order { "True" } else { "False" },
+            shape
+        );
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn test_dtypes_and_order() {
+        let data: Vec<u8> = [1.5f64, -2.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let array = NpyArray::read(&npy("<f8", false, "(2,)", &data)).unwrap();
+        assert_eq!(array.shape, vec![2]);
+        assert_eq!(array.values, vec![1.5, -2.0]);
+
+        let data: Vec<u8> = [-3i16, 7].iter().flat_map(|v| v.to_be_bytes()).collect();
+        let array = NpyArray::read(&npy(">i2", false, "(2, 1)", &data)).unwrap();
+        assert_eq!(array.values, vec![-3.0, 7.0]);
+
+        // [[1, 2, 3], [4, 5, 6]] stored column by column
+        let array = NpyArray::read(&npy("|u1", true, "(2, 3)", &[1, 4, 2, 5, 3, 6])).unwrap();
+        assert_eq!(array.values, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(array.item(1), &[4.0, 5.0, 6.0]);
+
+        let kind = |bytes: &[u8]| NpyArray::read(bytes).unwrap_err().kind();
+        assert_eq!(
+            kind(&npy("<c16", false, "(1,)", &[0; 16])),
+            ErrorKind::InvalidData
+        );
+        // the values of the shape overflow
+        assert_eq!(
+            kind(&npy("<f8", false, "(4294967296, 4294967296)", &[0; 8])),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(
+            kind(&npy("<f8", false, "(1, 2305843009213693952)", &[0; 8])),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(
+            kind(&npy("<f4", false, "(2,)", &[0; 4])),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(kind(b"PK\x03\x04"), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_npz() {
+        let dir = std::env::temp_dir().join(format!("npy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut archive = ZipWriter::new(fs::File::create(dir.join("data.train.npz")).unwrap());
+        archive.start_file("x.npy", FileOptions::default()).unwrap();
+        let inputs: Vec<u8> = [0.0f32, 0.5, 1.0, 0.25]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        archive
+            .write_all(&npy("<f4", false, "(2, 2)", &inputs))
+            .unwrap();
+        archive.start_file("y.npy", FileOptions::default()).unwrap();
+        let labels: Vec<u8> = [2i64, 0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        archive
+            .write_all(&npy("<i8", false, "(2,)", &labels))
+            .unwrap();
+        archive.finish().unwrap();
+
+        let path = format!("{}/", dir.display());
+        let data = NpyParser::default().parse(&path, "data", "train").unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].label(), Some(2));
+        assert_eq!(
+            data[1].data.iter().copied().collect::<Vec<_>>(),
+            vec![1.0, 0.25]
+        );
+
+        let missing = NpyParser::default()
+            .names("images", "labels")
+            .parse(&path, "data", "train");
+        assert_eq!(missing.unwrap_err().kind(), ErrorKind::NotFound);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,551 @@
+use std::{error::Error, fmt::Debug};
+
+use rayon::prelude::*;
+
+use crate::{linear_algebra::Matrix, machine_learning::neural_network::layers::LayerRecord};
+
+use super::{DataSet, DataVector};
+
+/// A transform of the inputs whose statistics are learned from the training data.
+///
+/// Every column of the inputs is a separate sample, every row a feature.
+pub trait Preprocessor: Debug + Send + Sync {
+    /// learns the statistics of the inputs of `data`
+    fn fit(&mut self, data: &[DataVector]) -> Result<(), Box<dyn Error>>;
+
+    fn transform(&self, inputs: &Matrix) -> Matrix;
+
+    /// describes the preprocessor so it can be saved and rebuilt with [`preprocessor_from_record`]
+    fn record(&self) -> LayerRecord;
+
+    /// the number of features of the inputs it was fitted on, `None` if it takes any number
+    fn input_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// the rows of a fitted statistic, `None` before fitting
+fn fitted_rows(statistic: &Matrix) -> Option<usize> {
+    Some(statistic.get_dims().0).filter(|rows| *rows > 0)
+}
+
+/// rebuilds a preprocessor from the record returned by [`Preprocessor::record`]
+pub fn preprocessor_from_record(
+    record: &LayerRecord,
+) -> Result<Box<dyn Preprocessor>, Box<dyn Error>> {
+    match record.kind.as_str() {
+        "standard_scaler" => Ok(Box::new(StandardScaler {
+            mean: record.get_state(0)?.clone(),
+            std_dev: record.get_state(1)?.clone(),
+        })),
+        "min_max_scaler" => Ok(Box::new(MinMaxScaler {
+            low: record.get_config("low")?,
+            high: record.get_config("high")?,
+            min: record.get_state(0)?.clone(),
+            max: record.get_state(1)?.clone(),
+        })),
+        "pca_whitening" => Ok(Box::new(PcaWhitening {
+            components: match record.get_config::<usize>("components")? {
+                0 => None,
+                components => Some(components),
+            },
+            epsilon: record.get_config("epsilon")?,
+            mean: record.get_state(0)?.clone(),
+            projection: record.get_state(1)?.clone(),
+        })),
+        "one_hot_encoder" => Ok(Box::new(OneHotEncoder {
+            features: record
+                .get_config::<String>("features")?
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(|f| f.parse())
+                .collect::<Result<_, _>>()?,
+            categories: record
+                .state
+                .iter()
+                .map(|s| s.iter().copied().collect())
+                .collect(),
+        })),
+        _ => Err(format!("Unknown preprocessor type `{}`", record.kind).into()),
+    }
+}
+
+/// the sum of every feature over the inputs of `data`, and the number of samples
+fn feature_sums(data: &[DataVector], value: impl Fn(f64) -> f64 + Sync) -> (Matrix, usize) {
+    let features = data.first().map_or(0, |d| d.data.get_dims().0);
+    let sums = data
+        .par_iter()
+        .fold(
+            || Matrix::zeros(features, 1),
+            |mut sums, d| {
+                sums.iter_mut()
+                    .zip(d.data.iter())
+                    .for_each(|(s, v)| *s += value(*v));
+                sums
+            },
+        )
+        .reduce(|| Matrix::zeros(features, 1), |a, b| a + b);
+    (sums, data.len())
+}
+
+/// Shifts and scales every feature to a mean of 0 and a standard deviation of 1.
+///
+/// Features without variance are only shifted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandardScaler {
+    mean: Matrix,
+    std_dev: Matrix,
+}
+
+impl Default for StandardScaler {
+    fn default() -> Self {
+        StandardScaler::new()
+    }
+}
+
+impl StandardScaler {
+    pub fn new() -> Self {
+        StandardScaler {
+            mean: Matrix::zeros(0, 1),
+            std_dev: Matrix::zeros(0, 1),
+        }
+    }
+}
+
+impl Preprocessor for StandardScaler {
+    fn fit(&mut self, data: &[DataVector]) -> Result<(), Box<dyn Error>> {
+        let (sums, n) = feature_sums(data, |v| v);
+        let (squares, _) = feature_sums(data, |v| v * v);
+        let n = n.max(1) as f64;
+
+        self.mean = sums / n;
+        self.std_dev = squares / n;
+        self.std_dev
+            .iter_mut()
+            .zip(self.mean.iter())
+            .for_each(|(s, m)| {
+                let std_dev = (*s - m * m).max(0.0).sqrt();
+                *s = if std_dev > 1e-12 { std_dev } else { 1.0 };
+            });
+        Ok(())
+    }
+
+    fn transform(&self, inputs: &Matrix) -> Matrix {
+        let mut outputs = inputs.clone();
+        let (features, samples) = outputs.get_dims();
+        for j in 0..samples {
+            for i in 0..features {
+                outputs[(i, j)] = (outputs[(i, j)] - self.mean[(i, 0)]) / self.std_dev[(i, 0)];
+            }
+        }
+        outputs
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("standard_scaler")
+            .with_state(self.mean.clone())
+            .with_state(self.std_dev.clone())
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        fitted_rows(&self.mean)
+    }
+}
+
+/// Scales every feature linearly so its smallest training value maps to `low`
+/// and its largest to `high`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinMaxScaler {
+    low: f64,
+    high: f64,
+    min: Matrix,
+    max: Matrix,
+}
+
+impl Default for MinMaxScaler {
+    /// scales to 0-1
+    fn default() -> Self {
+        MinMaxScaler::new(0.0, 1.0)
+    }
+}
+
+impl MinMaxScaler {
+    pub fn new(low: f64, high: f64) -> Self {
+        MinMaxScaler {
+            low,
+            high,
+            min: Matrix::zeros(0, 1),
+            max: Matrix::zeros(0, 1),
+        }
+    }
+}
+
+impl Preprocessor for MinMaxScaler {
+    fn fit(&mut self, data: &[DataVector]) -> Result<(), Box<dyn Error>> {
+        let features = data.first().map_or(0, |d| d.data.get_dims().0);
+        self.min = Matrix::from_value(features, 1, f64::INFINITY);
+        self.max = Matrix::from_value(features, 1, f64::NEG_INFINITY);
+        for d in data {
+            for (i, v) in d.data.iter().enumerate() {
+                self.min[(i, 0)] = self.min[(i, 0)].min(*v);
+                self.max[(i, 0)] = self.max[(i, 0)].max(*v);
+            }
+        }
+        Ok(())
+    }
+
+    fn transform(&self, inputs: &Matrix) -> Matrix {
+        let mut outputs = inputs.clone();
+        let (features, samples) = outputs.get_dims();
+        for i in 0..features {
+            let (min, span) = (self.min[(i, 0)], self.max[(i, 0)] - self.min[(i, 0)]);
+            // constant features map to `low`
+            let scale = if span > 0.0 {
+                (self.high - self.low) / span
+            } else {
+                0.0
+            };
+            for j in 0..samples {
+                outputs[(i, j)] = self.low + (outputs[(i, j)] - min) * scale;
+            }
+        }
+        outputs
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("min_max_scaler")
+            .with_config("low", self.low)
+            .with_config("high", self.high)
+            .with_state(self.min.clone())
+            .with_state(self.max.clone())
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        fitted_rows(&self.min)
+    }
+}
+
+/// Projects the centered inputs onto the principal components of the training data,
+/// scaled to unit variance: `diag(1 / sqrt(eigenvalues + epsilon)) * eigenvectors^T * (x - mean)`.
+///
+/// The eigen decomposition of the covariance is O(features^3).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcaWhitening {
+    /// keeps the components with the largest variance, all if `None`
+    components: Option<usize>,
+    epsilon: f64,
+    mean: Matrix,
+    /// one row per component
+    projection: Matrix,
+}
+
+impl PcaWhitening {
+    pub fn new(components: Option<usize>, epsilon: f64) -> Self {
+        PcaWhitening {
+            components,
+            epsilon,
+            mean: Matrix::zeros(0, 1),
+            projection: Matrix::zeros(0, 0),
+        }
+    }
+}
+
+impl Preprocessor for PcaWhitening {
+    fn fit(&mut self, data: &[DataVector]) -> Result<(), Box<dyn Error>> {
+        let (sums, n) = feature_sums(data, |v| v);
+        self.mean = sums / n.max(1) as f64;
+
+        // the covariance is accumulated a batch of samples at a time
+        let negative_mean = self.mean.clone() * -1.0;
+        let features = self.mean.get_dims().0;
+        let covariance = data
+            .par_chunks(256)
+            .map(|chunk| {
+                let centered = Matrix::from_columns(chunk.iter().map(|d| &d.data))
+                    .add_to_columns(&negative_mean);
+                &centered * centered.transpose()
+            })
+            .reduce(|| Matrix::zeros(features, features), |a, b| a + b)
+            / (n.max(2) - 1) as f64;
+
+        let (values, vectors) = covariance
+            .symmetric_eigen()
+            .ok_or("The covariance of the inputs has no eigen decomposition")?;
+        let components = self.components.unwrap_or(features).min(features);
+        let rows: Vec<Matrix> = (0..components)
+            .map(|k| vectors.column(k) / (values[k].max(0.0) + self.epsilon).sqrt())
+            .collect();
+        self.projection = Matrix::from_columns(&rows).transpose();
+        Ok(())
+    }
+
+    fn transform(&self, inputs: &Matrix) -> Matrix {
+        &self.projection * inputs.clone().add_to_columns(&(self.mean.clone() * -1.0))
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("pca_whitening")
+            .with_config("components", self.components.unwrap_or(0))
+            .with_config("epsilon", self.epsilon)
+            .with_state(self.mean.clone())
+            .with_state(self.projection.clone())
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        fitted_rows(&self.mean)
+    }
+}
+
+/// Replaces categorical features by one-hot rows, one per category seen while fitting.
+/// Categories that were not seen are all zeros.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OneHotEncoder {
+    /// rows of the inputs that hold categories
+    features: Vec<usize>,
+    /// the sorted categories of every feature
+    categories: Vec<Vec<f64>>,
+}
+
+impl OneHotEncoder {
+    pub fn new(features: Vec<usize>) -> Self {
+        OneHotEncoder {
+            categories: vec![vec![]; features.len()],
+            features,
+        }
+    }
+}
+
+impl Preprocessor for OneHotEncoder {
+    fn fit(&mut self, data: &[DataVector]) -> Result<(), Box<dyn Error>> {
+        for (feature, categories) in self.features.iter().zip(self.categories.iter_mut()) {
+            if let Some(d) = data.iter().find(|d| d.data.get_dims().0 <= *feature) {
+                return Err(format!(
+                    "Cannot one-hot encode feature {} of inputs with {} features",
+                    feature,
+                    d.data.get_dims().0
+                )
+                .into());
+            }
+            *categories = data.iter().map(|d| d.data[(*feature, 0)]).collect();
+            categories.sort_by(f64::total_cmp);
+            categories.dedup();
+        }
+        Ok(())
+    }
+
+    fn transform(&self, inputs: &Matrix) -> Matrix {
+        let (features, samples) = inputs.get_dims();
+        let columns: Vec<Matrix> = (0..samples)
+            .map(|j| {
+                let mut values = vec![];
+                for i in 0..features {
+                    let value = inputs[(i, j)];
+                    match self.features.iter().position(|f| *f == i) {
+                        Some(k) => values.extend(self.categories[k].iter().map(|c| {
+                            if *c == value {
+                                1.0
+                            } else {
+                                0.0
+                            }
+                        })),
+                        None => values.push(value),
+                    }
+                }
+                Matrix::from_vec(values.len(), 1, values)
+            })
+            .collect();
+        Matrix::from_columns(&columns)
+    }
+
+    fn record(&self) -> LayerRecord {
+        let features: Vec<String> = self.features.iter().map(|f| f.to_string()).collect();
+        self.categories.iter().fold(
+            LayerRecord::new("one_hot_encoder").with_config("features", features.join(",")),
+            |record, categories| {
+                record.with_state(Matrix::from_vec(categories.len(), 1, categories.clone()))
+            },
+        )
+    }
+}
+
+/// Preprocessors applied one after the other, see [`Preprocessing::fit_transform`].
+///
+/// ### Example
+/// ```
+/// # use mathematics::{linear_algebra::Matrix, machine_learning::dataset::{DataSet, DataVector, preprocessing::{Preprocessing, StandardScaler}}};
+/// let sample = |x: f64| DataVector::new(Matrix::from_value(1, 1, x), 0);
+/// let mut ds = DataSet {
+///     training_data: vec![sample(1.0), sample(3.0)],
+///     validation_data: vec![],
+///     testing_data: vec![sample(5.0)],
+/// };
+///
+/// let mut preprocessing = Preprocessing::default();
+/// preprocessing.push(StandardScaler::new());
+/// preprocessing.fit_transform(&mut ds)?;
+/// assert_eq!(ds.testing_data[0].data[(0, 0)], 3.0);
+/// assert_eq!(preprocessing.transform(&Matrix::from_value(1, 1, 2.0))[(0, 0)], 0.0);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct Preprocessing {
+    steps: Vec<Box<dyn Preprocessor>>,
+}
+
+impl Preprocessing {
+    pub fn push(&mut self, step: impl Preprocessor + 'static) {
+        self.steps.push(Box::new(step));
+    }
+
+    pub fn steps(&self) -> &[Box<dyn Preprocessor>] {
+        &self.steps
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// the number of features of the raw inputs, if the first step knows it
+    pub fn input_size(&self) -> Option<usize> {
+        self.steps.first().and_then(|s| s.input_size())
+    }
+
+    /// Fits every step on the training data, as transformed by the steps before it,
+    /// then transforms the inputs of every split.
+    pub fn fit_transform(&mut self, data_set: &mut DataSet) -> Result<(), Box<dyn Error>> {
+        for step in self.steps.iter_mut() {
+            step.fit(&data_set.training_data)?;
+            for data in [
+                &mut data_set.training_data,
+                &mut data_set.validation_data,
+                &mut data_set.testing_data,
+            ] {
+                data.par_iter_mut()
+                    .for_each(|d| d.data = step.transform(&d.data));
+            }
+        }
+        Ok(())
+    }
+
+    /// transforms new inputs the same way as the data set, e.g. before a prediction
+    pub fn transform(&self, inputs: &Matrix) -> Matrix {
+        self.steps
+            .iter()
+            .fold(inputs.clone(), |inputs, step| step.transform(&inputs))
+    }
+
+    pub fn record(&self) -> LayerRecord {
+        let mut record = LayerRecord::new("preprocessing");
+        record.layers = self.steps.iter().map(|s| s.record()).collect();
+        record
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Ok(Preprocessing {
+            steps: record
+                .layers
+                .iter()
+                .map(preprocessor_from_record)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl PartialEq for Preprocessing {
+    fn eq(&self, other: &Self) -> bool {
+        self.record() == other.record()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        preprocessor_from_record, MinMaxScaler, OneHotEncoder, PcaWhitening, Preprocessor,
+        StandardScaler,
+    };
+    use crate::{linear_algebra::Matrix, machine_learning::dataset::DataVector};
+
+    fn data(samples: &[&[f64]]) -> Vec<DataVector> {
+        samples
+            .iter()
+            .map(|s| DataVector::new(Matrix::from_vec(s.len(), 1, s.to_vec()), 0))
+            .collect()
+    }
+
+    fn close(a: &Matrix, b: &Matrix) -> bool {
+        a.get_dims() == b.get_dims() && a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    fn round_trip(preprocessor: &dyn Preprocessor, inputs: &Matrix) {
+        let rebuilt = preprocessor_from_record(&preprocessor.record()).unwrap();
+        assert!(close(
+            &rebuilt.transform(inputs),
+            &preprocessor.transform(inputs)
+        ));
+    }
+
+    #[test]
+    fn test_scalers() {
+        let data = data(&[&[1.0, 5.0], &[3.0, 5.0]]);
+        let inputs = Matrix::from_vec(2, 2, vec![2.0, 5.0, 5.0, 7.0]);
+
+        let mut standard = StandardScaler::new();
+        assert_eq!(standard.input_size(), None);
+        standard.fit(&data).unwrap();
+        assert_eq!(standard.input_size(), Some(2));
+        assert!(close(
+            &standard.transform(&inputs),
+            &Matrix::from_vec(2, 2, vec![0.0, 0.0, 3.0, 2.0])
+        ));
+        round_trip(&standard, &inputs);
+
+        let mut min_max = MinMaxScaler::new(-1.0, 1.0);
+        min_max.fit(&data).unwrap();
+        assert!(close(
+            &min_max.transform(&inputs),
+            &Matrix::from_vec(2, 2, vec![0.0, -1.0, 3.0, -1.0])
+        ));
+        round_trip(&min_max, &inputs);
+    }
+
+    #[test]
+    fn test_pca_whitening() {
+        // correlated features along (1, 1)
+        let data = data(&[
+            &[-2.0, -2.1],
+            &[-1.0, -0.9],
+            &[0.0, 0.1],
+            &[1.0, 0.9],
+            &[2.0, 2.0],
+        ]);
+        let mut pca = PcaWhitening::new(None, 0.0);
+        pca.fit(&data).unwrap();
+
+        // the whitened training data has an identity covariance
+        let whitened = pca.transform(&Matrix::from_columns(data.iter().map(|d| &d.data)));
+        let covariance = &whitened * whitened.transpose() / 4.0;
+        assert!(close(&covariance, &Matrix::identity(2, 2)));
+        round_trip(&pca, &whitened);
+
+        let mut first = PcaWhitening::new(Some(1), 1e-5);
+        first.fit(&data).unwrap();
+        assert_eq!(first.transform(&data[0].data).get_dims(), (1, 1));
+    }
+
+    #[test]
+    fn test_one_hot_encoder() {
+        let data = data(&[&[0.5, 2.0], &[0.1, 0.0], &[0.2, 2.0]]);
+        let mut encoder = OneHotEncoder::new(vec![1]);
+        encoder.fit(&data).unwrap();
+
+        let inputs = Matrix::from_vec(2, 2, vec![0.3, 2.0, 0.4, 1.0]);
+        assert_eq!(
+            encoder.transform(&inputs),
+            Matrix::from_vec(3, 2, vec![0.3, 0.0, 1.0, 0.4, 0.0, 0.0])
+        );
+        round_trip(&encoder, &inputs);
+
+        // the inputs have no feature 2
+        assert!(OneHotEncoder::new(vec![2]).fit(&data).is_err());
+    }
+}
@@ -1,2 +1,6 @@
+pub mod config;
 pub mod dataset;
 pub mod neural_network;
+pub mod search;
+pub mod trainer;
+pub mod visualization;
@@ -0,0 +1,546 @@
+//! A training loop that calls [`Callback`]s at every stage of training.
+//!
+//! Early stopping, checkpoints, logs and changes of the learning rate are all callbacks,
+//! so they are added to a [`TrainingLoop`] instead of being written into it.
+//! Every [`TrainingLogger`] is a callback as well.
+//!
+//! ### Example
+//! ```no_run
+//! # use mathematics::machine_learning::{dataset::{DataSet, data_loader::DataLoader}, neural_network::{
+//! #     callbacks::TrainingLoop, checkpoint::Checkpointer, cost_functions::CostFunction,
+//! #     early_stopping::{EarlyStopping, Metric}, learning_rate::LrSchedule,
+//! #     logging::ProgressLogger, NeuralNetwork}};
+//! # fn train(mut nn: NeuralNetwork, ds: DataSet) -> Result<(), Box<dyn std::error::Error>> {
+//! let (cost, mut schedule) = (CostFunction::quadratic(), LrSchedule::constant(1.0));
+//! let mut loader = DataLoader::new(&ds.training_data, 16);
+//! let mut early_stopping = EarlyStopping::new(Metric::Accuracy, 3);
+//! let mut checkpointer = Checkpointer::new("checkpoints");
+//! let mut progress = ProgressLogger::default();
+//!
+//! TrainingLoop::new(&mut loader, &mut schedule, &cost)
+//!     .epochs(100)
+//!     .validation(&ds.validation_data)
+//!     .callback(&mut early_stopping)
+//!     .callback(&mut checkpointer)
+//!     .callback(&mut progress)
+//!     .run(&mut nn)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{error::Error, path::PathBuf, time::Instant};
+
+use crate::machine_learning::dataset::{data_loader::DataLoader, DataVector};
+
+use super::{
+    checkpoint::{Checkpointer, Progress},
+    cost_functions::CostFunction,
+    early_stopping::{EarlyStopping, Metric},
+    evaluation::Evaluation,
+    learning_rate::LrSchedule,
+    logging::{BatchRecord, EpochRecord, EpochStats, TrainingLogger},
+    NeuralNetwork,
+};
+
+/// What a callback can see of training
+pub struct TrainingState<'a, 'd> {
+    pub network: &'a mut NeuralNetwork,
+    pub progress: &'a Progress,
+    pub schedule: &'a LrSchedule,
+    pub loader: &'a DataLoader<'d>,
+    /// the evaluation of the validation data, at the end of an epoch
+    pub evaluation: Option<&'a Evaluation>,
+}
+
+/// What a callback asks of the training loop, done once every callback of a stage was called
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Control {
+    stop: bool,
+    learning_rate: Option<f64>,
+    save: Vec<PathBuf>,
+}
+
+impl Control {
+    /// stops training after the current batch
+    pub fn stop(&mut self) {
+        self.stop = true;
+    }
+
+    pub fn stopping(&self) -> bool {
+        self.stop
+    }
+
+    /// Changes the learning rate, the schedule continues from it scaled accordingly.
+    ///
+    /// Training fails if the schedule is at 0 then, see [`LrSchedule::set_learning_rate`].
+    pub fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = Some(learning_rate);
+    }
+
+    /// saves the network to `path`
+    pub fn save(&mut self, path: impl Into<PathBuf>) {
+        self.save.push(path.into());
+    }
+
+    fn apply(
+        &mut self,
+        network: &NeuralNetwork,
+        schedule: &mut LrSchedule,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(learning_rate) = self.learning_rate.take() {
+            schedule.set_learning_rate(learning_rate)?;
+        }
+        for path in self.save.drain(..) {
+            network.save(&path.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+/// Called by a [`TrainingLoop`] at every stage of training, every method does nothing by default
+pub trait Callback {
+    fn on_epoch_start(
+        &mut self,
+        _state: &mut TrainingState,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn on_batch_start(
+        &mut self,
+        _state: &mut TrainingState,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// after the step, `state.progress.batch` already counts the batch
+    fn on_batch_end(
+        &mut self,
+        _state: &mut TrainingState,
+        _record: &BatchRecord,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// after the validation, `state.progress` already counts the epoch
+    fn on_epoch_end(
+        &mut self,
+        _state: &mut TrainingState,
+        _record: &EpochRecord,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// when training ran out of epochs or was stopped, not when it failed
+    fn on_train_end(
+        &mut self,
+        _state: &mut TrainingState,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+impl<L: TrainingLogger> Callback for L {
+    fn on_batch_end(
+        &mut self,
+        _state: &mut TrainingState,
+        record: &BatchRecord,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.log_batch(record)?)
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        _state: &mut TrainingState,
+        record: &EpochRecord,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.log_epoch(record)?)
+    }
+}
+
+/// Stops once the validation has not improved for long enough,
+/// and restores the best weights at the end of training
+impl Callback for EarlyStopping {
+    fn on_epoch_end(
+        &mut self,
+        state: &mut TrainingState,
+        _record: &EpochRecord,
+        control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(evaluation) = state.evaluation {
+            if self.observe(state.network, evaluation)? {
+                control.stop();
+            }
+        }
+        Ok(())
+    }
+
+    fn on_train_end(
+        &mut self,
+        state: &mut TrainingState,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        self.restore_best(state.network)
+    }
+}
+
+impl Callback for Checkpointer {
+    fn on_batch_end(
+        &mut self,
+        state: &mut TrainingState,
+        _record: &BatchRecord,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.after_batch(state.network, state.progress, state.schedule, state.loader)?)
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        state: &mut TrainingState,
+        _record: &EpochRecord,
+        _control: &mut Control,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.after_epoch(state.network, state.progress, state.schedule, state.loader)?)
+    }
+}
+
+/// Trains a network for a number of epochs, calling its [`Callback`]s along the way.
+///
+/// The schedule is stepped after every batch or epoch, as it is set up to be,
+/// and observes `metric` of the validation data after every epoch.
+pub struct TrainingLoop<'a, 'd> {
+    loader: &'a mut DataLoader<'d>,
+    schedule: &'a mut LrSchedule,
+    cost_function: &'a CostFunction,
+    callbacks: Vec<&'a mut dyn Callback>,
+    epochs: usize,
+    validation: Option<&'a [DataVector]>,
+    metric: Metric,
+    top_k: usize,
+    progress: Progress,
+}
+
+impl<'a, 'd> TrainingLoop<'a, 'd> {
+    /// trains for a single epoch, without validation
+    pub fn new(
+        loader: &'a mut DataLoader<'d>,
+        schedule: &'a mut LrSchedule,
+        cost_function: &'a CostFunction,
+    ) -> Self {
+        TrainingLoop {
+            loader,
+            schedule,
+            cost_function,
+            callbacks: vec![],
+            epochs: 1,
+            validation: None,
+            metric: Metric::Accuracy,
+            top_k: 3,
+            progress: Progress::default(),
+        }
+    }
+
+    /// the total number of epochs, including those already trained when resuming
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// evaluated after every epoch
+    pub fn validation(mut self, data: &'a [DataVector]) -> Self {
+        self.validation = Some(data);
+        self
+    }
+
+    /// the metric of the validation observed by the schedule
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// the `k` of the top-k accuracy of the validation
+    pub fn top_k(mut self, k: usize) -> Self {
+        self.top_k = k;
+        self
+    }
+
+    /// continues from `progress`, e.g. the one of [`NeuralNetwork::resume_from`]
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// called in the order the callbacks were added
+    pub fn callback(mut self, callback: &'a mut dyn Callback) -> Self {
+        self.callbacks.push(callback);
+        self
+    }
+
+    /// Trains `network`, returning how far it came.
+    ///
+    /// Stops at the first error, of training or of a callback.
+    pub fn run(mut self, network: &mut NeuralNetwork) -> Result<Progress, Box<dyn Error>> {
+        let mut control = Control::default();
+        let started = Instant::now();
+
+        'training: while self.progress.epoch < self.epochs {
+            self.call(network, None, &mut control, |c, state, control| {
+                c.on_epoch_start(state, control)
+            })?;
+            if control.stopping() {
+                break;
+            }
+
+            let (epoch_started, learning_rate) = (Instant::now(), self.schedule.learning_rate());
+            let mut stats = EpochStats::default();
+            let mut last = epoch_started;
+
+            let batches = self.loader.len();
+            for batch in self.loader.epoch() {
+                self.call(network, None, &mut control, |c, state, control| {
+                    c.on_batch_start(state, control)
+                })?;
+
+                let batch_learning_rate = self.schedule.learning_rate();
+                let batch_stats = network
+                    .train_batch(
+                        &batch,
+                        self.schedule,
+                        self.cost_function,
+                        self.progress.batch,
+                    )
+                    .map_err(|e| format!("Training stopped: {}", e))?;
+                let record = BatchRecord::new(
+                    self.progress.epoch,
+                    self.progress.batch,
+                    batches,
+                    &batch_stats,
+                    batch_learning_rate,
+                    last.elapsed().as_secs_f64(),
+                    started.elapsed().as_secs_f64(),
+                );
+                last = Instant::now();
+                stats.add(&batch_stats);
+                self.progress.batch += 1;
+
+                self.call(network, None, &mut control, |c, state, control| {
+                    c.on_batch_end(state, &record, control)
+                })?;
+                if control.stopping() {
+                    break 'training;
+                }
+            }
+
+            let evaluation = self
+                .validation
+                .map(|data| network.evaluate(data, self.cost_function, self.top_k));
+            if let Some(evaluation) = &evaluation {
+                self.schedule.observe(self.metric.value(evaluation));
+            }
+            self.schedule.step_epoch();
+            self.progress.end_epoch(evaluation.as_ref());
+
+            let record = stats.record(
+                self.progress.epoch - 1,
+                learning_rate,
+                self.progress.history.last().cloned().unwrap_or_default(),
+                epoch_started.elapsed().as_secs_f64(),
+                started.elapsed().as_secs_f64(),
+            );
+            self.call(
+                network,
+                evaluation.as_ref(),
+                &mut control,
+                |c, state, control| c.on_epoch_end(state, &record, control),
+            )?;
+            if control.stopping() {
+                break;
+            }
+        }
+
+        self.call(network, None, &mut control, |c, state, control| {
+            c.on_train_end(state, control)
+        })?;
+        Ok(self.progress)
+    }
+
+    /// calls every callback at a stage, then does what they asked for
+    fn call(
+        &mut self,
+        network: &mut NeuralNetwork,
+        evaluation: Option<&Evaluation>,
+        control: &mut Control,
+        stage: impl Fn(
+            &mut dyn Callback,
+            &mut TrainingState,
+            &mut Control,
+        ) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        for callback in self.callbacks.iter_mut() {
+            let mut state = TrainingState {
+                network: &mut *network,
+                progress: &self.progress,
+                schedule: &*self.schedule,
+                loader: &*self.loader,
+                evaluation,
+            };
+            stage(&mut **callback, &mut state, control)?;
+        }
+        control.apply(network, self.schedule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::{Callback, Control, TrainingLoop, TrainingState};
+    use crate::{
+        calculus::functions::Function,
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::{data_loader::DataLoader, DataVector},
+            neural_network::{
+                cost_functions::CostFunction,
+                early_stopping::{EarlyStopping, Metric},
+                learning_rate::LrSchedule,
+                logging::{BatchRecord, EpochRecord},
+                NeuralNetwork,
+            },
+        },
+    };
+
+    /// records the stages it was called at, and halves the learning rate after every epoch
+    #[derive(Default)]
+    struct Recorder {
+        stages: Vec<String>,
+        learning_rates: Vec<f64>,
+        stop_after: Option<usize>,
+    }
+
+    impl Callback for Recorder {
+        fn on_epoch_start(
+            &mut self,
+            state: &mut TrainingState,
+            _control: &mut Control,
+        ) -> Result<(), Box<dyn Error>> {
+            self.stages.push(format!("epoch {}", state.progress.epoch));
+            Ok(())
+        }
+
+        fn on_batch_end(
+            &mut self,
+            state: &mut TrainingState,
+            record: &BatchRecord,
+            control: &mut Control,
+        ) -> Result<(), Box<dyn Error>> {
+            self.stages.push(format!("batch {}", record.batch));
+            self.learning_rates.push(record.learning_rate);
+            let batches = state.progress.epoch * record.batches + state.progress.batch;
+            if Some(batches) == self.stop_after {
+                control.stop();
+            }
+            Ok(())
+        }
+
+        fn on_epoch_end(
+            &mut self,
+            state: &mut TrainingState,
+            record: &EpochRecord,
+            control: &mut Control,
+        ) -> Result<(), Box<dyn Error>> {
+            assert!(state.evaluation.is_some());
+            self.stages.push(format!("end {}", record.epoch));
+            control.set_learning_rate(state.schedule.learning_rate() / 2.0);
+            Ok(())
+        }
+
+        fn on_train_end(
+            &mut self,
+            _state: &mut TrainingState,
+            _control: &mut Control,
+        ) -> Result<(), Box<dyn Error>> {
+            self.stages.push("done".to_string());
+            Ok(())
+        }
+    }
+
+    fn data() -> Vec<DataVector> {
+        (0..8)
+            .map(|x| {
+                let y = x as f64 / 8.0;
+                DataVector::new(Matrix::from_vec(1, 1, vec![y]), (y < 0.5) as usize)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let data = data();
+        let cost = CostFunction::quadratic();
+        let mut nn = NeuralNetwork::random(vec![1, 4, 2], Function::sigmoid());
+        let mut loader = DataLoader::new(&data, 4).with_seed(1);
+        let mut schedule = LrSchedule::constant(1.0);
+        let mut recorder = Recorder::default();
+
+        let progress = TrainingLoop::new(&mut loader, &mut schedule, &cost)
+            .epochs(2)
+            .validation(&data)
+            .callback(&mut recorder)
+            .run(&mut nn)
+            .unwrap();
+
+        assert_eq!(progress.epoch, 2);
+        assert_eq!(progress.history.len(), 2);
+        assert_eq!(
+            recorder.stages,
+            vec![
+                "epoch 0", "batch 0", "batch 1", "end 0", "epoch 1", "batch 0", "batch 1", "end 1",
+                "done"
+            ]
+        );
+        assert_eq!(recorder.learning_rates, vec![1.0, 1.0, 0.5, 0.5]);
+        assert_eq!(schedule.learning_rate(), 0.25);
+
+        // stopping ends training after the batch, without ending the epoch
+        let mut recorder = Recorder {
+            stop_after: Some(3),
+            ..Recorder::default()
+        };
+        let progress = TrainingLoop::new(&mut loader, &mut schedule, &cost)
+            .epochs(5)
+            .validation(&data)
+            .callback(&mut recorder)
+            .run(&mut nn)
+            .unwrap();
+        assert_eq!((progress.epoch, progress.batch), (1, 1));
+        assert_eq!(recorder.stages.last().unwrap(), "done");
+    }
+
+    #[test]
+    fn test_early_stopping_callback() {
+        let data = data();
+        let cost = CostFunction::quadratic();
+        let mut nn = NeuralNetwork::random(vec![1, 4, 2], Function::sigmoid());
+        let mut loader = DataLoader::new(&data, 4).with_seed(1);
+        // without a learning rate nothing improves after the first epoch
+        let mut schedule = LrSchedule::constant(0.0);
+        let mut early_stopping = EarlyStopping::new(Metric::MeanLoss, 2);
+
+        let progress = TrainingLoop::new(&mut loader, &mut schedule, &cost)
+            .epochs(10)
+            .validation(&data)
+            .callback(&mut early_stopping)
+            .run(&mut nn)
+            .unwrap();
+        assert_eq!(progress.epoch, 3);
+        assert_eq!(early_stopping.best().map(|(epoch, _)| epoch), Some(0));
+    }
+}
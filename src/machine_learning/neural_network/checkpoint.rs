@@ -0,0 +1,548 @@
+//! Checkpoints of a training run, written by a [`Checkpointer`].
+//!
+//! A checkpoint is a [`model_file`] with an extra `TRNS` section, so it can also be
+//! read with [`NeuralNetwork::load`]. The section holds
+//!
+//! ```text
+//! progress  epoch, batch (u64)
+//! schedule  step (u64), best metric (u32 flag, f64), bad observations (u64), plateau scale (f64)
+//! loader    seed, epoch (u64)
+//! history   epochs (u32), each a count (u32) of metric names and values (f64)
+//! ```
+//!
+//! The layers (including dropout seeds and normalization statistics), metadata and
+//! preprocessing are stored as in a network file. The configuration of training, such as
+//! the kind of schedule, the regularization, and the cost function, is not saved;
+//! it is set up again before resuming with [`NeuralNetwork::resume_from`].
+
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::machine_learning::dataset::data_loader::DataLoader;
+
+use super::{
+    early_stopping::Metric,
+    evaluation::Evaluation,
+    layers::Layer,
+    learning_rate::{LrSchedule, SchedulePosition},
+    model_file::{self, Decoder, Encoder},
+    NeuralNetwork,
+};
+
+/// How far training has come, and the metrics of every completed epoch
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Progress {
+    /// the number of completed epochs
+    pub epoch: usize,
+    /// the number of completed batches of the current epoch
+    pub batch: usize,
+    /// the named metrics of every completed epoch, empty when it was not evaluated
+    pub history: Vec<Vec<(String, f64)>>,
+}
+
+impl Progress {
+    /// Completes the current epoch, recording every [`Metric`] of `evaluation`
+    pub fn end_epoch(&mut self, evaluation: Option<&Evaluation>) {
+        let metrics = evaluation
+            .map(|evaluation| {
+                Metric::ALL
+                    .iter()
+                    .map(|metric| (metric.name().to_string(), metric.value(evaluation)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.history.push(metrics);
+        self.epoch += 1;
+        self.batch = 0;
+    }
+
+    /// the value of `metric` after every completed epoch, `None` where it was not evaluated
+    pub fn values(&self, metric: Metric) -> Vec<Option<f64>> {
+        self.history
+            .iter()
+            .map(|metrics| {
+                metrics
+                    .iter()
+                    .find(|(name, _)| name == metric.name())
+                    .map(|(_, value)| *value)
+            })
+            .collect()
+    }
+}
+
+/// Everything needed to continue training, see [`NeuralNetwork::resume_from`]
+#[derive(Debug, PartialEq)]
+pub struct Checkpoint {
+    pub network: NeuralNetwork,
+    pub progress: Progress,
+    pub schedule: SchedulePosition,
+    /// the seed of the data loader, and the epoch it is in
+    pub loader: (u64, u64),
+}
+
+impl Checkpoint {
+    pub fn encode(
+        nn: &NeuralNetwork,
+        progress: &Progress,
+        schedule: &LrSchedule,
+        loader: &DataLoader,
+    ) -> Vec<u8> {
+        let mut training = Encoder::default();
+        training.u64(progress.epoch as u64);
+        training.u64(progress.batch as u64);
+
+        let position = schedule.position();
+        training.u64(position.step as u64);
+        training.u32(position.best_metric.is_some() as u32);
+        training.f64(position.best_metric.unwrap_or_default());
+        training.u64(position.bad_observations as u64);
+        training.f64(position.plateau_scale);
+
+        // the loader has already started the epoch of an unfinished one
+        training.u64(loader.seed());
+        training.u64(loader.epochs() - (progress.batch > 0 && loader.epochs() > 0) as u64);
+
+        training.u32(progress.history.len() as u32);
+        for metrics in progress.history.iter() {
+            training.u32(metrics.len() as u32);
+            for (name, value) in metrics {
+                training.string(name);
+                training.f64(*value);
+            }
+        }
+
+        model_file::encode_with_sections(nn, &[(b"TRNS", &training.0)])
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Checkpoint, Box<dyn Error>> {
+        if !model_file::is_model_file(bytes) {
+            return Err("Not a checkpoint, the magic number is missing".into());
+        }
+        let (network, sections) = model_file::decode_with_sections(bytes)?;
+        let (_, payload) = sections
+            .into_iter()
+            .find(|(tag, _)| tag == b"TRNS")
+            .ok_or("Not a checkpoint, the file has no training progress")?;
+
+        let mut training = Decoder::new(payload);
+        let mut progress = Progress {
+            epoch: training.u64()? as usize,
+            batch: training.u64()? as usize,
+            history: vec![],
+        };
+
+        let step = training.u64()? as usize;
+        let has_best = training.u32()? != 0;
+        let best_metric = training.f64()?;
+        let schedule = SchedulePosition {
+            step,
+            best_metric: if has_best { Some(best_metric) } else { None },
+            bad_observations: training.u64()? as usize,
+            plateau_scale: training.f64()?,
+        };
+
+        let loader = (training.u64()?, training.u64()?);
+
+        for _ in 0..training.u32()? {
+            let mut metrics = vec![];
+            for _ in 0..training.u32()? {
+                metrics.push((training.string()?, training.f64()?));
+            }
+            progress.history.push(metrics);
+        }
+
+        Ok(Checkpoint {
+            network,
+            progress,
+            schedule,
+            loader,
+        })
+    }
+
+    pub fn load(file_path: impl AsRef<Path>) -> Result<Checkpoint, Box<dyn Error>> {
+        Checkpoint::decode(&fs::read(file_path)?)
+    }
+
+    /// Moves `nn`, `schedule` and `loader` to where the checkpoint was taken,
+    /// returning the progress to continue from.
+    ///
+    /// `nn` keeps its regularization and gradient clipping.
+    pub fn resume(
+        self,
+        nn: &mut NeuralNetwork,
+        schedule: &mut LrSchedule,
+        loader: &mut DataLoader,
+    ) -> Progress {
+        let network = self.network;
+        nn._model = network._model;
+        nn._model.set_regularization(nn._regularization);
+        nn._preprocessing = network._preprocessing;
+        nn._metadata = network._metadata;
+
+        schedule.seek(self.schedule);
+        let (seed, epoch) = self.loader;
+        loader.seek(seed, epoch, self.progress.batch);
+        self.progress
+    }
+}
+
+/// Saves checkpoints while training and removes old ones.
+///
+/// Periodic checkpoints are named `checkpoint-{epoch}-{batch}.ckpt`, with the progress
+/// at the time they were taken. The one of the best epoch is kept as `best.ckpt`.
+/// Which checkpoints to remove is decided from the files in the directory,
+/// so a resumed run keeps to the same policy.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::{dataset::{DataSet, data_loader::DataLoader}, neural_network::{
+/// #     checkpoint::{Checkpointer, Progress}, cost_functions::CostFunction,
+/// #     early_stopping::Metric, learning_rate::LrSchedule, NeuralNetwork}};
+/// # fn train(mut nn: NeuralNetwork, ds: DataSet) -> Result<(), Box<dyn std::error::Error>> {
+/// let (cost, mut schedule) = (CostFunction::quadratic(), LrSchedule::constant(1.0));
+/// let mut loader = DataLoader::new(&ds.training_data, 16);
+/// let checkpointer = Checkpointer::new("output/checkpoints")
+///     .every_batches(500)
+///     .keep_last(2)
+///     .keep_best(Metric::Accuracy);
+///
+/// let mut progress = match checkpointer.latest() {
+///     Some(path) => nn.resume_from(path, &mut schedule, &mut loader)?,
+///     None => Progress::default(),
+/// };
+/// while progress.epoch < 30 {
+///     for batch in loader.epoch() {
+///         nn.train_batch(&batch, &mut schedule, &cost, progress.batch)?;
+///         progress.batch += 1;
+///         checkpointer.after_batch(&nn, &progress, &schedule, &loader)?;
+///     }
+///     let evaluation = nn.evaluate(&ds.validation_data, &cost, 3);
+///     progress.end_epoch(Some(&evaluation));
+///     schedule.step_epoch();
+///     checkpointer.after_epoch(&nn, &progress, &schedule, &loader)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Checkpointer {
+    directory: PathBuf,
+    every_batches: usize,
+    keep_last: usize,
+    keep_best: Option<Metric>,
+}
+
+impl Checkpointer {
+    /// saves at the end of every epoch into `directory`, keeping the last 3 checkpoints
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Checkpointer {
+            directory: directory.into(),
+            every_batches: 0,
+            keep_last: 3,
+            keep_best: None,
+        }
+    }
+
+    /// also saves after every `batches` batches of an epoch, `0` only saves at the end of epochs
+    pub fn every_batches(mut self, batches: usize) -> Self {
+        self.every_batches = batches;
+        self
+    }
+
+    /// the number of periodic checkpoints kept, `0` keeps every one
+    pub fn keep_last(mut self, checkpoints: usize) -> Self {
+        self.keep_last = checkpoints;
+        self
+    }
+
+    /// also keeps the checkpoint of the epoch with the best `metric`
+    pub fn keep_best(mut self, metric: Metric) -> Self {
+        self.keep_best = Some(metric);
+        self
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Saves a checkpoint if `progress.batch` is a multiple of the batches between them,
+    /// call after every batch.
+    pub fn after_batch(
+        &self,
+        nn: &NeuralNetwork,
+        progress: &Progress,
+        schedule: &LrSchedule,
+        loader: &DataLoader,
+    ) -> Result<(), io::Error> {
+        if self.every_batches == 0 || !progress.batch.is_multiple_of(self.every_batches) {
+            return Ok(());
+        }
+        self.save_periodic(nn, progress, schedule, loader)
+    }
+
+    /// Saves a checkpoint, and replaces the best one if the epoch improved on it;
+    /// call after [`Progress::end_epoch`] and stepping the schedule.
+    pub fn after_epoch(
+        &self,
+        nn: &NeuralNetwork,
+        progress: &Progress,
+        schedule: &LrSchedule,
+        loader: &DataLoader,
+    ) -> Result<(), io::Error> {
+        self.save_periodic(nn, progress, schedule, loader)?;
+
+        let Some(metric) = self.keep_best else {
+            return Ok(());
+        };
+        let mut values = progress.values(metric);
+        let Some(Some(value)) = values.pop() else {
+            return Ok(());
+        };
+        let best = values.into_iter().flatten().reduce(|best, v| {
+            match metric.mode().improves(v, Some(best), 0.0) {
+                true => v,
+                false => best,
+            }
+        });
+        if metric.mode().improves(value, best, 0.0) {
+            self.write(&self.best_path(), nn, progress, schedule, loader)?;
+        }
+        Ok(())
+    }
+
+    /// the most recent periodic checkpoint
+    pub fn latest(&self) -> Option<PathBuf> {
+        self.checkpoints().pop().map(|(_, path)| path)
+    }
+
+    /// the checkpoint of the best epoch, if one was saved
+    pub fn best(&self) -> Option<PathBuf> {
+        Some(self.best_path()).filter(|path| path.is_file())
+    }
+
+    fn best_path(&self) -> PathBuf {
+        self.directory.join("best.ckpt")
+    }
+
+    fn save_periodic(
+        &self,
+        nn: &NeuralNetwork,
+        progress: &Progress,
+        schedule: &LrSchedule,
+        loader: &DataLoader,
+    ) -> Result<(), io::Error> {
+        let name = format!("checkpoint-{}-{}.ckpt", progress.epoch, progress.batch);
+        self.write(&self.directory.join(name), nn, progress, schedule, loader)?;
+
+        if self.keep_last == 0 {
+            return Ok(());
+        }
+        let checkpoints = self.checkpoints();
+        let outdated = checkpoints.len().saturating_sub(self.keep_last);
+        for (_, path) in checkpoints.into_iter().take(outdated) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// writes to a temporary file first, so an interrupted save leaves the last checkpoint intact
+    fn write(
+        &self,
+        path: &Path,
+        nn: &NeuralNetwork,
+        progress: &Progress,
+        schedule: &LrSchedule,
+        loader: &DataLoader,
+    ) -> Result<(), io::Error> {
+        fs::create_dir_all(&self.directory)?;
+        let temporary = path.with_extension("tmp");
+        fs::write(
+            &temporary,
+            Checkpoint::encode(nn, progress, schedule, loader),
+        )?;
+        fs::rename(temporary, path)
+    }
+
+    /// the periodic checkpoints in the directory, oldest first
+    fn checkpoints(&self) -> Vec<((usize, usize), PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.directory) else {
+            return vec![];
+        };
+
+        let mut checkpoints: Vec<((usize, usize), PathBuf)> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_name()?.to_str()?;
+                let (epoch, batch) = name
+                    .strip_prefix("checkpoint-")?
+                    .strip_suffix(".ckpt")?
+                    .split_once('-')?;
+                Some(((epoch.parse().ok()?, batch.parse().ok()?), path))
+            })
+            .collect();
+        checkpoints.sort();
+        checkpoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoint, Checkpointer, Progress};
+    use crate::{
+        calculus::functions::Function,
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::{data_loader::DataLoader, DataVector},
+            neural_network::{
+                cost_functions::CostFunction,
+                early_stopping::Metric,
+                layers::{
+                    activation::Activation, dense::Dense, dropout::Dropout, sequential::Sequential,
+                },
+                learning_rate::{LrSchedule, Stepping},
+                NeuralNetwork,
+            },
+        },
+    };
+    use std::fs;
+
+    fn network() -> NeuralNetwork {
+        let mut model = Sequential::default();
+        model.push(Dense::random(1, 6));
+        model.push(Activation::new(Function::sigmoid()));
+        model.push(Dropout::new(0.2));
+        model.push(Dense::random(6, 2));
+        model.push(Activation::new(Function::sigmoid()));
+        NeuralNetwork::new(model)
+    }
+
+    fn decaying_schedule() -> LrSchedule {
+        LrSchedule::step_decay(1.0, 7, 0.5).stepped_per(Stepping::Batch)
+    }
+
+    fn train(
+        nn: &mut NeuralNetwork,
+        data: &[DataVector],
+        loader: &mut DataLoader,
+        schedule: &mut LrSchedule,
+        progress: &mut Progress,
+        checkpointer: &Checkpointer,
+    ) {
+        let cost = CostFunction::quadratic();
+        while progress.epoch < 3 {
+            for batch in loader.epoch() {
+                nn.train_batch(&batch, schedule, &cost, progress.batch)
+                    .unwrap();
+                progress.batch += 1;
+                checkpointer
+                    .after_batch(nn, progress, schedule, loader)
+                    .unwrap();
+            }
+            let evaluation = nn.evaluate(data, &cost, 1);
+            progress.end_epoch(Some(&evaluation));
+            schedule.step_epoch();
+            checkpointer
+                .after_epoch(nn, progress, schedule, loader)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_resuming_is_exact() {
+        let data: Vec<DataVector> = (0..40)
+            .map(|x| {
+                let y = x as f64 / 40.0;
+                DataVector::new(Matrix::from_vec(1, 1, vec![y]), (y < 0.5) as usize)
+            })
+            .collect();
+        let (first, second) = ("output/checkpoints-first", "output/checkpoints-second");
+
+        let mut nn = network();
+        let mut loader = DataLoader::new(&data, 4).with_seed(5);
+        let mut schedule = decaying_schedule();
+        let mut progress = Progress::default();
+        let checkpointer = Checkpointer::new(first).every_batches(3).keep_last(0);
+        train(
+            &mut nn,
+            &data,
+            &mut loader,
+            &mut schedule,
+            &mut progress,
+            &checkpointer,
+        );
+
+        // every batch of the uninterrupted run is repeated, with the same shuffling and dropout
+        let mut resumed = network();
+        let mut resumed_loader = DataLoader::new(&data, 4);
+        let mut resumed_schedule = decaying_schedule();
+        let mut resumed_progress = resumed
+            .resume_from(
+                format!("{}/checkpoint-1-6.ckpt", first),
+                &mut resumed_schedule,
+                &mut resumed_loader,
+            )
+            .unwrap();
+        assert_eq!((resumed_progress.epoch, resumed_progress.batch), (1, 6));
+        assert_eq!(resumed_progress.history.len(), 1);
+
+        let checkpointer = Checkpointer::new(second).every_batches(3).keep_last(2);
+        train(
+            &mut resumed,
+            &data,
+            &mut resumed_loader,
+            &mut resumed_schedule,
+            &mut resumed_progress,
+            &checkpointer,
+        );
+
+        assert_eq!(resumed, nn);
+        assert_eq!(resumed_schedule, schedule);
+        assert_eq!(resumed_progress, progress);
+
+        // only the last two checkpoints are kept
+        assert_eq!(fs::read_dir(second).unwrap().count(), 2);
+        let latest = checkpointer.latest().unwrap();
+        assert!(latest.ends_with("checkpoint-3-0.ckpt"));
+        assert_eq!(Checkpoint::load(&latest).unwrap().network, nn);
+
+        fs::remove_dir_all(first).unwrap();
+        fs::remove_dir_all(second).unwrap();
+    }
+
+    #[test]
+    fn test_keeping_the_best_epoch() {
+        let directory = "output/checkpoints-best";
+        let (nn, data) = (network(), vec![]);
+        let (schedule, loader) = (decaying_schedule(), DataLoader::new(&data, 1));
+        let checkpointer = Checkpointer::new(directory).keep_best(Metric::MeanLoss);
+
+        let mut progress = Progress::default();
+        for loss in [0.5, 0.3, 0.4] {
+            progress.history.push(vec![("mean_loss".to_string(), loss)]);
+            progress.epoch += 1;
+            checkpointer
+                .after_epoch(&nn, &progress, &schedule, &loader)
+                .unwrap();
+        }
+
+        let best = checkpointer.best().unwrap();
+        assert_eq!(Checkpoint::load(&best).unwrap().progress.epoch, 2);
+        assert_eq!(progress.values(Metric::MeanLoss)[1], Some(0.3));
+
+        // a checkpoint is also a network file
+        assert_eq!(NeuralNetwork::load(best.to_str().unwrap()).unwrap(), nn);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_network_files_are_not_checkpoints() {
+        let nn = network();
+        let bytes = crate::machine_learning::neural_network::model_file::encode(&nn);
+        let error = Checkpoint::decode(&bytes).unwrap_err().to_string();
+        assert!(error.contains("training progress"), "{}", error);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::linear_algebra::Matrix;
 
 pub struct CostFunction {
+    name: &'static str,
     cost: fn(&Matrix, &Matrix) -> Matrix,
     derivative: fn(&Matrix, &Matrix) -> Matrix,
 }
@@ -13,25 +14,44 @@ impl CostFunction {
     pub fn derive(&self) -> fn(&Matrix, &Matrix) -> Matrix {
         self.derivative
     }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// finds a cost function by the name returned from [`CostFunction::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "quadratic" => Some(CostFunction::quadratic()),
+            _ => None,
+        }
+    }
 }
 
 impl CostFunction {
     pub fn quadratic() -> Self {
         CostFunction {
+            name: "quadratic",
             cost: quadratic_cost,
             derivative: quadratic_cost_derivative,
         }
     }
 }
+
 /// calculates the cost the nueral network; `C = (R - E)^2`
 /// - `C` cost Matrix
 /// - `R - E` Difference of actual result verses expected
-fn quadratic_cost(r: &Matrix, e: &Matrix) -> Matrix {}
+fn quadratic_cost(r: &Matrix, e: &Matrix) -> Matrix {
+    let difference = r.clone() - e;
+    difference.clone().component_mul(&difference)
+}
 
 /// calculates the derivative of the cost; `C' = 2(R - E)`
 /// - `C'` cost derivative Matrix
 /// - `R - E` Difference of actual result verses expected
-fn quadratic_cost_derivative(r: &Matrix, e: &Matrix) -> Matrix {}
+fn quadratic_cost_derivative(r: &Matrix, e: &Matrix) -> Matrix {
+    2.0 * (r.clone() - e)
+}
 
 // /// calculates the cost the nueral network; `C = E * ln(R) + (1 - E) ln(1 - R)`
 // /// - `C` cost Matrix
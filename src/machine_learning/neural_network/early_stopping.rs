@@ -0,0 +1,246 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    evaluation::Evaluation,
+    layers::{Layer, LayerRecord},
+    learning_rate::PlateauMode,
+    NeuralNetwork,
+};
+
+/// A value of an [`Evaluation`] to monitor
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Accuracy,
+    TopKAccuracy,
+    MacroF1,
+    MeanLoss,
+    LogLoss,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::Accuracy,
+        Metric::TopKAccuracy,
+        Metric::MacroF1,
+        Metric::MeanLoss,
+        Metric::LogLoss,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Accuracy => "accuracy",
+            Metric::TopKAccuracy => "top_k_accuracy",
+            Metric::MacroF1 => "macro_f1",
+            Metric::MeanLoss => "mean_loss",
+            Metric::LogLoss => "log_loss",
+        }
+    }
+
+    /// finds a metric by the name returned from [`Metric::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Metric::ALL.into_iter().find(|metric| metric.name() == name)
+    }
+
+    pub fn value(&self, evaluation: &Evaluation) -> f64 {
+        match self {
+            Metric::Accuracy => evaluation.accuracy(),
+            Metric::TopKAccuracy => evaluation.top_k_accuracy().1,
+            Metric::MacroF1 => evaluation.macro_average().2,
+            Metric::MeanLoss => evaluation.mean_loss(),
+            Metric::LogLoss => evaluation.log_loss(),
+        }
+    }
+
+    /// whether the metric should be minimized or maximized
+    pub fn mode(&self) -> PlateauMode {
+        match self {
+            Metric::MeanLoss | Metric::LogLoss => PlateauMode::Min,
+            _ => PlateauMode::Max,
+        }
+    }
+}
+
+/// Stops training once a metric has not improved by more than `min_delta`
+/// for `patience` epochs, then restores the weights of the best epoch.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::{dataset::{DataSet, data_loader::DataLoader}, neural_network::{
+/// #     cost_functions::CostFunction, early_stopping::{EarlyStopping, Metric},
+/// #     learning_rate::LrSchedule, NeuralNetwork}};
+/// # fn train(mut nn: NeuralNetwork, ds: DataSet) -> Result<(), Box<dyn std::error::Error>> {
+/// let (cost, mut schedule) = (CostFunction::quadratic(), LrSchedule::constant(1.0));
+/// let mut early_stopping = EarlyStopping::new(Metric::Accuracy, 3).with_min_delta(0.001);
+/// let mut loader = DataLoader::new(&ds.training_data, 16);
+/// for _ in 0..100 {
+///     nn.train_verbose(&mut loader, &mut schedule, &cost)?;
+///     let evaluation = nn.evaluate(&ds.validation_data, &cost, 3);
+///     if early_stopping.observe(&mut nn, &evaluation)? {
+///         break;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+    metric: Metric,
+    patience: usize,
+    min_delta: f64,
+    restore_best: bool,
+    epoch: usize,
+    bad_epochs: usize,
+    best: Option<(usize, f64)>,
+    best_model: Option<LayerRecord>,
+}
+
+impl EarlyStopping {
+    pub fn new(metric: Metric, patience: usize) -> Self {
+        EarlyStopping {
+            metric,
+            patience,
+            min_delta: 0.0,
+            restore_best: true,
+            epoch: 0,
+            bad_epochs: 0,
+            best: None,
+            best_model: None,
+        }
+    }
+
+    /// smaller changes of the metric are not counted as an improvement
+    pub fn with_min_delta(mut self, min_delta: f64) -> Self {
+        self.min_delta = min_delta.abs();
+        self
+    }
+
+    /// whether the best weights are restored when stopping (default `true`)
+    pub fn restoring_best(mut self, restore_best: bool) -> Self {
+        self.restore_best = restore_best;
+        self
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    /// the epoch (counting from 0) and value of the best observed metric
+    pub fn best(&self) -> Option<(usize, f64)> {
+        self.best
+    }
+
+    /// Reports the evaluation after an epoch, see [`EarlyStopping::observe_value`]
+    pub fn observe(
+        &mut self,
+        network: &mut NeuralNetwork,
+        evaluation: &Evaluation,
+    ) -> Result<bool, Box<dyn Error>> {
+        self.observe_value(network, self.metric.value(evaluation))
+    }
+
+    /// Reports the value of the metric after an epoch.
+    ///
+    /// Returns `true` when training should stop, in which case the best weights
+    /// have already been restored.
+    pub fn observe_value(
+        &mut self,
+        network: &mut NeuralNetwork,
+        value: f64,
+    ) -> Result<bool, Box<dyn Error>> {
+        if self.update(value) {
+            if self.restore_best {
+                self.best_model = Some(network.model().record());
+            }
+            return Ok(false);
+        }
+
+        if self.bad_epochs < self.patience {
+            return Ok(false);
+        }
+
+        self.restore_best(network)?;
+        Ok(true)
+    }
+
+    /// Continues from the values of the metric observed before, e.g. when resuming from a
+    /// checkpoint, with `best_model` as the weights of the best of them.
+    ///
+    /// `None` values, of epochs that were not evaluated, are skipped.
+    pub fn replay(&mut self, values: &[Option<f64>], best_model: Option<LayerRecord>) {
+        for value in values.iter().flatten() {
+            self.update(*value);
+        }
+        if self.restore_best {
+            self.best_model = best_model;
+        }
+    }
+
+    /// counts an observed value, returns whether it improved on the best one
+    fn update(&mut self, value: f64) -> bool {
+        let epoch = self.epoch;
+        self.epoch += 1;
+
+        let best = self.best.map(|(_, best)| best);
+        if self.metric.mode().improves(value, best, self.min_delta) {
+            self.best = Some((epoch, value));
+            self.bad_epochs = 0;
+            return true;
+        }
+        self.bad_epochs += 1;
+        false
+    }
+
+    /// Restores the weights of the best epoch, e.g. when training ran out of epochs
+    /// before stopping early.
+    pub fn restore_best(&self, network: &mut NeuralNetwork) -> Result<(), Box<dyn Error>> {
+        match &self.best_model {
+            Some(record) if self.restore_best => network.restore(record),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EarlyStopping, Metric};
+    use crate::{
+        calculus::functions::Function,
+        machine_learning::neural_network::{layers::Layer, NeuralNetwork},
+    };
+
+    #[test]
+    fn test_early_stopping() {
+        let mut nn = NeuralNetwork::random(vec![2, 3, 2], Function::sigmoid());
+        let mut early_stopping = EarlyStopping::new(Metric::MeanLoss, 2).with_min_delta(0.01);
+
+        assert!(!early_stopping.observe_value(&mut nn, 1.0).unwrap());
+        assert!(!early_stopping.observe_value(&mut nn, 0.5).unwrap());
+        let best = nn.model().record();
+
+        // worse, then too small an improvement
+        nn = NeuralNetwork::random(vec![2, 3, 2], Function::sigmoid());
+        assert!(!early_stopping.observe_value(&mut nn, 0.6).unwrap());
+        assert!(early_stopping.observe_value(&mut nn, 0.495).unwrap());
+
+        assert_eq!(early_stopping.best(), Some((1, 0.5)));
+        assert_eq!(nn.model().record(), best);
+    }
+
+    #[test]
+    fn test_replaying_observed_values() {
+        let mut nn = NeuralNetwork::random(vec![2, 3, 2], Function::sigmoid());
+        let best = nn.model().record();
+
+        // resumed after the 3rd epoch, the 2nd was not evaluated
+        let mut early_stopping = EarlyStopping::new(Metric::Accuracy, 2);
+        early_stopping.replay(&[Some(0.8), None, Some(0.7)], Some(best.clone()));
+        assert_eq!(early_stopping.best(), Some((0, 0.8)));
+
+        nn = NeuralNetwork::random(vec![2, 3, 2], Function::sigmoid());
+        assert!(early_stopping.observe_value(&mut nn, 0.75).unwrap());
+        assert_eq!(nn.model().record(), best);
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Write},
+};
+
+use rayon::prelude::*;
+
+use crate::{linear_algebra::Matrix, machine_learning::dataset::DataVector};
+
+use super::{cost_functions::CostFunction, NeuralNetwork};
+
+/// number of confidence bins used for the expected calibration error
+const CALIBRATION_BINS: usize = 10;
+
+/// A report of how a network performs on a set of samples.
+///
+/// The classification metrics only consider single-label samples
+/// ([`Target::Class`](crate::machine_learning::dataset::Target::Class)); for these the outputs are
+/// treated as class scores and normalized to probabilities summing to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// `confusion[actual][predicted]` counts the samples of class `actual` predicted as `predicted`
+    confusion: Vec<Vec<usize>>,
+    top_k: usize,
+    top_k_correct: usize,
+    samples: usize,
+    total_loss: f64,
+    total_log_loss: f64,
+    /// `(samples, correct, summed confidence)` of every confidence bin
+    calibration: [(usize, usize, f64); CALIBRATION_BINS],
+}
+
+/// A class, and the probability the network gives it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    pub class: usize,
+    pub probability: f64,
+}
+
+/// what a single sample adds to an [`Evaluation`]
+struct Outcome {
+    loss: f64,
+    class: Option<(usize, usize, bool, f64, f64)>,
+}
+
+// NN Methods
+impl NeuralNetwork {
+    /// Checks the targets of `data_set` against [`NeuralNetwork::output_size`],
+    /// training or evaluating on a target that does not fit the outputs panics.
+    pub fn check_targets(&self, data_set: &[DataVector]) -> Result<(), Box<dyn Error>> {
+        let Some(outputs) = self.output_size() else {
+            return Ok(());
+        };
+        for (i, sample) in data_set.iter().enumerate() {
+            sample
+                .target()
+                .check(outputs)
+                .map_err(|e| format!("Sample {}: {}", i, e))?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates the network on `data_set`, top-k accuracy counts a sample as correct
+    /// when its class is among the `top_k` largest outputs.
+    ///
+    /// Panics if a target does not fit the outputs, see [`NeuralNetwork::check_targets`].
+    pub fn evaluate(
+        &self,
+        data_set: &[DataVector],
+        cost_function: &CostFunction,
+        top_k: usize,
+    ) -> Evaluation {
+        let outcomes: Vec<Outcome> = data_set
+            .par_iter()
+            .map(|sample| {
+                let output = self.propagate(&sample.data);
+                let expected = sample.expected_matrix(output.get_dims());
+                let loss = cost_function.calc_cost()(&output, &expected).iter().sum();
+
+                Outcome {
+                    loss,
+                    class: sample.label().map(|label| {
+                        let probabilities = probabilities(&output);
+                        let predicted = output.index_of_max();
+                        let in_top_k =
+                            output.iter().filter(|o| **o > output[(label, 0)]).count() < top_k;
+                        (
+                            label,
+                            predicted,
+                            in_top_k,
+                            probabilities[label],
+                            probabilities[predicted],
+                        )
+                    }),
+                }
+            })
+            .collect();
+
+        let classes = data_set
+            .iter()
+            .filter_map(|d| d.label())
+            .chain(outcomes.iter().filter_map(|o| o.class.map(|c| c.1)))
+            .max()
+            .map_or(0, |c| c + 1);
+
+        let mut evaluation = Evaluation {
+            confusion: vec![vec![0; classes]; classes],
+            top_k,
+            top_k_correct: 0,
+            samples: data_set.len(),
+            total_loss: 0.0,
+            total_log_loss: 0.0,
+            calibration: [(0, 0, 0.0); CALIBRATION_BINS],
+        };
+
+        for outcome in outcomes {
+            evaluation.total_loss += outcome.loss;
+            if let Some((label, predicted, in_top_k, probability, confidence)) = outcome.class {
+                evaluation.confusion[label][predicted] += 1;
+                evaluation.top_k_correct += in_top_k as usize;
+                evaluation.total_log_loss -= probability.max(1e-15).ln();
+
+                let bin =
+                    ((confidence * CALIBRATION_BINS as f64) as usize).min(CALIBRATION_BINS - 1);
+                let (count, correct, total) = &mut evaluation.calibration[bin];
+                *count += 1;
+                *correct += (label == predicted) as usize;
+                *total += confidence;
+            }
+        }
+
+        evaluation
+    }
+}
+
+impl NeuralNetwork {
+    /// The `top_k` most likely classes of an input, most likely first.
+    ///
+    /// The input is transformed by the preprocessing of the network, and the outputs are
+    /// normalized to probabilities like those of an [`Evaluation`].
+    pub fn predict(&self, input: &Matrix, top_k: usize) -> Vec<Prediction> {
+        let output = self.propagate(&self.preprocessing().transform(input));
+        let mut predictions: Vec<Prediction> = probabilities(&output)
+            .into_iter()
+            .enumerate()
+            .map(|(class, probability)| Prediction { class, probability })
+            .collect();
+        predictions.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        predictions.truncate(top_k);
+        predictions
+    }
+}
+
+/// Normalizes the outputs to sum to 1, negative outputs count as 0.
+fn probabilities(output: &Matrix) -> Vec<f64> {
+    let scores: Vec<f64> = output.iter().map(|o| o.max(0.0)).collect();
+    let total: f64 = scores.iter().sum();
+    if total > 0.0 {
+        scores.iter().map(|s| s / total).collect()
+    } else {
+        vec![1.0 / scores.len() as f64; scores.len()]
+    }
+}
+
+impl Evaluation {
+    pub fn classes(&self) -> usize {
+        self.confusion.len()
+    }
+
+    pub fn confusion_matrix(&self) -> &[Vec<usize>] {
+        &self.confusion
+    }
+
+    /// number of single-label samples
+    pub fn classified(&self) -> usize {
+        self.confusion.iter().flatten().sum()
+    }
+
+    fn correct(&self) -> usize {
+        (0..self.classes()).map(|c| self.confusion[c][c]).sum()
+    }
+
+    fn predicted(&self, class: usize) -> usize {
+        self.confusion.iter().map(|row| row[class]).sum()
+    }
+
+    fn support(&self, class: usize) -> usize {
+        self.confusion[class].iter().sum()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.correct(), self.classified())
+    }
+
+    /// the fraction of samples with their class among the `k` largest outputs
+    pub fn top_k_accuracy(&self) -> (usize, f64) {
+        (self.top_k, ratio(self.top_k_correct, self.classified()))
+    }
+
+    /// the mean cost of all samples
+    pub fn mean_loss(&self) -> f64 {
+        self.total_loss / self.samples.max(1) as f64
+    }
+
+    /// the mean negative log-probability of the correct class
+    pub fn log_loss(&self) -> f64 {
+        self.total_log_loss / self.classified().max(1) as f64
+    }
+
+    /// Expected calibration error: how far the confidence of the predictions is from
+    /// their accuracy, averaged over confidence bins.
+    pub fn expected_calibration_error(&self) -> f64 {
+        let samples = self.classified().max(1) as f64;
+        self.calibration
+            .iter()
+            .filter(|(count, _, _)| *count > 0)
+            .map(|(_, correct, confidence)| (*correct as f64 - confidence).abs() / samples)
+            .sum()
+    }
+
+    /// the fraction of predictions of `class` that were correct
+    pub fn precision(&self, class: usize) -> f64 {
+        ratio(self.confusion[class][class], self.predicted(class))
+    }
+
+    /// the fraction of samples of `class` that were predicted correctly
+    pub fn recall(&self, class: usize) -> f64 {
+        ratio(self.confusion[class][class], self.support(class))
+    }
+
+    pub fn f1(&self, class: usize) -> f64 {
+        harmonic_mean(self.precision(class), self.recall(class))
+    }
+
+    /// `(precision, recall, f1)` averaged over the classes
+    pub fn macro_average(&self) -> (f64, f64, f64) {
+        let classes = self.classes().max(1) as f64;
+        let mean =
+            |metric: &dyn Fn(usize) -> f64| (0..self.classes()).map(metric).sum::<f64>() / classes;
+        (
+            mean(&|c| self.precision(c)),
+            mean(&|c| self.recall(c)),
+            mean(&|c| self.f1(c)),
+        )
+    }
+
+    /// `(precision, recall, f1)` over all predictions at once
+    ///
+    /// Every sample has exactly one predicted class, so all three equal the accuracy.
+    pub fn micro_average(&self) -> (f64, f64, f64) {
+        let accuracy = self.accuracy();
+        (accuracy, accuracy, accuracy)
+    }
+
+    /// The confusion matrix and per-class metrics as a table, with the classes named by `label`.
+    ///
+    /// ```no_run
+    /// # use mathematics::machine_learning::{dataset::mnist::letter_from_number, neural_network::evaluation::Evaluation};
+    /// # fn print(evaluation: Evaluation) {
+    /// let letter = |class: usize| letter_from_number(class).unwrap_or('?').to_string();
+    /// println!("{}", evaluation.table(letter));
+    /// # }
+    /// ```
+    pub fn table(&self, label: impl Fn(usize) -> String) -> String {
+        let labels: Vec<String> = (0..self.classes()).map(label).collect();
+        let width = labels
+            .iter()
+            .map(|l| l.len())
+            .chain(self.confusion.iter().flatten().map(|c| c.to_string().len()))
+            .max()
+            .unwrap_or(1)
+            + 1;
+
+        let mut table = String::new();
+        let _ = write!(table, "{:>width$} |", "");
+        for l in labels.iter() {
+            let _ = write!(table, "{:>width$}", l);
+        }
+        let _ = writeln!(table, " | precision    recall        f1   support");
+
+        for (class, row) in self.confusion.iter().enumerate() {
+            let _ = write!(table, "{:>width$} |", labels[class]);
+            for count in row {
+                let _ = write!(table, "{:>width$}", count);
+            }
+            let _ = writeln!(
+                table,
+                " | {:>9.4} {:>9.4} {:>9.4} {:>9}",
+                self.precision(class),
+                self.recall(class),
+                self.f1(class),
+                self.support(class)
+            );
+        }
+
+        let (precision, recall, f1) = self.macro_average();
+        let _ = writeln!(
+            table,
+            "\nmacro average: precision {:.4} - recall {:.4} - f1 {:.4}",
+            precision, recall, f1
+        );
+        let _ = write!(table, "{}", self);
+        table
+    }
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (k, top_k) = self.top_k_accuracy();
+        writeln!(
+            f,
+            "accuracy {:.4} - top-{} accuracy {:.4} - micro f1 {:.4} - macro f1 {:.4}",
+            self.accuracy(),
+            k,
+            top_k,
+            self.micro_average().2,
+            self.macro_average().2
+        )?;
+        writeln!(
+            f,
+            "mean loss {:.4} - log-loss {:.4} - calibration error {:.4}",
+            self.mean_loss(),
+            self.log_loss(),
+            self.expected_calibration_error()
+        )
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+fn harmonic_mean(a: f64, b: f64) -> f64 {
+    if a + b == 0.0 {
+        0.0
+    } else {
+        2.0 * a * b / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        calculus::functions::Function,
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::DataVector,
+            neural_network::{
+                cost_functions::CostFunction,
+                layers::{activation::Activation, dense::Dense, sequential::Sequential},
+                NeuralNetwork,
+            },
+        },
+    };
+
+    #[test]
+    fn test_evaluation() {
+        // the network outputs its two inputs (plus a constant 0.1 for a third class)
+        let mut model = Sequential::default();
+        model.push(Dense::new(
+            Matrix::from_vec(3, 2, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            Matrix::from_vec(3, 1, vec![0.0, 0.0, 0.1]),
+        ));
+        model.push(Activation::new(Function::relu()));
+        let nn = NeuralNetwork::new(model);
+
+        let sample =
+            |a: f64, b: f64, label| DataVector::new(Matrix::from_vec(2, 1, vec![a, b]), label);
+        let data = vec![
+            sample(0.9, 0.0, 0),
+            sample(0.8, 0.1, 0),
+            sample(0.6, 0.3, 1),
+            sample(0.0, 0.9, 1),
+            sample(0.7, 0.2, 2),
+        ];
+        assert!(nn.check_targets(&data).is_ok());
+        assert!(nn.check_targets(&[sample(0.5, 0.5, 3)]).is_err());
+
+        let evaluation = nn.evaluate(&data, &CostFunction::quadratic(), 2);
+        assert_eq!(
+            evaluation.confusion_matrix(),
+            &[vec![2, 0, 0], vec![1, 1, 0], vec![1, 0, 0]]
+        );
+        assert_eq!(evaluation.accuracy(), 0.6);
+        assert_eq!(evaluation.top_k_accuracy(), (2, 0.8));
+
+        assert_eq!(evaluation.precision(0), 0.5);
+        assert_eq!(evaluation.recall(0), 1.0);
+        assert_eq!(evaluation.recall(1), 0.5);
+        assert_eq!(evaluation.f1(2), 0.0);
+        assert!((evaluation.macro_average().2 - (2.0 / 3.0 + 2.0 / 3.0) / 3.0).abs() < 1e-12);
+        assert_eq!(evaluation.micro_average(), (0.6, 0.6, 0.6));
+
+        assert!(evaluation.log_loss() > 0.0);
+        let ece = evaluation.expected_calibration_error();
+        assert!((0.0..=1.0).contains(&ece));
+
+        let table = evaluation.table(|c| ["x", "y", "z"][c].to_string());
+        assert!(table.lines().nth(1).unwrap().starts_with(" x | 2 0 0 |"));
+    }
+
+    #[test]
+    fn test_predictions() {
+        let mut model = Sequential::default();
+        model.push(Dense::new(
+            Matrix::from_vec(3, 2, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            Matrix::from_vec(3, 1, vec![0.0, 0.0, 0.1]),
+        ));
+        model.push(Activation::new(Function::relu()));
+        let nn = NeuralNetwork::new(model);
+
+        let predictions = nn.predict(&Matrix::from_vec(2, 1, vec![0.3, 0.6]), 2);
+        assert_eq!(predictions.len(), 2);
+        assert_eq!(predictions[0].class, 1);
+        assert!((predictions[0].probability - 0.6).abs() < 1e-12);
+        assert_eq!(predictions[1].class, 0);
+        assert!((predictions[1].probability - 0.3).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::linear_algebra::Matrix;
+
+/// Limits the size of the gradient before every step.
+///
+/// The thresholds must be positive and finite, [`GradientClipping::value`] and
+/// [`GradientClipping::global_norm`] check them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GradientClipping {
+    #[default]
+    None,
+    /// clamps every value of the gradient to `[-v, v]`
+    Value(f64),
+    /// rescales the whole gradient when its L2 norm exceeds the given norm
+    GlobalNorm(f64),
+}
+
+impl GradientClipping {
+    pub fn value(max: f64) -> Result<Self, String> {
+        positive("clipping value", max).map(GradientClipping::Value)
+    }
+
+    pub fn global_norm(max_norm: f64) -> Result<Self, String> {
+        positive("clipping norm", max_norm).map(GradientClipping::GlobalNorm)
+    }
+
+    /// Clips `gradients` that were summed over `batch_size` samples,
+    /// the thresholds apply to the averaged gradient.
+    ///
+    /// Returns the global norm of the averaged gradient prior to clipping.
+    pub fn clip(&self, gradients: Vec<&mut Matrix>, batch_size: f64) -> f64 {
+        let batch_size = batch_size.max(1.0);
+        let norm = gradients
+            .iter()
+            .flat_map(|g| g.iter())
+            .map(|v| (v / batch_size).powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        match *self {
+            GradientClipping::None => {}
+
+            GradientClipping::Value(max) => {
+                let max = max * batch_size;
+                for g in gradients {
+                    g.iter_mut().for_each(|v| *v = v.clamp(-max, max));
+                }
+            }
+
+            GradientClipping::GlobalNorm(max_norm) => {
+                if norm > max_norm {
+                    let scale = max_norm / norm;
+                    for g in gradients {
+                        g.iter_mut().for_each(|v| *v *= scale);
+                    }
+                }
+            }
+        }
+
+        norm
+    }
+}
+
+fn positive(name: &str, value: f64) -> Result<f64, String> {
+    match value > 0.0 && value.is_finite() {
+        true => Ok(value),
+        false => Err(format!(
+            "The {} must be a positive number, not {}",
+            name, value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GradientClipping;
+    use crate::linear_algebra::Matrix;
+
+    #[test]
+    fn test_value_clipping() {
+        let mut a = Matrix::from_vec(3, 1, vec![4.0, -10.0, 1.0]);
+        let norm = GradientClipping::Value(1.0).clip(vec![&mut a], 2.0);
+
+        assert!((norm - (4.0f64 + 25.0 + 0.25).sqrt()).abs() < 1e-12);
+        assert_eq!(a, Matrix::from_vec(3, 1, vec![2.0, -2.0, 1.0]));
+    }
+
+    #[test]
+    fn test_global_norm_clipping() {
+        let mut a = Matrix::from_vec(1, 1, vec![3.0]);
+        let mut b = Matrix::from_vec(1, 1, vec![-4.0]);
+        let norm = GradientClipping::GlobalNorm(1.0).clip(vec![&mut a, &mut b], 1.0);
+
+        assert_eq!(norm, 5.0);
+        assert!((a[(0, 0)] - 0.6).abs() < 1e-12);
+        assert!((b[(0, 0)] + 0.8).abs() < 1e-12);
+
+        // gradients within the norm are left untouched
+        let mut a = Matrix::from_vec(1, 1, vec![0.5]);
+        GradientClipping::GlobalNorm(1.0).clip(vec![&mut a], 1.0);
+        assert_eq!(a[(0, 0)], 0.5);
+    }
+
+    #[test]
+    fn test_thresholds() {
+        assert_eq!(
+            GradientClipping::value(2.0),
+            Ok(GradientClipping::Value(2.0))
+        );
+        for max in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(GradientClipping::value(max).is_err());
+            assert!(GradientClipping::global_norm(max).is_err());
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+
+use crate::{calculus::functions::Function, linear_algebra::Matrix};
+
+use super::{Layer, LayerRecord};
+
+/// Applies an activation function to every node
+#[derive(Debug, Clone)]
+pub struct Activation {
+    function: Function,
+    input: Option<Matrix>,
+}
+
+impl Activation {
+    pub fn new(function: Function) -> Self {
+        Activation {
+            function,
+            input: None,
+        }
+    }
+
+    pub fn function(&self) -> &Function {
+        &self.function
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        let name: String = record.get_config("function")?;
+        let function =
+            Function::from_name(&name).ok_or(format!("Unknown activation function `{}`", name))?;
+        Ok(Activation::new(function))
+    }
+}
+
+impl Layer for Activation {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        input.clone().apply_into(self.function.activate())
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        self.input = Some(input.clone());
+        self.forward(input)
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        self.input
+            .clone()
+            .expect("backward called before forward_train")
+            .apply_into(self.function.derive())
+            .component_mul(output_gradient)
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("activation").with_config("function", self.function.name())
+    }
+}
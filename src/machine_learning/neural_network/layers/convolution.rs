@@ -0,0 +1,403 @@
+use std::error::Error;
+
+use rand::distributions::Uniform;
+
+use crate::{
+    linear_algebra::Matrix, machine_learning::neural_network::regularization::Regularization,
+};
+
+use super::{Layer, LayerRecord};
+
+/// The dimensions of the images passed between image layers.
+///
+/// Each column of a batch holds one image, stored channel by channel
+/// with every channel stored row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageShape {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl ImageShape {
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        ImageShape {
+            channels,
+            height,
+            width,
+        }
+    }
+
+    /// number of values in one image
+    pub fn size(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+
+    /// position of a pixel within the column of an image
+    pub fn index(&self, channel: usize, y: usize, x: usize) -> usize {
+        (channel * self.height + y) * self.width + x
+    }
+
+    /// The shape after sliding a `kernel_size` window with the given stride and
+    /// zero padding over the image, keeping the number of channels.
+    pub fn windowed(&self, kernel_size: usize, stride: usize, padding: usize) -> Option<Self> {
+        let fits = |length: usize| {
+            (length + 2 * padding)
+                .checked_sub(kernel_size)
+                .map(|l| l / stride + 1)
+        };
+
+        if stride == 0 || kernel_size == 0 {
+            return None;
+        }
+
+        Some(ImageShape::new(
+            self.channels,
+            fits(self.height)?,
+            fits(self.width)?,
+        ))
+    }
+
+    pub(super) fn add_to_record(&self, record: LayerRecord) -> LayerRecord {
+        record
+            .with_config("channels", self.channels)
+            .with_config("height", self.height)
+            .with_config("width", self.width)
+    }
+
+    pub(super) fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Ok(ImageShape::new(
+            record.get_config("channels")?,
+            record.get_config("height")?,
+            record.get_config("width")?,
+        ))
+    }
+}
+
+/// A 2-D convolution over images of `input_shape`.
+///
+/// The patches under the kernel are unrolled into the columns of a matrix (im2col),
+/// so a whole batch is convolved with a single matrix multiplication.
+#[derive(Debug, Clone)]
+pub struct Conv2D {
+    input_shape: ImageShape,
+    output_shape: ImageShape,
+    kernel_size: usize,
+    stride: usize,
+    padding: usize,
+    /// one row per output channel, the columns follow the im2col rows
+    weights: Matrix,
+    biases: Matrix,
+    weight_gradient: Matrix,
+    bias_gradient: Matrix,
+    regularization: Regularization,
+    columns: Option<Matrix>,
+}
+
+// Conv2D constructors
+impl Conv2D {
+    /// `weights` has one row per output channel, holding the kernels of every input
+    /// channel; `biases` has one row per output channel.
+    pub fn new(
+        input_shape: ImageShape,
+        kernel_size: usize,
+        stride: usize,
+        padding: usize,
+        weights: Matrix,
+        biases: Matrix,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (out_channels, patch_size) = weights.get_dims();
+        if patch_size != input_shape.channels * kernel_size * kernel_size {
+            return Err(format!(
+                "convolution weights have {} columns, expected {}",
+                patch_size,
+                input_shape.channels * kernel_size * kernel_size
+            )
+            .into());
+        }
+        if biases.get_dims() != (out_channels, 1) {
+            return Err("convolution biases must have one row per output channel".into());
+        }
+
+        let mut output_shape = input_shape
+            .windowed(kernel_size, stride, padding)
+            .ok_or("convolution kernel does not fit the input")?;
+        output_shape.channels = out_channels;
+
+        Ok(Conv2D {
+            input_shape,
+            output_shape,
+            kernel_size,
+            stride,
+            padding,
+            weight_gradient: Matrix::zeros(out_channels, patch_size),
+            bias_gradient: Matrix::zeros(out_channels, 1),
+            weights,
+            biases,
+            regularization: Regularization::default(),
+            columns: None,
+        })
+    }
+
+    /// The weights are sampled uniformly from `±sqrt(6 / fan_in)` (He initialization)
+    /// and the biases start at zero.
+    ///
+    /// # Panics
+    /// if the kernel does not fit the padded input or the stride is zero
+    pub fn random(
+        input_shape: ImageShape,
+        out_channels: usize,
+        kernel_size: usize,
+        stride: usize,
+        padding: usize,
+    ) -> Self {
+        let fan_in = input_shape.channels * kernel_size * kernel_size;
+        let bound = (6.0 / fan_in as f64).sqrt();
+        Conv2D::new(
+            input_shape,
+            kernel_size,
+            stride,
+            padding,
+            Matrix::from_distribution(out_channels, fan_in, &Uniform::new(-bound, bound)),
+            Matrix::zeros(out_channels, 1),
+        )
+        .unwrap()
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Conv2D::new(
+            ImageShape::from_record(record)?,
+            record.get_config("kernel_size")?,
+            record.get_config("stride")?,
+            record.get_config("padding")?,
+            record.get_state(0)?.clone(),
+            record.get_state(1)?.clone(),
+        )
+    }
+}
+
+impl Conv2D {
+    pub fn weights(&self) -> &Matrix {
+        &self.weights
+    }
+
+    pub fn biases(&self) -> &Matrix {
+        &self.biases
+    }
+
+    pub fn input_shape(&self) -> ImageShape {
+        self.input_shape
+    }
+
+    pub fn output_shape(&self) -> ImageShape {
+        self.output_shape
+    }
+
+    /// Unrolls every patch under the kernel into a column.
+    ///
+    /// Row `(c, ky, kx)` holds the pixel at offset `(ky, kx)` of channel `c`,
+    /// and column `n * positions + (oy, ox)` the patch at output `(oy, ox)` of sample `n`.
+    fn im2col(&self, input: &Matrix) -> Matrix {
+        let batch_size = input.get_dims().1;
+        let positions = self.output_positions();
+        let mut columns = Matrix::zeros(self.weights.get_dims().1, batch_size * positions);
+
+        for n in 0..batch_size {
+            self.for_each_tap(|row, position, pixel| {
+                columns[(row, n * positions + position)] = input[(pixel, n)];
+            });
+        }
+
+        columns
+    }
+
+    /// Adds every entry of the unrolled patches back to the pixel it was taken from,
+    /// the inverse of [`Conv2D::im2col`].
+    fn col2im(&self, columns: &Matrix, batch_size: usize) -> Matrix {
+        let positions = self.output_positions();
+        let mut image = Matrix::zeros(self.input_shape.size(), batch_size);
+
+        for n in 0..batch_size {
+            self.for_each_tap(|row, position, pixel| {
+                image[(pixel, n)] += columns[(row, n * positions + position)];
+            });
+        }
+
+        image
+    }
+
+    /// Calls `f(row, position, pixel)` for every kernel tap that lands inside the image,
+    /// taps on the zero padding are skipped.
+    fn for_each_tap(&self, mut f: impl FnMut(usize, usize, usize)) {
+        let (k, s, p) = (self.kernel_size, self.stride, self.padding);
+        let (input, output) = (self.input_shape, self.output_shape);
+
+        for c in 0..input.channels {
+            for ky in 0..k {
+                for kx in 0..k {
+                    let row = (c * k + ky) * k + kx;
+                    for oy in 0..output.height {
+                        let y = oy * s + ky;
+                        if y < p || y - p >= input.height {
+                            continue;
+                        }
+                        for ox in 0..output.width {
+                            let x = ox * s + kx;
+                            if x < p || x - p >= input.width {
+                                continue;
+                            }
+                            f(row, oy * output.width + ox, input.index(c, y - p, x - p));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn output_positions(&self) -> usize {
+        self.output_shape.height * self.output_shape.width
+    }
+
+    fn convolve(&self, columns: &Matrix, batch_size: usize) -> Matrix {
+        // one row per output channel, one column per (sample, position)
+        let convolved = (&self.weights * columns).add_to_columns(&self.biases);
+
+        let positions = self.output_positions();
+        let mut output = Matrix::zeros(self.output_shape.size(), batch_size);
+        for n in 0..batch_size {
+            for o in 0..self.output_shape.channels {
+                for position in 0..positions {
+                    output[(o * positions + position, n)] =
+                        convolved[(o, n * positions + position)];
+                }
+            }
+        }
+        output
+    }
+}
+
+impl Layer for Conv2D {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        self.convolve(&self.im2col(input), input.get_dims().1)
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        let columns = self.im2col(input);
+        let output = self.convolve(&columns, input.get_dims().1);
+        self.columns = Some(columns);
+        output
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let columns = self
+            .columns
+            .as_ref()
+            .expect("backward called before forward_train");
+        let batch_size = output_gradient.get_dims().1;
+        let positions = self.output_positions();
+
+        // rearrange to match the convolved matrix
+        let mut gradient = Matrix::zeros(self.output_shape.channels, batch_size * positions);
+        for n in 0..batch_size {
+            for o in 0..self.output_shape.channels {
+                for position in 0..positions {
+                    gradient[(o, n * positions + position)] =
+                        output_gradient[(o * positions + position, n)];
+                }
+            }
+        }
+
+        self.weight_gradient += &gradient * columns.transpose();
+        self.bias_gradient += gradient.sum_columns();
+        self.weight_gradient += batch_size as f64 * self.regularization.gradient(&self.weights);
+
+        self.col2im(&(self.weights.transpose() * &gradient), batch_size)
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.weights, &self.biases]
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![&self.weight_gradient, &self.bias_gradient]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        vec![
+            (&mut self.weights, &mut self.weight_gradient),
+            (&mut self.biases, &mut self.bias_gradient),
+        ]
+    }
+
+    fn set_regularization(&mut self, regularization: Regularization) {
+        self.regularization = regularization;
+    }
+
+    fn apply_constraints(&mut self) {
+        self.regularization.constrain(&mut self.weights);
+    }
+
+    fn record(&self) -> LayerRecord {
+        self.input_shape
+            .add_to_record(LayerRecord::new("conv2d"))
+            .with_config("kernel_size", self.kernel_size)
+            .with_config("stride", self.stride)
+            .with_config("padding", self.padding)
+            .with_state(self.weights.clone())
+            .with_state(self.biases.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::{Conv2D, ImageShape};
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::neural_network::layers::{gradient_check::check_gradients, Layer},
+    };
+
+    #[test]
+    fn test_conv2d_forward() {
+        // a single 3x3 image convolved with a 2x2 kernel that sums the window
+        let input = Matrix::from_vec(9, 1, (1..=9).map(|v| v as f64).collect());
+        let conv = Conv2D::new(
+            ImageShape::new(1, 3, 3),
+            2,
+            1,
+            0,
+            Matrix::from_value(1, 4, 1.0),
+            Matrix::from_value(1, 1, 0.5),
+        )
+        .unwrap();
+
+        assert_eq!(conv.output_shape(), ImageShape::new(1, 2, 2));
+        assert_eq!(
+            conv.forward(&input),
+            Matrix::from_vec(4, 1, vec![12.5, 16.5, 24.5, 28.5])
+        );
+
+        // zero padding keeps the size with a stride of 1
+        let padded = Conv2D::random(ImageShape::new(1, 3, 3), 4, 3, 1, 1);
+        assert_eq!(padded.output_shape(), ImageShape::new(4, 3, 3));
+        assert!(Conv2D::new(
+            ImageShape::new(1, 3, 3),
+            4,
+            1,
+            0,
+            Matrix::zeros(1, 16),
+            Matrix::zeros(1, 1)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_conv2d_gradients() {
+        let shape = ImageShape::new(2, 5, 4);
+        let input = Matrix::from_distribution(shape.size(), 3, &Uniform::new(-1.0, 1.0));
+
+        check_gradients(&mut Conv2D::random(shape, 3, 3, 1, 0), &input);
+        check_gradients(&mut Conv2D::random(shape, 2, 3, 2, 1), &input);
+    }
+}
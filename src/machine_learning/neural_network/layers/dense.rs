@@ -0,0 +1,131 @@
+use std::error::Error;
+
+use rand::distributions::Uniform;
+
+use crate::{
+    linear_algebra::Matrix, machine_learning::neural_network::regularization::Regularization,
+};
+
+use super::{Layer, LayerRecord};
+
+/// A fully-connected layer; `output = weights * input + biases`
+#[derive(Debug, Clone)]
+pub struct Dense {
+    weights: Matrix,
+    biases: Matrix,
+    weight_gradient: Matrix,
+    bias_gradient: Matrix,
+    regularization: Regularization,
+    input: Option<Matrix>,
+}
+
+// Dense contructors
+impl Dense {
+    pub fn new(weights: Matrix, biases: Matrix) -> Self {
+        let (r, c) = weights.get_dims();
+        Dense {
+            weight_gradient: Matrix::zeros(r, c),
+            bias_gradient: Matrix::zeros(r, 1),
+            weights,
+            biases,
+            regularization: Regularization::default(),
+            input: None,
+        }
+    }
+
+    /// weights and biases are sampled uniformly from `[-1, 1)`
+    pub fn random(input_size: usize, output_size: usize) -> Self {
+        let distribution = Uniform::new(-1.0, 1.0);
+        Dense::new(
+            Matrix::from_distribution(output_size, input_size, &distribution),
+            Matrix::from_distribution(output_size, 1, &distribution),
+        )
+    }
+
+    pub fn scalar(input_size: usize, output_size: usize, scale: f64) -> Self {
+        Dense::new(
+            Matrix::from_value(output_size, input_size, scale),
+            Matrix::from_value(output_size, 1, scale),
+        )
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        let (weights, biases) = (record.get_state(0)?, record.get_state(1)?);
+        if weights.get_dims().0 != biases.get_dims().0 {
+            return Err("dense layer weights and biases have a different number of rows".into());
+        }
+        Ok(Dense::new(weights.clone(), biases.clone()))
+    }
+}
+
+impl Dense {
+    pub fn weights(&self) -> &Matrix {
+        &self.weights
+    }
+
+    pub fn biases(&self) -> &Matrix {
+        &self.biases
+    }
+
+    /// `(input_size, output_size)`
+    pub fn get_dims(&self) -> (usize, usize) {
+        let (r, c) = self.weights.get_dims();
+        (c, r)
+    }
+}
+
+impl Layer for Dense {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        (&self.weights * input).add_to_columns(&self.biases)
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        self.input = Some(input.clone());
+        self.forward(input)
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let input = self
+            .input
+            .as_ref()
+            .expect("backward called before forward_train");
+        let batch_size = input.get_dims().1 as f64;
+
+        self.weight_gradient += output_gradient * input.transpose();
+        self.bias_gradient += output_gradient.sum_columns();
+
+        // the penalties are added once per sample, since the step is averaged over the batch
+        self.weight_gradient += batch_size * self.regularization.gradient(&self.weights);
+
+        self.weights.transpose() * output_gradient
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.weights, &self.biases]
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![&self.weight_gradient, &self.bias_gradient]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        vec![
+            (&mut self.weights, &mut self.weight_gradient),
+            (&mut self.biases, &mut self.bias_gradient),
+        ]
+    }
+
+    fn set_regularization(&mut self, regularization: Regularization) {
+        self.regularization = regularization;
+    }
+
+    fn apply_constraints(&mut self) {
+        self.regularization.constrain(&mut self.weights);
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("dense")
+            .with_state(self.weights.clone())
+            .with_state(self.biases.clone())
+    }
+}
@@ -0,0 +1,160 @@
+use std::error::Error;
+
+use rand::{distributions::Bernoulli, rngs::StdRng, Rng, SeedableRng};
+
+use crate::{linear_algebra::Matrix, machine_learning::neural_network::Mode};
+
+use super::{Layer, LayerRecord};
+
+/// Inverted dropout; during training each node is zeroed with probability `rate`
+/// and the remaining nodes are scaled by `1 / (1 - rate)`, so nothing
+/// needs to be rescaled during evaluation.
+///
+/// The masks are drawn from `seed` and the number of masks drawn so far,
+/// both are saved with the layer so resumed training drops the same nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dropout {
+    rate: f64,
+    seed: u64,
+    draws: u64,
+    mask: Option<Matrix>,
+}
+
+impl Dropout {
+    /// `rate` is clamped to 0-0.99, [`NetworkConfig::build`] rejects rates outside of 0-1
+    ///
+    /// [`NetworkConfig::build`]: crate::machine_learning::config::NetworkConfig::build
+    pub fn new(rate: f64) -> Self {
+        Dropout {
+            rate: rate.clamp(0.0, 0.99),
+            seed: rand::random(),
+            draws: 0,
+            mask: None,
+        }
+    }
+
+    /// makes the masks reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.draws = 0;
+        self
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        let mut dropout = Dropout::new(record.get_config("rate")?);
+        // records written before the masks were seeded keep a random seed
+        if let (Ok(seed), Ok(draws)) = (record.get_config("seed"), record.get_config("draws")) {
+            dropout.seed = seed;
+            dropout.draws = draws;
+        }
+        Ok(dropout)
+    }
+
+    /// Samples a dropout mask to multiply the nodes with.
+    ///
+    /// Returns `None` when evaluating or when nothing would be dropped.
+    pub fn mask(&self, dims: (usize, usize), mode: Mode) -> Option<Matrix> {
+        if mode == Mode::Eval || self.rate == 0.0 {
+            return None;
+        }
+
+        let keep = 1.0 - self.rate;
+        let distribution = Bernoulli::new(keep).ok()?;
+        let mut rng =
+            StdRng::seed_from_u64(self.seed ^ self.draws.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        Some(Matrix::from_iterator(
+            dims.0,
+            dims.1,
+            &mut (0..dims.0 * dims.1).map(|_| {
+                if rng.sample(distribution) {
+                    1.0 / keep
+                } else {
+                    0.0
+                }
+            }),
+        ))
+    }
+}
+
+impl Default for Dropout {
+    fn default() -> Self {
+        Dropout::new(0.0)
+    }
+}
+
+impl Layer for Dropout {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        input.clone()
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        self.mask = self.mask(input.get_dims(), Mode::Train);
+        self.draws += 1;
+        match &self.mask {
+            Some(mask) => input.clone().component_mul(mask),
+            None => input.clone(),
+        }
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        // only the nodes kept by dropout contributed
+        match &self.mask {
+            Some(mask) => output_gradient.clone().component_mul(mask),
+            None => output_gradient.clone(),
+        }
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("dropout")
+            .with_config("rate", self.rate)
+            .with_config("seed", self.seed)
+            .with_config("draws", self.draws)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dropout;
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::neural_network::{layers::Layer, Mode},
+    };
+
+    #[test]
+    fn test_dropout_mask() {
+        let d = Dropout::new(0.5);
+
+        assert!(d.mask((100, 1), Mode::Eval).is_none());
+        assert!(Dropout::default().mask((100, 1), Mode::Train).is_none());
+
+        let mask = d.mask((1000, 1), Mode::Train).unwrap();
+        assert!(mask.iter().all(|m| *m == 0.0 || *m == 2.0));
+
+        // the expected value of every node is unchanged
+        let mean = mask.iter().sum::<f64>() / 1000.0;
+        assert!((mean - 1.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_dropout_train_and_eval() {
+        let mut d = Dropout::new(0.5);
+        let input = Matrix::from_value(10, 4, 1.0);
+
+        assert_eq!(d.forward(&input), input);
+
+        let dropped = d.forward_train(&input);
+        let gradient = d.backward(&Matrix::from_value(10, 4, 1.0));
+        assert_eq!(dropped, gradient);
+
+        // the next mask is a different one, reproduced from the seed
+        let mut a = Dropout::new(0.5).with_seed(3);
+        let mut b = Dropout::new(0.5).with_seed(3);
+        let first = a.forward_train(&input);
+        assert_ne!(a.forward_train(&input), first);
+        assert_eq!(b.forward_train(&input), first);
+    }
+}
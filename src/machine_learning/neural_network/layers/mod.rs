@@ -0,0 +1,246 @@
+use std::{error::Error, fmt::Debug, str::FromStr};
+
+use crate::linear_algebra::Matrix;
+
+use super::regularization::Regularization;
+
+pub mod activation;
+pub mod convolution;
+pub mod dense;
+pub mod dropout;
+pub mod normalization;
+pub mod pooling;
+pub mod recurrent;
+pub mod sequential;
+
+use activation::Activation;
+use convolution::Conv2D;
+use dense::Dense;
+use dropout::Dropout;
+use normalization::{BatchNorm1d, BatchNorm2d, LayerNorm};
+use pooling::{AvgPool2D, MaxPool2D};
+use recurrent::{Gru, Lstm, Rnn};
+use sequential::Sequential;
+
+/// A building block of a network.
+///
+/// Every column of the matrices passed between layers is a separate sample,
+/// so a whole batch is propagated at once.
+pub trait Layer: Debug + Send + Sync {
+    /// evaluates the layer (`Mode::Eval`)
+    fn forward(&self, input: &Matrix) -> Matrix;
+
+    /// evaluates the layer while training (`Mode::Train`),
+    /// caching whatever [`Layer::backward`] needs
+    fn forward_train(&mut self, input: &Matrix) -> Matrix;
+
+    /// Takes the derivative of the cost with respect to the output of the last
+    /// [`Layer::forward_train`] call, adds the parameter gradients of the batch to
+    /// [`Layer::gradients`] and returns the derivative with respect to the input.
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix;
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![]
+    }
+
+    /// gradients accumulated by [`Layer::backward`], in the same order as [`Layer::parameters`]
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![]
+    }
+
+    /// pairs of `(parameter, gradient)`
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        vec![]
+    }
+
+    fn zero_gradients(&mut self) {
+        for (_, gradient) in self.parameters_mut() {
+            gradient.iter_mut().for_each(|g| *g = 0.0);
+        }
+    }
+
+    /// layers with weights add the penalties to their gradients
+    fn set_regularization(&mut self, _regularization: Regularization) {}
+
+    /// applies weight constraints, called after every step
+    fn apply_constraints(&mut self) {}
+
+    /// describes the layer so it can be saved and rebuilt with [`layer_from_record`]
+    fn record(&self) -> LayerRecord;
+}
+
+/// The type, configuration, and state (parameters and buffers) of a layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerRecord {
+    pub kind: String,
+    pub config: Vec<(String, String)>,
+    pub state: Vec<Matrix>,
+    /// nested layers of containers
+    pub layers: Vec<LayerRecord>,
+}
+
+impl LayerRecord {
+    pub fn new(kind: &str) -> Self {
+        LayerRecord {
+            kind: kind.to_string(),
+            config: vec![],
+            state: vec![],
+            layers: vec![],
+        }
+    }
+
+    pub fn with_config(mut self, key: &str, value: impl ToString) -> Self {
+        self.config.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_state(mut self, state: Matrix) -> Self {
+        self.state.push(state);
+        self
+    }
+
+    pub fn get_config<T: FromStr>(&self, key: &str) -> Result<T, Box<dyn Error>> {
+        let (_, value) = self
+            .config
+            .iter()
+            .find(|(k, _)| k == key)
+            .ok_or(format!("{} layer is missing `{}`", self.kind, key))?;
+
+        value
+            .parse()
+            .map_err(|_| format!("{} layer has an invalid `{}`: {}", self.kind, key, value).into())
+    }
+
+    pub fn get_state(&self, i: usize) -> Result<&Matrix, Box<dyn Error>> {
+        self.state
+            .get(i)
+            .ok_or(format!("{} layer is missing state {}", self.kind, i).into())
+    }
+}
+
+/// rebuilds a layer from the record returned by [`Layer::record`]
+pub fn layer_from_record(record: &LayerRecord) -> Result<Box<dyn Layer>, Box<dyn Error>> {
+    match record.kind.as_str() {
+        "dense" => Ok(Box::new(Dense::from_record(record)?)),
+        "activation" => Ok(Box::new(Activation::from_record(record)?)),
+        "dropout" => Ok(Box::new(Dropout::from_record(record)?)),
+        "conv2d" => Ok(Box::new(Conv2D::from_record(record)?)),
+        "maxpool2d" => Ok(Box::new(MaxPool2D::from_record(record)?)),
+        "avgpool2d" => Ok(Box::new(AvgPool2D::from_record(record)?)),
+        "batchnorm1d" => Ok(Box::new(BatchNorm1d::from_record(record)?)),
+        "batchnorm2d" => Ok(Box::new(BatchNorm2d::from_record(record)?)),
+        "layernorm" => Ok(Box::new(LayerNorm::from_record(record)?)),
+        "rnn" => Ok(Box::new(Rnn::from_record(record)?)),
+        "gru" => Ok(Box::new(Gru::from_record(record)?)),
+        "lstm" => Ok(Box::new(Lstm::from_record(record)?)),
+        "sequential" => Ok(Box::new(Sequential::from_record(record)?)),
+        _ => Err(format!("Unknown layer type `{}`", record.kind).into()),
+    }
+}
+
+#[cfg(test)]
+pub mod gradient_check {
+    use rand::distributions::Uniform;
+
+    use super::Layer;
+    use crate::linear_algebra::Matrix;
+
+    /// Compares the gradients from [`Layer::backward`] against central differences
+    /// of the cost `sum(output * weights)` for random weights.
+    pub fn check_gradients(layer: &mut dyn Layer, input: &Matrix) {
+        let epsilon = 1e-6;
+        let output = layer.forward_train(input);
+        let (r, c) = output.get_dims();
+        let weights = Matrix::from_distribution(r, c, &Uniform::new(-1.0, 1.0));
+        let cost = |layer: &mut dyn Layer, input: &Matrix| -> f64 {
+            layer
+                .forward_train(input)
+                .component_mul(&weights)
+                .iter()
+                .sum()
+        };
+
+        layer.zero_gradients();
+        layer.forward_train(input);
+        let input_gradient = layer.backward(&weights);
+        let gradients: Vec<Matrix> = layer.gradients().into_iter().cloned().collect();
+
+        let assert_close = |analytic: f64, numeric: f64, what: &str| {
+            assert!(
+                (analytic - numeric).abs() <= 1e-4 * (1.0 + numeric.abs()),
+                "{} gradient mismatch: analytic {} - numeric {}",
+                what,
+                analytic,
+                numeric
+            );
+        };
+
+        let (ir, ic) = input.get_dims();
+        for i in 0..ir {
+            for j in 0..ic {
+                let (mut plus, mut minus) = (input.clone(), input.clone());
+                plus[(i, j)] += epsilon;
+                minus[(i, j)] -= epsilon;
+                let numeric = (cost(layer, &plus) - cost(layer, &minus)) / (2.0 * epsilon);
+                assert_close(input_gradient[(i, j)], numeric, "input");
+            }
+        }
+
+        for (p, gradient) in gradients.iter().enumerate() {
+            for k in 0..gradient.iter().count() {
+                let shift = |layer: &mut dyn Layer, delta: f64| {
+                    let mut parameters = layer.parameters_mut();
+                    let value = parameters[p].0.iter_mut().nth(k).unwrap();
+                    *value += delta;
+                };
+
+                shift(layer, epsilon);
+                let plus = cost(layer, input);
+                shift(layer, -2.0 * epsilon);
+                let minus = cost(layer, input);
+                shift(layer, epsilon);
+
+                let numeric = (plus - minus) / (2.0 * epsilon);
+                assert_close(*gradient.iter().nth(k).unwrap(), numeric, "parameter");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        activation::Activation, dense::Dense, dropout::Dropout, gradient_check::check_gradients,
+        layer_from_record, sequential::Sequential, Layer,
+    };
+    use crate::{calculus::functions::Function, linear_algebra::Matrix};
+    use rand::distributions::Uniform;
+
+    #[test]
+    fn test_dense_and_activation_gradients() {
+        let input = Matrix::from_distribution(3, 4, &Uniform::new(-1.0, 1.0));
+
+        check_gradients(&mut Dense::random(3, 2), &input);
+        check_gradients(&mut Activation::new(Function::sigmoid()), &input);
+
+        let mut model = Sequential::default();
+        model.push(Dense::random(3, 5));
+        model.push(Activation::new(Function::swish()));
+        model.push(Dense::random(5, 2));
+        check_gradients(&mut model, &input);
+    }
+
+    #[test]
+    fn test_records_rebuild_layers() {
+        let mut model = Sequential::default();
+        model.push(Dense::random(3, 5));
+        model.push(Activation::new(Function::leaky_relu()));
+        model.push(Dropout::new(0.3));
+
+        let rebuilt = layer_from_record(&model.record()).unwrap();
+        assert_eq!(rebuilt.record(), model.record());
+
+        let input = Matrix::from_value(3, 1, 0.5);
+        assert_eq!(rebuilt.forward(&input), model.forward(&input));
+    }
+}
@@ -0,0 +1,506 @@
+use std::error::Error;
+
+use crate::linear_algebra::Matrix;
+
+use super::{convolution::ImageShape, Layer, LayerRecord};
+
+/// Batch normalization of `channels` groups of `positions` consecutive rows,
+/// shared by [`BatchNorm1d`] and [`BatchNorm2d`]
+#[derive(Debug, Clone)]
+struct BatchNorm {
+    positions: usize,
+    gamma: Matrix,
+    beta: Matrix,
+    gamma_gradient: Matrix,
+    beta_gradient: Matrix,
+    running_mean: Matrix,
+    running_variance: Matrix,
+    momentum: f64,
+    epsilon: f64,
+    /// the normalized input and `1 / sqrt(variance + epsilon)` of every channel
+    normalized: Option<(Matrix, Vec<f64>)>,
+}
+
+impl BatchNorm {
+    fn new(channels: usize, positions: usize) -> Self {
+        BatchNorm {
+            positions,
+            gamma: Matrix::from_value(channels, 1, 1.0),
+            beta: Matrix::zeros(channels, 1),
+            gamma_gradient: Matrix::zeros(channels, 1),
+            beta_gradient: Matrix::zeros(channels, 1),
+            running_mean: Matrix::zeros(channels, 1),
+            running_variance: Matrix::from_value(channels, 1, 1.0),
+            momentum: 0.1,
+            epsilon: 1e-5,
+            normalized: None,
+        }
+    }
+
+    fn from_record(
+        record: &LayerRecord,
+        channels: usize,
+        positions: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut norm = BatchNorm::new(channels, positions);
+        norm.momentum = record.get_config("momentum")?;
+        norm.epsilon = record.get_config("epsilon")?;
+
+        for (i, state) in [
+            &mut norm.gamma,
+            &mut norm.beta,
+            &mut norm.running_mean,
+            &mut norm.running_variance,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let saved = record.get_state(i)?;
+            if saved.get_dims() != (channels, 1) {
+                return Err(format!("{} layer has a state of the wrong size", record.kind).into());
+            }
+            *state = saved.clone();
+        }
+
+        Ok(norm)
+    }
+
+    fn channels(&self) -> usize {
+        self.gamma.get_dims().0
+    }
+
+    /// the `(row, column)` of every value in a channel
+    fn entries(&self, channel: usize, batch_size: usize) -> impl Iterator<Item = (usize, usize)> {
+        let rows = channel * self.positions..(channel + 1) * self.positions;
+        (0..batch_size).flat_map(move |n| rows.clone().map(move |r| (r, n)))
+    }
+
+    fn forward(&self, input: &Matrix) -> Matrix {
+        let mut output = input.clone();
+        for c in 0..self.channels() {
+            let scale = self.gamma[(c, 0)] / (self.running_variance[(c, 0)] + self.epsilon).sqrt();
+            for entry in self.entries(c, input.get_dims().1) {
+                output[entry] =
+                    (input[entry] - self.running_mean[(c, 0)]) * scale + self.beta[(c, 0)];
+            }
+        }
+        output
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        let batch_size = input.get_dims().1;
+        let count = (batch_size * self.positions) as f64;
+        let mut normalized = input.clone();
+        let mut output = input.clone();
+        let mut inverse_deviations = vec![];
+
+        for c in 0..self.channels() {
+            let mean = self.entries(c, batch_size).map(|e| input[e]).sum::<f64>() / count;
+            let variance = self
+                .entries(c, batch_size)
+                .map(|e| (input[e] - mean).powi(2))
+                .sum::<f64>()
+                / count;
+            let inverse_deviation = 1.0 / (variance + self.epsilon).sqrt();
+
+            for entry in self.entries(c, batch_size) {
+                normalized[entry] = (input[entry] - mean) * inverse_deviation;
+                output[entry] = self.gamma[(c, 0)] * normalized[entry] + self.beta[(c, 0)];
+            }
+
+            // the running variance is unbiased, the batch itself uses the biased estimate
+            let unbiased = variance * count / (count - 1.0).max(1.0);
+            self.running_mean[(c, 0)] += self.momentum * (mean - self.running_mean[(c, 0)]);
+            self.running_variance[(c, 0)] +=
+                self.momentum * (unbiased - self.running_variance[(c, 0)]);
+            inverse_deviations.push(inverse_deviation);
+        }
+
+        self.normalized = Some((normalized, inverse_deviations));
+        output
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let (normalized, inverse_deviations) = self
+            .normalized
+            .as_ref()
+            .expect("backward called before forward_train");
+        let batch_size = output_gradient.get_dims().1;
+        let count = (batch_size * self.positions) as f64;
+        let mut gradient = output_gradient.clone();
+
+        for (c, inverse_deviation) in inverse_deviations.iter().enumerate() {
+            let (mut sum, mut sum_normalized) = (0.0, 0.0);
+            for e in self.entries(c, batch_size) {
+                sum += output_gradient[e];
+                sum_normalized += output_gradient[e] * normalized[e];
+            }
+            self.beta_gradient[(c, 0)] += sum;
+            self.gamma_gradient[(c, 0)] += sum_normalized;
+
+            let scale = self.gamma[(c, 0)] * inverse_deviation / count;
+            for e in self.entries(c, batch_size) {
+                gradient[e] =
+                    scale * (count * output_gradient[e] - sum - normalized[e] * sum_normalized);
+            }
+        }
+
+        gradient
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        vec![
+            (&mut self.gamma, &mut self.gamma_gradient),
+            (&mut self.beta, &mut self.beta_gradient),
+        ]
+    }
+
+    fn record(&self, record: LayerRecord) -> LayerRecord {
+        record
+            .with_config("momentum", self.momentum)
+            .with_config("epsilon", self.epsilon)
+            .with_state(self.gamma.clone())
+            .with_state(self.beta.clone())
+            .with_state(self.running_mean.clone())
+            .with_state(self.running_variance.clone())
+    }
+}
+
+/// Normalizes every node over the batch, then scales and shifts it by learned parameters.
+///
+/// Training uses the statistics of the batch and updates running averages of them,
+/// which are used during evaluation.
+#[derive(Debug, Clone)]
+pub struct BatchNorm1d {
+    norm: BatchNorm,
+}
+
+impl BatchNorm1d {
+    pub fn new(features: usize) -> Self {
+        BatchNorm1d {
+            norm: BatchNorm::new(features, 1),
+        }
+    }
+
+    /// weight of the latest batch in the running statistics (default 0.1)
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.norm.momentum = momentum;
+        self
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Ok(BatchNorm1d {
+            norm: BatchNorm::from_record(record, record.get_config("features")?, 1)?,
+        })
+    }
+
+    pub fn running_mean(&self) -> &Matrix {
+        &self.norm.running_mean
+    }
+
+    pub fn running_variance(&self) -> &Matrix {
+        &self.norm.running_variance
+    }
+}
+
+impl Layer for BatchNorm1d {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        self.norm.forward(input)
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        self.norm.forward_train(input)
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        self.norm.backward(output_gradient)
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.norm.gamma, &self.norm.beta]
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![&self.norm.gamma_gradient, &self.norm.beta_gradient]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        self.norm.parameters_mut()
+    }
+
+    fn record(&self) -> LayerRecord {
+        self.norm
+            .record(LayerRecord::new("batchnorm1d").with_config("features", self.norm.channels()))
+    }
+}
+
+/// Batch normalization of images, every channel is normalized over the batch
+/// and all of its pixels.
+#[derive(Debug, Clone)]
+pub struct BatchNorm2d {
+    shape: ImageShape,
+    norm: BatchNorm,
+}
+
+impl BatchNorm2d {
+    pub fn new(shape: ImageShape) -> Self {
+        BatchNorm2d {
+            shape,
+            norm: BatchNorm::new(shape.channels, shape.height * shape.width),
+        }
+    }
+
+    /// weight of the latest batch in the running statistics (default 0.1)
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.norm.momentum = momentum;
+        self
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        let shape = ImageShape::from_record(record)?;
+        Ok(BatchNorm2d {
+            shape,
+            norm: BatchNorm::from_record(record, shape.channels, shape.height * shape.width)?,
+        })
+    }
+
+    pub fn shape(&self) -> ImageShape {
+        self.shape
+    }
+
+    pub fn running_mean(&self) -> &Matrix {
+        &self.norm.running_mean
+    }
+
+    pub fn running_variance(&self) -> &Matrix {
+        &self.norm.running_variance
+    }
+}
+
+impl Layer for BatchNorm2d {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        self.norm.forward(input)
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        self.norm.forward_train(input)
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        self.norm.backward(output_gradient)
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.norm.gamma, &self.norm.beta]
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![&self.norm.gamma_gradient, &self.norm.beta_gradient]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        self.norm.parameters_mut()
+    }
+
+    fn record(&self) -> LayerRecord {
+        self.norm
+            .record(self.shape.add_to_record(LayerRecord::new("batchnorm2d")))
+    }
+}
+
+/// Normalizes every sample over its nodes, then scales and shifts each node by
+/// learned parameters; behaves the same during training and evaluation.
+#[derive(Debug, Clone)]
+pub struct LayerNorm {
+    gamma: Matrix,
+    beta: Matrix,
+    gamma_gradient: Matrix,
+    beta_gradient: Matrix,
+    epsilon: f64,
+    /// the normalized input and `1 / sqrt(variance + epsilon)` of every sample
+    normalized: Option<(Matrix, Vec<f64>)>,
+}
+
+impl LayerNorm {
+    pub fn new(features: usize) -> Self {
+        LayerNorm {
+            gamma: Matrix::from_value(features, 1, 1.0),
+            beta: Matrix::zeros(features, 1),
+            gamma_gradient: Matrix::zeros(features, 1),
+            beta_gradient: Matrix::zeros(features, 1),
+            epsilon: 1e-5,
+            normalized: None,
+        }
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        let (gamma, beta) = (record.get_state(0)?, record.get_state(1)?);
+        if gamma.get_dims() != beta.get_dims() || gamma.get_dims().1 != 1 {
+            return Err("layernorm scale and shift must be columns of the same size".into());
+        }
+
+        let mut norm = LayerNorm::new(gamma.get_dims().0);
+        norm.gamma = gamma.clone();
+        norm.beta = beta.clone();
+        norm.epsilon = record.get_config("epsilon")?;
+        Ok(norm)
+    }
+
+    /// returns the normalized input and the inverse deviation of every sample
+    fn normalize(&self, input: &Matrix) -> (Matrix, Vec<f64>) {
+        let (features, batch_size) = input.get_dims();
+        let mut normalized = input.clone();
+        let mut inverse_deviations = vec![];
+
+        for n in 0..batch_size {
+            let mean = (0..features).map(|i| input[(i, n)]).sum::<f64>() / features as f64;
+            let variance = (0..features)
+                .map(|i| (input[(i, n)] - mean).powi(2))
+                .sum::<f64>()
+                / features as f64;
+            let inverse_deviation = 1.0 / (variance + self.epsilon).sqrt();
+
+            for i in 0..features {
+                normalized[(i, n)] = (input[(i, n)] - mean) * inverse_deviation;
+            }
+            inverse_deviations.push(inverse_deviation);
+        }
+
+        (normalized, inverse_deviations)
+    }
+
+    fn scale_and_shift(&self, normalized: &Matrix) -> Matrix {
+        normalized
+            .clone()
+            .component_mul(&Matrix::from_columns(vec![
+                &self.gamma;
+                normalized.get_dims().1
+            ]))
+            .add_to_columns(&self.beta)
+    }
+}
+
+impl Layer for LayerNorm {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        self.scale_and_shift(&self.normalize(input).0)
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        let (normalized, inverse_deviations) = self.normalize(input);
+        let output = self.scale_and_shift(&normalized);
+        self.normalized = Some((normalized, inverse_deviations));
+        output
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let (normalized, inverse_deviations) = self
+            .normalized
+            .as_ref()
+            .expect("backward called before forward_train");
+        let features = output_gradient.get_dims().0;
+        let mut gradient = output_gradient.clone();
+
+        for (n, inverse_deviation) in inverse_deviations.iter().enumerate() {
+            let (mut sum, mut sum_normalized) = (0.0, 0.0);
+            for i in 0..features {
+                let scaled = output_gradient[(i, n)] * self.gamma[(i, 0)];
+                sum += scaled;
+                sum_normalized += scaled * normalized[(i, n)];
+
+                self.beta_gradient[(i, 0)] += output_gradient[(i, n)];
+                self.gamma_gradient[(i, 0)] += output_gradient[(i, n)] * normalized[(i, n)];
+            }
+
+            let count = features as f64;
+            for i in 0..features {
+                let scaled = output_gradient[(i, n)] * self.gamma[(i, 0)];
+                gradient[(i, n)] = inverse_deviation / count
+                    * (count * scaled - sum - normalized[(i, n)] * sum_normalized);
+            }
+        }
+
+        gradient
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![&self.gamma_gradient, &self.beta_gradient]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        vec![
+            (&mut self.gamma, &mut self.gamma_gradient),
+            (&mut self.beta, &mut self.beta_gradient),
+        ]
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new("layernorm")
+            .with_config("epsilon", self.epsilon)
+            .with_state(self.gamma.clone())
+            .with_state(self.beta.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::{BatchNorm1d, BatchNorm2d, LayerNorm};
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::neural_network::layers::{
+            convolution::ImageShape, gradient_check::check_gradients, layer_from_record, Layer,
+        },
+    };
+
+    #[test]
+    fn test_batch_norm_statistics() {
+        let input = Matrix::from_vec(2, 4, vec![1.0, 10.0, 2.0, 20.0, 3.0, 30.0, 4.0, 40.0]);
+        let mut norm = BatchNorm1d::new(2).with_momentum(1.0);
+
+        // every node is normalized to a mean of 0 and a variance of 1 over the batch
+        let output = norm.forward_train(&input);
+        for row in 0..2 {
+            let values = output.get_row(row).unwrap();
+            let mean = values.iter().sum::<f64>() / 4.0;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 4.0;
+            assert!(mean.abs() < 1e-9);
+            assert!((variance - 1.0).abs() < 1e-4);
+        }
+
+        assert_eq!(
+            norm.running_mean(),
+            &Matrix::from_vec(2, 1, vec![2.5, 25.0])
+        );
+        assert!((norm.running_variance()[(0, 0)] - 5.0 / 3.0).abs() < 1e-9);
+
+        // evaluation uses the running statistics, so it matches the unbiased variance
+        let rebuilt = layer_from_record(&norm.record()).unwrap();
+        assert_eq!(rebuilt.record(), norm.record());
+        assert_eq!(rebuilt.forward(&input), norm.forward(&input));
+    }
+
+    #[test]
+    fn test_normalization_gradients() {
+        let distribution = Uniform::new(-1.0, 1.0);
+        let mut batch_norm = BatchNorm1d::new(3);
+        let mut layer_norm = LayerNorm::new(3);
+        for norm in [&mut batch_norm as &mut dyn Layer, &mut layer_norm] {
+            for (parameter, _) in norm.parameters_mut() {
+                *parameter = Matrix::from_distribution(3, 1, &distribution);
+            }
+        }
+
+        let input = Matrix::from_distribution(3, 5, &distribution);
+        check_gradients(&mut batch_norm, &input);
+        check_gradients(&mut layer_norm, &input);
+
+        let shape = ImageShape::new(2, 3, 3);
+        let images = Matrix::from_distribution(shape.size(), 2, &distribution);
+        check_gradients(&mut BatchNorm2d::new(shape), &images);
+    }
+}
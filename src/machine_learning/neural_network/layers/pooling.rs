@@ -0,0 +1,258 @@
+use std::error::Error;
+
+use crate::linear_algebra::Matrix;
+
+use super::{convolution::ImageShape, Layer, LayerRecord};
+
+/// The windows a pooling layer reduces, shared by [`MaxPool2D`] and [`AvgPool2D`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Windows {
+    input_shape: ImageShape,
+    output_shape: ImageShape,
+    size: usize,
+    stride: usize,
+}
+
+impl Windows {
+    fn new(input_shape: ImageShape, size: usize, stride: usize) -> Result<Self, Box<dyn Error>> {
+        Ok(Windows {
+            output_shape: input_shape
+                .windowed(size, stride, 0)
+                .ok_or("pooling window does not fit the input")?,
+            input_shape,
+            size,
+            stride,
+        })
+    }
+
+    fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Windows::new(
+            ImageShape::from_record(record)?,
+            record.get_config("size")?,
+            record.get_config("stride")?,
+        )
+    }
+
+    fn record(&self, kind: &str) -> LayerRecord {
+        self.input_shape
+            .add_to_record(LayerRecord::new(kind))
+            .with_config("size", self.size)
+            .with_config("stride", self.stride)
+    }
+
+    /// calls `f(output, pixels)` with the pixels of every window
+    fn for_each(&self, mut f: impl FnMut(usize, &mut dyn Iterator<Item = usize>)) {
+        let (input, output) = (self.input_shape, self.output_shape);
+        for c in 0..output.channels {
+            for oy in 0..output.height {
+                for ox in 0..output.width {
+                    let (y, x) = (oy * self.stride, ox * self.stride);
+                    f(
+                        output.index(c, oy, ox),
+                        &mut (0..self.size * self.size)
+                            .map(|i| input.index(c, y + i / self.size, x + i % self.size)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the largest value of every `size`×`size` window
+#[derive(Debug, Clone)]
+pub struct MaxPool2D {
+    windows: Windows,
+    /// the pixel chosen for every output of the last batch
+    selected: Option<Vec<usize>>,
+}
+
+impl MaxPool2D {
+    /// # Panics
+    /// if the window does not fit the input or the stride is zero
+    pub fn new(input_shape: ImageShape, size: usize, stride: usize) -> Self {
+        MaxPool2D {
+            windows: Windows::new(input_shape, size, stride).unwrap(),
+            selected: None,
+        }
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Ok(MaxPool2D {
+            windows: Windows::from_record(record)?,
+            selected: None,
+        })
+    }
+
+    pub fn input_shape(&self) -> ImageShape {
+        self.windows.input_shape
+    }
+
+    pub fn output_shape(&self) -> ImageShape {
+        self.windows.output_shape
+    }
+
+    /// returns the pooled images and the index into `input` of every chosen value
+    fn pool(&self, input: &Matrix) -> (Matrix, Vec<usize>) {
+        let (rows, batch_size) = (self.windows.output_shape.size(), input.get_dims().1);
+        let mut output = Matrix::zeros(rows, batch_size);
+        let mut selected = vec![0; rows * batch_size];
+
+        for n in 0..batch_size {
+            self.windows.for_each(|o, pixels| {
+                let max = pixels
+                    .max_by(|a, b| input[(*a, n)].total_cmp(&input[(*b, n)]))
+                    .unwrap();
+                output[(o, n)] = input[(max, n)];
+                selected[o + rows * n] = max;
+            });
+        }
+
+        (output, selected)
+    }
+}
+
+impl Layer for MaxPool2D {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        self.pool(input).0
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        let (output, selected) = self.pool(input);
+        self.selected = Some(selected);
+        output
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let selected = self
+            .selected
+            .as_ref()
+            .expect("backward called before forward_train");
+        let (rows, batch_size) = output_gradient.get_dims();
+
+        // only the largest value of each window affected the output
+        let mut gradient = Matrix::zeros(self.windows.input_shape.size(), batch_size);
+        for n in 0..batch_size {
+            for o in 0..rows {
+                gradient[(selected[o + rows * n], n)] += output_gradient[(o, n)];
+            }
+        }
+        gradient
+    }
+
+    fn record(&self) -> LayerRecord {
+        self.windows.record("maxpool2d")
+    }
+}
+
+/// Averages every `size`×`size` window
+#[derive(Debug, Clone)]
+pub struct AvgPool2D {
+    windows: Windows,
+}
+
+impl AvgPool2D {
+    /// # Panics
+    /// if the window does not fit the input or the stride is zero
+    pub fn new(input_shape: ImageShape, size: usize, stride: usize) -> Self {
+        AvgPool2D {
+            windows: Windows::new(input_shape, size, stride).unwrap(),
+        }
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Ok(AvgPool2D {
+            windows: Windows::from_record(record)?,
+        })
+    }
+
+    pub fn input_shape(&self) -> ImageShape {
+        self.windows.input_shape
+    }
+
+    pub fn output_shape(&self) -> ImageShape {
+        self.windows.output_shape
+    }
+}
+
+impl Layer for AvgPool2D {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        let batch_size = input.get_dims().1;
+        let area = (self.windows.size * self.windows.size) as f64;
+        let mut output = Matrix::zeros(self.windows.output_shape.size(), batch_size);
+
+        for n in 0..batch_size {
+            self.windows.for_each(|o, pixels| {
+                output[(o, n)] = pixels.map(|i| input[(i, n)]).sum::<f64>() / area;
+            });
+        }
+        output
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        self.forward(input)
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let batch_size = output_gradient.get_dims().1;
+        let area = (self.windows.size * self.windows.size) as f64;
+        let mut gradient = Matrix::zeros(self.windows.input_shape.size(), batch_size);
+
+        for n in 0..batch_size {
+            self.windows.for_each(|o, pixels| {
+                for i in pixels {
+                    gradient[(i, n)] += output_gradient[(o, n)] / area;
+                }
+            });
+        }
+        gradient
+    }
+
+    fn record(&self) -> LayerRecord {
+        self.windows.record("avgpool2d")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::{AvgPool2D, MaxPool2D};
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::neural_network::layers::{
+            convolution::ImageShape, gradient_check::check_gradients, Layer,
+        },
+    };
+
+    #[test]
+    fn test_pooling_forward() {
+        // a 4x4 image holding 1..=16 row by row
+        let input = Matrix::from_vec(16, 1, (1..=16).map(|v| v as f64).collect());
+        let shape = ImageShape::new(1, 4, 4);
+
+        let mut max = MaxPool2D::new(shape, 2, 2);
+        assert_eq!(max.output_shape(), ImageShape::new(1, 2, 2));
+        assert_eq!(
+            max.forward_train(&input),
+            Matrix::from_vec(4, 1, vec![6.0, 8.0, 14.0, 16.0])
+        );
+
+        let gradient = max.backward(&Matrix::from_value(4, 1, 1.0));
+        assert_eq!(gradient.iter().sum::<f64>(), 4.0);
+        assert_eq!(gradient[(5, 0)], 1.0);
+
+        assert_eq!(
+            AvgPool2D::new(shape, 2, 2).forward(&input),
+            Matrix::from_vec(4, 1, vec![3.5, 5.5, 11.5, 13.5])
+        );
+    }
+
+    #[test]
+    fn test_pooling_gradients() {
+        let shape = ImageShape::new(2, 5, 5);
+        let input = Matrix::from_distribution(shape.size(), 2, &Uniform::new(-1.0, 1.0));
+
+        check_gradients(&mut MaxPool2D::new(shape, 2, 2), &input);
+        check_gradients(&mut AvgPool2D::new(shape, 3, 1), &input);
+    }
+}
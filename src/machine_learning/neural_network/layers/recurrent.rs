@@ -0,0 +1,520 @@
+//! Recurrent layers over sequences.
+//!
+//! A sequence is passed as a single column per sample with the timesteps stacked on top
+//! of each other, so a sequence of `t` steps with `n` values each is a column of `t * n`
+//! values (see [`DataVector::from_sequence`](crate::machine_learning::dataset::DataVector::from_sequence)).
+//! All sequences of a batch must have the same length.
+
+use std::{error::Error, fmt::Debug, marker::PhantomData};
+
+use rand::distributions::Uniform;
+
+use crate::{
+    calculus::functions::Function, linear_algebra::Matrix,
+    machine_learning::neural_network::regularization::Regularization,
+};
+
+use super::{Layer, LayerRecord};
+
+/// The computation of a single timestep of a [`Recurrent`] layer.
+///
+/// The gates are stacked on top of each other; `input` holds `W x + b` and
+/// `hidden` holds `U h` for every gate.
+pub trait RecurrentCell: Debug + Clone + Send + Sync + 'static {
+    /// the kind of the layer in its record
+    const KIND: &'static str;
+    /// `W`, `U` and `b` have `GATES * hidden_size` rows
+    const GATES: usize;
+    /// number of states carried between timesteps, the first is the hidden state
+    const STATES: usize;
+
+    /// returns the next states and whatever [`RecurrentCell::backward`] needs
+    fn forward(input: Matrix, hidden: Matrix, states: &[Matrix]) -> (Vec<Matrix>, Vec<Matrix>);
+
+    /// Takes the gradients of the next states and returns the gradients of `input` and
+    /// `hidden`, and of the previous states apart from the path through `hidden`.
+    fn backward(
+        cache: &[Matrix],
+        states: &[Matrix],
+        state_gradients: &[Matrix],
+    ) -> (Matrix, Matrix, Vec<Matrix>);
+
+    /// the initial biases
+    fn biases(hidden_size: usize) -> Matrix {
+        Matrix::zeros(Self::GATES * hidden_size, 1)
+    }
+}
+
+fn sigmoid(m: Matrix) -> Matrix {
+    m.apply_into(Function::sigmoid().activate())
+}
+
+fn tanh(m: Matrix) -> Matrix {
+    m.apply_into(Function::tanh().activate())
+}
+
+/// the derivative of sigmoid given its output
+fn sigmoid_slope(s: &Matrix) -> Matrix {
+    s.clone().apply_into(|v| *v *= 1.0 - *v)
+}
+
+/// the derivative of tanh given its output
+fn tanh_slope(t: &Matrix) -> Matrix {
+    t.clone().apply_into(|v| *v = 1.0 - *v * *v)
+}
+
+/// `h' = tanh(W x + U h + b)`
+#[derive(Debug, Clone)]
+pub struct RnnCell;
+
+impl RecurrentCell for RnnCell {
+    const KIND: &'static str = "rnn";
+    const GATES: usize = 1;
+    const STATES: usize = 1;
+
+    fn forward(input: Matrix, hidden: Matrix, _states: &[Matrix]) -> (Vec<Matrix>, Vec<Matrix>) {
+        let h = tanh(input + hidden);
+        (vec![h.clone()], vec![h])
+    }
+
+    fn backward(
+        cache: &[Matrix],
+        states: &[Matrix],
+        state_gradients: &[Matrix],
+    ) -> (Matrix, Matrix, Vec<Matrix>) {
+        let gradient = tanh_slope(&cache[0]).component_mul(&state_gradients[0]);
+        let (r, c) = states[0].get_dims();
+        (gradient.clone(), gradient, vec![Matrix::zeros(r, c)])
+    }
+}
+
+/// Gated recurrent unit with reset, update and candidate gates (in that order):
+///
+/// `h' = (1 - z) * n + z * h` where `n = tanh(W_n x + b_n + r * (U_n h))`
+#[derive(Debug, Clone)]
+pub struct GruCell;
+
+impl RecurrentCell for GruCell {
+    const KIND: &'static str = "gru";
+    const GATES: usize = 3;
+    const STATES: usize = 1;
+
+    fn forward(input: Matrix, hidden: Matrix, states: &[Matrix]) -> (Vec<Matrix>, Vec<Matrix>) {
+        let size = states[0].get_dims().0;
+        let reset = sigmoid(input.rows(0..size) + hidden.rows(0..size));
+        let update = sigmoid(input.rows(size..2 * size) + hidden.rows(size..2 * size));
+        let hidden_candidate = hidden.rows(2 * size..3 * size);
+        let candidate =
+            tanh(input.rows(2 * size..3 * size) + hidden_candidate.clone().component_mul(&reset));
+
+        let h = candidate.clone() + (states[0].clone() - &candidate).component_mul(&update);
+        (vec![h], vec![reset, update, candidate, hidden_candidate])
+    }
+
+    fn backward(
+        cache: &[Matrix],
+        states: &[Matrix],
+        state_gradients: &[Matrix],
+    ) -> (Matrix, Matrix, Vec<Matrix>) {
+        let (reset, update, candidate, hidden_candidate) =
+            (&cache[0], &cache[1], &cache[2], &cache[3]);
+        let gradient = &state_gradients[0];
+
+        let candidate_gradient = (gradient.clone() - gradient.clone().component_mul(update))
+            .component_mul(&tanh_slope(candidate));
+        let update_gradient = (states[0].clone() - candidate)
+            .component_mul(gradient)
+            .component_mul(&sigmoid_slope(update));
+        let reset_gradient = candidate_gradient
+            .clone()
+            .component_mul(hidden_candidate)
+            .component_mul(&sigmoid_slope(reset));
+
+        let hidden_gradient = Matrix::from_rows([
+            &reset_gradient,
+            &update_gradient,
+            &candidate_gradient.clone().component_mul(reset),
+        ]);
+        let input_gradient =
+            Matrix::from_rows([&reset_gradient, &update_gradient, &candidate_gradient]);
+
+        (
+            input_gradient,
+            hidden_gradient,
+            vec![gradient.clone().component_mul(update)],
+        )
+    }
+}
+
+/// Long short-term memory with input, forget, cell and output gates (in that order),
+/// carrying the hidden and the cell state.
+#[derive(Debug, Clone)]
+pub struct LstmCell;
+
+impl RecurrentCell for LstmCell {
+    const KIND: &'static str = "lstm";
+    const GATES: usize = 4;
+    const STATES: usize = 2;
+
+    fn forward(input: Matrix, hidden: Matrix, states: &[Matrix]) -> (Vec<Matrix>, Vec<Matrix>) {
+        let size = states[0].get_dims().0;
+        let gates = input + hidden;
+        let input_gate = sigmoid(gates.rows(0..size));
+        let forget = sigmoid(gates.rows(size..2 * size));
+        let cell = tanh(gates.rows(2 * size..3 * size));
+        let output = sigmoid(gates.rows(3 * size..4 * size));
+
+        let c = states[1].clone().component_mul(&forget) + cell.clone().component_mul(&input_gate);
+        let activated = tanh(c.clone());
+        let h = activated.clone().component_mul(&output);
+
+        (
+            vec![h, c],
+            vec![input_gate, forget, cell, output, activated],
+        )
+    }
+
+    fn backward(
+        cache: &[Matrix],
+        states: &[Matrix],
+        state_gradients: &[Matrix],
+    ) -> (Matrix, Matrix, Vec<Matrix>) {
+        let (input_gate, forget, cell, output, activated) =
+            (&cache[0], &cache[1], &cache[2], &cache[3], &cache[4]);
+        let (h_gradient, c_gradient) = (&state_gradients[0], &state_gradients[1]);
+
+        let c_gradient = c_gradient.clone()
+            + h_gradient
+                .clone()
+                .component_mul(output)
+                .component_mul(&tanh_slope(activated));
+
+        let gradient = Matrix::from_rows([
+            &c_gradient
+                .clone()
+                .component_mul(cell)
+                .component_mul(&sigmoid_slope(input_gate)),
+            &c_gradient
+                .clone()
+                .component_mul(&states[1])
+                .component_mul(&sigmoid_slope(forget)),
+            &c_gradient
+                .clone()
+                .component_mul(input_gate)
+                .component_mul(&tanh_slope(cell)),
+            &h_gradient
+                .clone()
+                .component_mul(activated)
+                .component_mul(&sigmoid_slope(output)),
+        ]);
+
+        let (r, c) = states[0].get_dims();
+        (
+            gradient.clone(),
+            gradient,
+            vec![Matrix::zeros(r, c), c_gradient.component_mul(forget)],
+        )
+    }
+
+    /// the forget gate starts open, so the cell state is remembered early in training
+    fn biases(hidden_size: usize) -> Matrix {
+        let mut biases = Matrix::zeros(4 * hidden_size, 1);
+        for i in hidden_size..2 * hidden_size {
+            biases[(i, 0)] = 1.0;
+        }
+        biases
+    }
+}
+
+pub type Rnn = Recurrent<RnnCell>;
+pub type Gru = Recurrent<GruCell>;
+pub type Lstm = Recurrent<LstmCell>;
+
+/// the values cached for every timestep while training
+#[derive(Debug, Clone)]
+struct Step {
+    input: Matrix,
+    states: Vec<Matrix>,
+    cache: Vec<Matrix>,
+}
+
+/// Applies a [`RecurrentCell`] to every timestep of a sequence, starting from zero states.
+///
+/// Outputs the last hidden state, or the hidden states of every timestep stacked like
+/// the input when returning sequences, so recurrent layers can be chained.
+///
+/// Like the other layers it panics on inputs of the wrong shape, here sequences that do not
+/// divide into timesteps; [`NeuralNetwork::check_input`] rejects those with an error.
+///
+/// [`NeuralNetwork::check_input`]: crate::machine_learning::neural_network::NeuralNetwork::check_input
+#[derive(Debug, Clone)]
+pub struct Recurrent<C: RecurrentCell> {
+    input_size: usize,
+    hidden_size: usize,
+    return_sequences: bool,
+    truncation: Option<usize>,
+    input_weights: Matrix,
+    hidden_weights: Matrix,
+    biases: Matrix,
+    input_weight_gradient: Matrix,
+    hidden_weight_gradient: Matrix,
+    bias_gradient: Matrix,
+    regularization: Regularization,
+    steps: Vec<Step>,
+    cell: PhantomData<C>,
+}
+
+// Recurrent constructors
+impl<C: RecurrentCell> Recurrent<C> {
+    /// weights are sampled uniformly from `±1 / sqrt(hidden_size)`
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        let bound = 1.0 / (hidden_size as f64).sqrt();
+        let distribution = Uniform::new_inclusive(-bound, bound);
+        let rows = C::GATES * hidden_size;
+
+        Recurrent {
+            input_size,
+            hidden_size,
+            return_sequences: false,
+            truncation: None,
+            input_weights: Matrix::from_distribution(rows, input_size, &distribution),
+            hidden_weights: Matrix::from_distribution(rows, hidden_size, &distribution),
+            biases: C::biases(hidden_size),
+            input_weight_gradient: Matrix::zeros(rows, input_size),
+            hidden_weight_gradient: Matrix::zeros(rows, hidden_size),
+            bias_gradient: Matrix::zeros(rows, 1),
+            regularization: Regularization::default(),
+            steps: vec![],
+            cell: PhantomData,
+        }
+    }
+
+    /// outputs the hidden state of every timestep instead of only the last one
+    pub fn returning_sequences(mut self) -> Self {
+        self.return_sequences = true;
+        self
+    }
+
+    /// Truncated backpropagation through time; the sequence is split into chunks of
+    /// `steps` timesteps and gradients do not flow back from one chunk into the previous.
+    pub fn with_truncation(mut self, steps: usize) -> Self {
+        self.truncation = Some(steps).filter(|s| *s > 0);
+        self
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        let mut layer = Recurrent::<C>::new(
+            record.get_config("input_size")?,
+            record.get_config("hidden_size")?,
+        );
+        layer.return_sequences = record.get_config("return_sequences")?;
+        layer = layer.with_truncation(record.get_config("truncation")?);
+
+        let (input_weights, hidden_weights, biases) = (
+            record.get_state(0)?,
+            record.get_state(1)?,
+            record.get_state(2)?,
+        );
+        if input_weights.get_dims() != layer.input_weights.get_dims()
+            || hidden_weights.get_dims() != layer.hidden_weights.get_dims()
+            || biases.get_dims() != layer.biases.get_dims()
+        {
+            return Err(format!("{} layer has weights of the wrong size", record.kind).into());
+        }
+
+        layer.input_weights = input_weights.clone();
+        layer.hidden_weights = hidden_weights.clone();
+        layer.biases = biases.clone();
+        Ok(layer)
+    }
+}
+
+impl<C: RecurrentCell> Recurrent<C> {
+    /// `(input_size, hidden_size)`
+    pub fn get_dims(&self) -> (usize, usize) {
+        (self.input_size, self.hidden_size)
+    }
+
+    /// runs the sequence, keeping the steps for backpropagation if `steps` is given
+    fn run(&self, input: &Matrix, mut steps: Option<&mut Vec<Step>>) -> Matrix {
+        let (rows, batch_size) = input.get_dims();
+        assert_eq!(
+            rows % self.input_size,
+            0,
+            "sequence of {} values does not divide into timesteps of {}",
+            rows,
+            self.input_size
+        );
+
+        let mut states = vec![Matrix::zeros(self.hidden_size, batch_size); C::STATES];
+        let mut outputs = vec![];
+        for t in 0..rows / self.input_size {
+            let x = input.rows(t * self.input_size..(t + 1) * self.input_size);
+            let (next, cache) = C::forward(
+                (&self.input_weights * &x).add_to_columns(&self.biases),
+                &self.hidden_weights * &states[0],
+                &states,
+            );
+
+            if self.return_sequences {
+                outputs.push(next[0].clone());
+            }
+
+            let previous = std::mem::replace(&mut states, next);
+            if let Some(steps) = steps.as_mut() {
+                steps.push(Step {
+                    input: x,
+                    states: previous,
+                    cache,
+                });
+            }
+        }
+
+        if self.return_sequences {
+            Matrix::from_rows(&outputs)
+        } else {
+            states.swap_remove(0)
+        }
+    }
+}
+
+impl<C: RecurrentCell> Layer for Recurrent<C> {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        self.run(input, None)
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        let mut steps = vec![];
+        let output = self.run(input, Some(&mut steps));
+        self.steps = steps;
+        output
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let steps = std::mem::take(&mut self.steps);
+        let batch_size = output_gradient.get_dims().1;
+        let size = self.hidden_size;
+
+        let mut carried = vec![Matrix::zeros(size, batch_size); C::STATES];
+        let mut input_gradients = vec![Matrix::zeros(self.input_size, batch_size); steps.len()];
+        for (t, step) in steps.iter().enumerate().rev() {
+            if self.return_sequences {
+                carried[0] += output_gradient.rows(t * size..(t + 1) * size);
+            } else if t + 1 == steps.len() {
+                carried[0] += output_gradient;
+            }
+
+            let (input_gradient, hidden_gradient, mut previous) =
+                C::backward(&step.cache, &step.states, &carried);
+
+            self.input_weight_gradient += &input_gradient * step.input.transpose();
+            self.hidden_weight_gradient += &hidden_gradient * step.states[0].transpose();
+            self.bias_gradient += input_gradient.sum_columns();
+
+            input_gradients[t] = self.input_weights.transpose() * &input_gradient;
+            previous[0] += self.hidden_weights.transpose() * &hidden_gradient;
+            carried = previous;
+
+            if self.truncation.is_some_and(|k| t % k == 0) {
+                carried
+                    .iter_mut()
+                    .for_each(|c| c.iter_mut().for_each(|v| *v = 0.0));
+            }
+        }
+
+        let batch_size = batch_size as f64;
+        self.input_weight_gradient +=
+            batch_size * self.regularization.gradient(&self.input_weights);
+        self.hidden_weight_gradient +=
+            batch_size * self.regularization.gradient(&self.hidden_weights);
+
+        self.steps = steps;
+        Matrix::from_rows(&input_gradients)
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.input_weights, &self.hidden_weights, &self.biases]
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        vec![
+            &self.input_weight_gradient,
+            &self.hidden_weight_gradient,
+            &self.bias_gradient,
+        ]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        vec![
+            (&mut self.input_weights, &mut self.input_weight_gradient),
+            (&mut self.hidden_weights, &mut self.hidden_weight_gradient),
+            (&mut self.biases, &mut self.bias_gradient),
+        ]
+    }
+
+    fn set_regularization(&mut self, regularization: Regularization) {
+        self.regularization = regularization;
+    }
+
+    fn apply_constraints(&mut self) {
+        self.regularization.constrain(&mut self.input_weights);
+        self.regularization.constrain(&mut self.hidden_weights);
+    }
+
+    fn record(&self) -> LayerRecord {
+        LayerRecord::new(C::KIND)
+            .with_config("input_size", self.input_size)
+            .with_config("hidden_size", self.hidden_size)
+            .with_config("return_sequences", self.return_sequences)
+            .with_config("truncation", self.truncation.unwrap_or(0))
+            .with_state(self.input_weights.clone())
+            .with_state(self.hidden_weights.clone())
+            .with_state(self.biases.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::{Gru, Lstm, Rnn};
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::{DataVector, Target},
+            neural_network::layers::{gradient_check::check_gradients, layer_from_record, Layer},
+        },
+    };
+
+    #[test]
+    fn test_recurrent_gradients() {
+        // 4 timesteps of 2 values for 3 samples
+        let input = Matrix::from_distribution(8, 3, &Uniform::new(-1.0, 1.0));
+
+        check_gradients(&mut Rnn::new(2, 3), &input);
+        check_gradients(&mut Gru::new(2, 3), &input);
+        check_gradients(&mut Lstm::new(2, 3), &input);
+        check_gradients(&mut Gru::new(2, 3).returning_sequences(), &input);
+        check_gradients(&mut Lstm::new(2, 3).returning_sequences(), &input);
+    }
+
+    #[test]
+    fn test_recurrent_sequences() {
+        let steps: Vec<Matrix> = (0..5).map(|t| Matrix::from_value(2, 1, t as f64)).collect();
+        let sample = DataVector::from_sequence(&steps, Target::Class(0));
+        assert_eq!(sample.sequence(2), steps);
+
+        let lstm = Lstm::new(2, 3).returning_sequences();
+        assert_eq!(lstm.forward(&sample.data).get_dims(), (15, 1));
+
+        let rebuilt = layer_from_record(&lstm.record()).unwrap();
+        assert_eq!(rebuilt.forward(&sample.data), lstm.forward(&sample.data));
+
+        // chunks of 2 steps: [0, 1], [2, 3], [4]; only the last step gets a gradient
+        let mut rnn = Rnn::new(2, 3).with_truncation(2);
+        rnn.forward_train(&sample.data);
+        let gradient = rnn.backward(&Matrix::from_value(3, 1, 1.0));
+        assert!(gradient.rows(0..8).iter().all(|g| *g == 0.0));
+        assert!(gradient.rows(8..10).iter().any(|g| *g != 0.0));
+    }
+}
@@ -0,0 +1,112 @@
+use std::error::Error;
+
+use crate::{
+    linear_algebra::Matrix, machine_learning::neural_network::regularization::Regularization,
+};
+
+use super::{layer_from_record, Layer, LayerRecord};
+
+/// Runs its layers one after the other
+#[derive(Debug, Default)]
+pub struct Sequential {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl Sequential {
+    pub fn new(layers: Vec<Box<dyn Layer>>) -> Self {
+        Sequential { layers }
+    }
+
+    pub fn push(&mut self, layer: impl Layer + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Box<dyn Layer>] {
+        &mut self.layers
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn from_record(record: &LayerRecord) -> Result<Self, Box<dyn Error>> {
+        Ok(Sequential::new(
+            record
+                .layers
+                .iter()
+                .map(layer_from_record)
+                .collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
+impl Layer for Sequential {
+    fn forward(&self, input: &Matrix) -> Matrix {
+        let mut output = input.clone();
+        for layer in self.layers.iter() {
+            output = layer.forward(&output);
+        }
+        output
+    }
+
+    fn forward_train(&mut self, input: &Matrix) -> Matrix {
+        let mut output = input.clone();
+        for layer in self.layers.iter_mut() {
+            output = layer.forward_train(&output);
+        }
+        output
+    }
+
+    fn backward(&mut self, output_gradient: &Matrix) -> Matrix {
+        let mut gradient = output_gradient.clone();
+        for layer in self.layers.iter_mut().rev() {
+            gradient = layer.backward(&gradient);
+        }
+        gradient
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        self.layers.iter().flat_map(|l| l.parameters()).collect()
+    }
+
+    fn gradients(&self) -> Vec<&Matrix> {
+        self.layers.iter().flat_map(|l| l.gradients()).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<(&mut Matrix, &mut Matrix)> {
+        self.layers
+            .iter_mut()
+            .flat_map(|l| l.parameters_mut())
+            .collect()
+    }
+
+    fn set_regularization(&mut self, regularization: Regularization) {
+        self.layers
+            .iter_mut()
+            .for_each(|l| l.set_regularization(regularization));
+    }
+
+    fn apply_constraints(&mut self) {
+        self.layers.iter_mut().for_each(|l| l.apply_constraints());
+    }
+
+    fn record(&self) -> LayerRecord {
+        let mut record = LayerRecord::new("sequential");
+        record.layers = self.layers.iter().map(|l| l.record()).collect();
+        record
+    }
+}
+
+impl PartialEq for Sequential {
+    fn eq(&self, other: &Self) -> bool {
+        self.record() == other.record()
+    }
+}
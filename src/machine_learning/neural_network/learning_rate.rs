@@ -0,0 +1,474 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// Controls when a [`LrSchedule`] advances to its next step
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stepping {
+    /// advance after every mini-batch
+    Batch,
+    /// advance once per epoch, via [`LrSchedule::step_epoch`]
+    Epoch,
+}
+
+/// Whether a lower or a higher observed metric counts as an improvement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlateauMode {
+    /// e.g. loss
+    Min,
+    /// e.g. accuracy
+    Max,
+}
+
+impl PlateauMode {
+    /// whether `metric` beats `best` by more than `min_delta`, anything beats `None`
+    pub fn improves(&self, metric: f64, best: Option<f64>, min_delta: f64) -> bool {
+        match (best, self) {
+            (None, _) => true,
+            (Some(best), PlateauMode::Min) => metric < best - min_delta,
+            (Some(best), PlateauMode::Max) => metric > best + min_delta,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Constant,
+    /// `lr * gamma^(step / step_size)`
+    StepDecay {
+        step_size: usize,
+        gamma: f64,
+    },
+    /// `lr * gamma^step`
+    ExponentialDecay {
+        gamma: f64,
+    },
+    /// Cosine annealing with warm restarts (SGDR).
+    /// Every restart multiplies the length of the period by `period_multiplier`.
+    CosineAnnealing {
+        period: usize,
+        period_multiplier: usize,
+        min_learning_rate: f64,
+    },
+    /// Linearly ramps up to the learning rate over `warmup_steps`, then defers to `after`
+    LinearWarmup {
+        warmup_steps: usize,
+        after: Box<Schedule>,
+    },
+    /// Ramps up from `lr / div_factor` to `lr` over the first `pct_start` of `total_steps`,
+    /// then anneals down to `lr / (div_factor * final_div_factor)`.
+    OneCycle {
+        total_steps: usize,
+        pct_start: f64,
+        div_factor: f64,
+        final_div_factor: f64,
+    },
+    /// Multiplies the learning rate by `factor` when the observed metric
+    /// has not improved by at least `min_delta` for `patience` observations.
+    ReduceOnPlateau {
+        mode: PlateauMode,
+        factor: f64,
+        patience: usize,
+        min_delta: f64,
+        min_learning_rate: f64,
+    },
+}
+
+/// How far a [`LrSchedule`] has advanced, saved in checkpoints
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulePosition {
+    pub step: usize,
+    pub best_metric: Option<f64>,
+    pub bad_observations: usize,
+    /// the factor of every reduction on a plateau, and of [`LrSchedule::scale`]
+    pub plateau_scale: f64,
+}
+
+/// A learning rate that changes over the course of training.
+///
+/// ### Example
+/// ```
+/// # use mathematics::machine_learning::neural_network::learning_rate::{LrSchedule, Stepping};
+/// let mut schedule = LrSchedule::step_decay(1.0, 2, 0.5).with_warmup(1);
+/// assert_eq!(schedule.stepping(), Stepping::Epoch);
+///
+/// let rates: Vec<f64> = (0..4)
+///     .map(|_| {
+///         let lr = schedule.learning_rate();
+///         schedule.step_epoch();
+///         lr
+///     })
+///     .collect();
+/// assert_eq!(rates, vec![1.0, 1.0, 1.0, 0.5]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LrSchedule {
+    base_learning_rate: f64,
+    schedule: Schedule,
+    stepping: Stepping,
+    step: usize,
+
+    // reduce-on-plateau state
+    best_metric: Option<f64>,
+    bad_observations: usize,
+    plateau_scale: f64,
+}
+
+// LrSchedule constructors
+impl LrSchedule {
+    pub fn new(base_learning_rate: f64, schedule: Schedule) -> Self {
+        LrSchedule {
+            base_learning_rate,
+            schedule,
+            stepping: Stepping::Epoch,
+            step: 0,
+            best_metric: None,
+            bad_observations: 0,
+            plateau_scale: 1.0,
+        }
+    }
+
+    pub fn constant(learning_rate: f64) -> Self {
+        Self::new(learning_rate, Schedule::Constant)
+    }
+
+    pub fn step_decay(learning_rate: f64, step_size: usize, gamma: f64) -> Self {
+        Self::new(
+            learning_rate,
+            Schedule::StepDecay {
+                step_size: step_size.max(1),
+                gamma,
+            },
+        )
+    }
+
+    pub fn exponential_decay(learning_rate: f64, gamma: f64) -> Self {
+        Self::new(learning_rate, Schedule::ExponentialDecay { gamma })
+    }
+
+    pub fn cosine_annealing(
+        learning_rate: f64,
+        period: usize,
+        period_multiplier: usize,
+        min_learning_rate: f64,
+    ) -> Self {
+        Self::new(
+            learning_rate,
+            Schedule::CosineAnnealing {
+                period: period.max(1),
+                period_multiplier: period_multiplier.max(1),
+                min_learning_rate,
+            },
+        )
+    }
+
+    /// one-cycle policy with the commonly used defaults;
+    /// 30% warmup, `div_factor = 25` and `final_div_factor = 1e4`
+    pub fn one_cycle(max_learning_rate: f64, total_steps: usize) -> Self {
+        Self::new(
+            max_learning_rate,
+            Schedule::OneCycle {
+                total_steps: total_steps.max(1),
+                pct_start: 0.3,
+                div_factor: 25.0,
+                final_div_factor: 1e4,
+            },
+        )
+    }
+
+    pub fn reduce_on_plateau(
+        learning_rate: f64,
+        mode: PlateauMode,
+        factor: f64,
+        patience: usize,
+    ) -> Self {
+        Self::new(
+            learning_rate,
+            Schedule::ReduceOnPlateau {
+                mode,
+                factor,
+                patience,
+                min_delta: 0.0,
+                min_learning_rate: 0.0,
+            },
+        )
+    }
+
+    /// Prepends a linear warmup of `warmup_steps` to the current schedule
+    pub fn with_warmup(mut self, warmup_steps: usize) -> Self {
+        if warmup_steps > 0 {
+            self.schedule = Schedule::LinearWarmup {
+                warmup_steps,
+                after: Box::new(self.schedule),
+            };
+        }
+        self
+    }
+
+    pub fn stepped_per(mut self, stepping: Stepping) -> Self {
+        self.stepping = stepping;
+        self
+    }
+}
+
+// LrSchedule methods
+impl LrSchedule {
+    pub fn learning_rate(&self) -> f64 {
+        let lr = self
+            .schedule
+            .learning_rate(self.base_learning_rate, self.step);
+        match self.schedule.plateau() {
+            Some(Schedule::ReduceOnPlateau {
+                min_learning_rate, ..
+            }) => (lr * self.plateau_scale).max(*min_learning_rate),
+            _ => lr * self.plateau_scale,
+        }
+    }
+
+    /// Multiplies the learning rate by `factor` from now on, on top of the schedule
+    pub fn scale(&mut self, factor: f64) {
+        self.plateau_scale *= factor;
+    }
+
+    /// Scales the schedule so its current learning rate is `learning_rate`,
+    /// which fails while the schedule itself is at 0, e.g. on the first step of a warmup.
+    pub fn set_learning_rate(&mut self, learning_rate: f64) -> Result<(), String> {
+        let unscaled = self
+            .schedule
+            .learning_rate(self.base_learning_rate, self.step);
+        if unscaled == 0.0 {
+            return Err(format!(
+                "Cannot set the learning rate to {} at step {}, where the schedule is at 0",
+                learning_rate, self.step
+            ));
+        }
+        self.plateau_scale = learning_rate / unscaled;
+        Ok(())
+    }
+
+    pub fn stepping(&self) -> Stepping {
+        self.stepping
+    }
+
+    /// The number of steps taken so far
+    pub fn current_step(&self) -> usize {
+        self.step
+    }
+
+    pub fn position(&self) -> SchedulePosition {
+        SchedulePosition {
+            step: self.step,
+            best_metric: self.best_metric,
+            bad_observations: self.bad_observations,
+            plateau_scale: self.plateau_scale,
+        }
+    }
+
+    /// Continues from a position of the same schedule, e.g. one read from a checkpoint
+    pub fn seek(&mut self, position: SchedulePosition) {
+        self.step = position.step;
+        self.best_metric = position.best_metric;
+        self.bad_observations = position.bad_observations;
+        self.plateau_scale = position.plateau_scale;
+    }
+
+    /// Advances the schedule if it is stepped per batch
+    pub fn step_batch(&mut self) {
+        if self.stepping == Stepping::Batch {
+            self.step += 1;
+        }
+    }
+
+    /// Advances the schedule if it is stepped per epoch
+    pub fn step_epoch(&mut self) {
+        if self.stepping == Stepping::Epoch {
+            self.step += 1;
+        }
+    }
+
+    /// Reports a validation metric to the schedule.
+    ///
+    /// Only `ReduceOnPlateau` reacts to it, every other schedule ignores the value.
+    pub fn observe(&mut self, metric: f64) {
+        let Some(Schedule::ReduceOnPlateau {
+            mode,
+            factor,
+            patience,
+            min_delta,
+            ..
+        }) = self.schedule.plateau()
+        else {
+            return;
+        };
+
+        if mode.improves(metric, self.best_metric, *min_delta) {
+            self.best_metric = Some(metric);
+            self.bad_observations = 0;
+            return;
+        }
+
+        self.bad_observations += 1;
+        if self.bad_observations > *patience {
+            self.plateau_scale *= factor;
+            self.bad_observations = 0;
+        }
+    }
+}
+
+impl Schedule {
+    fn learning_rate(&self, base: f64, step: usize) -> f64 {
+        match self {
+            Schedule::Constant | Schedule::ReduceOnPlateau { .. } => base,
+
+            Schedule::StepDecay { step_size, gamma } => {
+                base * gamma.powi((step / step_size) as i32)
+            }
+
+            Schedule::ExponentialDecay { gamma } => base * gamma.powi(step as i32),
+
+            Schedule::CosineAnnealing {
+                period,
+                period_multiplier,
+                min_learning_rate,
+            } => {
+                // find the position within the current restart period
+                let (mut position, mut length) = (step, *period);
+                while position >= length {
+                    position -= length;
+                    length *= period_multiplier;
+                }
+
+                let progress = position as f64 / length as f64;
+                min_learning_rate + (base - min_learning_rate) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+
+            Schedule::LinearWarmup {
+                warmup_steps,
+                after,
+            } => {
+                if step < *warmup_steps {
+                    return base * (step + 1) as f64 / *warmup_steps as f64;
+                }
+                after.learning_rate(base, step - warmup_steps)
+            }
+
+            Schedule::OneCycle {
+                total_steps,
+                pct_start,
+                div_factor,
+                final_div_factor,
+            } => {
+                let initial = base / div_factor;
+                let minimum = initial / final_div_factor;
+                let peak = ((*total_steps as f64 * pct_start) as usize).max(1);
+                let step = step.min(*total_steps);
+
+                if step < peak {
+                    let progress = step as f64 / peak as f64;
+                    return base + (initial - base) * (1.0 + (PI * progress).cos()) / 2.0;
+                }
+
+                let progress = (step - peak) as f64 / (total_steps - peak).max(1) as f64;
+                minimum + (base - minimum) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+        }
+    }
+
+    /// finds a nested `ReduceOnPlateau` schedule
+    fn plateau(&self) -> Option<&Schedule> {
+        match self {
+            Schedule::ReduceOnPlateau { .. } => Some(self),
+            Schedule::LinearWarmup { after, .. } => after.plateau(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LrSchedule, PlateauMode, Stepping};
+
+    fn rates(schedule: &mut LrSchedule, steps: usize) -> Vec<f64> {
+        (0..steps)
+            .map(|_| {
+                let lr = schedule.learning_rate();
+                schedule.step_batch();
+                schedule.step_epoch();
+                lr
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decay_schedules() {
+        let mut s = LrSchedule::step_decay(1.0, 2, 0.1);
+        let r = rates(&mut s, 5);
+        assert_eq!(r[0..2], [1.0, 1.0]);
+        assert!((r[2] - 0.1).abs() < 1e-12);
+        assert!((r[4] - 0.01).abs() < 1e-12);
+
+        let mut s = LrSchedule::exponential_decay(2.0, 0.5);
+        assert_eq!(rates(&mut s, 3), vec![2.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_setting_the_learning_rate() {
+        let mut s = LrSchedule::exponential_decay(2.0, 0.5);
+        s.set_learning_rate(0.0).unwrap();
+        assert_eq!(s.learning_rate(), 0.0);
+        // the rate is set absolutely, not relative to the current one
+        s.set_learning_rate(0.5).unwrap();
+        assert_eq!(rates(&mut s, 2), vec![0.5, 0.25]);
+
+        // a schedule at 0 cannot be scaled to another rate
+        let mut s = LrSchedule::exponential_decay(1.0, 0.0);
+        s.step_epoch();
+        assert!(s.set_learning_rate(0.1).is_err());
+    }
+
+    #[test]
+    fn test_cosine_annealing_restarts() {
+        let mut s = LrSchedule::cosine_annealing(1.0, 2, 2, 0.0);
+        let r = rates(&mut s, 7);
+
+        // periods of 2 then 4 steps
+        assert!((r[0] - 1.0).abs() < 1e-12);
+        assert!((r[1] - 0.5).abs() < 1e-12);
+        assert!((r[2] - 1.0).abs() < 1e-12);
+        assert!((r[4] - 0.5).abs() < 1e-12);
+        assert!((r[6] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_warmup_and_one_cycle() {
+        let mut s = LrSchedule::constant(1.0)
+            .with_warmup(4)
+            .stepped_per(Stepping::Batch);
+        assert_eq!(rates(&mut s, 5), vec![0.25, 0.5, 0.75, 1.0, 1.0]);
+
+        let mut s = LrSchedule::one_cycle(1.0, 10);
+        let r = rates(&mut s, 11);
+        assert!((r[0] - 0.04).abs() < 1e-12);
+        assert!((r[3] - 1.0).abs() < 1e-12);
+        assert!(r[10] < 1e-5);
+    }
+
+    #[test]
+    fn test_reduce_on_plateau() {
+        let mut s = LrSchedule::reduce_on_plateau(1.0, PlateauMode::Max, 0.5, 1);
+
+        s.observe(0.5);
+        s.observe(0.6);
+        s.observe(0.6);
+        assert_eq!(s.learning_rate(), 1.0);
+
+        s.observe(0.55);
+        assert_eq!(s.learning_rate(), 0.5);
+
+        // other schedules ignore observations
+        let mut s = LrSchedule::constant(1.0);
+        (0..10).for_each(|_| s.observe(0.0));
+        assert_eq!(s.learning_rate(), 1.0);
+    }
+}
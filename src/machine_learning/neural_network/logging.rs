@@ -0,0 +1,463 @@
+//! Records of training, written by a [`TrainingLogger`] as they happen.
+//!
+//! The progress on standard output is one logger, [`CsvLogger`] and [`JsonLinesLogger`]
+//! write the same records to files so runs can be plotted and compared.
+
+use std::{
+    fmt::Write as _,
+    io::{self, stdout, Write},
+};
+
+use serde::{Serialize, Serializer};
+
+/// What a training step measured on its batch, before the step was taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchStats {
+    pub samples: usize,
+    /// the summed cost of the samples
+    pub loss: f64,
+    /// the number of samples predicted correctly
+    pub correct: usize,
+    /// the global norm of the averaged gradient, before clipping
+    pub gradient_norm: f64,
+}
+
+/// A record of a training step
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchRecord {
+    pub epoch: usize,
+    /// the position of the batch within the epoch
+    pub batch: usize,
+    /// the number of batches of the epoch
+    pub batches: usize,
+    pub samples: usize,
+    /// the mean cost of the samples
+    pub loss: f64,
+    pub accuracy: f64,
+    pub learning_rate: f64,
+    pub gradient_norm: f64,
+    /// seconds since training started
+    pub elapsed: f64,
+    pub samples_per_second: f64,
+}
+
+impl BatchRecord {
+    /// the record of `stats`, from a step of `seconds`
+    pub fn new(
+        epoch: usize,
+        batch: usize,
+        batches: usize,
+        stats: &BatchStats,
+        learning_rate: f64,
+        seconds: f64,
+        elapsed: f64,
+    ) -> Self {
+        BatchRecord {
+            epoch,
+            batch,
+            batches,
+            samples: stats.samples,
+            loss: stats.loss / stats.samples.max(1) as f64,
+            accuracy: stats.correct as f64 / stats.samples.max(1) as f64,
+            learning_rate,
+            gradient_norm: stats.gradient_norm,
+            elapsed,
+            samples_per_second: stats.samples as f64 / seconds.max(f64::EPSILON),
+        }
+    }
+}
+
+/// A record of a completed epoch
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct EpochRecord {
+    pub epoch: usize,
+    /// the learning rate at the start of the epoch
+    pub learning_rate: f64,
+    /// the mean cost of the training samples, as they were trained on
+    pub loss: f64,
+    pub accuracy: f64,
+    /// the mean over the batches
+    pub gradient_norm: f64,
+    /// the named metrics on the validation data
+    #[serde(serialize_with = "metrics")]
+    pub validation: Vec<(String, f64)>,
+    /// seconds since training started
+    pub elapsed: f64,
+    /// seconds the epoch took, including validation
+    pub duration: f64,
+    pub samples_per_second: f64,
+}
+
+fn metrics<S: Serializer>(metrics: &[(String, f64)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(metrics.iter().map(|(name, value)| (name, value)))
+}
+
+/// Sums the batches of an epoch into an [`EpochRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EpochStats {
+    batches: usize,
+    samples: usize,
+    loss: f64,
+    correct: usize,
+    gradient_norm: f64,
+}
+
+impl EpochStats {
+    pub fn add(&mut self, stats: &BatchStats) {
+        self.batches += 1;
+        self.samples += stats.samples;
+        self.loss += stats.loss;
+        self.correct += stats.correct;
+        self.gradient_norm += stats.gradient_norm;
+    }
+
+    /// the record of the epoch, which took `seconds`
+    pub fn record(
+        &self,
+        epoch: usize,
+        learning_rate: f64,
+        validation: Vec<(String, f64)>,
+        seconds: f64,
+        elapsed: f64,
+    ) -> EpochRecord {
+        let samples = self.samples.max(1) as f64;
+        EpochRecord {
+            epoch,
+            learning_rate,
+            loss: self.loss / samples,
+            accuracy: self.correct as f64 / samples,
+            gradient_norm: self.gradient_norm / self.batches.max(1) as f64,
+            validation,
+            elapsed,
+            duration: seconds,
+            samples_per_second: self.samples as f64 / seconds.max(f64::EPSILON),
+        }
+    }
+}
+
+/// Receives the records of a training run
+pub trait TrainingLogger {
+    fn log_batch(&mut self, _record: &BatchRecord) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn log_epoch(&mut self, _record: &EpochRecord) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A progress bar of every epoch and a summary line after it, on standard output
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressLogger {
+    bar: bool,
+}
+
+impl Default for ProgressLogger {
+    fn default() -> Self {
+        ProgressLogger { bar: true }
+    }
+}
+
+impl ProgressLogger {
+    /// without a bar only the summaries are printed
+    pub fn bar(mut self, bar: bool) -> Self {
+        self.bar = bar;
+        self
+    }
+}
+
+impl TrainingLogger for ProgressLogger {
+    fn log_batch(&mut self, record: &BatchRecord) -> io::Result<()> {
+        if !self.bar {
+            return Ok(());
+        }
+        let fraction = (record.batch + 1) as f64 / record.batches.max(1) as f64;
+        let filled = (fraction * 20.0) as usize;
+        print!(
+            "\rEpoch {} [{}{}] {:>6.2}% - loss {:.4} - {:.0} samples/s",
+            record.epoch,
+            "█".repeat(filled),
+            "_".repeat(20 - filled.min(20)),
+            fraction * 100.0,
+            record.loss,
+            record.samples_per_second
+        );
+        stdout().flush()
+    }
+
+    fn log_epoch(&mut self, record: &EpochRecord) -> io::Result<()> {
+        if self.bar {
+            println!();
+        }
+        let mut line = format!(
+            "Epoch {} - learning rate {} - loss {:.4} - accuracy {:.4}",
+            record.epoch, record.learning_rate, record.loss, record.accuracy
+        );
+        for (name, value) in record.validation.iter() {
+            let _ = write!(line, " - validation {} {:.4}", name, value);
+        }
+        println!(
+            "{} - {:.1}s, {:.0} samples/s",
+            line, record.duration, record.samples_per_second
+        );
+        Ok(())
+    }
+}
+
+/// Writes a row per epoch, or every few batches, as CSV.
+///
+/// Every metric of the validation becomes a column prefixed by `validation_`.
+pub struct CsvLogger<W: Write> {
+    writer: W,
+    every_batches: usize,
+    header: bool,
+}
+
+impl<W: Write> CsvLogger<W> {
+    /// logs every epoch
+    pub fn new(writer: W) -> Self {
+        CsvLogger {
+            writer,
+            every_batches: 0,
+            header: true,
+        }
+    }
+
+    /// logs every `batches` batches instead of the epochs
+    pub fn batches(mut self, batches: usize) -> Self {
+        self.every_batches = batches;
+        self
+    }
+
+    /// whether to start with a header line, e.g. not when appending to a log
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self, columns: &str) -> io::Result<()> {
+        if self.header {
+            writeln!(self.writer, "{}", columns)?;
+            self.header = false;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> TrainingLogger for CsvLogger<W> {
+    fn log_batch(&mut self, r: &BatchRecord) -> io::Result<()> {
+        if self.every_batches == 0 || !(r.batch + 1).is_multiple_of(self.every_batches) {
+            return Ok(());
+        }
+        self.write_header(
+            "epoch,batch,samples,loss,accuracy,learning_rate,gradient_norm,elapsed,samples_per_second",
+        )?;
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{},{}",
+            r.epoch,
+            r.batch,
+            r.samples,
+            r.loss,
+            r.accuracy,
+            r.learning_rate,
+            r.gradient_norm,
+            r.elapsed,
+            r.samples_per_second
+        )
+    }
+
+    fn log_epoch(&mut self, r: &EpochRecord) -> io::Result<()> {
+        if self.every_batches > 0 {
+            return self.writer.flush();
+        }
+        let mut columns =
+            "epoch,learning_rate,loss,accuracy,gradient_norm,elapsed,duration,samples_per_second"
+                .to_string();
+        for (name, _) in r.validation.iter() {
+            let _ = write!(columns, ",validation_{}", name);
+        }
+        self.write_header(&columns)?;
+
+        write!(
+            self.writer,
+            "{},{},{},{},{},{},{},{}",
+            r.epoch,
+            r.learning_rate,
+            r.loss,
+            r.accuracy,
+            r.gradient_norm,
+            r.elapsed,
+            r.duration,
+            r.samples_per_second
+        )?;
+        for (_, value) in r.validation.iter() {
+            write!(self.writer, ",{}", value)?;
+        }
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+/// Reads back the epochs written by a [`CsvLogger`], e.g. to plot them.
+///
+/// Columns it does not know are skipped.
+pub fn read_epochs(csv: &str) -> io::Result<Vec<EpochRecord>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<&str> = match lines.next() {
+        Some(header) => header.split(',').map(str::trim).collect(),
+        None => return Ok(vec![]),
+    };
+
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            let mut record = EpochRecord::default();
+            for (column, value) in header.iter().zip(line.split(',')) {
+                let value: f64 = value.trim().parse().map_err(|_| {
+                    invalid(format!("Invalid {} `{}` on line {}", column, value, i + 2))
+                })?;
+                match *column {
+                    "epoch" => record.epoch = value as usize,
+                    "learning_rate" => record.learning_rate = value,
+                    "loss" => record.loss = value,
+                    "accuracy" => record.accuracy = value,
+                    "gradient_norm" => record.gradient_norm = value,
+                    "elapsed" => record.elapsed = value,
+                    "duration" => record.duration = value,
+                    "samples_per_second" => record.samples_per_second = value,
+                    column => {
+                        if let Some(metric) = column.strip_prefix("validation_") {
+                            record.validation.push((metric.to_string(), value));
+                        }
+                    }
+                }
+            }
+            Ok(record)
+        })
+        .collect()
+}
+
+/// Writes every record as a line of JSON, with a `type` of `batch` or `epoch`
+pub struct JsonLinesLogger<W: Write> {
+    writer: W,
+    every_batches: usize,
+}
+
+#[derive(Serialize)]
+struct Line<'a, R> {
+    r#type: &'a str,
+    #[serde(flatten)]
+    record: &'a R,
+}
+
+impl<W: Write> JsonLinesLogger<W> {
+    /// logs every epoch
+    pub fn new(writer: W) -> Self {
+        JsonLinesLogger {
+            writer,
+            every_batches: 0,
+        }
+    }
+
+    /// also logs every `batches` batches
+    pub fn batches(mut self, batches: usize) -> Self {
+        self.every_batches = batches;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write<R: Serialize>(&mut self, r#type: &str, record: &R) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &Line { r#type, record })?;
+        writeln!(self.writer)
+    }
+}
+
+impl<W: Write> TrainingLogger for JsonLinesLogger<W> {
+    fn log_batch(&mut self, record: &BatchRecord) -> io::Result<()> {
+        if self.every_batches == 0 || !(record.batch + 1).is_multiple_of(self.every_batches) {
+            return Ok(());
+        }
+        self.write("batch", record)
+    }
+
+    fn log_epoch(&mut self, record: &EpochRecord) -> io::Result<()> {
+        self.write("epoch", record)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_epochs, BatchRecord, BatchStats, CsvLogger, EpochStats, JsonLinesLogger,
+        TrainingLogger,
+    };
+
+    fn log(logger: &mut impl TrainingLogger) {
+        let stats = BatchStats {
+            samples: 4,
+            loss: 2.0,
+            correct: 3,
+            gradient_norm: 0.5,
+        };
+        let mut epoch = EpochStats::default();
+        for batch in 0..2 {
+            epoch.add(&stats);
+            let record = BatchRecord::new(0, batch, 2, &stats, 0.1, 0.5, 1.0);
+            logger.log_batch(&record).unwrap();
+        }
+        let validation = vec![("accuracy".to_string(), 0.5)];
+        logger
+            .log_epoch(&epoch.record(0, 0.1, validation, 2.0, 2.0))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_csv_logs() {
+        let mut epochs = CsvLogger::new(vec![]);
+        log(&mut epochs);
+        let text = String::from_utf8(epochs.into_inner()).unwrap();
+        assert_eq!(
+            text,
+            "epoch,learning_rate,loss,accuracy,gradient_norm,elapsed,duration,samples_per_second,validation_accuracy\n\
+             0,0.1,0.5,0.75,0.5,2,2,4,0.5\n"
+        );
+
+        let records = read_epochs(&text).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].accuracy, 0.75);
+        assert_eq!(records[0].validation, vec![("accuracy".to_string(), 0.5)]);
+
+        let mut batches = CsvLogger::new(vec![]).batches(2).header(false);
+        log(&mut batches);
+        let text = String::from_utf8(batches.into_inner()).unwrap();
+        assert_eq!(text, "0,1,4,0.5,0.75,0.1,0.5,1,8\n");
+    }
+
+    #[test]
+    fn test_json_lines_logs() {
+        let mut logger = JsonLinesLogger::new(vec![]).batches(1);
+        log(&mut logger);
+        let text = String::from_utf8(logger.into_inner()).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["type"], "batch");
+        assert_eq!(lines[1]["batch"], 1);
+        assert_eq!(lines[2]["type"], "epoch");
+        assert_eq!(lines[2]["accuracy"], 0.75);
+        assert_eq!(lines[2]["validation"]["accuracy"], 0.5);
+    }
+}
@@ -1,106 +1,113 @@
-use std::{iter::Map, slice::Chunks};
+use std::iter::Map;
 
 use crate::{
-    calculus::functions::Function,
     linear_algebra::Matrix,
-    machine_learning::dataset::{DataSet, DataVector},
+    machine_learning::dataset::{
+        data_loader::{Batch, Batches, DataLoader},
+        DataSet, DataVector,
+    },
 };
 
 use rayon::prelude::*;
 
-use super::{cost_functions::CostFunction, NeuralNetwork};
+use super::{
+    cost_functions::CostFunction,
+    layers::Layer,
+    learning_rate::LrSchedule,
+    logging::BatchStats,
+    training_error::{NonFiniteValue, TrainingError},
+    NeuralNetwork,
+};
 
 // NN Methods
 impl NeuralNetwork {
-    fn step(&mut self, other: Self, learning_rate: f64) {
-        for (a, b) in self._weigths.iter_mut().zip(other._weigths) {
-            *a -= b * learning_rate;
+    fn step(&mut self, learning_rate: f64) {
+        for (parameter, gradient) in self._model.parameters_mut() {
+            parameter
+                .iter_mut()
+                .zip(gradient.iter())
+                .for_each(|(p, g)| *p -= g * learning_rate);
         }
 
-        for (a, b) in self._biases.iter_mut().zip(other._biases) {
-            *a -= b * learning_rate;
-        }
+        self._model.apply_constraints();
     }
 
-    pub fn propagate(&self, input: &Matrix, activation_function: fn(&mut f64)) -> Matrix {
-        let mut propagating_nodes: &Matrix = input;
-        let mut x = Matrix::zeros(0, 0);
-
-        for (weight_matrix, bias_vector) in self._weigths.iter().zip(&self._biases) {
-            x = (weight_matrix * propagating_nodes + bias_vector).apply_into(activation_function);
-            propagating_nodes = &x;
-        }
-
-        x
+    /// index of the first layer with a non-finite parameter
+    fn non_finite_layer(&self) -> Option<usize> {
+        self._model
+            .layers()
+            .iter()
+            .position(|l| l.parameters().iter().any(|p| !p.is_finite()))
     }
 
-    /// Returns a vector of intermediate node_vectors including the output vector and excluding the input vector
-    fn propagate_returning_all_nodes_prior_to_activation(
-        &self,
-        input: &Matrix,
-        activation_function: fn(&mut f64),
-    ) -> Vec<Matrix> {
-        // initialize resulting array;
-        let mut nodes_array: Vec<Matrix> = vec![];
-
-        let mut propagating_nodes: Matrix = input.clone();
-
-        for (weight_matrix, bias_vector) in self._weigths.iter().zip(&self._biases) {
-            nodes_array.push(weight_matrix * propagating_nodes + bias_vector);
-            propagating_nodes = nodes_array
-                .last()
-                .unwrap()
-                .clone()
-                .apply_into(activation_function);
-        }
-
-        nodes_array
+    /// propagates a single input, or a batch of inputs stored as columns
+    pub fn propagate(&self, input: &Matrix) -> Matrix {
+        self._model.forward(input)
     }
 
-    // train using stochastic gradient descent
-    pub fn train<'a>(
+    // train using stochastic gradient descent over one epoch of `loader`
+    // batch-stepped schedules advance after every batch,
+    // epoch-stepped schedules are advanced by the caller
+    //
+    // every batch yields an error if a non-finite value shows up,
+    // the network should not be trained any further after that
+    pub fn train<'a, 'd: 'a>(
         &'a mut self,
-        data_set: &'a DataSet,
-        batch_size: usize,
-        learning_rate: f64,
-        activation_function: &'a Function,
+        loader: &mut DataLoader<'d>,
+        schedule: &'a mut LrSchedule,
         cost_function: &'a CostFunction,
-    ) -> Map<Chunks<'_, DataVector>, impl FnMut(&'a [DataVector])> {
-        data_set
-            .training_data
-            .chunks(batch_size)
-            .map(move |data_slice: &'a [DataVector]| {
-                self.step(
-                    self.calculate_batch_step(data_slice, activation_function, cost_function)
-                        .unwrap(),
-                    learning_rate / data_slice.len() as f64,
-                )
-            })
+    ) -> Map<Batches<'d>, impl FnMut(Batch<'d>) -> Result<(), TrainingError> + 'a> {
+        let mut index = 0;
+        loader.epoch().map(move |batch: Batch<'d>| {
+            self.train_batch(&batch, schedule, cost_function, index)?;
+            index += 1;
+            Ok(())
+        })
+    }
+
+    /// Takes a single step of stochastic gradient descent on `batch`,
+    /// `index` is the position of the batch within the epoch, reported in errors.
+    ///
+    /// [`NeuralNetwork::train`] calls this for every batch of an epoch,
+    /// a training loop can call it directly to act between batches, e.g. to save a checkpoint.
+    /// The returned statistics are measured before the step.
+    pub fn train_batch(
+        &mut self,
+        batch: &Batch,
+        schedule: &mut LrSchedule,
+        cost_function: &CostFunction,
+        index: usize,
+    ) -> Result<BatchStats, TrainingError> {
+        let stats = self.calculate_batch_step(batch, cost_function, index)?;
+        self.step(schedule.learning_rate() / batch.len() as f64);
+        schedule.step_batch();
+
+        if let Some(layer) = self.non_finite_layer() {
+            return Err(TrainingError::NonFinite {
+                value: NonFiniteValue::Weight,
+                layer,
+                batch: index,
+            });
+        }
+        Ok(stats)
     }
 
     // train using stochastic gradient descent
     pub fn train_verbose(
         &mut self,
-        data_set: &DataSet,
-        batch_size: usize,
-        learning_rate: f64,
-        activation_function: &Function,
+        loader: &mut DataLoader,
+        schedule: &mut LrSchedule,
         cost_function: &CostFunction,
-    ) {
-        let map = self.train(
-            data_set,
-            batch_size,
-            learning_rate,
-            activation_function,
-            cost_function,
-        );
+    ) -> Result<(), TrainingError> {
+        let mut map = self.train(loader, schedule, cost_function);
 
         let total_iterations = map.len() as f64;
         let mut current_iteration = 1.0;
 
         let mut loading_indicator: [char; 10] = ['_'; 10];
 
-        map.for_each(|_| {
+        let result = map.try_for_each(|batch_result| {
+            batch_result?;
             current_iteration += 1.0;
             let fraction = (current_iteration - 1.0) / total_iterations;
 
@@ -110,84 +117,79 @@ impl NeuralNetwork {
                 "\rTraining in progress: {} - {:0>3.2}% complete",
                 loading_indicator.iter().collect::<String>(),
                 fraction * 100.0,
-            )
+            );
+            Ok(())
         });
-        println!("");
+        println!();
+        result
     }
 
+    /// Propagates the batch through every layer and back,
+    /// leaving the summed gradients of the batch in the layers.
     fn calculate_batch_step(
-        &self,
-        data_set: &[DataVector],
-        activation_function: &Function,
+        &mut self,
+        batch: &Batch,
         cost_function: &CostFunction,
-    ) -> Option<Self> {
-        let mut delta_network = NeuralNetwork::zeros(self._shape.clone());
-        for d in data_set.iter() {
-            let training_data = d;
-            let mut nodes = self.propagate_returning_all_nodes_prior_to_activation(
-                &training_data.data,
-                activation_function.activate(),
-            );
-
-            let nodes_cur = nodes.pop()?;
-            let mut index = nodes.len();
-
-            let difference = nodes_cur.clone().apply_into(activation_function.activate())
-                - training_data.expected_matrix(nodes_cur.get_dims());
-            let mut delta_cost_by_delta_nodes = difference.apply_into(cost_function.derive());
-
-            let mut delta_nodes_by_delta_activation =
-                nodes_cur.apply_into(activation_function.derive());
-
-            loop {
-                let nodes_cur = nodes.pop().unwrap_or(training_data.data.clone());
-                let weights_cur = &self._weigths[index];
-
-                // calculate and store bias delta for each layer
-                let delta_cost_by_delta_biases =
-                    delta_cost_by_delta_nodes.component_mul(&delta_nodes_by_delta_activation);
-                delta_network._biases[index] = delta_cost_by_delta_biases;
-
-                // calculate and store weight delta for each layer
-                let delta_cost_by_delta_weights = &delta_network._biases[index]
-                    * nodes_cur
-                        .clone()
-                        .apply_into(activation_function.activate())
-                        .transpose();
-
-                if index == 0 {
-                    delta_network._weigths[index] =
-                        &delta_network._biases[index] * nodes_cur.transpose();
-                    break;
-                }
-
-                delta_network._weigths[index] = delta_cost_by_delta_weights;
-
-                // calculate new delta for nodes
-                delta_cost_by_delta_nodes = weights_cur.transpose() * &delta_network._biases[index];
-
-                // calculate new activation function delta
-                delta_nodes_by_delta_activation =
-                    nodes_cur.apply_into(activation_function.derive());
+        index: usize,
+    ) -> Result<BatchStats, TrainingError> {
+        let non_finite = |value, layer| TrainingError::NonFinite {
+            value,
+            layer,
+            batch: index,
+        };
+
+        // every sample is a column of the input
+        let mut nodes = batch.inputs.clone();
+
+        self._model.zero_gradients();
+        for (index, layer) in self._model.layers_mut().iter_mut().enumerate() {
+            nodes = layer.forward_train(&nodes);
+            if !nodes.is_finite() {
+                return Err(non_finite(NonFiniteValue::Activation, index));
+            }
+        }
 
-                index -= 1;
+        let expected = batch.expected(nodes.get_dims().0);
+        let loss = cost_function.calc_cost()(&nodes, &expected).iter().sum();
+        let correct = batch
+            .samples
+            .iter()
+            .enumerate()
+            .filter(|(i, sample)| sample.target().is_correct(&nodes.column(*i)))
+            .count();
+        let mut delta_cost_by_delta_nodes = cost_function.derive()(&nodes, &expected);
+
+        for (index, layer) in self._model.layers_mut().iter_mut().enumerate().rev() {
+            delta_cost_by_delta_nodes = layer.backward(&delta_cost_by_delta_nodes);
+            if layer.gradients().iter().any(|g| !g.is_finite()) {
+                return Err(non_finite(NonFiniteValue::Gradient, index));
             }
         }
 
-        Some(delta_network)
+        let gradients = self
+            ._model
+            .parameters_mut()
+            .into_iter()
+            .map(|(_, gradient)| gradient)
+            .collect();
+        let gradient_norm = self._gradient_clipping.clip(gradients, batch.len() as f64);
+
+        Ok(BatchStats {
+            samples: batch.len(),
+            loss,
+            correct,
+            gradient_norm,
+        })
     }
 
-    pub fn test(&self, data_set: &DataSet, activation_function: &Function) -> f64 {
+    /// the fraction of the testing data predicted correctly, see [`Target::is_correct`](crate::machine_learning::dataset::Target::is_correct)
+    pub fn test(&self, data_set: &DataSet) -> f64 {
         let data_set_length = data_set.testing_data.len() as f64;
 
-        let correct_filtered = data_set.testing_data.par_iter().filter(|image| {
-            let res = self.propagate(&image.data, activation_function.activate());
-            if res.index_of_max() == image.expected_matrix(res.get_dims()).index_of_max() {
-                return true;
-            }
-
-            false
-        });
+        let correct_filtered = data_set
+            .testing_data
+            .par_iter()
+            .filter(|sample| sample.target().is_correct(&self.propagate(&sample.data)));
 
         let u = correct_filtered.collect::<Vec<&DataVector>>().len();
         u as f64 / data_set_length
@@ -199,51 +201,55 @@ mod tests {
     use crate::{
         calculus::functions::Function,
         linear_algebra::Matrix,
-        machine_learning::neural_network::{cost_functions::CostFunction, test_config::init_network},
+        machine_learning::dataset::{
+            data_loader::{Batch, DataLoader, Sampling},
+            DataSet, DataVector, Target,
+        },
+        machine_learning::neural_network::{
+            cost_functions::CostFunction,
+            layers::{
+                activation::Activation, dense::Dense, dropout::Dropout, sequential::Sequential,
+                Layer,
+            },
+            learning_rate::LrSchedule,
+            regularization::Regularization,
+            test_config::init_network,
+            training_error::{NonFiniteValue, TrainingError},
+            NeuralNetwork,
+        },
     };
 
     #[test]
     fn test_both_propagation_methods_are_equivalent() {
-        let (nn, ds) = init_network(vec![2]);
-
-        let f = Function::sigmoid();
+        let (mut nn, ds) = init_network(vec![2], Function::sigmoid());
 
         let x = nn
-            .propagate_returning_all_nodes_prior_to_activation(
-                &ds.training_data.first().unwrap().data,
-                f.activate(),
-            )
-            .last()
-            .unwrap()
-            .clone()
-            .apply_into(f.activate());
-        let y = nn.propagate(&ds.training_data.first().unwrap().data, f.activate());
+            .model_mut()
+            .forward_train(&ds.training_data.first().unwrap().data);
+        let y = nn.propagate(&ds.training_data.first().unwrap().data);
 
         assert_eq!(x, y);
     }
 
     #[test]
     fn test_multiple_propagation_calls() {
-        let (nn, ds) = init_network(vec![2]);
-        let f = Function::sigmoid();
+        let (nn, ds) = init_network(vec![2], Function::sigmoid());
 
-        let y = nn.propagate(&ds.training_data.first().unwrap().data, f.activate());
-        let x = nn.propagate(&ds.training_data.first().unwrap().data, f.activate());
+        let y = nn.propagate(&ds.training_data.first().unwrap().data);
+        let x = nn.propagate(&ds.training_data.first().unwrap().data);
 
         assert_eq!(x, y);
     }
 
     #[test]
     fn test_cost() {
-        let (nn, ds) = init_network(vec![2]);
-        let f = Function::sigmoid();
+        let (nn, ds) = init_network(vec![2], Function::sigmoid());
         let c = CostFunction::quadratic();
 
         let datavec = ds.testing_data.first().unwrap();
 
-        let output = nn.propagate(&datavec.data, f.activate());
-        let res = output.clone() - datavec.expected_matrix(output.get_dims());
-        let cost = res.apply_into(c.calc_cost());
+        let output = nn.propagate(&datavec.data);
+        let cost = c.calc_cost()(&output, &datavec.expected_matrix(output.get_dims()));
 
         assert!(cost[(0, 0)] - 0.3 < 0.01);
         assert!(cost[(1, 0)] - 0.2 < 0.01);
@@ -251,15 +257,13 @@ mod tests {
 
     #[test]
     fn test_cost_derivative() {
-        let (nn, ds) = init_network(vec![2]);
-        let f = Function::sigmoid();
+        let (nn, ds) = init_network(vec![2], Function::sigmoid());
         let c = CostFunction::quadratic();
 
         let datavec = ds.testing_data.first().unwrap();
 
-        let output = nn.propagate(&datavec.data, f.activate());
-        let res = output.clone() - datavec.expected_matrix(output.get_dims());
-        let derivative = res.apply_into(c.derive());
+        let output = nn.propagate(&datavec.data);
+        let derivative = c.derive()(&output, &datavec.expected_matrix(output.get_dims()));
 
         assert!(derivative[(0, 0)] - 1.1 < 0.01);
         assert!(derivative[(1, 0)] + 0.9 < 0.01);
@@ -267,85 +271,202 @@ mod tests {
 
     #[test]
     fn test_batch_step() {
-        let (nn, ds) = init_network(vec![2]);
+        let (mut nn, ds) = init_network(vec![2], Function::sigmoid());
         let batch_step = nn.calculate_batch_step(
-            &ds.training_data[0..1],
-            &Function::sigmoid(),
+            &Batch::new(ds.training_data[0..1].iter().collect()),
             &CostFunction::quadratic(),
+            0,
         );
 
-        match batch_step {
-            Some(step) => {
-                assert!(step._biases[0][(0, 0)] - 0.0012 < 0.0001);
-                assert!(step._biases[0][(1, 0)] - 0.0012 < 0.0001);
+        if let Err(e) = batch_step {
+            panic!("No batch step calculated. {}", e);
+        }
 
-                assert!(step._weigths[0][(0, 0)] == 0.0);
-                assert!(step._weigths[0][(1, 0)] == 0.0);
+        // the dense layers are followed by activation layers
+        let layers = nn.model().layers();
+        let (weights_0, biases_0) = (layers[0].gradients()[0], layers[0].gradients()[1]);
+        let (weights_1, biases_1) = (layers[2].gradients()[0], layers[2].gradients()[1]);
 
-                assert!(step._biases[1][(0, 0)] - 0.27 < 0.01);
-                assert!(step._biases[1][(1, 0)] - 0.27 < 0.01);
+        assert!(biases_0[(0, 0)] - 0.0012 < 0.0001);
+        assert!(biases_0[(1, 0)] - 0.0012 < 0.0001);
 
-                assert!(step._weigths[1][(0, 0)] - 0.14 < 0.01);
-                assert!(step._weigths[1][(1, 0)] + 0.11 < 0.01);
-                assert!(step._weigths[1][(0, 1)] - 0.14 < 0.01);
-                assert!(step._weigths[1][(1, 1)] + 0.11 < 0.01);
-            }
-            None => panic!("No batch step calculated."),
-        }
+        assert!(weights_0[(0, 0)] == 0.0);
+        assert!(weights_0[(1, 0)] == 0.0);
+
+        assert!(biases_1[(0, 0)] - 0.27 < 0.01);
+        assert!(biases_1[(1, 0)] - 0.27 < 0.01);
+
+        assert!(weights_1[(0, 0)] - 0.14 < 0.01);
+        assert!(weights_1[(1, 0)] + 0.11 < 0.01);
+        assert!(weights_1[(0, 1)] - 0.14 < 0.01);
+        assert!(weights_1[(1, 1)] + 0.11 < 0.01);
     }
 
     #[test]
-    fn test_training_sigmoid() {
-        let (mut nn, ds) = init_network(vec![3]);
+    fn test_batch_step_regularization() {
+        let (mut nn, ds) = init_network(vec![2], Function::sigmoid());
+        let c = CostFunction::quadratic();
+        let gradients = |nn: &NeuralNetwork| -> Vec<Matrix> {
+            nn.model().gradients().into_iter().cloned().collect()
+        };
+
+        nn.calculate_batch_step(&Batch::new(ds.training_data[0..2].iter().collect()), &c, 0)
+            .unwrap();
+        let plain = gradients(&nn);
 
+        nn.set_regularization(Regularization {
+            l2: 0.5,
+            ..Default::default()
+        });
+        nn.calculate_batch_step(&Batch::new(ds.training_data[0..2].iter().collect()), &c, 0)
+            .unwrap();
+        let decayed = gradients(&nn);
+
+        // [weights_0, biases_0, weights_1, biases_1], 2 samples * l2 * w
+        let difference = decayed[2].clone() - &plain[2];
+        assert!(difference.iter().all(|d| (d - 0.1).abs() < 1e-12));
+        assert_eq!(decayed[1], plain[1]);
+        assert_eq!(decayed[3], plain[3]);
+    }
+
+    #[test]
+    fn test_training_with_dropout() {
+        let (_, ds) = init_network(vec![], Function::sigmoid());
         let f = Function::sigmoid();
+        let mut model = Sequential::default();
+        model.push(Dense::scalar(1, 8, 0.1));
+        model.push(Activation::new(f));
+        model.push(Dropout::new(0.2));
+        model.push(Dense::scalar(8, 2, 0.1));
+        model.push(Activation::new(f));
+        let mut nn = NeuralNetwork::new(model);
+
+        let c = CostFunction::quadratic();
+
+        nn.train(
+            &mut DataLoader::new(&ds.training_data, 4),
+            &mut LrSchedule::constant(1.0),
+            &c,
+        )
+        .for_each(|_| {});
+
+        assert!(nn.test(&ds) > 0.7);
+    }
+
+    #[test]
+    fn test_training_stops_on_non_finite_values() {
+        let (mut nn, mut ds) = init_network(vec![2], Function::sigmoid());
+        ds.training_data[9] = DataVector::new(Matrix::from_vec(1, 1, vec![f64::NAN]), 0);
+
         let c = CostFunction::quadratic();
+        let mut loader = DataLoader::new(&ds.training_data, 4).with_sampling(Sampling::Sequential);
+        let result: Result<(), TrainingError> = nn
+            .train(&mut loader, &mut LrSchedule::constant(1.0), &c)
+            .collect();
+
+        assert_eq!(
+            result,
+            Err(TrainingError::NonFinite {
+                value: NonFiniteValue::Activation,
+                layer: 0,
+                batch: 2,
+            })
+        );
+    }
 
-        nn.train(&ds, 4, 1.0, &f, &c).for_each(|_| {});
+    #[test]
+    fn test_training_sigmoid() {
+        let (mut nn, ds) = init_network(vec![3], Function::sigmoid());
+
+        let c = CostFunction::quadratic();
+
+        nn.train(
+            &mut DataLoader::new(&ds.training_data, 4),
+            &mut LrSchedule::constant(1.0),
+            &c,
+        )
+        .for_each(|_| {});
 
         assert!(
             0 == nn
-                .propagate(&Matrix::from_vec(1, 1, vec![0.7]), f.activate())
+                .propagate(&Matrix::from_vec(1, 1, vec![0.7]))
                 .index_of_max()
         );
 
         assert!(
             1 == nn
-                .propagate(&Matrix::from_vec(1, 1, vec![0.4]), f.activate())
+                .propagate(&Matrix::from_vec(1, 1, vec![0.4]))
                 .index_of_max()
         );
     }
 
     #[test]
     fn test_training_normal_arctan() {
-        let (mut nn, ds) = init_network(vec![3]);
+        let (mut nn, ds) = init_network(vec![3], Function::normal_arctan());
 
-        let f = Function::normal_arctan();
         let c = CostFunction::quadratic();
 
-        nn.train(&ds, 4, 1.0, &f, &c).for_each(|_| {});
+        nn.train(
+            &mut DataLoader::new(&ds.training_data, 4),
+            &mut LrSchedule::constant(1.0),
+            &c,
+        )
+        .for_each(|_| {});
 
         assert!(
             0 == nn
-                .propagate(&Matrix::from_vec(1, 1, vec![0.7]), f.activate())
+                .propagate(&Matrix::from_vec(1, 1, vec![0.7]))
                 .index_of_max()
         );
         assert!(
             1 == nn
-                .propagate(&Matrix::from_vec(1, 1, vec![0.4]), f.activate())
+                .propagate(&Matrix::from_vec(1, 1, vec![0.4]))
                 .index_of_max()
         );
     }
 
     #[test]
     fn test_testing_network() {
-        let (mut nn, ds) = init_network(vec![3]);
+        let (mut nn, ds) = init_network(vec![3], Function::normal_arctan());
 
-        let f = Function::normal_arctan();
         let c = CostFunction::quadratic();
 
-        nn.train(&ds, 1, 1.0, &f, &c).for_each(|_| {});
+        nn.train(
+            &mut DataLoader::new(&ds.training_data, 1),
+            &mut LrSchedule::constant(1.0),
+            &c,
+        )
+        .for_each(|_| {});
+
+        assert!(nn.test(&ds) > 0.7);
+    }
+
+    #[test]
+    fn test_training_regression() {
+        let (mut nn, _) = init_network(vec![3], Function::sigmoid());
+        let sample = |y: f64| {
+            let target = Target::Values(Matrix::from_vec(2, 1, vec![y, 1.0 - y]));
+            DataVector::with_target(Matrix::from_vec(1, 1, vec![y]), target)
+        };
+        let ds = DataSet {
+            validation_data: vec![],
+            training_data: (0..10000).map(|_| sample(rand::random())).collect(),
+            testing_data: (0..100).map(|x| sample(x as f64 / 100.0)).collect(),
+        };
+
+        let c = CostFunction::quadratic();
+        for _ in 0..5 {
+            nn.train(
+                &mut DataLoader::new(&ds.training_data, 4),
+                &mut LrSchedule::constant(1.0),
+                &c,
+            )
+            .for_each(|_| {});
+        }
 
-        assert!(nn.test(&ds, &f) > 0.7);
+        let output = nn.propagate(&Matrix::from_vec(1, 1, vec![0.2]));
+        assert!((output[(0, 0)] - 0.2).abs() < 0.1);
+        assert!((output[(1, 0)] - 0.8).abs() < 0.1);
+        assert!(nn.test(&ds) > 0.9);
     }
 }
@@ -1,182 +1,361 @@
-use std::{error::Error, io};
+use std::{error::Error, io, path::Path, str::Lines};
 
-use rand::distributions::Uniform;
-
-use crate::linear_algebra::Matrix;
+use crate::{
+    calculus::functions::Function,
+    linear_algebra::Matrix,
+    machine_learning::dataset::{data_loader::DataLoader, preprocessing::Preprocessing},
+};
 
 pub mod methods;
 
+pub mod callbacks;
+pub mod checkpoint;
 pub mod cost_functions;
+pub mod early_stopping;
+pub mod evaluation;
+pub mod gradient_clipping;
+pub mod layers;
+pub mod learning_rate;
+pub mod logging;
+pub mod model_file;
+pub mod onnx;
+pub mod regularization;
+pub mod training_error;
+
+use checkpoint::{Checkpoint, Progress};
+use gradient_clipping::GradientClipping;
+use layers::{activation::Activation, dense::Dense, sequential::Sequential, Layer, LayerRecord};
+use learning_rate::LrSchedule;
+use model_file::Metadata;
+use regularization::Regularization;
+
+/// Whether the network is being trained or evaluated;
+/// dropout is only applied while training
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Train,
+    Eval,
+}
 
 #[derive(Debug, PartialEq)]
 /// ### Parameters
-/// - `_weights` : `Vec<Matrix>`
-/// - `hidden_layer` : `Vec<Matrix>`
+/// - `_model` : the layers of the network
+/// - `_gradient_clipping` : applied to the gradient before every step
+/// - `_regularization` : applied to every layer with weights
+/// - `_preprocessing` : the input transform of the training data, saved with the network
+/// - `_metadata` : how the network was trained, saved with the network
 pub struct NeuralNetwork {
-    _weigths: Vec<Matrix>,
-    _biases: Vec<Matrix>,
-    _shape: Vec<usize>,
+    _model: Sequential,
+    _gradient_clipping: GradientClipping,
+    _regularization: Regularization,
+    _preprocessing: Preprocessing,
+    _metadata: Metadata,
 }
 
 // NN contructors / destructors
 impl NeuralNetwork {
-    pub fn random(shape: Vec<usize>) -> NeuralNetwork {
-        let length = shape.len();
-        let weigths: Vec<Matrix> = shape[1..length]
-            .iter()
-            .zip(&shape[0..length - 1])
-            .map(|(a, b)| Matrix::from_distribution(*a, *b, &Uniform::new(-1.0, 1.0)))
-            .collect();
-        let biases: Vec<Matrix> = shape[1..length]
-            .iter()
-            .map(|a| Matrix::from_distribution(*a, 1, &Uniform::new(-1.0, 1.0)))
-            .collect();
-
+    pub fn new(model: Sequential) -> NeuralNetwork {
         NeuralNetwork {
-            _weigths: weigths,
-            _biases: biases,
-            _shape: shape,
+            _model: model,
+            _gradient_clipping: GradientClipping::default(),
+            _regularization: Regularization::default(),
+            _preprocessing: Preprocessing::default(),
+            _metadata: Metadata::default(),
         }
     }
 
-    pub fn zeros(shape: Vec<usize>) -> NeuralNetwork {
-        NeuralNetwork::scalar(shape, 0.0)
+    /// a fully-connected network, every `Dense` layer is followed by `activation_function`
+    pub fn random(shape: Vec<usize>, activation_function: Function) -> NeuralNetwork {
+        NeuralNetwork::fully_connected(&shape, activation_function, Dense::random)
     }
 
-    pub fn scalar(shape: Vec<usize>, scale: f64) -> NeuralNetwork {
-        let length = shape.len();
-        let weigths: Vec<Matrix> = shape[1..length]
-            .iter()
-            .zip(&shape[0..length - 1])
-            .map(|(a, b)| Matrix::from_value(*a, *b, scale))
-            .collect();
-        let biases: Vec<Matrix> = shape[1..length]
-            .iter()
-            .map(|a| Matrix::from_value(*a, 1, scale))
-            .collect();
+    pub fn zeros(shape: Vec<usize>, activation_function: Function) -> NeuralNetwork {
+        NeuralNetwork::scalar(shape, 0.0, activation_function)
+    }
 
-        NeuralNetwork {
-            _weigths: weigths,
-            _biases: biases,
-            _shape: shape,
+    pub fn scalar(shape: Vec<usize>, scale: f64, activation_function: Function) -> NeuralNetwork {
+        NeuralNetwork::fully_connected(&shape, activation_function, |a, b| {
+            Dense::scalar(a, b, scale)
+        })
+    }
+
+    fn fully_connected(
+        shape: &[usize],
+        activation_function: Function,
+        dense: impl Fn(usize, usize) -> Dense,
+    ) -> NeuralNetwork {
+        let mut model = Sequential::default();
+        for (a, b) in shape.iter().zip(&shape[1..]) {
+            model.push(dense(*a, *b));
+            model.push(Activation::new(activation_function));
         }
+
+        NeuralNetwork::new(model)
     }
-}
 
-// NN save / load
-impl NeuralNetwork {
-    pub fn save(&self, file_path: &str) -> Result<(), io::Error> {
-        let mut contents = String::new();
-
-        contents += &self
-            ._shape
-            .iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
-        contents += "\n\n";
-
-        for w in self._weigths.iter() {
-            contents += &w.to_str();
+    pub fn model(&self) -> &Sequential {
+        &self._model
+    }
+
+    pub fn model_mut(&mut self) -> &mut Sequential {
+        &mut self._model
+    }
+
+    /// The number of features of a raw input, taken from the preprocessing or else the first layer,
+    /// `None` if neither fixes it.
+    pub fn input_size(&self) -> Option<usize> {
+        if !self._preprocessing.is_empty() {
+            return self._preprocessing.input_size();
         }
 
-        contents += "\n";
+        let record = self._model.layers().first()?.record();
+        match record.kind.as_str() {
+            "dense" => record.get_state(0).ok().map(|w| w.get_dims().1),
+            "batchnorm1d" => record.get_config("features").ok(),
+            "conv2d" | "batchnorm2d" => {
+                let size = |key| record.get_config::<usize>(key).ok();
+                Some(size("channels")? * size("height")? * size("width")?)
+            }
+            _ => None,
+        }
+    }
+
+    /// The number of outputs, taken from the last layer that fixes it, `None` if no layer does.
+    pub fn output_size(&self) -> Option<usize> {
+        for layer in self._model.layers().iter().rev() {
+            let record = layer.record();
+            match record.kind.as_str() {
+                "activation" | "dropout" | "batchnorm1d" | "layernorm" => continue,
+                "dense" => return record.get_state(0).ok().map(|w| w.get_dims().0),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Checks that `input` has a sample per column with the number of features of
+    /// [`NeuralNetwork::input_size`], so it can be passed to [`NeuralNetwork::predict`].
+    ///
+    /// Recurrent networks take sequences of any number of timesteps instead.
+    pub fn check_input(&self, input: &Matrix) -> Result<(), Box<dyn Error>> {
+        let rows = input.get_dims().0;
+        let first = self._model.layers().first().map(|l| l.record());
+        if let Some(record) = first.filter(|_| self._preprocessing.is_empty()) {
+            if matches!(record.kind.as_str(), "rnn" | "gru" | "lstm") {
+                let step: usize = record.get_config("input_size")?;
+                return match rows > 0 && rows.is_multiple_of(step) {
+                    true => Ok(()),
+                    false => Err(format!(
+                        "The network takes timesteps of {} values, got {} values",
+                        step, rows
+                    )
+                    .into()),
+                };
+            }
+        }
 
-        for b in self._biases.iter() {
-            contents += &b.to_str();
+        match self.input_size() {
+            Some(size) if size != input.get_dims().0 => {
+                Err(format!("The network takes {} input values, got {}", size, rows).into())
+            }
+            _ => Ok(()),
         }
+    }
+}
 
-        contents += "\n";
+// NN training configuration
+impl NeuralNetwork {
+    /// applies `regularization` to every layer with weights
+    pub fn set_regularization(&mut self, regularization: Regularization) {
+        self._regularization = regularization;
+        self._model.set_regularization(regularization);
+    }
+
+    pub fn set_gradient_clipping(&mut self, gradient_clipping: GradientClipping) {
+        self._gradient_clipping = gradient_clipping;
+    }
+
+    /// Keeps the preprocessing fitted on the training data, so it is saved with the network.
+    ///
+    /// It is not applied by [`NeuralNetwork::propagate`], raw inputs are passed through
+    /// [`Preprocessing::transform`] first.
+    pub fn set_preprocessing(&mut self, preprocessing: Preprocessing) {
+        self._preprocessing = preprocessing;
+    }
+
+    pub fn preprocessing(&self) -> &Preprocessing {
+        &self._preprocessing
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self._metadata
+    }
 
-        std::fs::write(file_path, contents)?;
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self._metadata
+    }
+}
+
+// NN save / load
+impl NeuralNetwork {
+    /// replaces the layers with the ones described by `record`, e.g. a snapshot of [`NeuralNetwork::model`]
+    pub fn restore(&mut self, record: &LayerRecord) -> Result<(), Box<dyn Error>> {
+        self._model = Sequential::from_record(record)?;
+        self._model.set_regularization(self._regularization);
         Ok(())
     }
 
+    /// writes the versioned binary format described in [`model_file`]
+    pub fn save(&self, file_path: &str) -> Result<(), io::Error> {
+        std::fs::write(file_path, model_file::encode(self))
+    }
+
+    /// Continues training from a checkpoint written by a [`checkpoint::Checkpointer`].
+    ///
+    /// Restores the layers, metadata and preprocessing of the network, the position of
+    /// `schedule`, and the seed and batch of `loader`; which have to be set up as they were
+    /// for the checkpointed run. Returns the progress to continue the training loop from.
+    pub fn resume_from(
+        &mut self,
+        file_path: impl AsRef<Path>,
+        schedule: &mut LrSchedule,
+        loader: &mut DataLoader,
+    ) -> Result<Progress, Box<dyn Error>> {
+        Ok(Checkpoint::load(file_path)?.resume(self, schedule, loader))
+    }
+
+    /// writes an ONNX graph of the network and its preprocessing, see [`onnx`]
+    pub fn export_onnx(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        Ok(std::fs::write(file_path, onnx::export(self)?)?)
+    }
+
+    /// reads a fully-connected ONNX graph, such as one written by [`NeuralNetwork::export_onnx`]
+    pub fn import_onnx(file_path: &str) -> Result<NeuralNetwork, Box<dyn Error>> {
+        onnx::import(&std::fs::read(file_path)?)
+    }
+
+    /// Reads the binary format of [`NeuralNetwork::save`], as well as the older text layouts.
     pub fn load(file_path: &str) -> Result<NeuralNetwork, Box<dyn Error>> {
-        let binding = std::fs::read(file_path)?;
-        let contents = std::str::from_utf8(&binding)?;
+        let bytes = std::fs::read(file_path)?;
+        if model_file::is_model_file(&bytes) {
+            return model_file::decode(&bytes);
+        }
+
+        let contents = std::str::from_utf8(&bytes)?;
+        let mut lines = contents.lines();
+        if lines.next() != Some("layers") {
+            return NeuralNetwork::load_legacy(contents);
+        }
+
+        // the text layout of layer records, used before the binary format
+        let record = read_record(&mut lines)?;
+        if record.kind != "sequential" {
+            return Err(format!("Expected a sequential model, found `{}`", record.kind).into());
+        }
 
+        let mut nn = NeuralNetwork::new(Sequential::from_record(&record)?);
+        if lines.next() == Some("preprocessing") {
+            nn._preprocessing = Preprocessing::from_record(&read_record(&mut lines)?)?;
+        }
+        Ok(nn)
+    }
+
+    /// Reads the layout used before layers were introduced;
+    /// the shape, the weights, and then the biases of a fully-connected network.
+    ///
+    /// Activations were not saved in that layout, so sigmoid is assumed.
+    fn load_legacy(contents: &str) -> Result<NeuralNetwork, Box<dyn Error>> {
         let mut c_split = contents.split("\n\n");
-        let shape = c_split
-            .next()
-            .ok_or("File was not properly formatted, or was empty")?
-            .split(",")
-            .map(|x| x.parse().unwrap_or(1))
-            .collect::<Vec<usize>>();
-        let w = c_split
-            .next()
-            .ok_or("File was not properly formatted, or was empty")?;
-        let b = c_split
-            .next()
-            .ok_or("File was not properly formatted, or was empty")?;
-
-        let mut weights: Vec<Matrix> = Vec::new();
-        for w_line in w.split("\n") {
-            let mut iter = w_line.split(" - ");
-            let mut shape = iter.next().ok_or("Incorrect Format weights")?.split(",");
-            let mut vals = iter
+        let mut section = || {
+            c_split
                 .next()
-                .ok_or("Incorrect Format for wieght values")?
-                .split(",")
-                .map(|x| x.parse().unwrap_or_default());
-            weights.push(Matrix::from_iterator(
-                shape
-                    .next()
-                    .ok_or("Incorrect Format for weights rows")?
-                    .parse()?,
-                shape
-                    .next()
-                    .ok_or("Incorrect Format for weights cols")?
-                    .parse()?,
-                &mut vals,
-            ))
+                .ok_or("File was not properly formatted, or was empty")
+        };
+
+        let shape = section()?
+            .trim()
+            .split(',')
+            .map(|x| {
+                x.trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid layer size `{}`", x))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let matrices = |lines: &str| -> Result<Vec<Matrix>, String> {
+            lines
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.parse())
+                .collect()
+        };
+        let weights = matrices(section()?)?;
+        let biases = matrices(section()?)?;
+
+        if weights.len() != biases.len() || weights.len() + 1 != shape.len() {
+            return Err("File was not properly formatted, or was empty".into());
+        }
+        for (i, (w, b)) in weights.iter().zip(biases.iter()).enumerate() {
+            if w.get_dims() != (shape[i + 1], shape[i]) || b.get_dims() != (shape[i + 1], 1) {
+                return Err(format!("Layer {} does not match the shape {:?}", i, shape).into());
+            }
         }
 
-        let mut biases: Vec<Matrix> = Vec::new();
-        for b_line in b.split("\n") {
-            let mut iter = b_line.split(" - ");
-            let mut shape = iter.next().ok_or("Incorrect Format biases")?.split(",");
-            let mut vals = iter
-                .next()
-                .ok_or("Incorrect Format for bias values")?
-                .split(",")
-                .map(|x| x.parse().unwrap_or_default());
-            biases.push(Matrix::from_iterator(
-                shape
-                    .next()
-                    .ok_or("Incorrect Format for weights rows")?
-                    .parse()?,
-                shape
-                    .next()
-                    .ok_or("Incorrect Format for weights cols")?
-                    .parse()?,
-                &mut vals,
-            ))
+        let mut model = Sequential::default();
+        for (w, b) in weights.into_iter().zip(biases) {
+            model.push(Dense::new(w, b));
+            model.push(Activation::new(Function::sigmoid()));
         }
 
-        Ok(NeuralNetwork {
-            _weigths: weights,
-            _biases: biases,
-            _shape: shape,
-        })
+        Ok(NeuralNetwork::new(model))
     }
 }
 
+/// reads a record written as a header line; `kind key=value ... states=n layers=m`,
+/// followed by its state matrices and nested records
+fn read_record(lines: &mut Lines) -> Result<LayerRecord, Box<dyn Error>> {
+    let header = lines.next().ok_or("Unexpected end of file")?;
+    let mut words = header.split_whitespace();
+    let mut record = LayerRecord::new(words.next().ok_or("Missing layer type")?);
+
+    for word in words {
+        let (key, value) = word
+            .split_once('=')
+            .ok_or(format!("Incorrect Format for layer config `{}`", word))?;
+        record = record.with_config(key, value);
+    }
+
+    for _ in 0..record.get_config::<usize>("states")? {
+        let line = lines.next().ok_or("Unexpected end of file")?;
+        record.state.push(line.parse()?);
+    }
+
+    for _ in 0..record.get_config::<usize>("layers")? {
+        record.layers.push(read_record(lines)?);
+    }
+
+    record
+        .config
+        .retain(|(key, _)| key != "states" && key != "layers");
+    Ok(record)
+}
+
 #[cfg(test)]
 pub mod test_config {
     use super::NeuralNetwork;
     use crate::{
+        calculus::functions::Function,
         linear_algebra::Matrix,
         machine_learning::dataset::{DataSet, DataVector},
     };
 
-    pub fn init_network(v: Vec<usize>) -> (NeuralNetwork, DataSet) {
+    pub fn init_network(v: Vec<usize>, activation_function: Function) -> (NeuralNetwork, DataSet) {
         let mut v_ = vec![1];
         v.iter().for_each(|u| v_.push(*u));
         v_.push(2);
-        let nn = NeuralNetwork::scalar(v_, 0.1);
+        let nn = NeuralNetwork::scalar(v_, 0.1, activation_function);
 
         let ds = DataSet {
+            validation_data: vec![],
             training_data: (0..10000)
                 .map(|x| {
                     let y: f64 = rand::random::<f64>() * x as f64 / (x + 1) as f64;
@@ -191,18 +370,26 @@ pub mod test_config {
                 .collect(),
         };
 
-        return (nn, ds);
+        (nn, ds)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::NeuralNetwork;
+    use super::{layers::recurrent::Rnn, Layer, NeuralNetwork, Sequential};
+    use crate::{
+        calculus::functions::Function,
+        linear_algebra::Matrix,
+        machine_learning::dataset::{
+            preprocessing::{Preprocessing, StandardScaler},
+            DataSet, DataVector,
+        },
+    };
     use std::fs;
 
     #[test]
     fn test_saving_and_loading() {
-        let nn = NeuralNetwork::random(vec![12, 22, 21]);
+        let nn = NeuralNetwork::random(vec![12, 22, 21], Function::relu());
         let file = "output/before.nn";
 
         nn.save(file).unwrap();
@@ -211,4 +398,66 @@ mod tests {
         assert!(snn == nn);
         fs::remove_file(file).unwrap();
     }
+
+    #[test]
+    fn test_saving_with_preprocessing() {
+        let mut nn = NeuralNetwork::random(vec![2, 3, 2], Function::relu());
+        let sample = |x: f64| DataVector::new(Matrix::from_vec(2, 1, vec![x, 2.0 * x]), 0);
+        let mut ds = DataSet {
+            training_data: (0..10).map(|x| sample(x as f64)).collect(),
+            validation_data: vec![],
+            testing_data: vec![],
+        };
+        let mut preprocessing = Preprocessing::default();
+        preprocessing.push(StandardScaler::new());
+        preprocessing.fit_transform(&mut ds).unwrap();
+        assert_eq!(nn.input_size(), Some(2));
+        assert_eq!(nn.output_size(), Some(2));
+        nn.set_preprocessing(preprocessing);
+        assert!(nn.check_input(&Matrix::zeros(3, 1)).is_err());
+
+        let file = "output/preprocessing.nn";
+        nn.save(file).unwrap();
+        let snn = NeuralNetwork::load(file).unwrap();
+
+        assert!(snn == nn);
+        let input = Matrix::from_vec(2, 1, vec![3.0, 1.0]);
+        assert_eq!(
+            snn.preprocessing().transform(&input),
+            nn.preprocessing().transform(&input)
+        );
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_checking_sequences() {
+        let mut model = Sequential::default();
+        model.push(Rnn::new(2, 3));
+        let nn = NeuralNetwork::new(model);
+
+        assert!(nn.check_input(&Matrix::zeros(6, 4)).is_ok());
+        assert!(nn.check_input(&Matrix::zeros(5, 1)).is_err());
+        assert!(nn.check_input(&Matrix::zeros(0, 1)).is_err());
+    }
+
+    #[test]
+    fn test_loading_legacy_files() {
+        let file = "output/legacy.nn";
+        fs::write(file, "1,2\n\n2,1 - 0.5,-1.5\n\n2,1 - 0.25,0\n\n").unwrap();
+        let nn = NeuralNetwork::load(file).unwrap();
+        assert_eq!(
+            nn.model().parameters(),
+            vec![
+                &Matrix::from_vec(2, 1, vec![0.5, -1.5]),
+                &Matrix::from_vec(2, 1, vec![0.25, 0.0])
+            ]
+        );
+
+        // corrupted values are an error instead of loading as zeros
+        fs::write(file, "1,2\n\n2,1 - 0.5,x\n\n2,1 - 0.25,0\n\n").unwrap();
+        assert!(NeuralNetwork::load(file).is_err());
+        fs::write(file, "1,3\n\n2,1 - 0.5,1\n\n2,1 - 0.25,0\n\n").unwrap();
+        assert!(NeuralNetwork::load(file).is_err());
+        fs::remove_file(file).unwrap();
+    }
 }
@@ -0,0 +1,332 @@
+//! The binary file written by [`NeuralNetwork::save`].
+//!
+//! ```text
+//! magic    8 bytes  \x89MNN\r\n\x1a\n
+//! version  u32
+//! sections tag (4 bytes), length (u64), payload, crc32 of the payload (u32)
+//!          META  metadata; hyperparameters and label names
+//!          LAYR  the record of the model
+//!          PREP  the record of the preprocessing, if any
+//!          TRNS  the progress of training, only in checkpoints (see [`checkpoint`](super::checkpoint))
+//!          END\0 empty, marks the end of the file
+//! ```
+//!
+//! Integers and floats are little-endian, strings are a `u32` length followed by UTF-8,
+//! and matrices are their rows and columns (`u64`) followed by the values in column-major order.
+//! Sections with an unknown tag are skipped, so newer files can add sections.
+
+use std::error::Error;
+
+use flate2::Crc;
+
+use crate::{linear_algebra::Matrix, machine_learning::dataset::preprocessing::Preprocessing};
+
+use super::{
+    layers::{sequential::Sequential, Layer, LayerRecord},
+    NeuralNetwork,
+};
+
+pub const MAGIC: &[u8; 8] = b"\x89MNN\r\n\x1a\n";
+pub const VERSION: u32 = 1;
+
+/// Describes how a network was trained, saved alongside it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    /// e.g. the cost function, learning rate, and batch size
+    pub hyperparameters: Vec<(String, String)>,
+    /// the name of every class of the output, in order
+    pub labels: Vec<String>,
+}
+
+impl Metadata {
+    /// sets `key`, replacing an earlier value
+    pub fn set_hyperparameter(&mut self, key: &str, value: impl ToString) {
+        self.hyperparameters.retain(|(k, _)| k != key);
+        self.hyperparameters
+            .push((key.to_string(), value.to_string()));
+    }
+
+    pub fn hyperparameter(&self, key: &str) -> Option<&str> {
+        self.hyperparameters
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub fn is_model_file(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode(nn: &NeuralNetwork) -> Vec<u8> {
+    encode_with_sections(nn, &[])
+}
+
+/// the file of `nn`, with `extra` sections written before the end
+pub(super) fn encode_with_sections(nn: &NeuralNetwork, extra: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+
+    let mut metadata = Encoder::default();
+    let meta = nn.metadata();
+    metadata.u32(meta.hyperparameters.len() as u32);
+    for (key, value) in meta.hyperparameters.iter() {
+        metadata.string(key);
+        metadata.string(value);
+    }
+    metadata.u32(meta.labels.len() as u32);
+    meta.labels.iter().for_each(|label| metadata.string(label));
+    section(&mut bytes, b"META", &metadata.0);
+
+    let mut layers = Encoder::default();
+    layers.record(&nn.model().record());
+    section(&mut bytes, b"LAYR", &layers.0);
+
+    if !nn.preprocessing().is_empty() {
+        let mut preprocessing = Encoder::default();
+        preprocessing.record(&nn.preprocessing().record());
+        section(&mut bytes, b"PREP", &preprocessing.0);
+    }
+
+    for (tag, payload) in extra {
+        section(&mut bytes, tag, payload);
+    }
+
+    section(&mut bytes, b"END\0", &[]);
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<NeuralNetwork, Box<dyn Error>> {
+    Ok(decode_with_sections(bytes)?.0)
+}
+
+/// the tag and payload of every section not read by [`decode`]
+pub(super) type Sections<'a> = Vec<([u8; 4], &'a [u8])>;
+
+/// the network of the file, along with every section it does not know
+pub(super) fn decode_with_sections(
+    bytes: &[u8],
+) -> Result<(NeuralNetwork, Sections<'_>), Box<dyn Error>> {
+    let mut file = Decoder::new(bytes);
+    if file.take(MAGIC.len())? != MAGIC {
+        return Err("Not a network file, the magic number is missing".into());
+    }
+    let version = file.u32()?;
+    if version > VERSION {
+        return Err(format!(
+            "The file has version {}, only versions up to {} are supported",
+            version, VERSION
+        )
+        .into());
+    }
+
+    let (mut metadata, mut model, mut preprocessing) = (None, None, None);
+    let mut unknown = vec![];
+    loop {
+        let tag: [u8; 4] = file.take(4)?.try_into()?;
+        let length = file.u64()? as usize;
+        let payload = file.take(length)?;
+        let checksum = file.u32()?;
+        if crc32(payload) != checksum {
+            return Err(format!(
+                "The {} section is corrupted, its checksum does not match",
+                String::from_utf8_lossy(&tag)
+            )
+            .into());
+        }
+
+        let mut section = Decoder::new(payload);
+        match &tag {
+            b"META" => {
+                let mut meta = Metadata::default();
+                for _ in 0..section.u32()? {
+                    meta.hyperparameters
+                        .push((section.string()?, section.string()?));
+                }
+                for _ in 0..section.u32()? {
+                    meta.labels.push(section.string()?);
+                }
+                metadata = Some(meta);
+            }
+            b"LAYR" => model = Some(Sequential::from_record(&section.record()?)?),
+            b"PREP" => preprocessing = Some(Preprocessing::from_record(&section.record()?)?),
+            b"END\0" => break,
+            _ => unknown.push((tag, payload)),
+        }
+    }
+
+    let mut nn = NeuralNetwork::new(model.ok_or("The file has no layers")?);
+    *nn.metadata_mut() = metadata.unwrap_or_default();
+    if let Some(preprocessing) = preprocessing {
+        nn.set_preprocessing(preprocessing);
+    }
+    Ok((nn, unknown))
+}
+
+fn section(bytes: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    bytes.extend(tag);
+    bytes.extend((payload.len() as u64).to_le_bytes());
+    bytes.extend(payload);
+    bytes.extend(crc32(payload).to_le_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+#[derive(Default)]
+pub(super) struct Encoder(pub(super) Vec<u8>);
+
+impl Encoder {
+    pub(super) fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub(super) fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub(super) fn f64(&mut self, value: f64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub(super) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend(value.as_bytes());
+    }
+
+    fn matrix(&mut self, matrix: &Matrix) {
+        let (r, c) = matrix.get_dims();
+        self.u64(r as u64);
+        self.u64(c as u64);
+        matrix.iter().for_each(|v| self.0.extend(v.to_le_bytes()));
+    }
+
+    fn record(&mut self, record: &LayerRecord) {
+        self.string(&record.kind);
+        self.u32(record.config.len() as u32);
+        for (key, value) in record.config.iter() {
+            self.string(key);
+            self.string(value);
+        }
+        self.u32(record.state.len() as u32);
+        record.state.iter().for_each(|state| self.matrix(state));
+        self.u32(record.layers.len() as u32);
+        record.layers.iter().for_each(|layer| self.record(layer));
+    }
+}
+
+pub(super) struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("Unexpected end of file")?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub(super) fn f64(&mut self) -> Result<f64, Box<dyn Error>> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub(super) fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let length = self.u32()? as usize;
+        Ok(std::str::from_utf8(self.take(length)?)?.to_string())
+    }
+
+    fn matrix(&mut self) -> Result<Matrix, Box<dyn Error>> {
+        let (r, c) = (self.u64()? as usize, self.u64()? as usize);
+        let length = r.checked_mul(c).and_then(|n| n.checked_mul(8));
+        let values = self.take(length.ok_or("Invalid matrix shape")?)?;
+        Ok(Matrix::from_vec(
+            r,
+            c,
+            values
+                .chunks_exact(8)
+                .map(|v| f64::from_le_bytes(v.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+
+    fn record(&mut self) -> Result<LayerRecord, Box<dyn Error>> {
+        let mut record = LayerRecord::new(&self.string()?);
+        for _ in 0..self.u32()? {
+            record = record.with_config(&self.string()?, self.string()?);
+        }
+        for _ in 0..self.u32()? {
+            record.state.push(self.matrix()?);
+        }
+        for _ in 0..self.u32()? {
+            record.layers.push(self.record()?);
+        }
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, MAGIC};
+    use crate::{calculus::functions::Function, machine_learning::neural_network::NeuralNetwork};
+
+    fn network() -> NeuralNetwork {
+        let mut nn = NeuralNetwork::random(vec![3, 4, 2], Function::tanh());
+        nn.metadata_mut().set_hyperparameter("cost", "quadratic");
+        nn.metadata_mut()
+            .set_hyperparameter("learning_rate", 0.1 + 0.2);
+        nn.metadata_mut().labels = vec!["a".to_string(), "b".to_string()];
+        nn
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let nn = network();
+        let decoded = decode(&encode(&nn)).unwrap();
+
+        // weights are bit for bit the same
+        assert_eq!(decoded, nn);
+        assert_eq!(
+            decoded.metadata().hyperparameter("learning_rate"),
+            Some("0.30000000000000004")
+        );
+        assert_eq!(decoded.metadata().labels, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_corrupted_files() {
+        let bytes = encode(&network());
+        let error = |bytes: &[u8]| decode(bytes).unwrap_err().to_string();
+
+        let mut flipped = bytes.clone();
+        let last = flipped.len() - 30;
+        flipped[last] ^= 1;
+        assert!(error(&flipped).contains("checksum"), "{}", error(&flipped));
+
+        assert!(error(&bytes[..bytes.len() - 10]).contains("end of file"));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = 99;
+        assert!(error(&newer).contains("version"));
+    }
+}
@@ -0,0 +1,797 @@
+//! Exports networks to [ONNX](https://onnx.ai), and imports simple fully-connected ONNX graphs.
+//!
+//! The exported graph takes a `float` tensor `input` of shape `[batch, features]`
+//! and returns `output` of shape `[batch, classes]`, so samples are rows instead of columns.
+//! Weights are stored as 32-bit floats, the precision ONNX runtimes support everywhere.
+//!
+//! Layers are mapped to operators as follows:
+//!
+//! | layer                  | operators                    |
+//! |------------------------|------------------------------|
+//! | dense                  | `Gemm`                       |
+//! | sigmoid, relu, tanh    | `Sigmoid`, `Relu`, `Tanh`    |
+//! | leaky relu             | `LeakyRelu`                  |
+//! | arctan                 | `Atan`                       |
+//! | normalized arctan      | `Atan`, `Mul`                |
+//! | swish                  | `Sigmoid`, `Mul`             |
+//! | dropout                | nothing, it is the identity  |
+//!
+//! The preprocessing of the network is part of the graph, as a `Gemm` per scaler,
+//! so services can feed it raw inputs.
+//! Other layers, and one-hot encoding, are not supported yet.
+//!
+//! The importer accepts chains of `Gemm`, `MatMul` followed by `Add`, the activations above,
+//! and `Identity` or `Dropout`.
+//! A `Softmax` is only accepted as the last node and is dropped, since it keeps the order
+//! of the outputs; the imported network predicts the same classes but outputs the logits.
+
+use std::{error::Error, f64::consts::PI};
+
+use prost::Message;
+
+use crate::{
+    calculus::functions::Function,
+    linear_algebra::Matrix,
+    machine_learning::neural_network::layers::{
+        activation::Activation, dense::Dense, sequential::Sequential, Layer, LayerRecord,
+    },
+};
+
+use super::NeuralNetwork;
+
+/// the opset of the exported operators
+pub const OPSET_VERSION: i64 = 13;
+/// the IR version that introduced opset 13
+const IR_VERSION: i64 = 7;
+
+const FLOAT: i32 = 1;
+const DOUBLE: i32 = 11;
+const ATTRIBUTE_FLOAT: i32 = 1;
+const ATTRIBUTE_INT: i32 = 2;
+
+const LEAKY_RELU_SLOPE: f64 = 0.1;
+
+pub fn export(nn: &NeuralNetwork) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut graph = GraphBuilder::default();
+
+    for (i, step) in nn.preprocessing().steps().iter().enumerate() {
+        let record = step.record();
+        if record.kind == "one_hot_encoder" {
+            return Err("One-hot encoding can not be exported to ONNX".into());
+        }
+        let (weights, biases) = affine(&record, |x| step.transform(x))?;
+        graph.input_size.get_or_insert(weights.get_dims().1);
+        graph.gemm(&format!("preprocessing{}", i), &weights, &biases);
+    }
+
+    for record in flatten(nn.model().record()) {
+        match record.kind.as_str() {
+            "dense" => {
+                let (weights, biases) = (record.get_state(0)?, record.get_state(1)?);
+                graph.input_size.get_or_insert(weights.get_dims().1);
+                let name = format!("dense{}", graph.nodes.len());
+                graph.gemm(&name, weights, biases);
+            }
+            "activation" => graph.activation(&record.get_config::<String>("function")?)?,
+            "dropout" => {}
+            kind => {
+                return Err(format!("The `{}` layer can not be exported to ONNX yet", kind).into())
+            }
+        }
+    }
+
+    let input_size = graph.input_size.ok_or("The network has no dense layer")?;
+    Ok(graph.finish(input_size).encode_to_vec())
+}
+
+pub fn import(bytes: &[u8]) -> Result<NeuralNetwork, Box<dyn Error>> {
+    let model = ModelProto::decode(bytes)?;
+    let graph = model.graph.ok_or("The ONNX model has no graph")?;
+    let initializer = |name: &str| -> Option<&TensorProto> {
+        graph.initializer.iter().find(|tensor| tensor.name == name)
+    };
+
+    let input = graph
+        .input
+        .iter()
+        .find(|input| initializer(&input.name).is_none())
+        .ok_or("The ONNX graph has no input")?;
+    let mut current = input.name.clone();
+    // the number of values of the current tensor, once known
+    let mut width = input.width();
+    let mut dense = |weights: Matrix, biases: Matrix| -> Result<Dense, Box<dyn Error>> {
+        let (outputs, inputs) = weights.get_dims();
+        match width {
+            Some(width) if width != inputs => Err(format!(
+                "Weights taking {} inputs follow a tensor of {} values",
+                inputs, width
+            )
+            .into()),
+            _ => {
+                width = Some(outputs);
+                Ok(Dense::new(weights, biases))
+            }
+        }
+    };
+
+    let mut model = Sequential::default();
+    let mut nodes = graph.node.iter().peekable();
+    while let Some(node) = nodes.next() {
+        let inputs: Vec<&str> = node.input.iter().map(|i| i.as_str()).collect();
+        if inputs.first() != Some(&current.as_str()) {
+            return Err(format!(
+                "The `{}` node does not follow the previous node, only chains of nodes are supported",
+                node.op_type
+            )
+            .into());
+        }
+        let output = node
+            .output
+            .first()
+            .ok_or(format!("The `{}` node has no output", node.op_type))?;
+        let tensor = |i: usize| -> Result<Matrix, Box<dyn Error>> {
+            let name = inputs
+                .get(i)
+                .ok_or(format!("The `{}` node is missing an input", node.op_type))?;
+            to_matrix(initializer(name).ok_or(format!("`{}` is not an initializer", name))?)
+        };
+
+        // the node whose output is the new current tensor, when it is not `node`
+        let mut last = node;
+        match node.op_type.as_str() {
+            "Gemm" => {
+                if node.int("transA", 0) != 0 {
+                    return Err("Gemm with a transposed input is not supported".into());
+                }
+                let b = tensor(1)?;
+                let weights = match node.int("transB", 0) {
+                    0 => b.transpose(),
+                    _ => b,
+                } * node.float("alpha", 1.0);
+                let biases = match inputs.len() > 2 {
+                    true => bias(tensor(2)?, weights.get_dims().0)? * node.float("beta", 1.0),
+                    false => Matrix::zeros(weights.get_dims().0, 1),
+                };
+                model.push(dense(weights, biases)?);
+            }
+            "MatMul" => {
+                let weights = tensor(1)?.transpose();
+                let mut biases = Matrix::zeros(weights.get_dims().0, 1);
+                if let Some(add) =
+                    nodes.next_if(|n| n.op_type == "Add" && n.input.first() == Some(output))
+                {
+                    let name = add
+                        .input
+                        .get(1)
+                        .ok_or("The `Add` node is missing an input")?;
+                    let tensor =
+                        initializer(name).ok_or(format!("`{}` is not an initializer", name))?;
+                    biases = bias(to_matrix(tensor)?, weights.get_dims().0)?;
+                    last = add;
+                }
+                model.push(dense(weights, biases)?);
+            }
+            "Sigmoid" => {
+                // swish is `x * sigmoid(x)`
+                let swish = nodes.next_if(|n| {
+                    n.op_type == "Mul"
+                        && n.input.len() == 2
+                        && n.input.contains(&current)
+                        && n.input.contains(output)
+                });
+                match swish {
+                    Some(mul) => {
+                        model.push(Activation::new(Function::swish()));
+                        last = mul;
+                    }
+                    None => model.push(Activation::new(Function::sigmoid())),
+                }
+            }
+            "Relu" => model.push(Activation::new(Function::relu())),
+            "Tanh" => model.push(Activation::new(Function::tanh())),
+            "LeakyRelu" => {
+                let alpha = node.float("alpha", 0.01);
+                if (alpha - LEAKY_RELU_SLOPE).abs() > 1e-6 {
+                    return Err(
+                        format!("LeakyRelu with a slope of {} is not supported", alpha).into(),
+                    );
+                }
+                model.push(Activation::new(Function::leaky_relu()));
+            }
+            "Atan" => {
+                // the normalized arctan is `atan(x) * 2 / pi`
+                let is_scale = |name: &String| {
+                    initializer(name)
+                        .and_then(|tensor| to_matrix(tensor).ok())
+                        .is_some_and(|scale| {
+                            scale
+                                .iter()
+                                .next()
+                                .is_some_and(|s| (s - 2.0 / PI).abs() < 1e-6)
+                        })
+                };
+                let normalized = nodes.next_if(|n| {
+                    n.op_type == "Mul"
+                        && n.input.first() == Some(output)
+                        && n.input.get(1).is_some_and(is_scale)
+                });
+                match normalized {
+                    Some(mul) => {
+                        model.push(Activation::new(Function::normal_arctan()));
+                        last = mul;
+                    }
+                    None => model.push(Activation::new(Function::arctan())),
+                }
+            }
+            "Identity" | "Dropout" => {}
+            "Softmax" if nodes.peek().is_none() => {}
+            "Softmax" => return Err("Softmax is only supported as the last node".into()),
+            op => return Err(format!("The `{}` operator is not supported", op).into()),
+        }
+
+        current = last
+            .output
+            .first()
+            .ok_or(format!("The `{}` node has no output", last.op_type))?
+            .clone();
+    }
+
+    if model.is_empty() {
+        return Err("The ONNX graph has no supported nodes".into());
+    }
+    Ok(NeuralNetwork::new(model))
+}
+
+/// the layers of nested sequential models, in order
+fn flatten(record: LayerRecord) -> Vec<LayerRecord> {
+    match record.kind.as_str() {
+        "sequential" => record.layers.into_iter().flat_map(flatten).collect(),
+        _ => vec![record],
+    }
+}
+
+/// the weights and biases of an affine preprocessing step, found by transforming the unit vectors
+fn affine(
+    record: &LayerRecord,
+    transform: impl Fn(&Matrix) -> Matrix,
+) -> Result<(Matrix, Matrix), Box<dyn Error>> {
+    let input_size = record.get_state(0)?.get_dims().0;
+    let biases = transform(&Matrix::zeros(input_size, 1));
+    let weights = transform(&Matrix::identity(input_size, input_size))
+        .add_to_columns(&(biases.clone() * -1.0));
+    Ok((weights, biases))
+}
+
+/// a bias of shape `[size]` or `[1, size]` as a column
+fn bias(tensor: Matrix, size: usize) -> Result<Matrix, Box<dyn Error>> {
+    let (r, c) = tensor.get_dims();
+    match (r, c) {
+        (r, 1) if r == size => Ok(tensor),
+        (1, c) if c == size => Ok(tensor.transpose()),
+        _ => Err(format!(
+            "A bias of shape {}x{} does not match {} outputs",
+            r, c, size
+        )
+        .into()),
+    }
+}
+
+/// a tensor of at most two dimensions, 1-D tensors become columns
+fn to_matrix(tensor: &TensorProto) -> Result<Matrix, Box<dyn Error>> {
+    let dims = tensor
+        .dims
+        .iter()
+        .map(|d| usize::try_from(*d))
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| format!("`{}` has the invalid shape {:?}", tensor.name, tensor.dims))?;
+    let (r, c) = match dims.as_slice() {
+        [] => (1, 1),
+        [r] => (*r, 1),
+        [r, c] => (*r, *c),
+        dims => return Err(format!("`{}` has {} dimensions", tensor.name, dims.len()).into()),
+    };
+    let size = r.checked_mul(c).ok_or(format!(
+        "`{}` has the invalid shape {:?}",
+        tensor.name, dims
+    ))?;
+
+    let values: Vec<f64> = match tensor.data_type {
+        FLOAT if !tensor.raw_data.is_empty() => tensor
+            .raw_data
+            .chunks_exact(4)
+            .map(|v| f32::from_le_bytes(v.try_into().unwrap()) as f64)
+            .collect(),
+        FLOAT => tensor.float_data.iter().map(|v| *v as f64).collect(),
+        DOUBLE if !tensor.raw_data.is_empty() => tensor
+            .raw_data
+            .chunks_exact(8)
+            .map(|v| f64::from_le_bytes(v.try_into().unwrap()))
+            .collect(),
+        DOUBLE => tensor.double_data.clone(),
+        t => return Err(format!("`{}` has the unsupported data type {}", tensor.name, t).into()),
+    };
+    if values.len() != size {
+        return Err(format!(
+            "`{}` has {} values instead of {}",
+            tensor.name,
+            values.len(),
+            size
+        )
+        .into());
+    }
+
+    // tensors are row-major
+    Ok(Matrix::from_vec(c, r, values).transpose())
+}
+
+fn tensor(name: &str, matrix: &Matrix, dims: Vec<i64>) -> TensorProto {
+    TensorProto {
+        dims,
+        data_type: FLOAT,
+        name: name.to_string(),
+        raw_data: matrix
+            .transpose()
+            .iter()
+            .flat_map(|v| (*v as f32).to_le_bytes())
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[derive(Default)]
+struct GraphBuilder {
+    nodes: Vec<NodeProto>,
+    initializers: Vec<TensorProto>,
+    input_size: Option<usize>,
+    output_size: Option<usize>,
+}
+
+impl GraphBuilder {
+    /// the tensor the next node reads
+    fn current(&self) -> String {
+        match self.nodes.last() {
+            Some(node) => node.output[0].clone(),
+            None => "input".to_string(),
+        }
+    }
+
+    fn node(&mut self, op_type: &str, inputs: Vec<String>, attributes: Vec<AttributeProto>) {
+        let name = format!("{}{}", op_type.to_lowercase(), self.nodes.len());
+        self.nodes.push(NodeProto {
+            input: inputs,
+            output: vec![name.clone()],
+            name,
+            op_type: op_type.to_string(),
+            attribute: attributes,
+        });
+    }
+
+    /// `x * weightsᵀ + biases`, since samples are rows
+    fn gemm(&mut self, name: &str, weights: &Matrix, biases: &Matrix) {
+        let (r, c) = weights.get_dims();
+        let (weight, bias) = (format!("{}.weight", name), format!("{}.bias", name));
+        self.initializers
+            .push(tensor(&weight, weights, vec![r as i64, c as i64]));
+        self.initializers
+            .push(tensor(&bias, biases, vec![r as i64]));
+        self.output_size = Some(r);
+
+        let input = self.current();
+        self.node(
+            "Gemm",
+            vec![input, weight, bias],
+            vec![AttributeProto::int("transB", 1)],
+        );
+    }
+
+    fn activation(&mut self, function: &str) -> Result<(), Box<dyn Error>> {
+        let input = self.current();
+        match function {
+            "sigmoid" => self.node("Sigmoid", vec![input], vec![]),
+            "relu" => self.node("Relu", vec![input], vec![]),
+            "tanh" => self.node("Tanh", vec![input], vec![]),
+            "arctan" => self.node("Atan", vec![input], vec![]),
+            "leaky_relu" => self.node(
+                "LeakyRelu",
+                vec![input],
+                vec![AttributeProto::float("alpha", LEAKY_RELU_SLOPE)],
+            ),
+            "normal_arctan" => {
+                self.node("Atan", vec![input], vec![]);
+                let scale = format!("scale{}", self.nodes.len());
+                self.initializers.push(tensor(
+                    &scale,
+                    &Matrix::from_vec(1, 1, vec![2.0 / PI]),
+                    vec![],
+                ));
+                self.node("Mul", vec![self.current(), scale], vec![]);
+            }
+            "swish" => {
+                self.node("Sigmoid", vec![input.clone()], vec![]);
+                self.node("Mul", vec![input, self.current()], vec![]);
+            }
+            function => {
+                return Err(
+                    format!("The `{}` activation can not be exported to ONNX", function).into(),
+                )
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, input_size: usize) -> ModelProto {
+        let output_size = self.output_size.unwrap_or(input_size);
+        // the last node writes the output of the graph
+        let output = "output".to_string();
+        if self.nodes.is_empty() {
+            self.node("Identity", vec!["input".to_string()], vec![]);
+        }
+        if let Some(node) = self.nodes.last_mut() {
+            node.output = vec![output.clone()];
+        }
+
+        ModelProto {
+            ir_version: IR_VERSION,
+            producer_name: "mathematics".to_string(),
+            producer_version: env!("CARGO_PKG_VERSION").to_string(),
+            opset_import: vec![OperatorSetIdProto {
+                domain: String::new(),
+                version: OPSET_VERSION,
+            }],
+            graph: Some(GraphProto {
+                name: "network".to_string(),
+                node: self.nodes,
+                initializer: self.initializers,
+                input: vec![ValueInfoProto::matrix("input", input_size)],
+                output: vec![ValueInfoProto::matrix(&output, output_size)],
+            }),
+        }
+    }
+}
+
+// the subset of `onnx.proto` used by the exporter and importer, with the same field numbers
+
+#[derive(Clone, PartialEq, Message)]
+struct ModelProto {
+    #[prost(int64, tag = "1")]
+    ir_version: i64,
+    #[prost(string, tag = "2")]
+    producer_name: String,
+    #[prost(string, tag = "3")]
+    producer_version: String,
+    #[prost(message, optional, tag = "7")]
+    graph: Option<GraphProto>,
+    #[prost(message, repeated, tag = "8")]
+    opset_import: Vec<OperatorSetIdProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct OperatorSetIdProto {
+    #[prost(string, tag = "1")]
+    domain: String,
+    #[prost(int64, tag = "2")]
+    version: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct GraphProto {
+    #[prost(message, repeated, tag = "1")]
+    node: Vec<NodeProto>,
+    #[prost(string, tag = "2")]
+    name: String,
+    #[prost(message, repeated, tag = "5")]
+    initializer: Vec<TensorProto>,
+    #[prost(message, repeated, tag = "11")]
+    input: Vec<ValueInfoProto>,
+    #[prost(message, repeated, tag = "12")]
+    output: Vec<ValueInfoProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct NodeProto {
+    #[prost(string, repeated, tag = "1")]
+    input: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    output: Vec<String>,
+    #[prost(string, tag = "3")]
+    name: String,
+    #[prost(string, tag = "4")]
+    op_type: String,
+    #[prost(message, repeated, tag = "5")]
+    attribute: Vec<AttributeProto>,
+}
+
+impl NodeProto {
+    fn attribute(&self, name: &str) -> Option<&AttributeProto> {
+        self.attribute.iter().find(|a| a.name == name)
+    }
+
+    fn int(&self, name: &str, default: i64) -> i64 {
+        self.attribute(name).map_or(default, |a| a.i)
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.attribute(name).map_or(default, |a| a.f as f64)
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AttributeProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(float, tag = "2")]
+    f: f32,
+    #[prost(int64, tag = "3")]
+    i: i64,
+    #[prost(int32, tag = "20")]
+    r#type: i32,
+}
+
+impl AttributeProto {
+    fn int(name: &str, i: i64) -> Self {
+        AttributeProto {
+            name: name.to_string(),
+            i,
+            r#type: ATTRIBUTE_INT,
+            ..Default::default()
+        }
+    }
+
+    fn float(name: &str, f: f64) -> Self {
+        AttributeProto {
+            name: name.to_string(),
+            f: f as f32,
+            r#type: ATTRIBUTE_FLOAT,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorProto {
+    #[prost(int64, repeated, tag = "1")]
+    dims: Vec<i64>,
+    #[prost(int32, tag = "2")]
+    data_type: i32,
+    #[prost(float, repeated, tag = "4")]
+    float_data: Vec<f32>,
+    #[prost(string, tag = "8")]
+    name: String,
+    #[prost(bytes = "vec", tag = "9")]
+    raw_data: Vec<u8>,
+    #[prost(double, repeated, tag = "10")]
+    double_data: Vec<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueInfoProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, optional, tag = "2")]
+    r#type: Option<TypeProto>,
+}
+
+impl ValueInfoProto {
+    /// the fixed size of the last dimension of a `[batch, size]` tensor
+    fn width(&self) -> Option<usize> {
+        let shape = self.r#type.as_ref()?.tensor_type.as_ref()?.shape.as_ref()?;
+        match shape.dim.as_slice() {
+            [_, size] if size.dim_value > 0 => usize::try_from(size.dim_value).ok(),
+            _ => None,
+        }
+    }
+
+    /// a float tensor of shape `[batch, size]`
+    fn matrix(name: &str, size: usize) -> Self {
+        let dim = |value: i64, param: &str| Dimension {
+            dim_value: value,
+            dim_param: param.to_string(),
+        };
+        ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                tensor_type: Some(TensorTypeProto {
+                    elem_type: FLOAT,
+                    shape: Some(TensorShapeProto {
+                        dim: vec![dim(0, "batch"), dim(size as i64, "")],
+                    }),
+                }),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TypeProto {
+    #[prost(message, optional, tag = "1")]
+    tensor_type: Option<TensorTypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorTypeProto {
+    #[prost(int32, tag = "1")]
+    elem_type: i32,
+    #[prost(message, optional, tag = "2")]
+    shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorShapeProto {
+    #[prost(message, repeated, tag = "1")]
+    dim: Vec<Dimension>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Dimension {
+    #[prost(int64, tag = "1")]
+    dim_value: i64,
+    #[prost(string, tag = "2")]
+    dim_param: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, import, GraphProto, ModelProto, NodeProto, ValueInfoProto};
+    use crate::{
+        calculus::functions::Function,
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::{
+                preprocessing::{Preprocessing, StandardScaler},
+                DataSet, DataVector,
+            },
+            neural_network::{
+                layers::{
+                    activation::Activation, dense::Dense, dropout::Dropout, sequential::Sequential,
+                    Layer,
+                },
+                NeuralNetwork,
+            },
+        },
+    };
+    use prost::Message;
+
+    fn assert_close(a: &Matrix, b: &Matrix) {
+        assert_eq!(a.get_dims(), b.get_dims());
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-5, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut model = Sequential::default();
+        let functions = [
+            Function::sigmoid(),
+            Function::swish(),
+            Function::normal_arctan(),
+            Function::leaky_relu(),
+            Function::tanh(),
+        ];
+        for function in functions {
+            model.push(Dense::random(3, 3));
+            model.push(Activation::new(function));
+        }
+        model.push(Dropout::new(0.5));
+        model.push(Dense::random(3, 2));
+        let nn = NeuralNetwork::new(model);
+
+        let imported = import(&export(&nn).unwrap()).unwrap();
+        let configs = |nn: &NeuralNetwork| -> Vec<_> {
+            let record = nn.model().record();
+            record
+                .layers
+                .into_iter()
+                .filter(|l| l.kind != "dropout")
+                .map(|l| (l.kind, l.config))
+                .collect()
+        };
+        assert_eq!(configs(&imported), configs(&nn));
+        let input = Matrix::from_vec(3, 2, vec![0.5, -1.0, 2.0, 0.0, 0.25, -0.75]);
+        assert_close(&imported.propagate(&input), &nn.propagate(&input));
+    }
+
+    #[test]
+    fn test_preprocessing_is_exported() {
+        let mut nn = NeuralNetwork::random(vec![2, 3, 2], Function::relu());
+        let sample = |x: f64| DataVector::new(Matrix::from_vec(2, 1, vec![x, 3.0 * x + 1.0]), 0);
+        let mut ds = DataSet {
+            training_data: (0..10).map(|x| sample(x as f64)).collect(),
+            validation_data: vec![],
+            testing_data: vec![],
+        };
+        let mut preprocessing = Preprocessing::default();
+        preprocessing.push(StandardScaler::new());
+        preprocessing.fit_transform(&mut ds).unwrap();
+        nn.set_preprocessing(preprocessing);
+
+        let imported = import(&export(&nn).unwrap()).unwrap();
+        let input = Matrix::from_vec(2, 1, vec![4.0, -2.0]);
+        assert_close(
+            &imported.propagate(&input),
+            &nn.propagate(&nn.preprocessing().transform(&input)),
+        );
+    }
+
+    #[test]
+    fn test_graph_layout() {
+        let nn = NeuralNetwork::random(vec![4, 3], Function::sigmoid());
+        let model = ModelProto::decode(export(&nn).unwrap().as_slice()).unwrap();
+        let graph = model.graph.unwrap();
+        let ops: Vec<&str> = graph.node.iter().map(|n| n.op_type.as_str()).collect();
+        assert_eq!(ops, vec!["Gemm", "Sigmoid"]);
+        assert_eq!(graph.node[1].output, vec!["output"]);
+        assert_eq!(graph.initializer[0].dims, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_malformed_graphs() {
+        let nn = NeuralNetwork::random(vec![4, 3], Function::sigmoid());
+        let graph = || {
+            ModelProto::decode(export(&nn).unwrap().as_slice())
+                .unwrap()
+                .graph
+                .unwrap()
+        };
+        let import_graph = |graph: GraphProto| {
+            let model = ModelProto {
+                graph: Some(graph),
+                ..Default::default()
+            };
+            import(&model.encode_to_vec())
+        };
+
+        let mut no_inputs = graph();
+        no_inputs.node[1].input.clear();
+        assert!(import_graph(no_inputs).is_err());
+
+        // the node after a `MatMul` is checked for a bias
+        let mut no_bias_inputs = graph();
+        no_bias_inputs.node[0].op_type = "MatMul".to_string();
+        no_bias_inputs.node[1].op_type = "Add".to_string();
+        no_bias_inputs.node[1].input.clear();
+        assert!(import_graph(no_bias_inputs).is_err());
+
+        let mut no_outputs = graph();
+        no_outputs.node[0].output.clear();
+        assert!(import_graph(no_outputs).is_err());
+
+        for dims in [vec![-3, 4], vec![i64::MAX, i64::MAX]] {
+            let mut shape = graph();
+            shape.initializer[0].dims = dims;
+            assert!(import_graph(shape).is_err());
+        }
+
+        // the weights are checked against the width of the input
+        let mut wide_input = graph();
+        wide_input.input[0] = ValueInfoProto::matrix("input", 5);
+        assert!(import_graph(wide_input).is_err());
+
+        // a `Softmax` is only dropped at the end
+        let softmax = |input: &[String]| NodeProto {
+            input: input.to_vec(),
+            output: vec!["probabilities".to_string()],
+            op_type: "Softmax".to_string(),
+            ..Default::default()
+        };
+        let mut last = graph();
+        last.node.push(softmax(&last.node[1].output));
+        assert!(import_graph(last).is_ok());
+        let mut inner = graph();
+        inner.node.insert(1, softmax(&inner.node[0].output));
+        inner.node[2].input = vec!["probabilities".to_string()];
+        assert!(import_graph(inner).is_err());
+
+        // an empty scale after `Atan` is not a normalized arctan
+        let arctan = NeuralNetwork::random(vec![4, 3], Function::normal_arctan());
+        let mut graph = ModelProto::decode(export(&arctan).unwrap().as_slice())
+            .unwrap()
+            .graph
+            .unwrap();
+        let scale = graph.initializer.last_mut().unwrap();
+        scale.dims = vec![0];
+        scale.raw_data.clear();
+        scale.float_data.clear();
+        assert!(import_graph(graph).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::linear_algebra::Matrix;
+
+/// Weight penalties and constraints applied during training.
+///
+/// Biases are never regularized.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Regularization {
+    /// adds `l1 * |w|` to the cost
+    pub l1: f64,
+    /// adds `l2 / 2 * w^2` to the cost, i.e. weight decay
+    pub l2: f64,
+    /// caps the L2 norm of the incoming weights of every node after each step
+    pub max_norm: Option<f64>,
+}
+
+impl Regularization {
+    /// calculates the total penalty added to the cost by `weights`
+    pub fn penalty(&self, weights: &Matrix) -> f64 {
+        weights
+            .iter()
+            .map(|w| self.l1 * w.abs() + self.l2 / 2.0 * w * w)
+            .sum()
+    }
+
+    /// calculates the derivative of the penalty with respect to each weight;
+    /// `l1 * sign(w) + l2 * w`
+    pub fn gradient(&self, weights: &Matrix) -> Matrix {
+        let (r, c) = weights.get_dims();
+        Matrix::from_iterator(
+            r,
+            c,
+            &mut weights.iter().map(|w| {
+                let sign = if *w == 0.0 { 0.0 } else { w.signum() };
+                self.l1 * sign + self.l2 * w
+            }),
+        )
+    }
+
+    /// rescales every row of `weights` whose norm exceeds `max_norm`
+    pub fn constrain(&self, weights: &mut Matrix) {
+        let Some(max_norm) = self.max_norm else {
+            return;
+        };
+
+        let (r, c) = weights.get_dims();
+        for i in 0..r {
+            let norm = (0..c).map(|x| weights[(i, x)].powi(2)).sum::<f64>().sqrt();
+            if norm > max_norm {
+                (0..c).for_each(|x| weights[(i, x)] *= max_norm / norm);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regularization;
+    use crate::linear_algebra::Matrix;
+
+    #[test]
+    fn test_penalty_gradient() {
+        let r = Regularization {
+            l1: 0.5,
+            l2: 0.1,
+            max_norm: None,
+        };
+        let w = Matrix::from_vec(3, 1, vec![2.0, -1.0, 0.0]);
+
+        assert!((r.penalty(&w) - (1.5 + 0.25)).abs() < 1e-12);
+        assert_eq!(r.gradient(&w), Matrix::from_vec(3, 1, vec![0.7, -0.6, 0.0]));
+    }
+
+    #[test]
+    fn test_max_norm() {
+        let r = Regularization {
+            max_norm: Some(1.0),
+            ..Default::default()
+        };
+        // rows [3, 4] and [0.1, 0.1]
+        let mut w = Matrix::from_vec(2, 2, vec![3.0, 0.1, 4.0, 0.1]);
+        r.constrain(&mut w);
+
+        assert!((w[(0, 0)] - 0.6).abs() < 1e-12);
+        assert!((w[(0, 1)] - 0.8).abs() < 1e-12);
+        assert_eq!(w[(1, 1)], 0.1);
+    }
+}
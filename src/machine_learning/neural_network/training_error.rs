@@ -0,0 +1,39 @@
+use std::{error::Error, fmt::Display};
+
+/// Which values of the network turned out to be non-finite
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonFiniteValue {
+    Activation,
+    Gradient,
+    Weight,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrainingError {
+    /// a `NaN` or infinite value was found in `layer` while training on `batch`;
+    /// `layer` is the index into [`Sequential::layers`](super::layers::sequential::Sequential::layers),
+    /// activations and dropout included
+    NonFinite {
+        value: NonFiniteValue,
+        layer: usize,
+        batch: usize,
+    },
+}
+
+impl Display for TrainingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrainingError::NonFinite {
+                value,
+                layer,
+                batch,
+            } => write!(
+                f,
+                "non-finite {:?} found in layer {} (counting from 0) during batch {}",
+                value, layer, batch
+            ),
+        }
+    }
+}
+
+impl Error for TrainingError {}
@@ -0,0 +1,623 @@
+//! Searches for the hyperparameters of a [`Config`] by training a trial for each candidate.
+//!
+//! Every trial trains with a [`Trainer`] in its own output directory, `trial-{n}` within the
+//! directory of the base config, and is judged by the metric of the config on the validation
+//! data. Trials run in parallel, and the results are written to `results.csv`.
+//! The data is preprocessed once and shared by every trial.
+
+use std::{error::Error, fmt::Write as _, fs, path::Path, sync::Arc};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::{
+    config::{Config, LayerConfig},
+    dataset::{preprocessing::Preprocessing, DataSet, Split},
+    neural_network::{early_stopping::Metric, evaluation::Evaluation, learning_rate::PlateauMode},
+    trainer::Trainer,
+};
+
+/// The values tried for each hyperparameter, an empty list keeps the value of the base config
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SearchSpace {
+    /// random search draws log-uniformly between the smallest and the largest rate
+    pub learning_rate: Vec<f64>,
+    pub batch_size: Vec<usize>,
+    /// the sizes of the hidden dense layers, each followed by the activation
+    pub hidden: Vec<Vec<usize>>,
+    /// replaces the function of every activation layer
+    pub activation: Vec<String>,
+}
+
+/// The hyperparameters of a trial
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    /// the layers of the base config are kept when `None`
+    pub hidden: Option<Vec<usize>>,
+    pub activation: Option<String>,
+}
+
+impl Params {
+    /// `base` with these hyperparameters
+    pub fn apply(&self, base: &Config) -> Config {
+        let mut config = base.clone();
+        config.optimizer.learning_rate = self.learning_rate;
+        config.training.batch_size = self.batch_size;
+
+        if let Some(hidden) = &self.hidden {
+            let function = self.activation.clone().unwrap_or_else(|| {
+                activations(base)
+                    .next()
+                    .unwrap_or_else(|| "sigmoid".to_string())
+            });
+            let activation = || LayerConfig::Activation {
+                function: function.clone(),
+            };
+            config.network.layers = hidden
+                .iter()
+                .flat_map(|units| {
+                    [
+                        LayerConfig::Dense {
+                            units: Some(*units),
+                        },
+                        activation(),
+                    ]
+                })
+                .chain([LayerConfig::Dense { units: None }, activation()])
+                .collect();
+        }
+        if let Some(activation) = &self.activation {
+            for layer in config.network.layers.iter_mut() {
+                if let LayerConfig::Activation { function } = layer {
+                    *function = activation.clone();
+                }
+            }
+        }
+        config
+    }
+
+    /// the hidden layers as e.g. `128-64`
+    fn hidden_name(&self, base: &Config) -> String {
+        let units = match &self.hidden {
+            Some(hidden) => hidden.clone(),
+            None => {
+                let mut units: Vec<usize> = base
+                    .network
+                    .layers
+                    .iter()
+                    .filter_map(|layer| match layer {
+                        LayerConfig::Dense { units } => Some(units.unwrap_or(0)),
+                        _ => None,
+                    })
+                    .collect();
+                units.pop();
+                units
+            }
+        };
+        let names: Vec<String> = units.iter().map(|u| u.to_string()).collect();
+        match names.is_empty() {
+            true => "-".to_string(),
+            false => names.join("-"),
+        }
+    }
+}
+
+fn activations(config: &Config) -> impl Iterator<Item = String> + '_ {
+    config
+        .network
+        .layers
+        .iter()
+        .filter_map(|layer| match layer {
+            LayerConfig::Activation { function } => Some(function.clone()),
+            _ => None,
+        })
+}
+
+/// How the candidates are chosen and trained
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// every combination of the values, each trained for the epochs of the base config
+    Grid,
+    /// `trials` random combinations, each trained for the epochs of the base config
+    Random { trials: usize },
+    /// Successive halving; `trials` random combinations are trained for `min_epochs`,
+    /// the best `1 / reduction` of them continue for `reduction` times as many epochs,
+    /// and so on until the epochs of the base config.
+    Halving {
+        trials: usize,
+        min_epochs: usize,
+        reduction: usize,
+    },
+}
+
+/// The outcome of a trial, its `evaluation` is on the validation data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    pub index: usize,
+    pub params: Params,
+    pub epochs: usize,
+    /// the error that stopped training, e.g. a diverging learning rate
+    pub evaluation: Result<Evaluation, String>,
+}
+
+impl Trial {
+    pub fn value(&self, metric: Metric) -> Option<f64> {
+        self.evaluation.as_ref().ok().map(|e| metric.value(e))
+    }
+}
+
+/// Trains a trial for every candidate of a [`SearchSpace`].
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::{config::Config, search::{Search, SearchSpace, Strategy}};
+/// let space = SearchSpace {
+///     learning_rate: vec![0.1, 3.0],
+///     hidden: vec![vec![64], vec![128, 64]],
+///     ..SearchSpace::default()
+/// };
+/// let search = Search::new(Config::load("letters.toml")?, space)
+///     .strategy(Strategy::Random { trials: 8 })
+///     .seed(1);
+/// let trials = search.run()?;
+/// println!("{}", search.table(&trials));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Search {
+    base: Config,
+    space: SearchSpace,
+    strategy: Strategy,
+    seed: Option<u64>,
+    parallel: usize,
+}
+
+impl Search {
+    pub fn new(base: Config, space: SearchSpace) -> Self {
+        Search {
+            base,
+            space,
+            strategy: Strategy::Grid,
+            seed: None,
+            parallel: 0,
+        }
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// makes the random candidates reproducible
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// the number of trials trained at once, 0 uses every core
+    pub fn parallel(mut self, trials: usize) -> Self {
+        self.parallel = trials;
+        self
+    }
+
+    pub fn base(&self) -> &Config {
+        &self.base
+    }
+
+    /// the hyperparameters of every trial
+    pub fn candidates(&self) -> Vec<Params> {
+        match self.strategy {
+            Strategy::Grid => self.grid(),
+            Strategy::Random { trials } | Strategy::Halving { trials, .. } => {
+                let mut rng = match self.seed {
+                    Some(seed) => StdRng::seed_from_u64(seed),
+                    None => StdRng::from_entropy(),
+                };
+                (0..trials).map(|_| self.sample(&mut rng)).collect()
+            }
+        }
+    }
+
+    fn grid(&self) -> Vec<Params> {
+        let (base, space) = (&self.base, &self.space);
+        let learning_rates = or(&space.learning_rate, base.optimizer.learning_rate);
+        let batch_sizes = or(&space.batch_size, base.training.batch_size);
+        let hidden = options(&space.hidden);
+        let activations = options(&space.activation);
+
+        let mut candidates = vec![];
+        for learning_rate in &learning_rates {
+            for batch_size in &batch_sizes {
+                for hidden in &hidden {
+                    for activation in &activations {
+                        candidates.push(Params {
+                            learning_rate: *learning_rate,
+                            batch_size: *batch_size,
+                            hidden: hidden.clone(),
+                            activation: activation.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> Params {
+        let (base, space) = (&self.base, &self.space);
+        let rates = &space.learning_rate;
+        let learning_rate = match rates.iter().copied().reduce(f64::min) {
+            None => base.optimizer.learning_rate,
+            Some(low) => {
+                let high = rates.iter().copied().fold(low, f64::max);
+                match low < high && low > 0.0 {
+                    true => rng.gen_range(low.ln()..=high.ln()).exp(),
+                    false => low,
+                }
+            }
+        };
+        Params {
+            learning_rate,
+            batch_size: *or(&space.batch_size, base.training.batch_size)
+                .choose(rng)
+                .unwrap(),
+            hidden: options(&space.hidden).choose(rng).unwrap().clone(),
+            activation: options(&space.activation).choose(rng).unwrap().clone(),
+        }
+    }
+
+    /// Trains every trial, and writes the results to `results.csv` in the output directory
+    /// of the base config. The trials are sorted from best to worst.
+    pub fn run(&self) -> Result<Vec<Trial>, Box<dyn Error>> {
+        let mut data = self.base.data.load()?;
+        data.split_validation(Split::Fraction(self.base.data.validation_split));
+        self.run_with_data(data)
+    }
+
+    /// Trains every trial on `data`, see [`Search::run`]
+    pub fn run_with_data(&self, mut data: DataSet) -> Result<Vec<Trial>, Box<dyn Error>> {
+        let candidates = self.candidates();
+        if candidates.is_empty() {
+            return Err("There are no candidates to search".into());
+        }
+        let preprocessing = Trainer::preprocess(&self.base, &mut data)?;
+        let data = Arc::new(data);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.parallel)
+            .build()?;
+        let epochs = self.base.training.epochs;
+        let indices: Vec<usize> = (0..candidates.len()).collect();
+
+        let mut trials = match self.strategy {
+            Strategy::Grid | Strategy::Random { .. } => pool.install(|| {
+                self.train(&data, &preprocessing, &candidates, &indices, epochs, false)
+            }),
+            Strategy::Halving {
+                min_epochs,
+                reduction,
+                ..
+            } => {
+                let reduction = reduction.max(2);
+                let first = min_epochs.clamp(1, epochs.max(1));
+                let mut rung = first;
+                let mut survivors = indices;
+                let mut trials: Vec<Option<Trial>> = vec![None; candidates.len()];
+                loop {
+                    let results = pool.install(|| {
+                        self.train(
+                            &data,
+                            &preprocessing,
+                            &candidates,
+                            &survivors,
+                            rung,
+                            rung > first,
+                        )
+                    });
+                    let results = self.sorted(results);
+                    let keep = survivors.len().div_ceil(reduction);
+                    survivors = results
+                        .iter()
+                        .take(keep)
+                        .filter(|t| t.evaluation.is_ok())
+                        .map(|t| t.index)
+                        .collect();
+                    for trial in results {
+                        let index = trial.index;
+                        trials[index] = Some(trial);
+                    }
+
+                    if rung >= epochs {
+                        break;
+                    }
+                    if survivors.is_empty() {
+                        break;
+                    }
+                    rung = match survivors.len() {
+                        1 => epochs,
+                        _ => (rung * reduction).min(epochs),
+                    };
+                }
+                trials.into_iter().flatten().collect()
+            }
+        };
+        trials = self.sorted(trials);
+
+        let directory = Path::new(&self.base.output.directory);
+        fs::create_dir_all(directory)?;
+        fs::write(directory.join("results.csv"), self.csv(&trials))?;
+        Ok(trials)
+    }
+
+    /// trains the `trials` of `candidates` in parallel for `epochs`,
+    /// continuing from their checkpoints when `resume`
+    fn train(
+        &self,
+        data: &Arc<DataSet>,
+        preprocessing: &Preprocessing,
+        candidates: &[Params],
+        trials: &[usize],
+        epochs: usize,
+        resume: bool,
+    ) -> Vec<Trial> {
+        trials
+            .par_iter()
+            .map(|&index| {
+                let params = &candidates[index];
+                let mut config = params.apply(&self.base);
+                config.training.epochs = epochs;
+                config.output.directory = self.trial_directory(index);
+
+                let evaluation =
+                    Trainer::with_preprocessed_data(config, Arc::clone(data), preprocessing)
+                        .and_then(|trainer| {
+                            let mut trainer = trainer.silent(true).resume(resume);
+                            trainer.run()?;
+                            Ok(trainer.validate())
+                        })
+                        .map_err(|e| e.to_string());
+                Trial {
+                    index,
+                    params: params.clone(),
+                    epochs,
+                    evaluation,
+                }
+            })
+            .collect()
+    }
+
+    /// where trial `index` is saved
+    pub fn trial_directory(&self, index: usize) -> String {
+        Path::new(&self.base.output.directory)
+            .join(format!("trial-{}", index))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// best first, failed trials last
+    fn sorted(&self, mut trials: Vec<Trial>) -> Vec<Trial> {
+        let metric = self.base.training.metric;
+        let key = |trial: &Trial| {
+            trial.value(metric).map(|value| match metric.mode() {
+                PlateauMode::Max => -value,
+                PlateauMode::Min => value,
+            })
+        };
+        trials.sort_by(|a, b| match (key(a), key(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        });
+        trials
+    }
+
+    fn csv(&self, trials: &[Trial]) -> String {
+        let metric = self.base.training.metric;
+        let mut csv = format!(
+            "trial,learning_rate,batch_size,hidden,activation,epochs,{},error\n",
+            metric.name()
+        );
+        for trial in trials {
+            let params = &trial.params;
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                trial.index,
+                params.learning_rate,
+                params.batch_size,
+                params.hidden_name(&self.base),
+                self.activation_name(params),
+                trial.epochs,
+                trial.value(metric).map_or(String::new(), |v| v.to_string()),
+                trial
+                    .evaluation
+                    .as_ref()
+                    .err()
+                    .map_or("", |e| e.as_str())
+                    .replace(',', ";"),
+            );
+        }
+        csv
+    }
+
+    fn activation_name(&self, params: &Params) -> String {
+        params
+            .activation
+            .clone()
+            .or_else(|| activations(&self.base).next())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// the trials as an aligned table, best first
+    pub fn table(&self, trials: &[Trial]) -> String {
+        let metric = self.base.training.metric;
+        let mut table = format!(
+            "{:>5} {:>13} {:>10} {:>12} {:>12} {:>6} {:>14}\n",
+            "trial",
+            "learning rate",
+            "batch size",
+            "hidden",
+            "activation",
+            "epochs",
+            metric.name()
+        );
+        for trial in trials {
+            let params = &trial.params;
+            let value = match &trial.evaluation {
+                Ok(evaluation) => format!("{:.4}", metric.value(evaluation)),
+                Err(_) => "failed".to_string(),
+            };
+            let _ = writeln!(
+                table,
+                "{:>5} {:>13.6} {:>10} {:>12} {:>12} {:>6} {:>14}",
+                trial.index,
+                params.learning_rate,
+                params.batch_size,
+                params.hidden_name(&self.base),
+                self.activation_name(params),
+                trial.epochs,
+                value
+            );
+        }
+        table
+    }
+}
+
+/// the values, or the base value when there are none
+fn or<T: Clone>(values: &[T], base: T) -> Vec<T> {
+    match values.is_empty() {
+        true => vec![base],
+        false => values.to_vec(),
+    }
+}
+
+/// every value as an override, or no override when there are none
+fn options<T: Clone>(values: &[T]) -> Vec<Option<T>> {
+    match values.is_empty() {
+        true => vec![None],
+        false => values.iter().cloned().map(Some).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Search, SearchSpace, Strategy};
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::{
+            config::{Config, LayerConfig},
+            dataset::{augmentation::Transform, DataSet, DataVector},
+        },
+    };
+
+    fn space() -> SearchSpace {
+        SearchSpace {
+            learning_rate: vec![0.5, 2.0],
+            hidden: vec![vec![3], vec![4, 2]],
+            ..SearchSpace::default()
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let grid = Search::new(Config::default(), space()).candidates();
+        assert_eq!(grid.len(), 4);
+        assert!(grid.iter().all(|params| params.batch_size == 16));
+
+        let random = Search::new(Config::default(), space())
+            .strategy(Strategy::Random { trials: 10 })
+            .seed(3);
+        let candidates = random.candidates();
+        assert_eq!(candidates, random.candidates());
+        assert!(candidates
+            .iter()
+            .all(|params| (0.5..=2.0).contains(&params.learning_rate)));
+
+        let mut params = grid[3].clone();
+        params.activation = Some("relu".to_string());
+        let config = params.apply(&Config::default());
+        assert_eq!(config.optimizer.learning_rate, 2.0);
+        assert_eq!(config.network.layers.len(), 6);
+        assert_eq!(
+            config.network.layers[2],
+            LayerConfig::Dense { units: Some(2) }
+        );
+        assert_eq!(
+            config.network.layers[5],
+            LayerConfig::Activation {
+                function: "relu".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_successive_halving() {
+        let xor = |i: usize| {
+            let (a, b) = (i % 2, (i / 2) % 2);
+            DataVector::new(Matrix::from_vec(2, 1, vec![a as f64, b as f64]), a ^ b)
+        };
+        let data = DataSet {
+            training_data: (0..32).map(xor).collect(),
+            validation_data: (0..8).map(xor).collect(),
+            testing_data: (0..8).map(xor).collect(),
+        };
+
+        let mut base = Config::default();
+        base.training.epochs = 4;
+        base.training.batch_size = 4;
+        base.training.seed = Some(1);
+        base.output.directory = "output/search".to_string();
+
+        let search = Search::new(base, space())
+            .strategy(Strategy::Halving {
+                trials: 4,
+                min_epochs: 1,
+                reduction: 2,
+            })
+            .seed(2)
+            .parallel(2);
+        let trials = search.run_with_data(data).unwrap();
+
+        // 4 trials for 1 epoch, the best 2 for 2 epochs, and the best one for all 4
+        let mut epochs: Vec<usize> = trials.iter().map(|t| t.epochs).collect();
+        epochs.sort();
+        assert_eq!(epochs, vec![1, 1, 2, 4]);
+
+        let results = fs::read_to_string("output/search/results.csv").unwrap();
+        assert_eq!(results.lines().count(), 5);
+        assert!(
+            results.starts_with("trial,learning_rate,batch_size,hidden,activation,epochs,accuracy")
+        );
+        assert!(fs::metadata(search.trial_directory(trials[3].index)).is_ok());
+    }
+
+    #[test]
+    fn test_augmented_trials_on_a_single_thread() {
+        // 2 x 2 images, labelled by whether the top row is brighter
+        let image = |i: usize| {
+            let pixels = vec![(i % 2) as f64, 0.5, ((i + 1) % 2) as f64, 0.5];
+            DataVector::new(Matrix::from_vec(4, 1, pixels), i % 2)
+        };
+        let data = DataSet {
+            training_data: (0..16).map(image).collect(),
+            validation_data: (0..4).map(image).collect(),
+            testing_data: (0..4).map(image).collect(),
+        };
+
+        let mut base = Config::default();
+        base.data.image_size = 2;
+        base.training.epochs = 1;
+        base.training.batch_size = 4;
+        base.training.augmentation = vec![Transform::Noise { std_dev: 0.1 }];
+        base.output.directory = "output/search-augmented".to_string();
+
+        // the trials and their batches share the only thread
+        let trials = Search::new(base, space())
+            .parallel(1)
+            .run_with_data(data)
+            .unwrap();
+        assert_eq!(trials.len(), 4);
+        assert!(trials.iter().all(|t| t.evaluation.is_ok()));
+    }
+}
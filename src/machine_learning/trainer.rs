@@ -0,0 +1,583 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter},
+    sync::Arc,
+};
+
+use super::{
+    config::{Config, LogFormat, PreprocessingStep, SamplingConfig},
+    dataset::{
+        augmentation::Augmentation,
+        data_loader::{DataLoader, Sampling},
+        preprocessing::Preprocessing,
+        DataSet, DataVector, Split, Target,
+    },
+    neural_network::{
+        callbacks::{Callback, TrainingLoop},
+        checkpoint::{Checkpoint, Checkpointer, Progress},
+        cost_functions::CostFunction,
+        early_stopping::EarlyStopping,
+        evaluation::Evaluation,
+        gradient_clipping::GradientClipping,
+        layers::Layer,
+        learning_rate::Stepping,
+        logging::{CsvLogger, JsonLinesLogger, ProgressLogger, TrainingLogger},
+        regularization::Regularization,
+        NeuralNetwork,
+    },
+};
+
+/// Runs the training described by a [`Config`].
+///
+/// [`Trainer::from_config`] loads the data, fits the preprocessing and builds the network,
+/// [`Trainer::run`] trains it with checkpoints, then saves the network and the configuration
+/// to the output directory.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::{config::Config, trainer::Trainer};
+/// let config = Config::load("letters.toml")?;
+/// let mut trainer = Trainer::from_config(config)?.quiet(true);
+/// let evaluation = trainer.run()?;
+/// println!("{}", evaluation.accuracy());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Trainer {
+    config: Config,
+    data: Arc<DataSet>,
+    network: NeuralNetwork,
+    cost: CostFunction,
+    callbacks: Vec<Box<dyn Callback>>,
+    quiet: bool,
+    silent: bool,
+    resume: bool,
+}
+
+impl Trainer {
+    pub fn from_config(config: Config) -> Result<Trainer, Box<dyn Error>> {
+        check(&config)?;
+        let mut data = config.data.load()?;
+        data.split_validation(Split::Fraction(config.data.validation_split));
+        Trainer::with_data(config, data)
+    }
+
+    /// Trains on `data` instead of the dataset of the configuration,
+    /// the validation data is used as it is
+    pub fn with_data(config: Config, mut data: DataSet) -> Result<Trainer, Box<dyn Error>> {
+        check(&config)?;
+        let preprocessing = Trainer::preprocess(&config, &mut data)?;
+        Trainer::with_preprocessed_data(config, Arc::new(data), &preprocessing)
+    }
+
+    /// Fits the preprocessing of the configuration on `data` and transforms every split,
+    /// or transforms them the way the network the configuration starts from was trained.
+    ///
+    /// When the configuration augments the inputs, the training inputs are kept as they are,
+    /// to be augmented and then preprocessed batch by batch.
+    pub fn preprocess(
+        config: &Config,
+        data: &mut DataSet,
+    ) -> Result<Preprocessing, Box<dyn Error>> {
+        if data.training_data.is_empty() {
+            return Err("The training data is empty".into());
+        }
+        let augmented = !config.training.augmentation.is_empty();
+        match &config.network.init {
+            Some(path) => {
+                let preprocessing = initial_network(path)?.preprocessing().record();
+                let preprocessing = Preprocessing::from_record(&preprocessing)?;
+                let training: &mut [DataVector] = match augmented {
+                    true => &mut [],
+                    false => &mut data.training_data,
+                };
+                for split in [training, &mut data.validation_data, &mut data.testing_data] {
+                    split
+                        .iter_mut()
+                        .for_each(|d| d.data = preprocessing.transform(&d.data));
+                }
+                Ok(preprocessing)
+            }
+            None if augmented => {
+                // fitted on a copy of the training data, which is transformed step by step
+                let mut transformed = DataSet {
+                    training_data: data.training_data.clone(),
+                    validation_data: std::mem::take(&mut data.validation_data),
+                    testing_data: std::mem::take(&mut data.testing_data),
+                };
+                let mut preprocessing = PreprocessingStep::preprocessing(&config.preprocessing);
+                preprocessing.fit_transform(&mut transformed)?;
+                data.validation_data = transformed.validation_data;
+                data.testing_data = transformed.testing_data;
+                Ok(preprocessing)
+            }
+            None => {
+                let mut preprocessing = PreprocessingStep::preprocessing(&config.preprocessing);
+                preprocessing.fit_transform(data)?;
+                Ok(preprocessing)
+            }
+        }
+    }
+
+    /// Trains on `data` as transformed by [`Trainer::preprocess`], without copying it,
+    /// e.g. to share it between the trials of a search
+    pub fn with_preprocessed_data(
+        config: Config,
+        data: Arc<DataSet>,
+        preprocessing: &Preprocessing,
+    ) -> Result<Trainer, Box<dyn Error>> {
+        check(&config)?;
+        let training = &config.training;
+        let cost = CostFunction::from_name(&training.cost)
+            .ok_or(format!("unknown cost function `{}`", training.cost))?;
+        let (raw_size, input_size) = match data.training_data.first() {
+            Some(sample) if !training.augmentation.is_empty() => (
+                sample.data.get_dims().0,
+                preprocessing.transform(&sample.data).get_dims().0,
+            ),
+            Some(sample) => (sample.data.get_dims().0, sample.data.get_dims().0),
+            None => return Err("The training data is empty".into()),
+        };
+
+        let mut network = match &config.network.init {
+            // keeps the preprocessing the network was trained with
+            Some(path) => initial_network(path)?,
+            None => {
+                let outputs = output_size(data.training_data.iter().chain(&data.testing_data));
+                let model = config.network.build(
+                    config.data.image_shape(),
+                    input_size,
+                    outputs,
+                    training.seed,
+                )?;
+                let mut network = NeuralNetwork::new(model);
+                network.set_preprocessing(Preprocessing::from_record(&preprocessing.record())?);
+                network.metadata_mut().labels = config.data.label_names(outputs);
+                network
+            }
+        };
+
+        let optimizer = &config.optimizer;
+        network.set_regularization(Regularization {
+            l1: optimizer.l1,
+            l2: optimizer.l2,
+            max_norm: optimizer.max_norm,
+        });
+        network.set_gradient_clipping(match optimizer.clip_norm {
+            Some(norm) => GradientClipping::global_norm(norm)?,
+            None => GradientClipping::None,
+        });
+
+        let metadata = network.metadata_mut();
+        metadata.set_hyperparameter("cost", cost.name());
+        metadata.set_hyperparameter("epochs", training.epochs);
+        metadata.set_hyperparameter("batch_size", training.batch_size);
+        metadata.set_hyperparameter("learning_rate", optimizer.learning_rate);
+        metadata.set_hyperparameter("schedule", config.schedule.kind.name());
+        metadata.set_hyperparameter("augmentation", !training.augmentation.is_empty());
+
+        if !training.augmentation.is_empty() && config.data.image_shape().size() != raw_size {
+            return Err(format!("Cannot augment inputs of {} values as images", raw_size).into());
+        }
+
+        Ok(Trainer {
+            config,
+            data,
+            network,
+            cost,
+            callbacks: vec![],
+            quiet: false,
+            silent: false,
+            resume: false,
+        })
+    }
+
+    /// prints a line per epoch, without a progress bar
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// prints nothing while training, e.g. when runs are made in parallel
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+        self
+    }
+
+    /// Continues from the latest checkpoint in the output directory, which is otherwise
+    /// cleared. The configuration has to match the checkpointed run.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// also sends the records of training to `logger`
+    pub fn logger(mut self, logger: impl TrainingLogger + 'static) -> Self {
+        self.callbacks.push(Box::new(logger));
+        self
+    }
+
+    /// also calls `callback` during training, after the logs
+    pub fn callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// the data after preprocessing, except for the training inputs when they are augmented
+    pub fn data(&self) -> &DataSet {
+        &self.data
+    }
+
+    pub fn network(&self) -> &NeuralNetwork {
+        &self.network
+    }
+
+    pub fn into_network(self) -> NeuralNetwork {
+        self.network
+    }
+
+    /// The progress on standard output and the logs of the output directory,
+    /// which are continued when resuming.
+    fn open_logs(&self) -> Result<Vec<Box<dyn Callback>>, io::Error> {
+        let output = &self.config.output;
+        let open = |name: &str| -> Result<(File, bool), io::Error> {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(self.resume)
+                .write(true)
+                .truncate(!self.resume)
+                .open(output.log_path(name))?;
+            let empty = file.metadata()?.len() == 0;
+            Ok((file, empty))
+        };
+
+        let mut loggers: Vec<Box<dyn Callback>> = vec![];
+        if !self.silent {
+            loggers.push(Box::new(ProgressLogger::default().bar(!self.quiet)));
+        }
+        for format in output.logs.iter() {
+            match format {
+                LogFormat::Csv => {
+                    let (file, empty) = open("epochs.csv")?;
+                    loggers.push(Box::new(CsvLogger::new(BufWriter::new(file)).header(empty)));
+                    if output.log_every > 0 {
+                        let (file, empty) = open("batches.csv")?;
+                        let logger = CsvLogger::new(BufWriter::new(file))
+                            .batches(output.log_every)
+                            .header(empty);
+                        loggers.push(Box::new(logger));
+                    }
+                }
+                LogFormat::Jsonl => {
+                    let (file, _) = open("log.jsonl")?;
+                    let logger =
+                        JsonLinesLogger::new(BufWriter::new(file)).batches(output.log_every);
+                    loggers.push(Box::new(logger));
+                }
+            }
+        }
+        Ok(loggers)
+    }
+
+    /// evaluates the network on the data monitored during training
+    pub fn validate(&self) -> Evaluation {
+        self.network.evaluate(
+            validation_data(&self.data),
+            &self.cost,
+            self.config.training.top_k,
+        )
+    }
+
+    /// Trains until the last epoch or early stopping, then evaluates the network on the
+    /// testing data and saves it, with the configuration, to the output directory.
+    pub fn run(&mut self) -> Result<Evaluation, Box<dyn Error>> {
+        let (config, data) = (&self.config, &self.data);
+        let (training, output) = (&config.training, &config.output);
+        for split in [
+            &data.training_data,
+            &data.validation_data,
+            &data.testing_data,
+        ] {
+            self.network.check_targets(split)?;
+        }
+
+        let mut loader = DataLoader::new(&data.training_data, training.batch_size);
+        loader = match training.sampling {
+            SamplingConfig::Shuffled => loader,
+            SamplingConfig::Stratified => loader.with_sampling(Sampling::Stratified),
+            SamplingConfig::Balanced => {
+                loader.with_sampling(Sampling::class_balanced(&data.training_data))
+            }
+        };
+        if let Some(seed) = training.seed {
+            loader = loader.with_seed(seed);
+        }
+        if !training.augmentation.is_empty() {
+            let augmentation = training
+                .augmentation
+                .iter()
+                .fold(Augmentation::new(config.data.image_shape()), |a, t| {
+                    a.then(t.clone())
+                });
+            loader = loader.with_augmentation(augmentation);
+            // the training inputs are kept raw for the augmentation, see `Trainer::preprocess`
+            let preprocessing = self.network.preprocessing();
+            if !preprocessing.is_empty() {
+                let preprocessing = Preprocessing::from_record(&preprocessing.record())?;
+                loader = loader.with_preprocessing(Arc::new(preprocessing));
+            }
+        }
+
+        let steps = match config.schedule.stepping {
+            Stepping::Epoch => training.epochs,
+            Stepping::Batch => training.epochs * loader.len(),
+        };
+        let mut schedule =
+            config
+                .schedule
+                .schedule(config.optimizer.learning_rate, steps, training.metric);
+
+        fs::create_dir_all(&output.directory)?;
+        let directory = output.checkpoint_directory();
+        let mut checkpointer = Checkpointer::new(&directory)
+            .every_batches(output.checkpoint_every)
+            .keep_last(output.keep_last)
+            .keep_best(training.metric);
+        let mut progress = Progress::default();
+        if self.resume {
+            let path = checkpointer.latest().ok_or(format!(
+                "No checkpoint in {} to resume from",
+                directory.display()
+            ))?;
+            progress = self
+                .network
+                .resume_from(&path, &mut schedule, &mut loader)?;
+            if !self.silent {
+                println!("Resuming from {}", path.display());
+            }
+        } else if directory.exists() {
+            // older checkpoints would be kept over the ones of the new run
+            fs::remove_dir_all(&directory)?;
+        }
+
+        let mut logs = self.open_logs()?;
+        let patience = training.patience;
+        let mut early_stopping =
+            (patience > 0).then(|| EarlyStopping::new(training.metric, patience));
+        if let Some(early_stopping) = early_stopping.as_mut().filter(|_| self.resume) {
+            // continues with the patience and the best weights from before the checkpoint
+            let best = match checkpointer.best() {
+                Some(path) => Some(Checkpoint::load(path)?.network.model().record()),
+                None => None,
+            };
+            early_stopping.replay(&progress.values(training.metric), best);
+        }
+        let (nn, cost) = (&mut self.network, &self.cost);
+
+        let mut training_loop = TrainingLoop::new(&mut loader, &mut schedule, cost)
+            .epochs(training.epochs)
+            .validation(validation_data(data))
+            .metric(training.metric)
+            .top_k(training.top_k)
+            .progress(progress)
+            .callback(&mut checkpointer);
+        if let Some(early_stopping) = early_stopping.as_mut() {
+            training_loop = training_loop.callback(early_stopping);
+        }
+        for callback in logs.iter_mut().chain(self.callbacks.iter_mut()) {
+            training_loop = training_loop.callback(callback.as_mut());
+        }
+        let progress = training_loop.run(nn)?;
+
+        if let Some(early_stopping) = early_stopping.filter(|_| !self.silent) {
+            if progress.epoch < training.epochs {
+                println!("Stopped early, no improvement for {} epochs", patience);
+            }
+            if let Some((epoch, value)) = early_stopping.best() {
+                println!(
+                    "Using the weights of epoch {} ({} {})",
+                    epoch,
+                    value,
+                    training.metric.name()
+                );
+            }
+        }
+
+        let evaluation = nn.evaluate(&data.testing_data, cost, training.top_k);
+        nn.save(&output.network_path().to_string_lossy())?;
+        config.save(output.config_path())?;
+        Ok(evaluation)
+    }
+}
+
+/// the network a configuration starts from, instead of a new one
+fn initial_network(path: &str) -> Result<NeuralNetwork, Box<dyn Error>> {
+    match path.ends_with(".onnx") {
+        true => NeuralNetwork::import_onnx(path),
+        false => {
+            Ok(NeuralNetwork::load(path).map_err(|e| format!("Could not load {}: {}", path, e))?)
+        }
+    }
+}
+
+/// the values of the configuration that are only used once training has started
+fn check(config: &Config) -> Result<(), Box<dyn Error>> {
+    let split = config.data.validation_split;
+    if !(0.0..1.0).contains(&split) {
+        return Err(format!("The validation split {} is not between 0 and 1", split).into());
+    }
+    for transform in config.training.augmentation.iter() {
+        transform.validate()?;
+    }
+    if let Some(norm) = config.optimizer.clip_norm {
+        GradientClipping::global_norm(norm)?;
+    }
+    Ok(())
+}
+
+/// the data monitored during training, the testing data when nothing was held out
+fn validation_data(data: &DataSet) -> &[DataVector] {
+    match data.validation_data.is_empty() {
+        true => &data.testing_data,
+        false => &data.validation_data,
+    }
+}
+
+/// the number of outputs the network needs for `data`
+fn output_size<'a>(data: impl Iterator<Item = &'a DataVector>) -> usize {
+    data.map(|d| match d.target() {
+        Target::Class(class) => class + 1,
+        Target::Classes(classes) => classes.iter().max().map_or(0, |c| c + 1),
+        Target::Values(values) => values.get_dims().0,
+    })
+    .max()
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Trainer;
+    use crate::{
+        linear_algebra::Matrix,
+        machine_learning::{
+            config::{Config, Format, PreprocessingStep},
+            dataset::{augmentation::Transform, DataSet, DataVector, Target},
+            neural_network::NeuralNetwork,
+        },
+    };
+
+    #[test]
+    fn test_augmenting_raw_inputs() {
+        let sample = |x: usize| DataVector::new(Matrix::from_value(1, 1, x as f64 / 10.0), x % 2);
+        let data = DataSet {
+            training_data: (0..10).map(sample).collect(),
+            validation_data: vec![sample(2)],
+            testing_data: vec![sample(3)],
+        };
+        let mut config = Config::default();
+        config.data.image_size = 1;
+        config.preprocessing = vec![PreprocessingStep::Standardize];
+        config.training.augmentation = vec![Transform::Noise { std_dev: 0.05 }];
+        config.training.epochs = 1;
+        config.output.directory = "output/trainer-augmented".to_string();
+
+        // the noise is added to the raw pixels, which are standardized afterwards
+        let mut trainer = Trainer::with_data(config, data).unwrap().silent(true);
+        assert_eq!(trainer.data().training_data[3].data[(0, 0)], 0.3);
+        assert!(trainer.data().validation_data[0].data[(0, 0)] < 0.0);
+        assert!(trainer.run().is_ok());
+    }
+
+    #[test]
+    fn test_targets_not_fitting_the_outputs() {
+        let sample = |size: usize| {
+            DataVector::with_target(Matrix::zeros(1, 1), Target::Values(Matrix::zeros(size, 1)))
+        };
+        let data = DataSet {
+            training_data: vec![sample(2), sample(1)],
+            validation_data: vec![],
+            testing_data: vec![sample(2)],
+        };
+        let mut config = Config::default();
+        config.output.directory = "output/trainer-targets".to_string();
+
+        let mut trainer = Trainer::with_data(config, data).unwrap().silent(true);
+        assert!(trainer.run().is_err());
+    }
+
+    #[test]
+    fn test_running_a_config() {
+        let directory = "output/trainer";
+        fs::create_dir_all(directory).unwrap();
+        let rows: String = (0..40)
+            .map(|i| format!("{},{},{}\n", i % 2, (i / 2) % 2, (i % 2) ^ ((i / 2) % 2)))
+            .collect();
+        for split in ["train", "test"] {
+            let path = format!("{}/xor.{}.csv", directory, split);
+            fs::write(path, format!("a,b,label\n{}", rows)).unwrap();
+        }
+
+        let mut config = Config::from_toml(
+            r#"
+            [[network.layers]]
+            type = "dense"
+            units = 4
+
+            [[network.layers]]
+            type = "activation"
+            function = "tanh"
+
+            [[network.layers]]
+            type = "dense"
+
+            [[network.layers]]
+            type = "activation"
+            function = "sigmoid"
+
+            [training]
+            epochs = 2
+            batch_size = 4
+            seed = 3
+            "#,
+        )
+        .unwrap();
+        config.data.directory = directory.to_string();
+        config.data.name = "xor".to_string();
+        config.data.format = Format::Csv;
+        config.output.directory = format!("{}/run", directory);
+
+        let mut trainer = Trainer::from_config(config.clone()).unwrap().quiet(true);
+        assert_eq!(trainer.data().training_data.len(), 36);
+        let evaluation = trainer.run().unwrap();
+        assert_eq!(evaluation.classes(), 2);
+
+        // the run is recorded next to the network
+        let output = &config.output;
+        assert_eq!(Config::load(output.config_path()).unwrap(), config);
+        let nn = NeuralNetwork::load(&output.network_path().to_string_lossy()).unwrap();
+        assert_eq!(&nn, trainer.network());
+        assert!(output.checkpoint_directory().join("best.ckpt").exists());
+        let log = fs::read_to_string(output.log_path("epochs.csv")).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        assert!(log.starts_with("epoch,learning_rate,loss,accuracy"));
+
+        let invalid = |change: fn(&mut Config)| {
+            let mut config = config.clone();
+            change(&mut config);
+            Trainer::from_config(config).is_err()
+        };
+        assert!(invalid(|c| c.training.cost = "hinge".to_string()));
+        assert!(invalid(|c| c.data.validation_split = 1.5));
+        assert!(invalid(|c| c.data.validation_split = f64::NAN));
+        assert!(invalid(|c| c.optimizer.clip_norm = Some(-1.0)));
+        assert!(invalid(|c| {
+            c.training.augmentation = vec![Transform::Scale { min: 1.2, max: 0.8 }]
+        }));
+    }
+}
@@ -0,0 +1,830 @@
+//! Pictures of networks, data and training runs, rendered without any other tools.
+//!
+//! [`Tiles`] draws images stored as columns, such as samples, the weights of the first
+//! layer or the activations of a layer, side by side into a PNG. The confusion matrix of an
+//! [`Evaluation`] becomes a heatmap with [`confusion_svg`] or [`confusion_image`], and
+//! [`LinePlot`] charts curves like the ones of [`training_curves`] as SVG or PNG.
+
+use std::{error::Error, fmt::Write as _, fs, path::Path};
+
+use image::{Rgb, RgbImage};
+
+use super::{
+    dataset::DataVector,
+    neural_network::{
+        evaluation::Evaluation,
+        layers::{
+            convolution::{Conv2D, ImageShape},
+            pooling::{AvgPool2D, MaxPool2D},
+        },
+        logging::EpochRecord,
+        NeuralNetwork,
+    },
+};
+
+const BACKGROUND: Rgb<u8> = Rgb([48, 48, 48]);
+const NEGATIVE: [u8; 3] = [33, 102, 172];
+const POSITIVE: [u8; 3] = [178, 24, 43];
+const WHITE: [u8; 3] = [255, 255, 255];
+const BLACK: [u8; 3] = [0, 0, 0];
+const GRID: [u8; 3] = [238, 238, 238];
+/// the colors of the series of a [`LinePlot`], repeated when there are more series
+const PALETTE: [[u8; 3]; 6] = [
+    [31, 119, 180],
+    [214, 39, 40],
+    [44, 160, 44],
+    [255, 127, 14],
+    [148, 103, 189],
+    [140, 86, 75],
+];
+
+/// How the values of a tile become colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Levels {
+    /// values of 0-1 in grayscale, e.g. normalized pixels
+    Unit,
+    /// grayscale from the smallest to the largest value of every tile, e.g. activations
+    Stretched,
+    /// negative values blue and positive values red, scaled by the largest magnitude of
+    /// every tile, e.g. weights
+    Signed,
+}
+
+/// Draws images stored as columns into a grid of tiles.
+///
+/// Images with three channels are drawn in color when their [`Levels`] are `Unit`,
+/// otherwise every channel gets its own tile.
+///
+/// ### Example
+/// ```no_run
+/// # use mathematics::machine_learning::{neural_network::{NeuralNetwork, layers::convolution::ImageShape},
+/// #     visualization::{Levels, Tiles}};
+/// let nn = NeuralNetwork::load("output/network.nn")?;
+/// // EMNIST letters are stored transposed
+/// let tiles = Tiles::new(ImageShape::new(1, 28, 28)).transposed(true).scale(2);
+/// tiles.levels(Levels::Signed).weights(&nn)?.save("weights.png")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tiles {
+    shape: ImageShape,
+    columns: usize,
+    scale: u32,
+    transposed: bool,
+    levels: Levels,
+}
+
+impl Tiles {
+    /// images of `shape`, drawn in grayscale from 0 to 1
+    pub fn new(shape: ImageShape) -> Self {
+        Tiles {
+            shape,
+            columns: 0,
+            scale: 1,
+            transposed: false,
+            levels: Levels::Unit,
+        }
+    }
+
+    /// the number of tiles in a row, `0` makes the grid about square
+    pub fn columns(mut self, columns: usize) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// the size of a pixel of a tile, in pixels
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// whether the images are stored column by column, like those of EMNIST;
+    /// they are drawn upright either way
+    pub fn transposed(mut self, transposed: bool) -> Self {
+        self.transposed = transposed;
+        self
+    }
+
+    pub fn levels(mut self, levels: Levels) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    /// Draws every image, each a slice of `shape.size()` values
+    pub fn render(&self, images: &[Vec<f64>]) -> RgbImage {
+        let tiles: Vec<Vec<[u8; 3]>> = images.iter().flat_map(|image| self.tiles(image)).collect();
+
+        let count = tiles.len().max(1);
+        let columns = match self.columns {
+            0 => (count as f64).sqrt().ceil() as usize,
+            columns => columns.min(count),
+        };
+        let rows = count.div_ceil(columns);
+        let (width, height) = (self.shape.width as u32, self.shape.height as u32);
+        let (tile_width, tile_height) = (width * self.scale + 1, height * self.scale + 1);
+
+        let mut grid = RgbImage::from_pixel(
+            columns as u32 * tile_width + 1,
+            rows as u32 * tile_height + 1,
+            BACKGROUND,
+        );
+        for (i, tile) in tiles.iter().enumerate() {
+            let left = (i % columns) as u32 * tile_width + 1;
+            let top = (i / columns) as u32 * tile_height + 1;
+            for y in 0..height * self.scale {
+                for x in 0..width * self.scale {
+                    let pixel = tile[((y / self.scale) * width + x / self.scale) as usize];
+                    grid.put_pixel(left + x, top + y, Rgb(pixel));
+                }
+            }
+        }
+        grid
+    }
+
+    /// Draws the samples, e.g. to look at the data after loading and augmentation
+    pub fn samples(&self, data: &[DataVector]) -> RgbImage {
+        let images: Vec<Vec<f64>> = data
+            .iter()
+            .map(|d| d.data.iter().copied().collect())
+            .collect();
+        self.render(&images)
+    }
+
+    /// Draws the weights of every output of the first dense or convolution layer.
+    ///
+    /// The inputs of a dense layer are drawn in the shape of the tiles,
+    /// the kernels of a convolution in their own shape.
+    pub fn weights(&self, nn: &NeuralNetwork) -> Result<RgbImage, Box<dyn Error>> {
+        let record = nn
+            .model()
+            .layers()
+            .iter()
+            .map(|layer| layer.record())
+            .find(|record| record.kind == "dense" || record.kind == "conv2d")
+            .ok_or("The network has no dense or convolution layer")?;
+
+        let mut tiles = *self;
+        if record.kind == "conv2d" {
+            let kernel_size = record.get_config("kernel_size")?;
+            let channels = Conv2D::from_record(&record)?.input_shape().channels;
+            tiles.shape = ImageShape::new(channels, kernel_size, kernel_size);
+        }
+
+        let weights = record.get_state(0)?;
+        let (outputs, inputs) = weights.get_dims();
+        if inputs != tiles.shape.size() {
+            return Err(format!(
+                "The first layer has {} inputs, not the {} of a {}x{}x{} image",
+                inputs,
+                tiles.shape.size(),
+                tiles.shape.channels,
+                tiles.shape.height,
+                tiles.shape.width
+            )
+            .into());
+        }
+        let images: Vec<Vec<f64>> = (0..outputs)
+            .map(|output| (0..inputs).map(|input| weights[(output, input)]).collect())
+            .collect();
+        Ok(tiles.render(&images))
+    }
+
+    /// Draws every channel of the output of `layer` (counting from 0) for an input of the
+    /// shape of the tiles, which is preprocessed like the network's training data first.
+    pub fn activations(
+        &self,
+        nn: &NeuralNetwork,
+        input: &DataVector,
+        layer: usize,
+    ) -> Result<RgbImage, Box<dyn Error>> {
+        let layers = nn.model().layers();
+        if layer >= layers.len() {
+            return Err(format!("The network has {} layers", layers.len()).into());
+        }
+        nn.check_input(&input.data)?;
+
+        let mut output = nn.preprocessing().transform(&input.data);
+        let mut shape = Some(self.shape);
+        for l in layers[..=layer].iter() {
+            output = l.forward(&output);
+            let record = l.record();
+            shape = match record.kind.as_str() {
+                "conv2d" => Some(Conv2D::from_record(&record)?.output_shape()),
+                "maxpool2d" => Some(MaxPool2D::from_record(&record)?.output_shape()),
+                "avgpool2d" => Some(AvgPool2D::from_record(&record)?.output_shape()),
+                "activation" | "dropout" | "batchnorm2d" => shape,
+                _ => None,
+            };
+        }
+        let shape = shape.ok_or(format!("Layer {} does not output images", layer))?;
+
+        let mut tiles = *self;
+        tiles.shape = shape;
+        Ok(tiles.render(&[output.iter().copied().collect()]))
+    }
+
+    /// the tiles of an image, one per channel unless it is drawn in color
+    fn tiles(&self, image: &[f64]) -> Vec<Vec<[u8; 3]>> {
+        let (height, width) = (self.shape.height, self.shape.width);
+        let value = |channel: usize, y: usize, x: usize| {
+            let index = match self.transposed {
+                true => (channel * width + x) * height + y,
+                false => self.shape.index(channel, y, x),
+            };
+            image.get(index).copied().unwrap_or(0.0)
+        };
+        let pixels = |channel: usize| -> Vec<f64> {
+            (0..height * width)
+                .map(|i| value(channel, i / width, i % width))
+                .collect()
+        };
+
+        if self.shape.channels == 3 && self.levels == Levels::Unit {
+            let [r, g, b] = [pixels(0), pixels(1), pixels(2)];
+            let tile = (0..height * width)
+                .map(|i| [gray(r[i]), gray(g[i]), gray(b[i])])
+                .collect();
+            return vec![tile];
+        }
+
+        (0..self.shape.channels)
+            .map(|channel| {
+                let values = pixels(channel);
+                let lowest = values.iter().copied().fold(f64::INFINITY, f64::min);
+                let highest = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                let magnitude = lowest.abs().max(highest.abs());
+                values
+                    .iter()
+                    .map(|v| match self.levels {
+                        Levels::Unit => [gray(*v); 3],
+                        Levels::Stretched if highest > lowest => {
+                            [gray((v - lowest) / (highest - lowest)); 3]
+                        }
+                        Levels::Stretched => [0; 3],
+                        Levels::Signed if magnitude > 0.0 => signed(v / magnitude),
+                        Levels::Signed => WHITE,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn gray(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// white at 0, blue at -1 and red at 1
+fn signed(value: f64) -> [u8; 3] {
+    match value < 0.0 {
+        true => blend(WHITE, NEGATIVE, -value),
+        false => blend(WHITE, POSITIVE, value),
+    }
+}
+
+fn blend(from: [u8; 3], to: [u8; 3], t: f64) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    [0, 1, 2].map(|i| (from[i] as f64 + (to[i] as f64 - from[i] as f64) * t).round() as u8)
+}
+
+/// the share of the samples of every class predicted as every class
+fn confusion_shares(evaluation: &Evaluation) -> Vec<Vec<f64>> {
+    evaluation
+        .confusion_matrix()
+        .iter()
+        .map(|row| {
+            let total = row.iter().sum::<usize>().max(1) as f64;
+            row.iter().map(|count| *count as f64 / total).collect()
+        })
+        .collect()
+}
+
+/// The confusion matrix as an SVG heatmap; the actual classes are the rows, the predicted
+/// ones the columns, and every cell is shaded by its share of the row and shows its count.
+pub fn confusion_svg(evaluation: &Evaluation, label: impl Fn(usize) -> String) -> String {
+    const CELL: usize = 28;
+    let classes = evaluation.classes();
+    let labels: Vec<String> = (0..classes).map(label).collect();
+    let margin = 16 + 8 * labels.iter().map(|l| l.len()).max().unwrap_or(1);
+    let size = margin + classes * CELL;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="11">"#,
+        size + 8,
+        size + 30
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="14" text-anchor="middle">predicted</text>"#,
+        margin + classes * CELL / 2
+    );
+    let top = 30 + margin - 16;
+    for (class, name) in labels.iter().enumerate() {
+        let center = class * CELL + CELL / 2;
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            margin + center,
+            top - 6,
+            escape(name)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+            margin - 6,
+            top + center,
+            escape(name)
+        );
+    }
+
+    let counts = evaluation.confusion_matrix();
+    for (actual, row) in confusion_shares(evaluation).iter().enumerate() {
+        for (predicted, share) in row.iter().enumerate() {
+            let [r, g, b] = blend(WHITE, NEGATIVE, *share);
+            let (x, y) = (margin + predicted * CELL, top + actual * CELL);
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="rgb({},{},{})" stroke="#ddd"/>"##,
+                x, y, CELL, CELL, r, g, b
+            );
+            let count = counts[actual][predicted];
+            if count > 0 {
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" text-anchor="middle" dominant-baseline="middle" fill="{}">{}</text>"#,
+                    x + CELL / 2,
+                    y + CELL / 2,
+                    if *share > 0.5 { "white" } else { "black" },
+                    count
+                );
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The confusion matrix as a heatmap of `cell` x `cell` pixels per cell, without labels,
+/// shaded like [`confusion_svg`]
+pub fn confusion_image(evaluation: &Evaluation, cell: u32) -> RgbImage {
+    let shares = confusion_shares(evaluation);
+    let (classes, cell) = (shares.len() as u32, cell.max(1));
+    RgbImage::from_fn(classes * cell, classes * cell, |x, y| {
+        let share = shares[(y / cell) as usize][(x / cell) as usize];
+        Rgb(blend(WHITE, NEGATIVE, share))
+    })
+}
+
+/// Lines through points, drawn as a chart with axes and a legend,
+/// as an SVG or as an image without the text
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinePlot {
+    title: String,
+    x_label: String,
+    y_label: String,
+    series: Vec<(String, Vec<(f64, f64)>)>,
+    width: usize,
+    height: usize,
+}
+
+impl LinePlot {
+    pub fn new(title: &str) -> Self {
+        LinePlot {
+            title: title.to_string(),
+            x_label: String::new(),
+            y_label: String::new(),
+            series: vec![],
+            width: 640,
+            height: 400,
+        }
+    }
+
+    pub fn x_label(mut self, label: &str) -> Self {
+        self.x_label = label.to_string();
+        self
+    }
+
+    pub fn y_label(mut self, label: &str) -> Self {
+        self.y_label = label.to_string();
+        self
+    }
+
+    /// a line named `name` through `points`, series without points are left out
+    pub fn series(mut self, name: &str, points: Vec<(f64, f64)>) -> Self {
+        if !points.is_empty() {
+            self.series.push((name.to_string(), points));
+        }
+        self
+    }
+
+    /// the size of the chart in pixels (default 640 x 400)
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        (self.width, self.height) = (width, height);
+        self
+    }
+
+    pub fn svg(&self) -> String {
+        let layout = self.layout();
+        let (left, top) = (layout.left, layout.top);
+        let (plot_width, plot_height) = (layout.plot_width, layout.plot_height);
+        let (x_min, x_max, y_min, y_max) = (layout.x_min, layout.x_max, layout.y_min, layout.y_max);
+        let (x, y) = (|v| layout.x(v), |v| layout.y(v));
+        let height = self.height as f64;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#,
+            self.width, self.height
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="22" text-anchor="middle" font-size="15">{}</text>"#,
+            left + plot_width / 2.0,
+            escape(&self.title)
+        );
+
+        for i in 0..=4 {
+            let t = i as f64 / 4.0;
+            let (vx, vy) = (x_min + t * (x_max - x_min), y_min + t * (y_max - y_min));
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{0:.1}" y1="{1:.1}" x2="{0:.1}" y2="{2:.1}" stroke="#eee"/><text x="{0:.1}" y="{3:.1}" text-anchor="middle">{4}</text>"##,
+                x(vx),
+                top,
+                top + plot_height,
+                top + plot_height + 16.0,
+                number(vx)
+            );
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{0:.1}" y1="{1:.1}" x2="{2:.1}" y2="{1:.1}" stroke="#eee"/><text x="{3:.1}" y="{1:.1}" text-anchor="end" dominant-baseline="middle">{4}</text>"##,
+                left,
+                y(vy),
+                left + plot_width,
+                left - 6.0,
+                number(vy)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{:.1}" height="{:.1}" fill="none" stroke="black"/>"#,
+            left, top, plot_width, plot_height
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            left + plot_width / 2.0,
+            height - 10.0,
+            escape(&self.x_label)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text transform="translate(16,{:.1}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            top + plot_height / 2.0,
+            escape(&self.y_label)
+        );
+
+        for (i, (name, points)) in self.series.iter().enumerate() {
+            let color = hex(PALETTE[i % PALETTE.len()]);
+            let line: Vec<String> = points
+                .iter()
+                .map(|(px, py)| format!("{:.1},{:.1}", x(*px), y(*py)))
+                .collect();
+            let _ = writeln!(
+                svg,
+                r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+                line.join(" "),
+                color
+            );
+            let legend = top + 8.0 + i as f64 * 18.0;
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{0:.1}" y1="{1:.1}" x2="{2:.1}" y2="{1:.1}" stroke="{3}" stroke-width="2"/><text x="{4:.1}" y="{1:.1}" dominant-baseline="middle">{5}</text>"#,
+                left + plot_width + 12.0,
+                legend,
+                left + plot_width + 32.0,
+                color,
+                left + plot_width + 38.0,
+                escape(name)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The chart as an image, with the grid, the lines and the colors of the legend,
+    /// but without the title, labels and tick values, which need [`LinePlot::svg`]
+    pub fn image(&self) -> RgbImage {
+        let layout = self.layout();
+        let mut image = RgbImage::from_pixel(self.width as u32, self.height as u32, Rgb(WHITE));
+        let (left, top) = (layout.left, layout.top);
+        let (right, bottom) = (left + layout.plot_width, top + layout.plot_height);
+
+        for i in 0..=4 {
+            let t = i as f64 / 4.0;
+            let (x, y) = (left + t * layout.plot_width, top + t * layout.plot_height);
+            line(&mut image, (x, top), (x, bottom), GRID, 0);
+            line(&mut image, (left, y), (right, y), GRID, 0);
+        }
+        for (from, to) in [
+            ((left, top), (right, top)),
+            ((right, top), (right, bottom)),
+            ((right, bottom), (left, bottom)),
+            ((left, bottom), (left, top)),
+        ] {
+            line(&mut image, from, to, BLACK, 0);
+        }
+
+        for (i, (_, points)) in self.series.iter().enumerate() {
+            let color = PALETTE[i % PALETTE.len()];
+            let points: Vec<(f64, f64)> = points
+                .iter()
+                .map(|(x, y)| (layout.x(*x), layout.y(*y)))
+                .collect();
+            for pair in points.windows(2) {
+                line(&mut image, pair[0], pair[1], color, 1);
+            }
+            if let [point] = points[..] {
+                line(&mut image, point, point, color, 1);
+            }
+            let legend = top + 8.0 + i as f64 * 18.0;
+            line(
+                &mut image,
+                (right + 12.0, legend),
+                (right + 32.0, legend),
+                color,
+                1,
+            );
+        }
+        image
+    }
+
+    /// writes the chart to `path`, as an SVG if it ends in `.svg` and otherwise as an image
+    /// in the format of its extension, e.g. PNG
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        match path.extension().is_some_and(|e| e == "svg") {
+            true => fs::write(path, self.svg())?,
+            false => self.image().save(path)?,
+        }
+        Ok(())
+    }
+
+    /// where the points of the series are placed
+    fn layout(&self) -> Layout {
+        let (left, right, top, bottom) = (64.0, 120.0, 36.0, 48.0);
+        let (width, height) = (self.width as f64, self.height as f64);
+
+        let points = self.series.iter().flat_map(|(_, points)| points.iter());
+        let (x_range, y_range) = points.fold(
+            (
+                (f64::INFINITY, f64::NEG_INFINITY),
+                (f64::INFINITY, f64::NEG_INFINITY),
+            ),
+            |((x0, x1), (y0, y1)), (x, y)| ((x0.min(*x), x1.max(*x)), (y0.min(*y), y1.max(*y))),
+        );
+        let (x_min, x_max) = widen(x_range);
+        let (y_min, y_max) = widen(y_range);
+        Layout {
+            left,
+            top,
+            plot_width: width - left - right,
+            plot_height: height - top - bottom,
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+        }
+    }
+}
+
+/// The area of a [`LinePlot`] within its margins, and the ranges of its axes
+struct Layout {
+    left: f64,
+    top: f64,
+    plot_width: f64,
+    plot_height: f64,
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl Layout {
+    fn x(&self, value: f64) -> f64 {
+        self.left + (value - self.x_min) / (self.x_max - self.x_min) * self.plot_width
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        self.top + self.plot_height
+            - (value - self.y_min) / (self.y_max - self.y_min) * self.plot_height
+    }
+}
+
+/// draws a line `2 * radius + 1` pixels wide, leaving out the pixels outside of the image
+fn line(image: &mut RgbImage, from: (f64, f64), to: (f64, f64), color: [u8; 3], radius: i64) {
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil() as usize;
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    for step in 0..=steps {
+        let t = step as f64 / steps.max(1) as f64;
+        let x = (from.0 + t * (to.0 - from.0)).round() as i64;
+        let y = (from.1 + t * (to.1 - from.1)).round() as i64;
+        for py in y - radius..=y + radius {
+            for px in x - radius..=x + radius {
+                if (0..width).contains(&px) && (0..height).contains(&py) {
+                    image.put_pixel(px as u32, py as u32, Rgb(color));
+                }
+            }
+        }
+    }
+}
+
+/// a color as written in SVG
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The loss and accuracy of every epoch, on the training data and, where it was evaluated,
+/// on the validation data, e.g. of the records read with
+/// [`read_epochs`](super::neural_network::logging::read_epochs)
+pub fn training_curves(records: &[EpochRecord]) -> [LinePlot; 2] {
+    let training = |value: fn(&EpochRecord) -> f64| -> Vec<(f64, f64)> {
+        records.iter().map(|r| (r.epoch as f64, value(r))).collect()
+    };
+    let validation = |metric: &str| -> Vec<(f64, f64)> {
+        records
+            .iter()
+            .filter_map(|r| {
+                let (_, value) = r.validation.iter().find(|(name, _)| name == metric)?;
+                Some((r.epoch as f64, *value))
+            })
+            .collect()
+    };
+
+    [
+        LinePlot::new("Loss")
+            .x_label("epoch")
+            .y_label("mean loss")
+            .series("training", training(|r| r.loss))
+            .series("validation", validation("mean_loss")),
+        LinePlot::new("Accuracy")
+            .x_label("epoch")
+            .y_label("accuracy")
+            .series("training", training(|r| r.accuracy))
+            .series("validation", validation("accuracy")),
+    ]
+}
+
+/// the range of an axis, padded so flat lines and empty charts can be drawn
+fn widen((low, high): (f64, f64)) -> (f64, f64) {
+    match (low.is_finite() && high.is_finite(), high > low) {
+        (false, _) => (0.0, 1.0),
+        (true, false) => (low - 0.5, high + 0.5),
+        (true, true) => (low, high),
+    }
+}
+
+/// a tick label, without needless decimals
+fn number(value: f64) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{confusion_image, confusion_svg, training_curves, Levels, Tiles};
+    use crate::{
+        calculus::functions::Function,
+        linear_algebra::Matrix,
+        machine_learning::{
+            dataset::DataVector,
+            neural_network::{
+                cost_functions::CostFunction,
+                layers::{
+                    activation::Activation,
+                    convolution::{Conv2D, ImageShape},
+                    dense::Dense,
+                    sequential::Sequential,
+                },
+                logging::EpochRecord,
+                NeuralNetwork,
+            },
+        },
+    };
+
+    #[test]
+    fn test_tiles() {
+        // a 2x3 image with a bright top right corner
+        let shape = ImageShape::new(1, 2, 3);
+        let sample = DataVector::new(Matrix::from_vec(6, 1, vec![0., 0., 1., 0., 0., 0.]), 0);
+        let image = Tiles::new(shape)
+            .scale(2)
+            .samples(&[sample.clone(), sample]);
+        // two tiles of 6x4 pixels side by side, with a pixel between and around them
+        assert_eq!(image.dimensions(), (2 * 7 + 1, 4 + 2));
+        assert_eq!(image.get_pixel(5, 1).0, [255; 3]);
+        assert_eq!(image.get_pixel(1, 1).0, [0; 3]);
+        assert_eq!(image.get_pixel(12, 2).0, [255; 3]);
+
+        // stored transposed, the same corner is the fifth value
+        let transposed = vec![0., 0., 0., 0., 1., 0.];
+        let upright = Tiles::new(shape).transposed(true).render(&[transposed]);
+        assert_eq!(upright.get_pixel(3, 1).0, [255; 3]);
+
+        let weights = Dense::new(
+            Matrix::from_vec(2, 6, vec![1., -1., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0.]),
+            Matrix::zeros(2, 1),
+        );
+        let mut model = Sequential::default();
+        model.push(weights);
+        let nn = NeuralNetwork::new(model);
+        let image = Tiles::new(shape)
+            .levels(Levels::Signed)
+            .weights(&nn)
+            .unwrap();
+        assert_eq!(image.get_pixel(1, 1).0, [178, 24, 43]);
+        assert_eq!(image.get_pixel(5, 1).0, [33, 102, 172]);
+        assert!(Tiles::new(ImageShape::new(1, 2, 2)).weights(&nn).is_err());
+    }
+
+    #[test]
+    fn test_activations() {
+        let shape = ImageShape::new(1, 4, 4);
+        let mut model = Sequential::default();
+        model.push(Conv2D::random(shape, 3, 3, 1, 1));
+        model.push(Activation::new(Function::relu()));
+        model.push(Dense::random(48, 2));
+        let nn = NeuralNetwork::new(model);
+        let sample = DataVector::new(Matrix::from_vec(16, 1, vec![0.5; 16]), 0);
+
+        let tiles = Tiles::new(shape).levels(Levels::Stretched);
+        // three 4x4 channels
+        let image = tiles.activations(&nn, &sample, 1).unwrap();
+        assert_eq!(image.dimensions(), (2 * 5 + 1, 2 * 5 + 1));
+        assert!(tiles.activations(&nn, &sample, 2).is_err());
+        assert!(tiles.activations(&nn, &sample, 3).is_err());
+
+        let kernels = Tiles::new(shape)
+            .levels(Levels::Signed)
+            .weights(&nn)
+            .unwrap();
+        assert_eq!(kernels.dimensions(), (2 * 4 + 1, 2 * 4 + 1));
+    }
+
+    #[test]
+    fn test_charts() {
+        let mut model = Sequential::default();
+        model.push(Dense::new(Matrix::identity(2, 2), Matrix::zeros(2, 1)));
+        let nn = NeuralNetwork::new(model);
+        let sample =
+            |a: f64, b: f64, label| DataVector::new(Matrix::from_vec(2, 1, vec![a, b]), label);
+        let data = [sample(1., 0., 0), sample(0., 1., 0), sample(0., 1., 1)];
+        let evaluation = nn.evaluate(&data, &CostFunction::quadratic(), 1);
+
+        let svg = confusion_svg(&evaluation, |class| ["<a>", "b"][class].to_string());
+        assert!(svg.contains("&lt;a&gt;"));
+        assert_eq!(svg.matches("<rect").count(), 4);
+        let image = confusion_image(&evaluation, 3);
+        assert_eq!(image.dimensions(), (6, 6));
+        assert_eq!(image.get_pixel(4, 4).0, [33, 102, 172]);
+        assert_eq!(image.get_pixel(1, 1).0, image.get_pixel(4, 1).0);
+
+        let records: Vec<EpochRecord> = (0..3)
+            .map(|epoch| EpochRecord {
+                epoch,
+                loss: 1.0 / (epoch + 1) as f64,
+                validation: vec![("mean_loss".to_string(), 0.5)],
+                ..EpochRecord::default()
+            })
+            .collect();
+        let [loss, accuracy] = training_curves(&records);
+        let svg = loss.svg();
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(">validation<"));
+        assert_eq!(accuracy.svg().matches("<polyline").count(), 1);
+        assert!(svg.contains(r##"stroke="#1f77b4""##));
+
+        // the same chart as an image, with a plot of 216 x 216 pixels from (64, 36)
+        // where the validation loss of 0.5 is a flat line a quarter from the bottom
+        let image = loss.size(400, 300).image();
+        assert_eq!(image.dimensions(), (400, 300));
+        assert_eq!(image.get_pixel(120, 198).0, [214, 39, 40]);
+        assert_eq!(image.get_pixel(120, 188).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(64, 100).0, [0, 0, 0]);
+    }
+}
@@ -1,159 +1,15 @@
-mod calculus;
-mod linear_algebra;
-mod machine_learning;
-mod utilities;
+use std::process::ExitCode;
 
-extern crate cblas;
-extern crate openblas_src;
+use clap::Parser;
 
-use std::{io::stdin, io::Error};
+mod cli;
 
-use calculus::cost_functions::CostFunction;
-use machine_learning::{
-    dataset::{
-        mnist::{parse_mnist, INPUT_SIZE},
-        DataSet,
-    },
-    neural_network::NeuralNetwork,
-};
-
-use crate::calculus::functions::Function;
-
-// initialize constant values
-const OUTPUT_SIZE: usize = 26;
-
-fn main() -> Result<(), Error> {
-    // 28*28 is the expected image dimensions
-    let mut ds = DataSet::load_data("src/assets/machine_learning/", "letters", parse_mnist)?;
-    let mut nn = create_nn();
-
-    let mut activation_function = Function::sigmoid();
-    let cost_function = CostFunction::quadratic();
-    choose_activation_function(&mut activation_function);
-
-    // TODO: Rework CLI
-    let mut input: String;
-    loop {
-        input = String::new();
-        println!("1\t- reset the neural network");
-        println!("2\t- train the network");
-        println!("3\t- test the network");
-        println!("4\t- save the network");
-        println!("5\t- load the network");
-        println!("0\t- exit");
-        println!("");
-        stdin().read_line(&mut input)?;
-        println!("");
-        let chosen = input.trim().parse::<i8>().unwrap_or(-1);
-
-        match chosen {
-            1 => {
-                nn = create_nn();
-                choose_activation_function(&mut activation_function);
-            }
-
-            2 => {
-                println!("Select the number of epochs to train for (default 30):");
-                input = String::new();
-                stdin().read_line(&mut input)?;
-                println!("");
-                let epochs = input.trim().parse::<u32>().unwrap_or(30);
-
-                println!("Select a batch size (default 10):");
-                input = String::new();
-                stdin().read_line(&mut input)?;
-                println!("");
-                let batch_size = input.trim().parse::<u32>().unwrap_or(16);
-
-                println!("Select a learning rate (default 1.0):");
-                input = String::new();
-                stdin().read_line(&mut input)?;
-                let learning_rate = input.trim().parse::<f64>().unwrap_or(1.0);
-
-                for epi in 0..epochs {
-                    // randomize training data
-                    ds.training_data
-                        .sort_by(|_, _| rand::random::<f32>().partial_cmp(&0.5).unwrap());
-
-                    println!("=====Training-{}=====", epi);
-                    nn.train_verbose(
-                        &ds,
-                        batch_size as usize,
-                        learning_rate,
-                        &activation_function,
-                        &cost_function,
-                    );
-
-                    print!("Testing in Progress...");
-                    print!(
-                        "\rTesting completed with {}% accuracy\n",
-                        nn.test(&mut ds, &activation_function) * 100.0
-                    );
-                    println!("=====================");
-                }
-                println!("");
-            }
-
-            3 => {
-                print!("Testing in Progress...");
-                print!(
-                    "\rTesting completed with {}% accuracy\n",
-                    nn.test(&ds, &activation_function) * 100.0
-                );
-            }
-
-            4 => _ = nn.save("output/random_network.nn"),
-
-            5 => {
-                nn = NeuralNetwork::load("output/random_network.nn").unwrap_or_else(|_| create_nn())
-            }
-
-            0 => break,
-
-            _ => println!("Invalid Selection!"),
+fn main() -> ExitCode {
+    match cli::Cli::parse().run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
         }
     }
-
-    Ok(())
-}
-
-fn choose_activation_function(activation_function: &mut Function) {
-    let mut input = String::new();
-    println!("Choose an activation function (default sigmoid):");
-    println!("1\t- sigmoid");
-    println!("2\t- swish");
-    println!("3\t- arctan");
-    println!("4\t- normal_arctan");
-    println!("5\t- relu");
-    println!("6\t- leaky_relu");
-    println!("");
-    stdin().read_line(&mut input).unwrap_or_default();
-    println!("");
-    let function_choice = input.trim().parse::<u32>().unwrap_or(16);
-    match function_choice {
-        1 => *activation_function = Function::sigmoid(),
-        2 => *activation_function = Function::swish(),
-        3 => *activation_function = Function::arctan(),
-        4 => *activation_function = Function::normal_arctan(),
-        5 => *activation_function = Function::relu(),
-        6 => *activation_function = Function::leaky_relu(),
-        _ => *activation_function = Function::sigmoid(),
-    }
-}
-
-fn create_nn() -> NeuralNetwork {
-    let mut v = vec![INPUT_SIZE];
-
-    let mut input = String::new();
-    println!("Define the shape of the network with space separated values:");
-    stdin().read_line(&mut input).unwrap_or_default();
-    println!("");
-    let mut iter = input.trim().split_whitespace();
-
-    while let Some(cur) = iter.next() {
-        v.push(cur.parse::<usize>().unwrap_or(10))
-    }
-
-    v.push(OUTPUT_SIZE);
-    NeuralNetwork::random(v)
 }